@@ -1,12 +1,16 @@
+use std::sync::Arc;
+
 use dbkp_core::{
     databases::{DatabaseConfig, DatabaseConnection},
+    progress::ProgressReporter,
     storage::{
         provider::{StorageConfig, StorageProvider},
         Entry,
     },
-    DbBkp, RestoreOptions,
+    BackupOptions, DbBkp, RestoreOptions,
 };
 use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
 // use dbkp_core::{
 //     databases::{configs::databaseConfig, is_connected},
 //     storage::configs::StorageConfig,
@@ -17,6 +21,26 @@ pub struct BackupConnectionResult {
     connected: bool,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressPayload {
+    transferred: u64,
+    total: Option<u64>,
+}
+
+/// Forwards progress to the frontend as `event` events
+struct EventProgress {
+    app: AppHandle,
+    event: &'static str,
+}
+
+impl ProgressReporter for EventProgress {
+    fn on_bytes(&self, transferred: u64, total: Option<u64>) {
+        let _ = self
+            .app
+            .emit(self.event, ProgressPayload { transferred, total });
+    }
+}
+
 #[tauri::command]
 pub async fn list(storage_config: StorageConfig) -> Result<Vec<Entry>, String> {
     let storage_provider = StorageProvider::new(storage_config)
@@ -32,6 +56,7 @@ pub async fn list(storage_config: StorageConfig) -> Result<Vec<Entry>, String> {
 
 #[tauri::command]
 pub async fn backup(
+    app: AppHandle,
     database_config: DatabaseConfig,
     storage_config: StorageConfig,
 ) -> Result<String, String> {
@@ -45,7 +70,13 @@ pub async fn backup(
     let db_bkp = DbBkp::new(database_connection, storage_provider);
 
     db_bkp
-        .backup()
+        .backup_with(Some(BackupOptions {
+            progress: Some(Arc::new(EventProgress {
+                app,
+                event: "backup-progress",
+            })),
+            ..Default::default()
+        }))
         .await
         .map_err(|e| format!("Failed to backup database: {}", e))?;
 
@@ -70,6 +101,7 @@ pub async fn backup(
 
 #[tauri::command]
 pub async fn restore(
+    app: AppHandle,
     filename: String,
     database_config: DatabaseConfig,
     storage_config: StorageConfig,
@@ -89,6 +121,11 @@ pub async fn restore(
             name: filename,
             compression_format: None,
             drop_database_first: Some(drop_database),
+            progress: Some(Arc::new(EventProgress {
+                app,
+                event: "restore-progress",
+            })),
+            ..Default::default()
         })
         .await
         .map_err(|e| format!("Failed to restore backup: {}", e))?;
@@ -96,6 +133,29 @@ pub async fn restore(
     Ok("ok".into())
 }
 
+#[tauri::command]
+pub async fn delete_backup(
+    filename: String,
+    database_config: DatabaseConfig,
+    storage_config: StorageConfig,
+) -> Result<String, String> {
+    let database_connection = DatabaseConnection::new(database_config)
+        .await
+        .map_err(|e| format!("Failed to create database connection: {}", e))?;
+
+    let storage_provider = StorageProvider::new(storage_config)
+        .map_err(|e| format!("Failed to create storage provider: {}", e))?;
+
+    let db_bkp = DbBkp::new(database_connection, storage_provider);
+
+    db_bkp
+        .delete(&filename)
+        .await
+        .map_err(|e| format!("Failed to delete backup: {}", e))?;
+
+    Ok("ok".into())
+}
+
 #[tauri::command]
 pub async fn test_connection(
     database_config: DatabaseConfig,
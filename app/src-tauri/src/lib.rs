@@ -1,6 +1,6 @@
 use log::LevelFilter;
 mod commands;
-use commands::{backup, list, restore, test_connection};
+use commands::{backup, delete_backup, list, restore, test_connection};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -15,6 +15,7 @@ pub fn run() {
             list,
             backup,
             restore,
+            delete_backup,
             test_connection
         ])
         .run(tauri::generate_context!())
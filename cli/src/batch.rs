@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use std::{future::Future, sync::Arc};
+use tokio::sync::Semaphore;
+
+/// Runs `run` for every target, at most `concurrency` at a time. A failed target doesn't
+/// stop the others, every outcome is returned with the name of its target, in the order
+/// of `targets`.
+pub async fn run_batch<T, R, F, Fut>(
+    targets: Vec<(String, T)>,
+    concurrency: usize,
+    run: F,
+) -> Result<Vec<(String, Result<R>)>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+{
+    if concurrency == 0 {
+        return Err(anyhow!("--concurrency must be at least 1"));
+    }
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = Vec::new();
+
+    for (name, target) in targets {
+        let semaphore = semaphore.clone();
+        let future = run(target);
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .map_err(|e| anyhow!("Failed to wait for a backup slot: {}", e))?;
+
+            future.await
+        });
+
+        tasks.push((name, task));
+    }
+
+    let mut results = Vec::new();
+
+    for (name, task) in tasks {
+        let result = match task.await {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Backup task failed: {}", e)),
+        };
+
+        results.push((name, result));
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use anyhow::anyhow;
+
+    use super::run_batch;
+
+    #[tokio::test]
+    async fn test_failed_target_doesnt_stop_the_others() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+
+        let targets = (0..6).map(|i| (format!("target-{}", i), i)).collect();
+
+        let results = run_batch(targets, 2, |i| {
+            let running = running.clone();
+            let most_running = most_running.clone();
+
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now_running, Ordering::SeqCst);
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                }
+                running.fetch_sub(1, Ordering::SeqCst);
+
+                if i == 1 {
+                    Err(anyhow!("Connection refused"))
+                } else {
+                    Ok(i)
+                }
+            }
+        })
+        .await
+        .expect("Failed to run batch");
+
+        let outcomes: Vec<_> = results
+            .iter()
+            .map(|(name, result)| (name.as_str(), result.as_ref().ok().copied()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("target-0", Some(0)),
+                ("target-1", None),
+                ("target-2", Some(2)),
+                ("target-3", Some(3)),
+                ("target-4", Some(4)),
+                ("target-5", Some(5)),
+            ]
+        );
+        assert!(most_running.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_zero_concurrency() {
+        let targets = vec![("target".to_string(), ())];
+        assert!(run_batch(targets, 0, |_| async { Ok(()) }).await.is_err());
+    }
+}
@@ -1,22 +1,58 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 use anyhow::{anyhow, Result};
-use clap::{Args, Parser, Subcommand};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use dbkp_core::{
+    common::{
+        self, extract_database_from_filename, extract_timestamp_from_filename, generate_id,
+        validate_name_template,
+    },
+    compression::CompressionFormat,
     databases::{
-        ssh_tunnel::{SshAuthMethod, SshTunnelConfig},
-        ConnectionType, DatabaseConfig,
+        ssh_tunnel::{SshAuthMethod, SshJumpHost, SshTunnelConfig},
+        BackupScope, ConnectionType, DatabaseConfig,
+    },
+    encryption::{EncryptionKey, EncryptionOptions},
+    storage::{
+        provider::{
+            AzureStorageConfig, GcsStorageConfig, LocalStorageConfig, RetentionPolicy,
+            S3StorageConfig, SftpStorageConfig, SseConfig, StorageConfig,
+        },
+        Entry,
     },
-    storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig},
 };
+use globset::Glob;
+use serde::Serialize;
 
 mod tests;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Output format of list and backup. JSON keeps stdout free of anything else"
+    )]
+    pub format: OutputFormat,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored, human readable output
+    Text,
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Interactive,
@@ -24,18 +60,50 @@ pub enum Commands {
     Restore(RestoreArgs),
     List(ListArgs),
     Cleanup(CleanupArgs),
+    #[command(about = "Copy or move backups to the storage of another workspace")]
+    Copy(CopyArgs),
+    #[command(about = "Read a backup back from storage and check its integrity")]
+    Verify(VerifyArgs),
+    #[command(about = "Check that the storage is reachable")]
+    TestStorage(TestStorageArgs),
+    #[command(about = "Check that the database is reachable")]
+    TestDb(TestDbArgs),
+    #[command(about = "Check that both the database and the storage are reachable")]
+    Test(TestArgs),
     Workspace {
         #[command(subcommand)]
         command: WorkspaceCommands,
     },
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    #[command(about = "Upgrade the workspace file to the current schema version")]
+    Migrate,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum WorkspaceCommands {
-    List,
-    Create { name: String },
-    Delete { name: String },
-    Use { name: String },
+    List {
+        #[arg(
+            long,
+            help = "Most recently used workspaces first, with the time of their last use"
+        )]
+        sort_by_last_used: bool,
+    },
+    Create {
+        name: String,
+    },
+    Delete {
+        name: String,
+    },
+    Use {
+        name: String,
+    },
     Active,
 }
 
@@ -44,6 +112,59 @@ pub struct BackupArgs {
     #[arg(short, long, help = "Use workspace for configuration")]
     pub workspace: Option<String>,
 
+    #[arg(
+        long,
+        conflicts_with_all = ["workspace", "output", "mirror_workspace"],
+        help = "Back up every workspace with its own configuration, see --concurrency"
+    )]
+    pub all: bool,
+
+    #[arg(
+        long,
+        default_value_t = 4,
+        requires = "all",
+        help = "Number of workspaces backed up at the same time with --all"
+    )]
+    pub concurrency: usize,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["all", "output", "snapshot", "mirror_workspace"],
+        help = "Back up every database of the server to its own backup, named after the database"
+    )]
+    pub all_databases: bool,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "all_databases",
+        help = "Only back up the databases matching this glob pattern with --all-databases, can be repeated"
+    )]
+    pub include_database: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "all_databases",
+        help = "Skip the databases matching this glob pattern with --all-databases, can be repeated"
+    )]
+    pub exclude_database: Vec<String>,
+
+    #[arg(
+        long,
+        env = "DBKP_WEBHOOK_URL",
+        hide_env_values = true,
+        help = "Post the outcome to this webhook (Slack compatible), overrides the notifications of the workspace"
+    )]
+    pub webhook_url: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Keep Prometheus metrics of the run in this file, for the node exporter textfile collector"
+    )]
+    pub metrics_file: Option<PathBuf>,
+
     #[command(flatten)]
     pub database_config: DatabaseArgs,
 
@@ -52,6 +173,223 @@ pub struct BackupArgs {
 
     #[arg(short, long, help = "Retention period (e.g. '30d', '1w', '6m')")]
     pub retention: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "-",
+        value_parser = parse_output,
+        conflicts_with_all = ["mirror_workspace", "update_latest"],
+        help = "Stream the backup to stdout instead of the storage with '-', e.g. to pipe it into another tool"
+    )]
+    pub output: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_name_template,
+        help = "Prefix of the backup name, e.g. '{name}/{year}/{month}/{name}'. Placeholders: {name}, {database}, {type}, {host}, {date}, {time}, {year}, {month}, {day}"
+    )]
+    pub name_template: Option<String>,
+
+    #[arg(
+        long,
+        help = "Dump each table to its own schema and data file (MySQL, local server only)"
+    )]
+    pub per_table_files: bool,
+
+    #[arg(
+        long,
+        alias = "global-rate-limit",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Maximum upload rate per second shared by all writers, e.g. '10MB' or '512KiB'"
+    )]
+    pub max_rate: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Poll this many times for a just-written backup to appear, for eventually consistent S3 stores"
+    )]
+    pub visibility_retries: u32,
+
+    #[arg(
+        long,
+        default_value_t = 5,
+        help = "Attempts for storage operations failing with a temporary error (5xx, timeouts), 1 disables retries"
+    )]
+    pub storage_attempts: u32,
+
+    #[arg(
+        long,
+        help = "Write a latest.json pointer to the new backup once it completes"
+    )]
+    pub update_latest: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = BackupMode::Full,
+        help = "Backup mode. Incremental backs up MySQL binlogs or PostgreSQL WAL since the previous run"
+    )]
+    pub mode: BackupMode,
+
+    #[arg(
+        long,
+        help = "Buffer size in bytes for the dump to storage pipeline (default 256 KiB)"
+    )]
+    pub buffer_size: Option<usize>,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Retry the dump this many times on transient database errors (lock timeouts, dropped connections)"
+    )]
+    pub dump_retries: u32,
+
+    #[arg(
+        long,
+        help = "Directory where the full stderr of the dump tools is saved as <backup name>.log"
+    )]
+    pub log_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Dump as of a snapshot exported with pg_export_snapshot() in the same database (PostgreSQL)"
+    )]
+    pub snapshot: Option<String>,
+
+    #[arg(long, value_enum, help = "Compression of the backup (default gzip)")]
+    pub compression_format: Option<CompressionFormatArg>,
+
+    #[arg(
+        long,
+        help = "Compression level, 0-9 for gzip and xz, 1-22 for zstd (default 9)"
+    )]
+    pub compression_level: Option<u32>,
+
+    #[arg(long, help = "Number of threads used for zstd and xz compression")]
+    pub compression_threads: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Parts uploaded in parallel to object storage (default 5), ignored by local storage"
+    )]
+    pub upload_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        help = "File with extra pg_dump/mysqldump arguments, one per line, '#' starts a comment line"
+    )]
+    pub dump_args_from_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra pg_dump/mysqldump/mongodump argument, passed as is after the file ones, can be repeated, e.g. --dump-arg=--no-tablespaces"
+    )]
+    pub dump_arg: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Don't write the .manifest.json describing the backup, which verify --uncompressed and list --details rely on"
+    )]
+    pub no_manifest: bool,
+
+    // Manifests used to be opt-in with --manifest, they're now written by default
+    #[arg(long, hide = true, conflicts_with = "no_manifest")]
+    pub manifest: bool,
+
+    #[arg(
+        long,
+        help = "Dump PostgreSQL with this many parallel pg_dump jobs (directory format, stored as a tar archive)"
+    )]
+    pub jobs: Option<u8>,
+
+    #[arg(long, help = "Back up only the table definitions, without rows")]
+    pub schema_only: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "schema_only",
+        help = "Back up only the rows, to load into tables that already exist"
+    )]
+    pub data_only: bool,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Back up only the tables matching this glob (e.g. 'orders_*' or 'public.orders'), can be repeated"
+    )]
+    pub include_table: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Leave out the tables matching this glob, can be repeated"
+    )]
+    pub exclude_table: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "schema_only",
+        help = "Count the rows of every table into the manifest, for restore --verify-row-counts"
+    )]
+    pub record_row_counts: bool,
+
+    #[arg(
+        long,
+        help = "Back up even when the lock says another backup of the database is running"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "Also copy the backup to the storage of this workspace, can be repeated. Exits with code 6 if a copy fails"
+    )]
+    pub mirror_workspace: Vec<String>,
+
+    #[command(flatten)]
+    pub encryption: EncryptionArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackupMode {
+    /// Complete dump of the database
+    Full,
+    /// Binlog events (MySQL) or WAL segments (PostgreSQL) since the previous backup,
+    /// starting with a full base
+    Incremental,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionFormatArg {
+    Gzip,
+    Zstd,
+    Xz,
+    /// Write the raw dump
+    None,
+}
+
+impl From<CompressionFormatArg> for CompressionFormat {
+    fn from(format: CompressionFormatArg) -> Self {
+        match format {
+            CompressionFormatArg::Gzip => CompressionFormat::Gzip,
+            CompressionFormatArg::Zstd => CompressionFormat::Zstd,
+            CompressionFormatArg::Xz => CompressionFormat::Xz,
+            CompressionFormatArg::None => CompressionFormat::None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SseArg {
+    /// SSE-S3, keys managed by S3
+    #[value(name = "AES256")]
+    Aes256,
+    /// SSE-KMS, with the key of --sse-kms-key-id
+    #[value(name = "aws:kms")]
+    AwsKms,
 }
 
 #[derive(Args, Debug)]
@@ -59,59 +397,507 @@ pub struct RestoreArgs {
     #[arg(long)]
     pub name: Option<String>,
 
+    #[arg(
+        long,
+        help = "Restore into this database instead of the configured one, e.g. to clone production into staging"
+    )]
+    pub target_database: Option<String>,
+
     #[arg(long)]
     pub drop_database: bool,
 
+    #[arg(
+        long,
+        help = "Don't drop the objects of the backup before creating them, for restores into an empty database (PostgreSQL)"
+    )]
+    pub no_clean: bool,
+
     #[arg(long)]
     pub latest: bool,
 
+    #[arg(
+        long,
+        value_name = "GLOB",
+        conflicts_with_all = ["name", "latest"],
+        help = "Restore the newest backup whose name matches the glob (e.g. 'prod-orders-*.gz')"
+    )]
+    pub newest_of: Option<String>,
+
+    #[arg(
+        long,
+        help = "Restore from a local directory-format pg_dump output instead of storage"
+    )]
+    pub input_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["name", "latest", "newest_of", "input_dir", "with_increments"],
+        help = "Restore a backup file from disk instead of storage, its compression is detected"
+    )]
+    pub file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["input_dir", "file"],
+        help = "Download and check the backup and list what it contains, without restoring it"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["input_dir", "file", "dry_run", "target_database", "smoke_query"],
+        help = "Unpack a PostgreSQL incremental base and its WAL into this empty data directory, replayed on the next server start"
+    )]
+    pub data_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "RFC3339",
+        requires = "data_dir",
+        help = "Stop replaying the WAL at this time, e.g. '2024-03-01T12:30:00Z'"
+    )]
+    pub target_time: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Maximum download rate per second, e.g. '10MB' or '512KiB'"
+    )]
+    pub max_rate: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Number of parallel pg_restore jobs for directory-format restores and backups taken with --jobs"
+    )]
+    pub jobs: Option<u8>,
+
+    #[arg(
+        long,
+        help = "Replay the incremental backups recorded after this base backup"
+    )]
+    pub with_increments: bool,
+
+    #[arg(
+        long,
+        help = "Buffer size in bytes for the storage to restore pipeline (default 256 KiB)"
+    )]
+    pub buffer_size: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Bytes fetched from storage per request, e.g. '16MB' (default 8 MiB, raised for very large backups)"
+    )]
+    pub download_chunk_size: Option<u64>,
+
+    #[arg(long, help = "Chunks fetched from storage in parallel (default 4)")]
+    pub download_concurrency: Option<usize>,
+
+    #[arg(long, help = "Restore a backup created with --per-table-files")]
+    pub per_table_files: bool,
+
+    #[arg(
+        long,
+        help = "Restore a backup taken from a different MySQL or a newer PostgreSQL server version, with a warning"
+    )]
+    pub allow_version_mismatch: bool,
+
+    #[arg(
+        long,
+        help = "Directory where the full stderr of the restore tools is saved as <backup name>.log"
+    )]
+    pub log_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Query to run after the restore to check the data, e.g. 'SELECT count(*) FROM users'"
+    )]
+    pub smoke_query: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["file", "input_dir", "data_dir", "dry_run"],
+        help = "Compare the row count of every restored table with the counts recorded by backup --record-row-counts"
+    )]
+    pub verify_row_counts: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["file", "input_dir", "data_dir", "dry_run"],
+        help = "Like --verify-row-counts, but fail when the counts don't match or weren't recorded"
+    )]
+    pub strict_verify: bool,
+
+    #[arg(
+        long,
+        help = "File with extra psql/pg_restore/mysql arguments, one per line, '#' starts a comment line"
+    )]
+    pub restore_args_from_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra psql/pg_restore/mysql/mongorestore argument, passed as is after the file ones, can be repeated"
+    )]
+    pub restore_arg: Vec<String>,
+
+    #[command(flatten)]
+    pub encryption: EncryptionArgs,
+
+    #[arg(
+        long,
+        help = "Apply the restore in a single transaction, rolling back on the first error (PostgreSQL)"
+    )]
+    pub single_transaction: bool,
+
+    #[arg(
+        long,
+        help = "Character set for the recreated database. Without --charset and --collation, MySQL keeps the existing ones and PostgreSQL uses the server defaults"
+    )]
+    pub charset: Option<String>,
+
+    #[arg(long, help = "Collation for the recreated database, see --charset")]
+    pub collation: Option<String>,
+
+    #[arg(short, long, help = "Use workspace for configuration")]
+    pub workspace: Option<String>,
+
+    #[arg(
+        long,
+        env = "DBKP_WEBHOOK_URL",
+        hide_env_values = true,
+        help = "Post the outcome to this webhook (Slack compatible), overrides the notifications of the workspace"
+    )]
+    pub webhook_url: Option<String>,
+
+    #[command(flatten)]
+    pub database_config: DatabaseArgs,
+
+    #[command(flatten)]
+    pub storage_config: StorageArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    #[arg(short, long, help = "Only list backups of this database")]
+    pub database: Option<String>,
+
+    #[arg(long)]
+    pub latest_only: bool,
+
+    #[arg(long, default_value = "10")]
+    pub limit: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "latest_only",
+        help = "List every backup instead of the newest --limit ones"
+    )]
+    pub all: bool,
+
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = parse_date,
+        help = "Only list backups taken before this date, e.g. '2024-03-01' or '2024-03-01T12:00:00Z'"
+    )]
+    pub before: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = parse_date,
+        help = "Only list backups taken on or after this date, e.g. '2024-02-01'"
+    )]
+    pub after: Option<DateTime<Utc>>,
+
+    #[arg(long, value_enum, default_value_t = ListSort::Date, help = "Sort backups by this key")]
+    pub sort: ListSort,
+
+    #[arg(long, help = "Reverse the sort order")]
+    pub reverse: bool,
+
+    #[arg(
+        long,
+        help = "Show backup times in the local timezone (stored names are always UTC)"
+    )]
+    pub local_time: bool,
+
+    #[arg(
+        long,
+        help = "Print only backup names, one per line, for piping into other commands"
+    )]
+    pub filename_only: bool,
+
+    #[arg(long, help = "Only list backups directly under the location")]
+    pub no_recursive: bool,
+
+    #[arg(
+        long,
+        help = "Look up sizes the storage listing leaves out, one extra request per backup"
+    )]
+    pub stat_sizes: bool,
+
+    #[arg(long, help = "List the backups that failed verification instead")]
+    pub failed_verification: bool,
+
+    #[arg(
+        long,
+        help = "Show the database, server version and uncompressed size from each backup's manifest"
+    )]
+    pub details: bool,
+
+    #[arg(short, long, help = "Use workspace for configuration")]
+    pub workspace: Option<String>,
+
+    #[command(flatten)]
+    pub storage: StorageArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListSort {
+    /// Alphabetically by file name
+    Name,
+    /// Largest first
+    Size,
+    /// Newest first
+    Date,
+}
+
+#[derive(Args, Debug)]
+pub struct CleanupArgs {
+    #[arg(short, long, help = "Retention period (e.g. '30d', '1w', '6m')")]
+    pub retention: Option<String>,
+
+    #[arg(long, help = "Keep the newest backup of each of the last N days")]
+    pub keep_daily: Option<usize>,
+
+    #[arg(long, help = "Keep the newest backup of each of the last N weeks")]
+    pub keep_weekly: Option<usize>,
+
+    #[arg(long, help = "Keep the newest backup of each of the last N months")]
+    pub keep_monthly: Option<usize>,
+
+    #[arg(long, help = "Keep the newest backup of each of the last N years")]
+    pub keep_yearly: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Only show which backups would be deleted without actually removing them"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Allow cleanup when the storage location is empty or the bucket root"
+    )]
+    pub allow_bucket_root: bool,
+
+    #[arg(
+        long,
+        help = "Print the cutoff date the retention resolves to and exit without touching storage"
+    )]
+    pub explain: bool,
+
+    #[arg(
+        long,
+        help = "Also remove old objects without a timestamp in their name, aged by their modification time"
+    )]
+    pub strict: bool,
+
+    #[arg(short, long, help = "Database name to cleanup backups for")]
+    pub database: Option<String>,
+
+    #[arg(short, long, help = "Use workspace for configuration")]
+    pub workspace: Option<String>,
+
+    #[arg(
+        long,
+        env = "DBKP_WEBHOOK_URL",
+        hide_env_values = true,
+        help = "Post the outcome to this webhook (Slack compatible), overrides the notifications of the workspace"
+    )]
+    pub webhook_url: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Keep Prometheus metrics of the run in this file, for the node exporter textfile collector"
+    )]
+    pub metrics_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub storage: StorageArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CopyArgs {
+    #[arg(short, long, help = "Use workspace for the source storage")]
+    pub workspace: Option<String>,
+
+    #[command(flatten)]
+    pub storage: StorageArgs,
+
+    #[arg(long, help = "Workspace whose storage the backups are copied to")]
+    pub to_workspace: String,
+
+    #[arg(long, help = "Only copy backups older than this (e.g. '90d', '12w')")]
+    pub older_than: Option<String>,
+
+    #[arg(
+        long = "move",
+        alias = "delete-source-after-copy",
+        help = "Delete each backup and its sidecars from the source once its copy is verified"
+    )]
+    pub delete_source_after_copy: bool,
+
+    #[arg(long, help = "Only show which backups would be copied")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    #[arg(short, long, help = "Name of the backup to verify")]
+    pub name: String,
+
+    #[arg(
+        long,
+        help = "Check the decompressed dump against the SHA-256 recorded in the backup's manifest"
+    )]
+    pub uncompressed: bool,
+
+    #[arg(
+        long,
+        help = "Move the backup under 'quarantine/' if it is found damaged"
+    )]
+    pub quarantine: bool,
+
+    #[command(flatten)]
+    pub encryption: EncryptionArgs,
+
+    #[arg(short, long, help = "Use workspace for configuration")]
+    pub workspace: Option<String>,
+
+    #[command(flatten)]
+    pub storage: StorageArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct EncryptionArgs {
+    #[arg(
+        long,
+        env = "DBKP_ENCRYPTION_KEY",
+        hide_env_values = true,
+        conflicts_with = "encryption_passphrase",
+        help = "Encrypt/decrypt backups with this AES-256 key (64 hex characters)"
+    )]
+    pub encryption_key: Option<String>,
+
+    #[arg(
+        long,
+        env = "DBKP_ENCRYPTION_PASSPHRASE",
+        hide_env_values = true,
+        help = "Encrypt/decrypt backups with a key derived from this passphrase"
+    )]
+    pub encryption_passphrase: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TestStorageArgs {
+    #[arg(
+        long,
+        help = "Also write, read back and delete a probe object to verify permissions"
+    )]
+    pub deep: bool,
+
+    #[arg(long, help = "Print the result as JSON")]
+    pub json: bool,
+
+    #[arg(short, long, help = "Use workspace for configuration")]
+    pub workspace: Option<String>,
+
+    #[command(flatten)]
+    pub storage: StorageArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TestDbArgs {
+    #[arg(long, help = "Print the result as JSON")]
+    pub json: bool,
+
     #[arg(short, long, help = "Use workspace for configuration")]
     pub workspace: Option<String>,
 
     #[command(flatten)]
     pub database_config: DatabaseArgs,
-
-    #[command(flatten)]
-    pub storage_config: StorageArgs,
 }
 
 #[derive(Args, Debug)]
-pub struct ListArgs {
-    #[arg(short, long)]
-    pub database: Option<String>,
-
-    #[arg(long)]
-    pub latest_only: bool,
-
-    #[arg(long, default_value = "10")]
-    pub limit: Option<usize>,
+pub struct TestArgs {
+    #[arg(long, help = "Print one JSON result per component")]
+    pub json: bool,
 
     #[arg(short, long, help = "Use workspace for configuration")]
     pub workspace: Option<String>,
 
     #[command(flatten)]
-    pub storage: StorageArgs,
+    pub database_config: DatabaseArgs,
+
+    #[command(flatten)]
+    pub storage_config: StorageArgs,
 }
 
-#[derive(Args, Debug)]
-pub struct CleanupArgs {
-    #[arg(short, long, help = "Retention period (e.g. '30d', '1w', '6m')")]
-    pub retention: String,
+/// A backup of `list --format json`
+#[derive(Serialize, Debug)]
+pub struct ListedBackup {
+    pub name: String,
+    pub size: u64,
+    pub date: Option<DateTime<Utc>>,
+    pub database: Option<String>,
+}
 
-    #[arg(
-        long,
-        help = "Only show which backups would be deleted without actually removing them"
-    )]
-    pub dry_run: bool,
+impl From<&Entry> for ListedBackup {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            name: entry.path.clone(),
+            size: entry.metadata.content_length,
+            date: extract_timestamp_from_filename(&entry.metadata.name).ok(),
+            database: extract_database_from_filename(&entry.metadata.name).ok(),
+        }
+    }
+}
 
-    #[arg(short, long, help = "Database name to cleanup backups for")]
-    pub database: Option<String>,
+/// Result of `backup --format json`. The size is the one of the stored object, the
+/// checksum the one of the uncompressed dump, as in the manifest.
+#[derive(Serialize, Debug)]
+pub struct BackupReport {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
 
-    #[arg(short, long, help = "Use workspace for configuration")]
-    pub workspace: Option<String>,
+/// One line of the `backup --all` / `backup --all-databases` summary, `target` is the
+/// workspace or the database
+#[derive(Serialize, Debug)]
+pub struct BatchBackupReport {
+    pub target: String,
+    pub success: bool,
+    pub name: Option<String>,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
 
-    #[command(flatten)]
-    pub storage: StorageArgs,
+/// Result of `test-storage` / `test-db`, printed with `--json`. `test` prints one per
+/// component.
+#[derive(Serialize, Debug)]
+pub struct ConnectionTestReport {
+    pub target: String,
+    pub ok: bool,
+    pub error: Option<String>,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -119,19 +905,50 @@ pub struct SshArgs {
     #[arg(long)]
     pub ssh_host: Option<String>,
 
+    #[arg(long, help = "SSH port of the tunnel host [default: 22]")]
+    ssh_port: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "[USER@]HOST[:PORT]",
+        value_parser = parse_jump_host,
+        help = "Bastion to hop through before the tunnel host, repeat for several hops. Uses the tunnel credentials"
+    )]
+    ssh_jump_host: Vec<SshJumpHost>,
+
     #[arg(long)]
     ssh_username: Option<String>,
 
-    #[arg(long)]
+    #[arg(
+        long,
+        help = "Private key for the SSH tunnel, takes precedence over --ssh-agent"
+    )]
     ssh_key_path: Option<String>,
+
+    #[arg(
+        long,
+        env = "SSH_KEY_PASSPHRASE",
+        hide_env_values = true,
+        help = "Passphrase of the --ssh-key-path key"
+    )]
+    ssh_key_passphrase: Option<String>,
+
+    #[arg(
+        long,
+        help = "Authenticate the SSH tunnel with the identities of the running ssh-agent"
+    )]
+    ssh_agent: bool,
 }
 
 #[derive(Args, Clone, Debug)]
 pub struct DatabaseArgs {
-    #[arg(long, help = "Database type ('postgresql' or 'mysql')")]
+    #[arg(
+        long,
+        help = "Database type ('postgresql', 'mysql', 'mariadb', 'mongodb' or 'sqlite')"
+    )]
     pub database_type: Option<String>,
 
-    #[arg(long)]
+    #[arg(long, help = "Database name, or the database file for SQLite")]
     pub database: Option<String>,
 
     #[arg(long)]
@@ -146,6 +963,13 @@ pub struct DatabaseArgs {
     #[arg(long, env = "PGPASSWORD")]
     pub password: Option<String>,
 
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "How long to wait for the database server to accept a connection (default 10)"
+    )]
+    pub connect_timeout: Option<u64>,
+
     #[command(flatten)]
     pub ssh: Option<SshArgs>,
 }
@@ -175,6 +999,201 @@ pub struct StorageArgs {
 
     #[arg(long, env = "S3_SECRET_ACCESS_KEY", env = "S3_SECRET_KEY")]
     pub secret_key: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "S3 server-side encryption of the backups written, the bucket default when unset"
+    )]
+    pub sse: Option<SseArg>,
+
+    #[arg(
+        long,
+        env = "S3_SSE_KMS_KEY_ID",
+        help = "KMS key id or ARN for --sse aws:kms, implies it when --sse is not given"
+    )]
+    pub sse_kms_key_id: Option<String>,
+
+    #[arg(
+        long,
+        env = "GOOGLE_APPLICATION_CREDENTIALS",
+        help = "Service account JSON key file for GCS"
+    )]
+    pub gcs_credential_path: Option<String>,
+
+    #[arg(
+        long,
+        env = "GCS_CREDENTIAL",
+        hide_env_values = true,
+        help = "Service account JSON key for GCS, instead of a file"
+    )]
+    pub gcs_credential: Option<String>,
+
+    #[arg(
+        long,
+        env = "AZURE_STORAGE_ACCOUNT",
+        help = "Azure storage account name"
+    )]
+    pub azure_account: Option<String>,
+
+    #[arg(
+        long,
+        env = "AZURE_STORAGE_KEY",
+        hide_env_values = true,
+        help = "Azure storage account key"
+    )]
+    pub azure_account_key: Option<String>,
+
+    #[arg(
+        long,
+        env = "AZURE_STORAGE_SAS_TOKEN",
+        hide_env_values = true,
+        help = "Azure SAS token, used when there is no account key"
+    )]
+    pub azure_sas_token: Option<String>,
+
+    #[arg(long, help = "Azure Blob Storage container")]
+    pub container: Option<String>,
+
+    #[arg(long, env = "SFTP_HOST")]
+    pub sftp_host: Option<String>,
+
+    #[arg(long, env = "SFTP_PORT", default_value = "22")]
+    pub sftp_port: Option<u16>,
+
+    #[arg(long, env = "SFTP_USERNAME")]
+    pub sftp_username: Option<String>,
+
+    #[arg(
+        long,
+        env = "SFTP_KEY_PATH",
+        help = "Private key for SFTP, it must not be passphrase protected"
+    )]
+    pub sftp_key_path: Option<String>,
+}
+
+fn sse_from_cli(args: &StorageArgs) -> Result<Option<SseConfig>> {
+    match (args.sse, &args.sse_kms_key_id) {
+        (None, None) => Ok(None),
+        (Some(SseArg::Aes256), None) => Ok(Some(SseConfig::S3)),
+        (Some(SseArg::Aes256), Some(_)) => Err(anyhow!(
+            "--sse-kms-key-id can only be used with --sse aws:kms"
+        )),
+        (Some(SseArg::AwsKms), None) => Err(anyhow!("--sse aws:kms requires --sse-kms-key-id")),
+        (_, Some(key_id)) => Ok(Some(SseConfig::Kms {
+            key_id: key_id.clone(),
+        })),
+    }
+}
+
+pub fn encryption_from_cli(args: &EncryptionArgs) -> Result<Option<EncryptionOptions>> {
+    let key = match (&args.encryption_key, &args.encryption_passphrase) {
+        (Some(key), _) => EncryptionKey::Key(parse_hex_key(key)?),
+        (None, Some(passphrase)) => EncryptionKey::Passphrase(passphrase.clone()),
+        (None, None) => return Ok(None),
+    };
+
+    Ok(Some(EncryptionOptions { key }))
+}
+
+fn parse_hex_key(key: &str) -> Result<Vec<u8>> {
+    let key = key.trim();
+
+    if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "The encryption key must be 64 hexadecimal characters (32 bytes)"
+        ));
+    }
+
+    (0..key.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&key[i..i + 2], 16).map_err(|e| anyhow!(e)))
+        .collect()
+}
+
+/// Reads the extra tool arguments kept in `path`, see [`parse_args_file`].
+pub fn read_args_file(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(parse_args_file(&content))
+}
+
+/// One argument per line, so values may contain spaces without any quoting. Blank lines
+/// and lines starting with `#` are skipped.
+pub fn parse_args_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// The arguments of an args file followed by the repeated flags. Each one stays a single
+/// argument of the tool, nothing goes through a shell.
+fn extra_tool_args(file: Option<&Path>, args: &[String]) -> Result<Option<Vec<String>>> {
+    let mut extra_args = file.map(read_args_file).transpose()?;
+
+    if !args.is_empty() {
+        extra_args
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(args);
+    }
+
+    Ok(extra_args)
+}
+
+impl BackupArgs {
+    /// `--dump-args-from-file` and `--dump-arg`
+    pub fn extra_dump_args(&self) -> Result<Option<Vec<String>>> {
+        extra_tool_args(self.dump_args_from_file.as_deref(), &self.dump_arg)
+    }
+
+    pub fn scope(&self) -> BackupScope {
+        match (self.schema_only, self.data_only) {
+            (true, _) => BackupScope::SchemaOnly,
+            (_, true) => BackupScope::DataOnly,
+            _ => BackupScope::Full,
+        }
+    }
+}
+
+impl RestoreArgs {
+    /// `--restore-args-from-file` and `--restore-arg`
+    pub fn extra_restore_args(&self) -> Result<Option<Vec<String>>> {
+        extra_tool_args(self.restore_args_from_file.as_deref(), &self.restore_arg)
+    }
+}
+
+impl CleanupArgs {
+    /// The policy built from the `--keep-*` flags, if any of them was given
+    pub fn retention_policy(&self) -> Option<RetentionPolicy> {
+        if self.keep_daily.is_none()
+            && self.keep_weekly.is_none()
+            && self.keep_monthly.is_none()
+            && self.keep_yearly.is_none()
+        {
+            return None;
+        }
+
+        Some(RetentionPolicy {
+            daily: self.keep_daily.unwrap_or_default(),
+            weekly: self.keep_weekly.unwrap_or_default(),
+            monthly: self.keep_monthly.unwrap_or_default(),
+            yearly: self.keep_yearly.unwrap_or_default(),
+        })
+    }
+}
+
+/// The point in time before which `cleanup` deletes backups for the given retention.
+pub fn retention_cutoff(retention: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let days = parse_retention(retention)?;
+    let days = i64::try_from(days).map_err(|_| anyhow!("Retention period is too large"))?;
+
+    Duration::try_days(days)
+        .and_then(|period| now.checked_sub_signed(period))
+        .ok_or_else(|| anyhow!("Retention period is too large"))
 }
 
 pub fn parse_retention(retention: &str) -> Result<u64> {
@@ -198,6 +1217,131 @@ pub fn parse_retention(retention: &str) -> Result<u64> {
     }
 }
 
+/// Parses an RFC 3339 time, or a `YYYY-MM-DD` date taken as midnight UTC
+pub fn parse_date(date: &str) -> Result<DateTime<Utc>> {
+    let date = date.trim();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|day| day.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| {
+            anyhow!(
+                "Invalid date '{}', use a format like '2024-03-01' or '2024-03-01T12:00:00Z'",
+                date
+            )
+        })
+}
+
+/// Parses a size for clap with `common::parse_size`, a rate may end with `/s`
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+
+    common::parse_size(size.strip_suffix("/s").unwrap_or(size))
+}
+
+/// Parses `[user@]host[:port]`, the user and auth method default to the tunnel's
+pub fn parse_jump_host(jump_host: &str) -> Result<SshJumpHost> {
+    let jump_host = jump_host.trim();
+    let (username, address) = match jump_host.split_once('@') {
+        Some((username, address)) => (Some(username.to_string()), address),
+        None => (None, jump_host),
+    };
+
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| anyhow!("Invalid port in jump host: {}", jump_host))?,
+        ),
+        None => (address, 22),
+    };
+
+    if host.is_empty() || username.as_deref() == Some("") {
+        return Err(anyhow!("Invalid jump host: {}", jump_host));
+    }
+
+    Ok(SshJumpHost {
+        host: host.to_string(),
+        port,
+        username,
+        auth_method: None,
+    })
+}
+
+fn parse_output(output: &str) -> Result<String> {
+    if output != "-" {
+        return Err(anyhow!(
+            "Only '-' (stdout) is supported, use --storage-type local to write to a directory"
+        ));
+    }
+
+    Ok(output.to_string())
+}
+
+fn parse_name_template(template: &str) -> Result<String> {
+    validate_name_template(template)?;
+    Ok(template.to_string())
+}
+
+pub fn format_backup_timestamp(timestamp: DateTime<Utc>, local_time: bool) -> String {
+    let formatted = if local_time {
+        timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+            .to_string()
+    } else {
+        timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    };
+
+    // A backup stamped ahead of now points at a skewed clock on this machine or
+    // on the one that took the backup.
+    if timestamp > Utc::now() + Duration::minutes(5) {
+        format!("{} (in the future, check system clock)", formatted)
+    } else {
+        formatted
+    }
+}
+
+/// Picks the newest entry whose name matches `pattern`, using the timestamp embedded in
+/// the backup name.
+pub fn newest_matching<'a>(entries: &'a [Entry], pattern: &str) -> Result<Option<&'a Entry>> {
+    let matcher = Glob::new(pattern)
+        .map_err(|e| anyhow!("Invalid glob pattern '{}': {}", pattern, e))?
+        .compile_matcher();
+
+    Ok(entries
+        .iter()
+        .filter(|entry| matcher.is_match(&entry.metadata.name))
+        .filter_map(|entry| {
+            extract_timestamp_from_filename(&entry.metadata.name)
+                .ok()
+                .map(|timestamp| (timestamp, entry))
+        })
+        .max_by_key(|(timestamp, _)| *timestamp)
+        .map(|(_, entry)| entry))
+}
+
+pub fn sort_entries(entries: &mut [Entry], sort: ListSort, reverse: bool) {
+    match sort {
+        ListSort::Name => entries.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name)),
+        ListSort::Size => {
+            entries.sort_by(|a, b| b.metadata.content_length.cmp(&a.metadata.content_length))
+        }
+        ListSort::Date => entries.sort_by(|a, b| {
+            let a_timestamp = extract_timestamp_from_filename(&a.metadata.name).ok();
+            let b_timestamp = extract_timestamp_from_filename(&b.metadata.name).ok();
+            b_timestamp.cmp(&a_timestamp)
+        }),
+    }
+
+    if reverse {
+        entries.reverse();
+    }
+}
+
 pub fn storage_from_cli(args: &StorageArgs) -> Result<StorageConfig> {
     let default_storage_type = "local".to_string();
     let storage_type = args.storage_type.as_ref().unwrap_or(&default_storage_type);
@@ -239,15 +1383,93 @@ pub fn storage_from_cli(args: &StorageArgs) -> Result<StorageConfig> {
                     .location
                     .clone()
                     .ok_or_else(|| anyhow!("Location is required"))?,
-                id: "".into(),
+                upload_concurrency: None,
+                sse: sse_from_cli(args)?,
+                id: generate_id(),
+            }))
+        }
+        "gcs" => Ok(StorageConfig::Gcs(GcsStorageConfig {
+            name: args
+                .storage_name
+                .clone()
+                .unwrap_or_else(|| "default".to_string()),
+            bucket: args
+                .bucket
+                .clone()
+                .ok_or_else(|| anyhow!("GCS storage requires --bucket parameter"))?,
+            credential_path: args.gcs_credential_path.clone(),
+            credential: args.gcs_credential.clone(),
+            location: args
+                .location
+                .clone()
+                .ok_or_else(|| anyhow!("Location is required"))?,
+            id: generate_id(),
+        })),
+        "azure" => {
+            let account_name = args
+                .azure_account
+                .clone()
+                .ok_or_else(|| anyhow!("Azure storage requires --azure-account parameter"))?;
+
+            if args.azure_account_key.is_none() && args.azure_sas_token.is_none() {
+                return Err(anyhow!(
+                    "Azure storage requires --azure-account-key or --azure-sas-token"
+                ));
+            }
+
+            Ok(StorageConfig::Azure(AzureStorageConfig {
+                name: args
+                    .storage_name
+                    .clone()
+                    .unwrap_or_else(|| "default".to_string()),
+                account_name,
+                account_key: args.azure_account_key.clone(),
+                sas_token: args.azure_sas_token.clone(),
+                container: args
+                    .container
+                    .clone()
+                    .ok_or_else(|| anyhow!("Azure storage requires --container parameter"))?,
+                endpoint: None,
+                location: args
+                    .location
+                    .clone()
+                    .ok_or_else(|| anyhow!("Location is required"))?,
+                id: generate_id(),
             }))
         }
+        "sftp" => Ok(StorageConfig::Sftp(SftpStorageConfig {
+            name: args
+                .storage_name
+                .clone()
+                .unwrap_or_else(|| "default".to_string()),
+            host: args
+                .sftp_host
+                .clone()
+                .ok_or_else(|| anyhow!("SFTP storage requires --sftp-host parameter"))?,
+            port: args.sftp_port.unwrap_or(22),
+            username: args
+                .sftp_username
+                .clone()
+                .ok_or_else(|| anyhow!("SFTP storage requires --sftp-username parameter"))?,
+            auth_method: SshAuthMethod::PrivateKey {
+                key_path: args
+                    .sftp_key_path
+                    .clone()
+                    .ok_or_else(|| anyhow!("SFTP storage requires --sftp-key-path parameter"))?,
+                passphrase_key: None,
+            },
+            location: args
+                .location
+                .clone()
+                .ok_or_else(|| anyhow!("Location is required"))?,
+            id: generate_id(),
+        })),
         "local" => Ok(StorageConfig::Local(LocalStorageConfig {
             name: args
                 .storage_name
                 .clone()
                 .unwrap_or_else(|| "default".to_string()),
-            id: "".into(),
+            id: generate_id(),
             location: args
                 .location
                 .clone()
@@ -266,6 +1488,28 @@ pub fn database_config_from_cli(args: &DatabaseArgs) -> Result<DatabaseConfig> {
         .database
         .as_ref()
         .ok_or_else(|| anyhow!("Database name is required"))?;
+
+    // SQLite has no server to connect to, only the file path matters
+    if database_type == "sqlite" {
+        let name = Path::new(database)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| database.clone());
+
+        return Ok(DatabaseConfig {
+            connection_type: ConnectionType::SQLite,
+            database: database.clone(),
+            id: generate_id(),
+            name,
+            host: String::new(),
+            port: 0,
+            username: String::new(),
+            password: None,
+            ssh_tunnel: None,
+            connect_timeout: None,
+        });
+    }
+
     let host = args
         .host
         .as_ref()
@@ -275,18 +1519,35 @@ pub fn database_config_from_cli(args: &DatabaseArgs) -> Result<DatabaseConfig> {
         .username
         .as_ref()
         .ok_or_else(|| anyhow!("Username is required"))?;
-    let ssh_tunnel = if let Some(ssh) = &args.ssh {
+    // SSH_KEY_PASSPHRASE in the environment alone doesn't ask for a tunnel
+    let ssh_args = args.ssh.as_ref().filter(|ssh| {
+        ssh.ssh_host.is_some()
+            || ssh.ssh_port.is_some()
+            || ssh.ssh_key_path.is_some()
+            || ssh.ssh_agent
+            || !ssh.ssh_jump_host.is_empty()
+    });
+
+    let ssh_tunnel = if let Some(ssh) = ssh_args {
         let ssh_host = ssh
             .ssh_host
             .as_ref()
-            .ok_or_else(|| anyhow!("SSH key path is required when using SSH tunnel"))?
+            .ok_or_else(|| anyhow!("SSH host is required when using SSH tunnel"))?
             .clone();
 
-        let ssh_key_path = ssh
-            .ssh_key_path
-            .as_ref()
-            .ok_or_else(|| anyhow!("SSH key path is required when using SSH tunnel"))?
-            .clone();
+        // A key file wins over the agent, the passphrase only applies to the key file
+        let auth_method = match &ssh.ssh_key_path {
+            Some(key_path) => SshAuthMethod::PrivateKey {
+                key_path: key_path.clone(),
+                passphrase_key: ssh.ssh_key_passphrase.clone(),
+            },
+            None if ssh.ssh_agent => SshAuthMethod::Agent,
+            None => {
+                return Err(anyhow!(
+                    "SSH key path or --ssh-agent is required when using SSH tunnel"
+                ))
+            }
+        };
 
         let ssh_username = ssh
             .ssh_username
@@ -295,40 +1556,66 @@ pub fn database_config_from_cli(args: &DatabaseArgs) -> Result<DatabaseConfig> {
             .clone();
 
         Some(SshTunnelConfig {
-            port: 22,
+            port: ssh.ssh_port.unwrap_or(22),
             host: ssh_host,
             username: ssh_username,
-            auth_method: SshAuthMethod::PrivateKey {
-                key_path: ssh_key_path,
-                passphrase_key: None,
-            },
+            auth_method,
+            jump_hosts: ssh.ssh_jump_host.clone(),
         })
     } else {
         None
     };
 
+    let connect_timeout = args.connect_timeout.map(std::time::Duration::from_secs);
+
     match database_type.as_str() {
         "postgresql" => Ok(DatabaseConfig {
             connection_type: ConnectionType::PostgreSql,
             database: database.clone(),
-            id: "".into(),
+            id: generate_id(),
             name: database.clone(),
             host: host.clone(),
             port,
             username: username.clone(),
             password: args.password.clone(),
             ssh_tunnel,
+            connect_timeout,
         }),
         "mysql" => Ok(DatabaseConfig {
             connection_type: ConnectionType::MySql,
             database: database.clone(),
-            id: "".into(),
+            id: generate_id(),
+            name: database.clone(),
+            host: host.clone(),
+            port,
+            username: username.clone(),
+            password: args.password.clone(),
+            ssh_tunnel,
+            connect_timeout,
+        }),
+        "mariadb" => Ok(DatabaseConfig {
+            connection_type: ConnectionType::MariaDB,
+            database: database.clone(),
+            id: generate_id(),
+            name: database.clone(),
+            host: host.clone(),
+            port,
+            username: username.clone(),
+            password: args.password.clone(),
+            ssh_tunnel,
+            connect_timeout,
+        }),
+        "mongodb" => Ok(DatabaseConfig {
+            connection_type: ConnectionType::MongoDB,
+            database: database.clone(),
+            id: generate_id(),
             name: database.clone(),
             host: host.clone(),
             port,
             username: username.clone(),
             password: args.password.clone(),
             ssh_tunnel,
+            connect_timeout,
         }),
         _ => Err(anyhow!("Unsupported database type: {}", database_type)),
     }
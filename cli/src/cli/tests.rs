@@ -1,10 +1,47 @@
 #[cfg(test)]
 mod cli_test {
-    use dbkp_core::databases::ConnectionType;
+    use std::path::PathBuf;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use clap::Parser;
+    use dbkp_core::{
+        common::extract_timestamp_from_filename,
+        databases::{ssh_tunnel::SshAuthMethod, ConnectionType},
+        storage::{
+            provider::{SseConfig, StorageConfig},
+            Entry, EntryMetadata, EntryMode,
+        },
+    };
 
     use crate::cli::{
-        database_config_from_cli, storage_from_cli, DatabaseArgs, SshArgs, StorageArgs,
+        database_config_from_cli, encryption_from_cli, format_backup_timestamp, newest_matching,
+        parse_args_file, parse_date, parse_jump_host, parse_size, retention_cutoff, sort_entries,
+        storage_from_cli, Cli, Commands, DatabaseArgs, EncryptionArgs, ListSort, ListedBackup,
+        OutputFormat, SshArgs, StorageArgs, WorkspaceCommands,
     };
+    use dbkp_core::encryption::EncryptionKey;
+
+    fn entry(name: &str, content_length: u64) -> Entry {
+        Entry {
+            path: name.to_string(),
+            metadata: EntryMetadata {
+                mode: EntryMode::FILE,
+                name: name.to_string(),
+                is_file: true,
+                is_current: None,
+                is_deleted: false,
+                cache_control: None,
+                content_disposition: None,
+                content_length,
+                content_md5: None,
+                content_type: None,
+                content_encoding: None,
+                etag: None,
+                last_modified: None,
+                version: None,
+            },
+        }
+    }
 
     #[test]
     fn test_01_parse_backup_command() {
@@ -15,10 +52,15 @@ mod cli_test {
             port: Some(5432),
             username: Some("username".into()),
             password: Some("password".into()),
+            connect_timeout: Some(3),
             ssh: Some(SshArgs {
                 ssh_host: Some("ssh_host".into()),
+                ssh_port: Some(2222),
+                ssh_jump_host: vec![parse_jump_host("deploy@bastion.example.com:2200").unwrap()],
                 ssh_username: Some("ssh_username".into()),
                 ssh_key_path: Some("ssh_key_path".into()),
+                ssh_key_passphrase: Some("ssh_key_passphrase".into()),
+                ssh_agent: true,
             }),
         };
 
@@ -31,11 +73,22 @@ mod cli_test {
         assert_eq!(database_config.port, 5432);
         assert_eq!(database_config.username, "username");
         assert_eq!(database_config.password.clone().unwrap(), "password");
+        assert_eq!(
+            database_config.connect_timeout(),
+            std::time::Duration::from_secs(3)
+        );
 
         let ssh_config = database_config.ssh_tunnel.clone().unwrap();
 
         assert_eq!(ssh_config.host, "ssh_host");
         assert_eq!(ssh_config.username, "ssh_username");
+        assert_eq!(ssh_config.port, 2222);
+        assert_eq!(ssh_config.jump_hosts.len(), 1);
+        assert!(matches!(
+            ssh_config.auth_method,
+            SshAuthMethod::PrivateKey { ref key_path, passphrase_key: Some(ref passphrase) }
+                if key_path == "ssh_key_path" && passphrase == "ssh_key_passphrase"
+        ));
     }
 
     #[test]
@@ -49,10 +102,819 @@ mod cli_test {
             endpoint: Some("endpoint".into()),
             access_key: Some("access_key".into()),
             secret_key: Some("access_key".into()),
+            sse: None,
+            sse_kms_key_id: None,
+            gcs_credential_path: None,
+            gcs_credential: None,
+            azure_account: None,
+            azure_account_key: None,
+            azure_sas_token: None,
+            container: None,
+            sftp_host: None,
+            sftp_port: None,
+            sftp_username: None,
+            sftp_key_path: None,
         };
 
         let storage_config = storage_from_cli(&storage_args);
 
         println!("{:?}", storage_config);
     }
+
+    #[test]
+    fn test_03_sort_entries() {
+        let mut entries = vec![
+            entry("b-2024-01-02-000000-abc123.gz", 300),
+            entry("a-2024-01-03-000000-abc123.gz", 100),
+            entry("c-2024-01-01-000000-abc123.gz", 200),
+        ];
+
+        let names = |entries: &[Entry]| {
+            entries
+                .iter()
+                .map(|entry| entry.metadata.name.chars().next().unwrap())
+                .collect::<String>()
+        };
+
+        sort_entries(&mut entries, ListSort::Date, false);
+        assert_eq!(names(&entries), "abc");
+
+        sort_entries(&mut entries, ListSort::Size, false);
+        assert_eq!(names(&entries), "bca");
+
+        sort_entries(&mut entries, ListSort::Name, false);
+        assert_eq!(names(&entries), "abc");
+
+        sort_entries(&mut entries, ListSort::Name, true);
+        assert_eq!(names(&entries), "cba");
+    }
+
+    #[test]
+    fn test_04_backup_timestamps_are_utc() {
+        let timestamp = extract_timestamp_from_filename("db-2024-03-10-235959-abc123.gz").unwrap();
+
+        assert_eq!(
+            format_backup_timestamp(timestamp, false),
+            "2024-03-10 23:59:59 UTC"
+        );
+
+        let future = Utc::now() + Duration::days(1);
+        assert!(format_backup_timestamp(future, false).contains("check system clock"));
+    }
+
+    #[test]
+    fn test_05_newest_matching() {
+        let entries = vec![
+            entry("prod-orders-2024-01-02-000000-abc123.gz", 100),
+            entry("prod-orders-2024-01-03-000000-abc123.gz", 100),
+            entry("prod-users-2024-01-04-000000-abc123.gz", 100),
+        ];
+
+        let newest = newest_matching(&entries, "prod-orders-*.gz")
+            .expect("Failed to match entries")
+            .expect("Expected a matching entry");
+        assert_eq!(
+            newest.metadata.name,
+            "prod-orders-2024-01-03-000000-abc123.gz"
+        );
+
+        assert!(newest_matching(&entries, "staging-*")
+            .expect("Failed to match entries")
+            .is_none());
+    }
+
+    #[test]
+    fn test_06_retention_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            retention_cutoff("1w", now).expect("Failed to resolve retention"),
+            Utc.with_ymd_and_hms(2024, 3, 24, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            retention_cutoff("30d", now).expect("Failed to resolve retention"),
+            Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()
+        );
+        assert!(retention_cutoff("6x", now).is_err());
+    }
+
+    #[test]
+    fn test_07_encryption_args() {
+        let args = |key: Option<&str>, passphrase: Option<&str>| EncryptionArgs {
+            encryption_key: key.map(String::from),
+            encryption_passphrase: passphrase.map(String::from),
+        };
+
+        assert!(encryption_from_cli(&args(None, None))
+            .expect("Failed to parse encryption args")
+            .is_none());
+
+        let options = encryption_from_cli(&args(Some(&"0f".repeat(32)), None))
+            .expect("Failed to parse encryption args")
+            .expect("Expected encryption options");
+        assert!(matches!(options.key, EncryptionKey::Key(key) if key == vec![0x0f; 32]));
+
+        let options = encryption_from_cli(&args(None, Some("secret")))
+            .expect("Failed to parse encryption args")
+            .expect("Expected encryption options");
+        assert!(matches!(options.key, EncryptionKey::Passphrase(p) if p == "secret"));
+
+        assert!(encryption_from_cli(&args(Some("abc"), None)).is_err());
+        assert!(encryption_from_cli(&args(Some(&"zz".repeat(32)), None)).is_err());
+    }
+
+    #[test]
+    fn test_08_sqlite_database_config() {
+        let database_args = DatabaseArgs {
+            database_type: Some("sqlite".into()),
+            database: Some("/var/lib/app/app.db".into()),
+            host: None,
+            port: None,
+            username: None,
+            password: None,
+            connect_timeout: None,
+            ssh: None,
+        };
+
+        let database_config =
+            database_config_from_cli(&database_args).expect("Failed to parse database args");
+
+        assert_eq!(database_config.connection_type, ConnectionType::SQLite);
+        assert_eq!(database_config.database, "/var/lib/app/app.db");
+        assert_eq!(database_config.name, "app");
+    }
+
+    #[test]
+    fn test_09_parse_args_file() {
+        let content = "# Large tables are dumped separately\n\
+                       --exclude-table-data=audit_log\n\
+                       \n\
+                       \t--lock-wait-timeout=30000  \n\
+                       --exclude-table=public.\"Session Cache\"\n";
+
+        assert_eq!(
+            parse_args_file(content),
+            vec![
+                "--exclude-table-data=audit_log",
+                "--lock-wait-timeout=30000",
+                "--exclude-table=public.\"Session Cache\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_10_gcs_storage_config() {
+        let mut storage_args = StorageArgs {
+            storage_type: Some("gcs".into()),
+            storage_name: Some("archive".into()),
+            location: Some("backups".into()),
+            bucket: None,
+            region: None,
+            endpoint: None,
+            access_key: None,
+            secret_key: None,
+            sse: None,
+            sse_kms_key_id: None,
+            gcs_credential_path: Some("/etc/dbkp/gcs.json".into()),
+            gcs_credential: None,
+            azure_account: None,
+            azure_account_key: None,
+            azure_sas_token: None,
+            container: None,
+            sftp_host: None,
+            sftp_port: None,
+            sftp_username: None,
+            sftp_key_path: None,
+        };
+
+        assert!(
+            storage_from_cli(&storage_args).is_err(),
+            "GCS needs a bucket"
+        );
+
+        storage_args.bucket = Some("company-backups".into());
+
+        match storage_from_cli(&storage_args).expect("Failed to parse GCS config") {
+            StorageConfig::Gcs(config) => {
+                assert_eq!(config.bucket, "company-backups");
+                assert_eq!(config.location, "backups");
+                assert_eq!(
+                    config.credential_path.as_deref(),
+                    Some("/etc/dbkp/gcs.json")
+                );
+                assert!(config.credential.is_none());
+            }
+            other => panic!("Expected GCS storage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_11_azure_storage_config() {
+        let mut storage_args = StorageArgs {
+            storage_type: Some("azure".into()),
+            storage_name: Some("archive".into()),
+            location: Some("backups".into()),
+            bucket: None,
+            region: None,
+            endpoint: None,
+            access_key: None,
+            secret_key: None,
+            sse: None,
+            sse_kms_key_id: None,
+            gcs_credential_path: None,
+            gcs_credential: None,
+            azure_account: Some("companybackups".into()),
+            azure_account_key: None,
+            azure_sas_token: None,
+            container: Some("databases".into()),
+            sftp_host: None,
+            sftp_port: None,
+            sftp_username: None,
+            sftp_key_path: None,
+        };
+
+        assert!(
+            storage_from_cli(&storage_args).is_err(),
+            "Azure needs an account key or a SAS token"
+        );
+
+        storage_args.azure_sas_token = Some("sv=2022-11-02&sig=abc".into());
+
+        match storage_from_cli(&storage_args).expect("Failed to parse Azure config") {
+            StorageConfig::Azure(config) => {
+                assert_eq!(config.account_name, "companybackups");
+                assert_eq!(config.container, "databases");
+                assert!(config.account_key.is_none());
+                assert_eq!(config.sas_token.as_deref(), Some("sv=2022-11-02&sig=abc"));
+            }
+            other => panic!("Expected Azure storage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_12_sftp_storage_config() {
+        let mut storage_args = StorageArgs {
+            storage_type: Some("sftp".into()),
+            storage_name: Some("offsite".into()),
+            location: Some("/srv/backups".into()),
+            bucket: None,
+            region: None,
+            endpoint: None,
+            access_key: None,
+            secret_key: None,
+            sse: None,
+            sse_kms_key_id: None,
+            gcs_credential_path: None,
+            gcs_credential: None,
+            azure_account: None,
+            azure_account_key: None,
+            azure_sas_token: None,
+            container: None,
+            sftp_host: Some("backup.example.com".into()),
+            sftp_port: Some(2222),
+            sftp_username: Some("dbkp".into()),
+            sftp_key_path: None,
+        };
+
+        assert!(
+            storage_from_cli(&storage_args).is_err(),
+            "SFTP needs a private key"
+        );
+
+        storage_args.sftp_key_path = Some("/home/dbkp/.ssh/id_ed25519".into());
+
+        match storage_from_cli(&storage_args).expect("Failed to parse SFTP config") {
+            StorageConfig::Sftp(config) => {
+                assert_eq!(config.host, "backup.example.com");
+                assert_eq!(config.port, 2222);
+                assert_eq!(config.username, "dbkp");
+                assert_eq!(config.location, "/srv/backups");
+                assert!(matches!(
+                    config.auth_method,
+                    SshAuthMethod::PrivateKey { ref key_path, passphrase_key: None }
+                        if key_path == "/home/dbkp/.ssh/id_ed25519"
+                ));
+            }
+            other => panic!("Expected SFTP storage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_13_parse_size() {
+        assert_eq!(parse_size("1048576").unwrap(), 1_048_576);
+        assert_eq!(parse_size("10MB").unwrap(), 10_000_000);
+        assert_eq!(parse_size("10mb/s").unwrap(), 10_000_000);
+        assert_eq!(parse_size("512KiB").unwrap(), 524_288);
+        assert_eq!(parse_size("1.5 GiB").unwrap(), 1_610_612_736);
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("0MB").is_err());
+    }
+
+    #[test]
+    fn test_14_mariadb_database_config() {
+        let database_args = DatabaseArgs {
+            database_type: Some("mariadb".into()),
+            database: Some("shop".into()),
+            host: Some("db.example.com".into()),
+            port: Some(3306),
+            username: Some("backup".into()),
+            password: None,
+            connect_timeout: None,
+            ssh: None,
+        };
+
+        let database_config =
+            database_config_from_cli(&database_args).expect("Failed to parse database args");
+
+        assert_eq!(database_config.connection_type, ConnectionType::MariaDB);
+        assert_eq!(database_config.database, "shop");
+        assert_eq!(database_config.port, 3306);
+    }
+
+    #[test]
+    fn test_15_parse_jump_host() {
+        let jump_host = parse_jump_host("bastion.example.com").unwrap();
+        assert_eq!(jump_host.host, "bastion.example.com");
+        assert_eq!(jump_host.port, 22);
+        assert_eq!(jump_host.username, None);
+
+        let jump_host = parse_jump_host("deploy@10.0.0.5:2200").unwrap();
+        assert_eq!(jump_host.host, "10.0.0.5");
+        assert_eq!(jump_host.port, 2200);
+        assert_eq!(jump_host.username.as_deref(), Some("deploy"));
+
+        assert!(parse_jump_host("bastion:ssh").is_err());
+        assert!(parse_jump_host("@bastion").is_err());
+        assert!(parse_jump_host(":22").is_err());
+    }
+
+    #[test]
+    fn test_16_listed_backup_json() {
+        let mut entry = entry("shop-2024-01-02-030405-abc123.gz", 300);
+        entry.path = "shop/2024/shop-2024-01-02-030405-abc123.gz".into();
+
+        let value = serde_json::to_value(ListedBackup::from(&entry)).unwrap();
+
+        assert_eq!(value["name"], "shop/2024/shop-2024-01-02-030405-abc123.gz");
+        assert_eq!(value["size"], 300);
+        assert_eq!(value["date"], "2024-01-02T03:04:05Z");
+        assert_eq!(value["database"], "shop");
+
+        let cli = Cli::try_parse_from(["dbkp", "list", "--format", "json"]).unwrap();
+        assert_eq!(cli.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn test_17_restore_from_file() {
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--file", "./shop.dump.gz"]).unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => {
+                assert_eq!(args.file, Some(PathBuf::from("./shop.dump.gz")));
+            }
+            _ => panic!("Expected the restore command"),
+        }
+
+        for conflicting in ["--latest", "--dry-run", "--with-increments"] {
+            assert!(
+                Cli::try_parse_from(["dbkp", "restore", "--file", "./shop.dump.gz", conflicting])
+                    .is_err(),
+                "--file should conflict with {}",
+                conflicting
+            );
+        }
+    }
+
+    #[test]
+    fn test_18_restore_data_directory() {
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "restore",
+            "--latest",
+            "--data-dir",
+            "/var/lib/postgresql/restored",
+            "--target-time",
+            "2024-03-01T12:30:00Z",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => {
+                assert_eq!(
+                    args.data_dir,
+                    Some(PathBuf::from("/var/lib/postgresql/restored"))
+                );
+                assert_eq!(
+                    args.target_time,
+                    Some(Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap())
+                );
+            }
+            _ => panic!("Expected the restore command"),
+        }
+
+        // A target time only means something for a WAL replay
+        assert!(Cli::try_parse_from([
+            "dbkp",
+            "restore",
+            "--latest",
+            "--target-time",
+            "2024-03-01T12:30:00Z",
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "dbkp",
+            "restore",
+            "--latest",
+            "--data-dir",
+            "./restored",
+            "--target-time",
+            "yesterday",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_19_mongodb_database_config() {
+        let database_args = DatabaseArgs {
+            database_type: Some("mongodb".into()),
+            database: Some("catalog".into()),
+            host: Some("mongo.example.com".into()),
+            port: Some(27017),
+            username: Some("backup".into()),
+            password: Some("secret".into()),
+            connect_timeout: None,
+            ssh: None,
+        };
+
+        let database_config =
+            database_config_from_cli(&database_args).expect("Failed to parse database args");
+
+        assert_eq!(database_config.connection_type, ConnectionType::MongoDB);
+        assert_eq!(database_config.database, "catalog");
+        assert_eq!(database_config.port, 27017);
+        assert_eq!(database_config.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_20_backup_all() {
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--all", "--concurrency", "8"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => {
+                assert!(args.all);
+                assert_eq!(args.concurrency, 8);
+            }
+            _ => panic!("Expected the backup command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--all"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert_eq!(args.concurrency, 4),
+            _ => panic!("Expected the backup command"),
+        }
+
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--all", "--workspace", "shop"]).is_err());
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--all", "--output", "-"]).is_err());
+        // Only --all backs up several workspaces at once
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--concurrency", "8"]).is_err());
+    }
+
+    #[test]
+    fn test_21_backup_all_databases() {
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "backup",
+            "--workspace",
+            "prod",
+            "--all-databases",
+            "--include-database",
+            "shop*",
+            "--exclude-database",
+            "*_staging",
+            "--exclude-database",
+            "tmp_*",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => {
+                assert!(args.all_databases);
+                assert_eq!(args.include_database, vec!["shop*"]);
+                assert_eq!(args.exclude_database, vec!["*_staging", "tmp_*"]);
+            }
+            _ => panic!("Expected the backup command"),
+        }
+
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--include-database", "shop"]).is_err());
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--all-databases", "--all"]).is_err());
+        assert!(
+            Cli::try_parse_from(["dbkp", "backup", "--all-databases", "--output", "-"]).is_err()
+        );
+        assert!(Cli::try_parse_from([
+            "dbkp",
+            "backup",
+            "--all-databases",
+            "--mirror-workspace",
+            "offsite"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_22_row_count_verification() {
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--record-row-counts"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert!(args.record_row_counts),
+            _ => panic!("Expected the backup command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--latest", "--strict-verify"]).unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => {
+                assert!(args.strict_verify);
+                assert!(!args.verify_row_counts);
+            }
+            _ => panic!("Expected the restore command"),
+        }
+
+        assert!(
+            Cli::try_parse_from(["dbkp", "backup", "--record-row-counts", "--schema-only"])
+                .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["dbkp", "restore", "--verify-row-counts", "--dry-run"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["dbkp", "restore", "--file", "dump.sql", "--strict-verify"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_23_list_all_and_date_range() {
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "list",
+            "--all",
+            "--after",
+            "2024-02-01",
+            "--before",
+            "2024-03-01T12:00:00+02:00",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::List(args)) => {
+                assert!(args.all);
+                assert_eq!(
+                    args.after,
+                    Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap())
+                );
+                assert_eq!(
+                    args.before,
+                    Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap())
+                );
+            }
+            _ => panic!("Expected the list command"),
+        }
+
+        assert!(Cli::try_parse_from(["dbkp", "list", "--all", "--latest-only"]).is_err());
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_24_backup_force() {
+        let cli =
+            Cli::try_parse_from(["dbkp", "backup", "--workspace", "shop", "--force"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert!(args.force),
+            _ => panic!("Expected the backup command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--workspace", "shop"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert!(!args.force),
+            _ => panic!("Expected the backup command"),
+        }
+    }
+
+    #[test]
+    fn test_25_metrics_file() {
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "backup",
+            "--all",
+            "--metrics-file",
+            "/var/lib/node_exporter/dbkp.prom",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert_eq!(
+                args.metrics_file,
+                Some(PathBuf::from("/var/lib/node_exporter/dbkp.prom"))
+            ),
+            _ => panic!("Expected the backup command"),
+        }
+
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "cleanup",
+            "--retention",
+            "30d",
+            "--metrics-file",
+            "dbkp.prom",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Cleanup(args)) => {
+                assert_eq!(args.metrics_file, Some(PathBuf::from("dbkp.prom")))
+            }
+            _ => panic!("Expected the cleanup command"),
+        }
+    }
+
+    #[test]
+    fn test_26_extra_tool_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let args_file = dir.path().join("dump.args");
+        std::fs::write(
+            &args_file,
+            "# Consistent snapshot\n--serializable-deferrable\n",
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from([
+            "dbkp",
+            "backup",
+            "--dump-args-from-file",
+            args_file.to_str().unwrap(),
+            "--dump-arg",
+            "--exclude-schema=audit",
+            "--dump-arg=--no-comments",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert_eq!(
+                args.extra_dump_args().unwrap(),
+                Some(vec![
+                    "--serializable-deferrable".to_string(),
+                    "--exclude-schema=audit".to_string(),
+                    "--no-comments".to_string(),
+                ])
+            ),
+            _ => panic!("Expected the backup command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--latest", "--restore-arg=--no-owner"])
+            .unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => assert_eq!(
+                args.extra_restore_args().unwrap(),
+                Some(vec!["--no-owner".to_string()])
+            ),
+            _ => panic!("Expected the restore command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--latest"]).unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => assert_eq!(args.extra_restore_args().unwrap(), None),
+            _ => panic!("Expected the restore command"),
+        }
+    }
+
+    #[test]
+    fn test_27_test_connections() {
+        let cli =
+            Cli::try_parse_from(["dbkp", "test", "--workspace", "production", "--json"]).unwrap();
+        match cli.command {
+            Some(Commands::Test(args)) => {
+                assert_eq!(args.workspace.as_deref(), Some("production"));
+                assert!(args.json);
+            }
+            _ => panic!("Expected the test command"),
+        }
+
+        assert!(Cli::try_parse_from(["dbkp", "test", "--deep"]).is_err());
+    }
+
+    #[test]
+    fn test_28_s3_server_side_encryption() {
+        let storage_config = |extra: &[&str]| {
+            let mut args = vec![
+                "dbkp",
+                "backup",
+                "--storage-type",
+                "s3",
+                "--location",
+                "backups",
+                "--bucket",
+                "company-backups",
+                "--endpoint",
+                "https://s3.amazonaws.com",
+                "--access-key",
+                "access_key",
+                "--secret-key",
+                "secret_key",
+            ];
+            args.extend_from_slice(extra);
+
+            match Cli::try_parse_from(args).unwrap().command {
+                Some(Commands::Backup(args)) => storage_from_cli(&args.storage_config),
+                _ => panic!("Expected the backup command"),
+            }
+        };
+        let sse = |extra: &[&str]| match storage_config(extra) {
+            Ok(StorageConfig::S3(config)) => Ok(config.sse),
+            Ok(other) => panic!("Expected S3 storage, got {:?}", other),
+            Err(e) => Err(e),
+        };
+
+        assert_eq!(sse(&[]).unwrap(), None);
+        assert_eq!(sse(&["--sse", "AES256"]).unwrap(), Some(SseConfig::S3));
+
+        let kms = Some(SseConfig::Kms {
+            key_id: "alias/backups".to_string(),
+        });
+        assert_eq!(
+            sse(&["--sse", "aws:kms", "--sse-kms-key-id", "alias/backups"]).unwrap(),
+            kms
+        );
+        assert_eq!(sse(&["--sse-kms-key-id", "alias/backups"]).unwrap(), kms);
+
+        assert!(sse(&["--sse", "aws:kms"]).is_err());
+        assert!(sse(&["--sse", "AES256", "--sse-kms-key-id", "alias/backups"]).is_err());
+    }
+
+    #[test]
+    fn test_29_workspace_list_sort_by_last_used() {
+        let cli =
+            Cli::try_parse_from(["dbkp", "workspace", "list", "--sort-by-last-used"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Workspace {
+                command: WorkspaceCommands::List {
+                    sort_by_last_used: true
+                }
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["dbkp", "workspace", "list"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Workspace {
+                command: WorkspaceCommands::List {
+                    sort_by_last_used: false
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_30_restore_no_clean() {
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--latest", "--no-clean"]).unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => assert!(args.no_clean),
+            _ => panic!("Expected the restore command"),
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "restore", "--latest"]).unwrap();
+        match cli.command {
+            Some(Commands::Restore(args)) => assert!(!args.no_clean),
+            _ => panic!("Expected the restore command"),
+        }
+    }
+
+    #[test]
+    fn test_31_manifest_flags() {
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--no-manifest"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert!(args.no_manifest),
+            _ => panic!("Expected the backup command"),
+        }
+
+        // Still accepted from scripts written when manifests were opt-in
+        let cli = Cli::try_parse_from(["dbkp", "backup", "--manifest"]).unwrap();
+        match cli.command {
+            Some(Commands::Backup(args)) => assert!(!args.no_manifest),
+            _ => panic!("Expected the backup command"),
+        }
+
+        assert!(Cli::try_parse_from(["dbkp", "backup", "--manifest", "--no-manifest"]).is_err());
+    }
+
+    #[test]
+    fn test_32_copy_move() {
+        for flag in ["--move", "--delete-source-after-copy"] {
+            let cli =
+                Cli::try_parse_from(["dbkp", "copy", "--to-workspace", "cold", flag]).unwrap();
+            match cli.command {
+                Some(Commands::Copy(args)) => assert!(args.delete_source_after_copy),
+                _ => panic!("Expected the copy command"),
+            }
+        }
+
+        let cli = Cli::try_parse_from(["dbkp", "copy", "--to-workspace", "cold"]).unwrap();
+        match cli.command {
+            Some(Commands::Copy(args)) => assert!(!args.delete_source_after_copy),
+            _ => panic!("Expected the copy command"),
+        }
+    }
 }
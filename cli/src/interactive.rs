@@ -1,16 +1,23 @@
 use anyhow::{anyhow, Result};
 use colored::*;
 use dbkp_core::{
+    common::generate_id,
+    compression::CompressionFormat,
     databases::{
         ssh_tunnel::{SshAuthMethod, SshTunnelConfig},
         ConnectionType, DatabaseConfig,
     },
-    storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig},
+    storage::provider::{
+        AzureStorageConfig, GcsStorageConfig, LocalStorageConfig, S3StorageConfig,
+        SftpStorageConfig, StorageConfig,
+    },
+    BackupOptions,
 };
 use inquire::{Confirm, Password, Select, Text};
 
+use crate::cli::parse_retention;
 use crate::spinner::Spinner;
-use crate::workspace::{Workspace, WorkspaceCollection, WorkspaceManager};
+use crate::workspace::{BackupDefaults, Workspace, WorkspaceCollection, WorkspaceManager};
 
 pub struct InteractiveSetup {
     workspace_manager: WorkspaceManager,
@@ -92,8 +99,9 @@ impl InteractiveSetup {
                     }
                 }
                 MainAction::BackupDatabase => {
-                    if let Some(workspace) = collection.get_active() {
-                        self.run_backup(workspace).await?;
+                    if let Some(workspace) = collection.get_active().cloned() {
+                        self.record_use(&mut collection, &workspace.name);
+                        self.run_backup(&workspace).await?;
                     } else {
                         println!(
                             "{}",
@@ -102,8 +110,9 @@ impl InteractiveSetup {
                     }
                 }
                 MainAction::RestoreDatabase => {
-                    if let Some(workspace) = collection.get_active() {
-                        self.run_restore(workspace).await?;
+                    if let Some(workspace) = collection.get_active().cloned() {
+                        self.record_use(&mut collection, &workspace.name);
+                        self.run_restore(&workspace).await?;
                     } else {
                         println!(
                             "{}",
@@ -159,6 +168,19 @@ impl InteractiveSetup {
         Ok(action)
     }
 
+    /// Updates `last_used` of the workspace, the backup or restore goes on when it can't
+    /// be saved
+    fn record_use(&self, collection: &mut WorkspaceCollection, name: &str) {
+        collection.mark_used(name);
+
+        if let Err(e) = self.workspace_manager.save(collection) {
+            println!(
+                "{}",
+                format!("[WARN] Failed to record the use of the workspace: {}", e).yellow()
+            );
+        }
+    }
+
     async fn create_workspace_interactive(&self) -> Result<Workspace> {
         println!("Creating a new workspace...");
         println!();
@@ -167,6 +189,11 @@ impl InteractiveSetup {
             .with_help_message("Choose a descriptive name for this workspace")
             .prompt()?;
 
+        self.prompt_workspace(name).await
+    }
+
+    /// Walks through the database and storage prompts for a workspace named `name`
+    pub async fn prompt_workspace(&self, name: String) -> Result<Workspace> {
         println!();
         println!("Database Configuration");
         let database_config = self.setup_database_interactive().await?;
@@ -175,6 +202,11 @@ impl InteractiveSetup {
         println!("Storage Configuration");
         let storage_config = self.setup_storage_interactive().await?;
 
+        println!();
+        println!("Backup Defaults");
+        let backup_defaults = self.setup_backup_defaults_interactive()?;
+        let retention = self.setup_retention_interactive()?;
+
         let mut spinner = Spinner::new("Configuring workspace...");
         spinner.start();
 
@@ -184,16 +216,66 @@ impl InteractiveSetup {
             storage: storage_config,
             created_at: chrono::Utc::now().to_rfc3339(),
             last_used: None,
+            notifications: None,
+            backup_defaults,
+            retention,
         };
 
         spinner.stop();
         Ok(workspace)
     }
 
+    fn setup_backup_defaults_interactive(&self) -> Result<BackupDefaults> {
+        let compression = Select::new(
+            "Compression:",
+            vec![
+                CompressionType::Gzip,
+                CompressionType::Zstd,
+                CompressionType::Xz,
+                CompressionType::None,
+            ],
+        )
+        .with_help_message("Used unless a backup is run with --compression-format")
+        .prompt()?;
+
+        let compression_format = match compression {
+            CompressionType::Gzip => CompressionFormat::Gzip,
+            CompressionType::Zstd => CompressionFormat::Zstd,
+            CompressionType::Xz => CompressionFormat::Xz,
+            CompressionType::None => CompressionFormat::None,
+        };
+
+        Ok(BackupDefaults {
+            compression_format: Some(compression_format),
+            ..Default::default()
+        })
+    }
+
+    fn setup_retention_interactive(&self) -> Result<Option<String>> {
+        let retention = Text::new("Retention:")
+            .with_help_message(
+                "How long cleanup keeps backups, e.g. 30d, 4w, 6m, 1y (optional, cleanup then needs --retention)",
+            )
+            .prompt_skippable()?
+            .map(|retention| retention.trim().to_string())
+            .filter(|retention| !retention.is_empty());
+
+        if let Some(retention) = &retention {
+            parse_retention(retention)?;
+        }
+
+        Ok(retention)
+    }
+
     async fn setup_database_interactive(&self) -> Result<DatabaseConfig> {
         let db_type = Select::new(
             "Database type:",
-            vec![DatabaseType::PostgreSQL, DatabaseType::MySQL],
+            vec![
+                DatabaseType::PostgreSQL,
+                DatabaseType::MySQL,
+                DatabaseType::MariaDB,
+                DatabaseType::MongoDB,
+            ],
         )
         .prompt()?;
 
@@ -202,7 +284,8 @@ impl InteractiveSetup {
         let port = Text::new("Port:")
             .with_default(&match db_type {
                 DatabaseType::PostgreSQL => "5432".to_string(),
-                DatabaseType::MySQL => "3306".to_string(),
+                DatabaseType::MySQL | DatabaseType::MariaDB => "3306".to_string(),
+                DatabaseType::MongoDB => "27017".to_string(),
             })
             .prompt()?
             .parse::<u16>()?;
@@ -232,15 +315,18 @@ impl InteractiveSetup {
             connection_type: match db_type {
                 DatabaseType::PostgreSQL => ConnectionType::PostgreSql,
                 DatabaseType::MySQL => ConnectionType::MySql,
+                DatabaseType::MariaDB => ConnectionType::MariaDB,
+                DatabaseType::MongoDB => ConnectionType::MongoDB,
             },
             database: database.clone(),
-            id: "".into(),
+            id: generate_id(),
             name: database,
             host,
             port,
             username,
             password,
             ssh_tunnel,
+            connect_timeout: None,
         })
     }
 
@@ -261,26 +347,36 @@ impl InteractiveSetup {
                 key_path,
                 passphrase_key: None,
             },
+            jump_hosts: Vec::new(),
         })
     }
 
     async fn setup_storage_interactive(&self) -> Result<StorageConfig> {
-        let storage_type =
-            Select::new("Storage type:", vec![StorageType::Local, StorageType::S3]).prompt()?;
+        let storage_type = Select::new(
+            "Storage type:",
+            vec![
+                StorageType::Local,
+                StorageType::S3,
+                StorageType::Gcs,
+                StorageType::Azure,
+                StorageType::Sftp,
+            ],
+        )
+        .prompt()?;
 
         let name = Text::new("Storage name:")
             .with_default("default")
             .prompt()?;
 
         let location = Text::new("Location:")
-            .with_help_message("Directory path for local storage or prefix for S3")
+            .with_help_message("Directory path for local storage or prefix in the bucket")
             .with_default("backups")
             .prompt()?;
 
         match storage_type {
             StorageType::Local => Ok(StorageConfig::Local(LocalStorageConfig {
                 name,
-                id: "".into(),
+                id: generate_id(),
                 location,
             })),
             StorageType::S3 => {
@@ -306,7 +402,85 @@ impl InteractiveSetup {
                     access_key,
                     secret_key,
                     location,
-                    id: "".into(),
+                    upload_concurrency: None,
+                    sse: None,
+                    id: generate_id(),
+                }))
+            }
+            StorageType::Gcs => {
+                let bucket = Text::new("GCS Bucket:").prompt()?;
+
+                let credential_path = Text::new("Service account key file:")
+                    .with_help_message(
+                        "Path to a JSON key (optional, defaults to the environment's credentials)",
+                    )
+                    .prompt_skippable()?
+                    .filter(|path| !path.trim().is_empty());
+
+                Ok(StorageConfig::Gcs(GcsStorageConfig {
+                    name,
+                    bucket,
+                    credential_path,
+                    credential: None,
+                    location,
+                    id: generate_id(),
+                }))
+            }
+            StorageType::Azure => {
+                let account_name = Text::new("Storage account:").prompt()?;
+
+                let container = Text::new("Container:").prompt()?;
+
+                let account_key = Password::new("Account key:")
+                    .with_help_message("Leave empty to use a SAS token instead")
+                    .without_confirmation()
+                    .prompt()?;
+
+                let (account_key, sas_token) = if account_key.is_empty() {
+                    let sas_token = Password::new("SAS token:")
+                        .without_confirmation()
+                        .prompt()?;
+                    (None, Some(sas_token))
+                } else {
+                    (Some(account_key), None)
+                };
+
+                Ok(StorageConfig::Azure(AzureStorageConfig {
+                    name,
+                    account_name,
+                    account_key,
+                    sas_token,
+                    container,
+                    endpoint: None,
+                    location,
+                    id: generate_id(),
+                }))
+            }
+            StorageType::Sftp => {
+                let host = Text::new("SFTP Host:").prompt()?;
+
+                let port = Text::new("SFTP Port:")
+                    .with_default("22")
+                    .prompt()?
+                    .parse::<u16>()?;
+
+                let username = Text::new("SFTP Username:").prompt()?;
+
+                let key_path = Text::new("SFTP Private Key Path:")
+                    .with_help_message("The key must not be passphrase protected")
+                    .prompt()?;
+
+                Ok(StorageConfig::Sftp(SftpStorageConfig {
+                    name,
+                    host,
+                    port,
+                    username,
+                    auth_method: SshAuthMethod::PrivateKey {
+                        key_path,
+                        passphrase_key: None,
+                    },
+                    location,
+                    id: generate_id(),
                 }))
             }
         }
@@ -430,7 +604,12 @@ impl InteractiveSetup {
             }
         }
 
-        match core.backup().await {
+        match core
+            .backup_with(Some(
+                workspace.backup_defaults.apply(BackupOptions::default()),
+            ))
+            .await
+        {
             Ok(file) => {
                 spinner.success(format!("Backup completed successfully: {}", file));
             }
@@ -483,6 +662,11 @@ impl InteractiveSetup {
             .list_with_options(ListOptions {
                 latest_only: Some(false),
                 limit: Some(50),
+                recursive: None,
+                database: None,
+                before: None,
+                after: None,
+                include_quarantined: None,
             })
             .await
         {
@@ -578,6 +762,7 @@ impl InteractiveSetup {
                 name: selected_backup.clone(),
                 compression_format: None,
                 drop_database_first: Some(drop_database),
+                ..Default::default()
             })
             .await
         {
@@ -630,6 +815,11 @@ impl InteractiveSetup {
             .list_with_options(ListOptions {
                 latest_only: Some(false),
                 limit: Some(50),
+                recursive: None,
+                database: None,
+                before: None,
+                after: None,
+                include_quarantined: None,
             })
             .await
         {
@@ -713,6 +903,8 @@ impl std::fmt::Display for MainAction {
 enum DatabaseType {
     PostgreSQL,
     MySQL,
+    MariaDB,
+    MongoDB,
 }
 
 impl std::fmt::Display for DatabaseType {
@@ -720,6 +912,8 @@ impl std::fmt::Display for DatabaseType {
         match self {
             DatabaseType::PostgreSQL => write!(f, "PostgreSQL"),
             DatabaseType::MySQL => write!(f, "MySQL"),
+            DatabaseType::MariaDB => write!(f, "MariaDB"),
+            DatabaseType::MongoDB => write!(f, "MongoDB"),
         }
     }
 }
@@ -728,6 +922,9 @@ impl std::fmt::Display for DatabaseType {
 enum StorageType {
     Local,
     S3,
+    Gcs,
+    Azure,
+    Sftp,
 }
 
 impl std::fmt::Display for StorageType {
@@ -735,6 +932,28 @@ impl std::fmt::Display for StorageType {
         match self {
             StorageType::Local => write!(f, "Local filesystem"),
             StorageType::S3 => write!(f, "Amazon S3 / Compatible"),
+            StorageType::Gcs => write!(f, "Google Cloud Storage"),
+            StorageType::Azure => write!(f, "Azure Blob Storage"),
+            StorageType::Sftp => write!(f, "SFTP server"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum CompressionType {
+    Gzip,
+    Zstd,
+    Xz,
+    None,
+}
+
+impl std::fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionType::Gzip => write!(f, "Gzip"),
+            CompressionType::Zstd => write!(f, "Zstandard"),
+            CompressionType::Xz => write!(f, "XZ"),
+            CompressionType::None => write!(f, "None"),
         }
     }
 }
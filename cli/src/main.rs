@@ -1,30 +1,75 @@
 use anyhow::{anyhow, Result};
+use chrono::Utc;
 use clap::Parser;
 use cli::{
-    database_config_from_cli, parse_retention, storage_from_cli, Cli, Commands, WorkspaceCommands,
+    database_config_from_cli, encryption_from_cli, format_backup_timestamp, newest_matching,
+    parse_retention, retention_cutoff, sort_entries, storage_from_cli, BackupReport,
+    BatchBackupReport, Cli, Commands, ConfigCommands, ConnectionTestReport, ListedBackup,
+    OutputFormat, WorkspaceCommands,
 };
 use colored::*;
 use dbkp_core::{
-    databases::DatabaseConnection,
-    storage::provider::{ListOptions, StorageProvider},
-    DbBkp, RestoreOptions,
+    common::{get_db_name, get_version_name, tool_log_path, DEFAULT_PIPELINE_BUFFER_SIZE},
+    databases::{self, BackupScope, DatabaseConfig, DatabaseConnection},
+    manifest::{is_integrity_failure, read_manifest, verify_backup, BackupManifest, VerifyOptions},
+    metrics::MetricsFileNotifier,
+    notifications::{notify_all, Notification, NotificationConfig, Notifier, Operation},
+    quarantine::{
+        clear_verification_failure, list_verification_failures, quarantine_backup,
+        record_verification_failure,
+    },
+    restore_data_directory, restore_reader,
+    storage::{
+        provider::{
+            CleanupOptions, ListOptions, StorageConfig, StorageProvider, StorageProviderOptions,
+        },
+        retry::RetryConfig,
+        throttle::RateLimiter,
+        transfer::{transfer_backups, TransferOptions},
+    },
+    BackupOptions, BackupResult, DbBkp, RestoreOptions, RestorePlan,
+};
+use inquire::Confirm;
+use std::{
+    fs::File,
+    future::Future,
+    io::{BufReader, IsTerminal},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::Instant,
 };
 
+mod batch;
 mod cli;
 mod interactive;
 mod spinner;
 mod tests;
 mod workspace;
 
+use batch::run_batch;
 use interactive::InteractiveSetup;
-use spinner::Spinner;
-use workspace::WorkspaceManager;
+use spinner::{format_size, Spinner};
+use workspace::{BackupDefaults, Workspace, WorkspaceManager};
+
+/// Exit code of a backup that reached the primary storage but not every mirror
+const PARTIAL_SUCCESS_EXIT_CODE: i32 = 6;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let json = cli.format == OutputFormat::Json;
+
+    configure_colors();
+
+    let mut command = cli.command.unwrap_or(Commands::Interactive);
+    if let Commands::Cleanup(args) = &mut command {
+        if args.retention.is_none() && args.retention_policy().is_none() {
+            args.retention = resolve_workspace_retention(&args.workspace)?;
+        }
+    }
 
-    match cli.command.unwrap_or(Commands::Interactive) {
+    match command {
         Commands::Interactive => {
             let interactive = InteractiveSetup::new()?;
             interactive.run().await?;
@@ -32,10 +77,25 @@ async fn main() -> Result<()> {
         Commands::Workspace { command } => {
             handle_workspace_command(command).await?;
         }
+        Commands::Config { command } => {
+            handle_config_command(command).await?;
+        }
+        Commands::Backup(args) if args.all => backup_all(args, json).await?,
+        Commands::Backup(args) if args.all_databases => backup_all_databases(args, json).await?,
         Commands::Backup(args) => {
-            let mut spinner = Spinner::new("Resolving configuration...");
+            if json && args.output.is_some() {
+                return Err(anyhow!("--format json can't be combined with --output -"));
+            }
+
+            // Status lines would end up mixed into the dump or the JSON
+            let mut spinner = Spinner::new("Resolving configuration...")
+                .with_enabled(args.output.is_none() && !json);
             spinner.start();
 
+            let started_at = Instant::now();
+            let mut notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+            let target = args.workspace.as_deref().unwrap_or("the database");
+
             let (database_config, storage_config) = match resolve_configs_for_backup(&args).await {
                 Ok(configs) => {
                     spinner.update_message("Configuration resolved, connecting to database...");
@@ -43,10 +103,18 @@ async fn main() -> Result<()> {
                 }
                 Err(e) => {
                     spinner.error("Failed to resolve configuration");
+                    notify_failure(&notifiers, Operation::Backup, target, started_at, &e).await;
                     return Err(e);
                 }
             };
 
+            let target = database_config.name.clone();
+            notifiers.extend(metrics_notifier(
+                &args.metrics_file,
+                &database_config.name,
+                &storage_config,
+            ));
+
             let database_connection = match DatabaseConnection::new(database_config).await {
                 Ok(conn) => {
                     spinner.update_message("Database connected, connecting to storage...");
@@ -54,35 +122,90 @@ async fn main() -> Result<()> {
                 }
                 Err(e) => {
                     spinner.error("Failed to connect to database");
+                    notify_failure(&notifiers, Operation::Backup, &target, started_at, &e).await;
                     return Err(e);
                 }
             };
 
-            let storage_provider = match StorageProvider::new(storage_config) {
-                Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connections...");
-                    provider
-                }
-                Err(e) => {
-                    spinner.error("Failed to connect to storage");
-                    return Err(e);
-                }
-            };
+            let storage_options = backup_storage_options(&args);
 
-            let core = DbBkp::new(database_connection, storage_provider);
+            let storage_provider =
+                match StorageProvider::new_with_options(storage_config, storage_options.clone()) {
+                    Ok(provider) => {
+                        spinner.update_message("Storage connected, testing connections...");
+                        provider
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to connect to storage");
+                        notify_failure(&notifiers, Operation::Backup, &target, started_at, &e)
+                            .await;
+                        return Err(e);
+                    }
+                };
+
+            let mut core = DbBkp::new(database_connection, storage_provider);
+
+            for workspace_name in &args.mirror_workspace {
+                let mirror = match resolve_storage_config(&Some(workspace_name.clone()), &None)
+                    .await
+                    .and_then(|config| {
+                        StorageProvider::new_with_options(config, storage_options.clone())
+                    }) {
+                    Ok(provider) => provider,
+                    Err(e) => {
+                        spinner.error(format!("Failed to set up mirror '{}'", workspace_name));
+                        notify_failure(&notifiers, Operation::Backup, &target, started_at, &e)
+                            .await;
+                        return Err(e);
+                    }
+                };
+
+                core = core.with_mirror(workspace_name.clone(), mirror);
+            }
+
+            for notifier in notifiers.iter().cloned() {
+                core = core.with_notifier(notifier);
+            }
 
             // Test database & storage connection
             match core.test().await {
                 Ok(_) => spinner.update_message("Connections verified, starting backup..."),
                 Err(e) => {
                     spinner.error("Connection test failed");
+                    notify_failure(&notifiers, Operation::Backup, &target, started_at, &e).await;
                     return Err(e);
                 }
             }
 
-            match core.backup().await {
-                Ok(backup_file) => {
-                    spinner.success(format!("Backup completed successfully: {}", backup_file));
+            match core
+                .backup_with_result(Some(BackupOptions {
+                    progress: Some(spinner.progress("Backing up, uploaded")),
+                    ..resolve_backup_defaults(&args.workspace)?.apply(backup_options(&args)?)
+                }))
+                .await
+            {
+                Ok(result) if result.is_partial_success() => {
+                    if json {
+                        print_backup_report(&result)?;
+                    }
+                    spinner.error(format!(
+                        "Backup {} completed with warnings, some mirrors failed:",
+                        result.name
+                    ));
+                    for destination in result.failed_destinations() {
+                        eprintln!(
+                            "  {}: {}",
+                            destination.destination,
+                            destination.error.as_deref().unwrap_or_default()
+                        );
+                    }
+                    process::exit(PARTIAL_SUCCESS_EXIT_CODE);
+                }
+                Ok(result) => {
+                    if json {
+                        print_backup_report(&result)?;
+                    }
+                    spinner.success(format!("Backup completed successfully: {}", result.name));
                 }
                 Err(e) => {
                     spinner.error("Backup failed");
@@ -91,7 +214,8 @@ async fn main() -> Result<()> {
             }
         }
         Commands::List(args) => {
-            let mut spinner = Spinner::new("Resolving storage configuration...");
+            let mut spinner = Spinner::new("Resolving storage configuration...")
+                .with_enabled(!args.filename_only && !json);
             spinner.start();
 
             let storage_config =
@@ -106,17 +230,23 @@ async fn main() -> Result<()> {
                     }
                 };
 
-            let storage_provider = match StorageProvider::new(storage_config) {
-                Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connection...");
-                    provider
-                }
-                Err(e) => {
-                    spinner.error("Failed to connect to storage");
-                    return Err(e);
-                }
+            let storage_options = StorageProviderOptions {
+                stat_missing_sizes: args.stat_sizes,
+                ..Default::default()
             };
 
+            let storage_provider =
+                match StorageProvider::new_with_options(storage_config, storage_options) {
+                    Ok(provider) => {
+                        spinner.update_message("Storage connected, testing connection...");
+                        provider
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to connect to storage");
+                        return Err(e);
+                    }
+                };
+
             match storage_provider.test().await {
                 Ok(_) => spinner.update_message("Connection verified, fetching backup list..."),
                 Err(e) => {
@@ -125,10 +255,25 @@ async fn main() -> Result<()> {
                 }
             }
 
-            let entries = match storage_provider
+            if args.failed_verification {
+                spinner.stop();
+                return list_failed_verifications(
+                    &storage_provider,
+                    args.filename_only,
+                    args.local_time,
+                )
+                .await;
+            }
+
+            let mut entries = match storage_provider
                 .list_with_options(ListOptions {
                     latest_only: Some(args.latest_only),
-                    limit: args.limit,
+                    limit: if args.all { None } else { args.limit },
+                    recursive: Some(!args.no_recursive),
+                    database: args.database.clone(),
+                    before: args.before,
+                    after: args.after,
+                    include_quarantined: None,
                 })
                 .await
             {
@@ -142,45 +287,92 @@ async fn main() -> Result<()> {
                 }
             };
 
+            sort_entries(&mut entries, args.sort, args.reverse);
+
+            if json {
+                let backups: Vec<ListedBackup> = entries.iter().map(ListedBackup::from).collect();
+                println!("{}", serde_json::to_string(&backups)?);
+                return Ok(());
+            }
+
             if entries.is_empty() {
-                println!("{}", "[INFO] No backups found".cyan());
+                if !args.filename_only {
+                    println!("{}", "[INFO] No backups found".cyan());
+                }
+                return Ok(());
+            }
+
+            if args.filename_only {
+                for entry in &entries {
+                    println!("{}", entry.metadata.name);
+                }
                 return Ok(());
             }
 
+            // Flagging is best-effort, the listing itself matters more
+            let failed: Vec<String> = list_verification_failures(&storage_provider)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|failure| failure.name)
+                .collect();
+
             println!("\n{}:", "Available backups".green().bold());
 
             for (index, entry) in entries.iter().enumerate() {
                 let filename = &entry.metadata.name;
-                let size = entry.metadata.content_length;
-                let size_str = if size < 1024 {
-                    format!("{}B", size)
-                } else if size < 1024 * 1024 {
-                    format!("{:.2}KB", size as f64 / 1024.0)
-                } else if size < 1024 * 1024 * 1024 {
-                    format!("{:.2}MB", size as f64 / (1024.0 * 1024.0))
-                } else {
-                    format!("{:.2}GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
-                };
+                let size_str = format_size(entry.metadata.content_length);
 
                 // Try to extract and format timestamp
                 let date_str = match dbkp_core::common::extract_timestamp_from_filename(filename) {
-                    Ok(timestamp) => timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                    Ok(timestamp) => format_backup_timestamp(timestamp, args.local_time),
                     Err(_) => "Unknown date".to_string(),
                 };
 
+                let details = if args.details {
+                    match read_manifest(&storage_provider, &entry.path).await {
+                        Ok(Some(manifest)) => format!(" | {}", describe_manifest(&manifest)),
+                        Ok(None) => " | no manifest".to_string(),
+                        Err(e) => format!(" | unreadable manifest: {}", e),
+                    }
+                } else {
+                    String::new()
+                };
+
+                let flag = if failed.contains(&entry.path) {
+                    format!(" {}", "[FAILED VERIFICATION]".red())
+                } else {
+                    String::new()
+                };
+
                 println!(
-                    "  {:2}. {} | {} | {}",
+                    "  {:2}. {} | {} | {}{}{}",
                     index + 1,
                     date_str,
                     size_str,
-                    filename
+                    filename,
+                    details,
+                    flag
                 );
             }
         }
+        Commands::Restore(args) if args.input_dir.is_some() => {
+            restore_from_input_dir(&args).await?;
+        }
+        Commands::Restore(args) if args.file.is_some() => {
+            restore_from_file(&args).await?;
+        }
+        Commands::Restore(args) if args.data_dir.is_some() => {
+            restore_to_data_directory(&args).await?;
+        }
         Commands::Restore(args) => {
             let mut spinner = Spinner::new("Resolving configuration...");
             spinner.start();
 
+            let started_at = Instant::now();
+            let notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+            let target = args.workspace.as_deref().unwrap_or("the backup");
+
             let (database_config, storage_config) = match resolve_configs_for_restore(&args).await {
                 Ok(configs) => {
                     spinner.update_message("Configuration resolved, determining backup name...");
@@ -188,6 +380,7 @@ async fn main() -> Result<()> {
                 }
                 Err(e) => {
                     spinner.error("Failed to resolve configuration");
+                    notify_failure(&notifiers, Operation::Restore, target, started_at, &e).await;
                     return Err(e);
                 }
             };
@@ -199,33 +392,54 @@ async fn main() -> Result<()> {
                 }
                 Err(e) => {
                     spinner.error("Failed to resolve backup name");
+                    notify_failure(&notifiers, Operation::Restore, target, started_at, &e).await;
                     return Err(e);
                 }
             };
 
-            let database_connection = match DatabaseConnection::new(database_config).await {
+            let database_connection = match DatabaseConnection::new(database_config.clone()).await {
                 Ok(conn) => {
                     spinner.update_message("Database connected, connecting to storage...");
                     conn
                 }
                 Err(e) => {
                     spinner.error("Failed to connect to database");
+                    notify_failure(&notifiers, Operation::Restore, &backup_name, started_at, &e)
+                        .await;
                     return Err(e);
                 }
             };
 
-            let storage_provider = match StorageProvider::new(storage_config) {
-                Ok(provider) => {
-                    spinner.update_message("Storage connected, testing connections...");
-                    provider
-                }
-                Err(e) => {
-                    spinner.error("Failed to connect to storage");
-                    return Err(e);
-                }
+            let storage_options = StorageProviderOptions {
+                rate_limiter: args.max_rate.map(RateLimiter::new),
+                ..Default::default()
             };
 
-            let core = DbBkp::new(database_connection, storage_provider);
+            let storage_provider =
+                match StorageProvider::new_with_options(storage_config, storage_options) {
+                    Ok(provider) => {
+                        spinner.update_message("Storage connected, testing connections...");
+                        provider
+                    }
+                    Err(e) => {
+                        spinner.error("Failed to connect to storage");
+                        notify_failure(
+                            &notifiers,
+                            Operation::Restore,
+                            &backup_name,
+                            started_at,
+                            &e,
+                        )
+                        .await;
+                        return Err(e);
+                    }
+                };
+
+            let mut core = DbBkp::new(database_connection, storage_provider);
+
+            for notifier in notifiers.iter().cloned() {
+                core = core.with_notifier(notifier);
+            }
 
             // Test database & storage connection
             match core.test().await {
@@ -235,28 +449,124 @@ async fn main() -> Result<()> {
                 )),
                 Err(e) => {
                     spinner.error("Connection test failed");
+                    notify_failure(&notifiers, Operation::Restore, &backup_name, started_at, &e)
+                        .await;
                     return Err(e);
                 }
             }
 
-            match core
-                .restore(RestoreOptions {
-                    name: backup_name.clone(),
-                    compression_format: None,
-                    drop_database_first: Some(args.drop_database),
-                })
-                .await
-            {
-                Ok(_) => {
-                    spinner.success(format!("Restore completed successfully: {}", backup_name));
+            let restore_options = RestoreOptions {
+                name: backup_name.clone(),
+                compression_format: None,
+                drop_database_first: Some(args.drop_database),
+                per_table_files: Some(args.per_table_files),
+                single_transaction: Some(args.single_transaction),
+                charset: args.charset.clone(),
+                collation: args.collation.clone(),
+                apply_increments: Some(args.with_increments),
+                pipeline_buffer_size: args.buffer_size,
+                download_chunk_size: args.download_chunk_size.map(|size| size as usize),
+                download_concurrency: args.download_concurrency,
+                allow_version_mismatch: Some(args.allow_version_mismatch),
+                tool_log_dir: args.log_dir.clone(),
+                encryption: encryption_from_cli(&args.encryption)?,
+                extra_restore_args: args.extra_restore_args()?,
+                jobs: args.jobs,
+                target_database: args.target_database.clone(),
+                dry_run: Some(args.dry_run),
+                target_time: args.target_time,
+                verify_row_counts: Some(args.verify_row_counts || args.strict_verify),
+                strict_verify: Some(args.strict_verify),
+                clean: Some(!args.no_clean),
+                progress: Some(spinner.progress("Restoring, downloaded")),
+            };
+
+            if args.dry_run {
+                match core.plan_restore(restore_options).await {
+                    Ok(plan) => {
+                        spinner.success(format!("Backup checked: {}", backup_name));
+                        print_restore_plan(&plan);
+                    }
+                    Err(e) => {
+                        spinner.error("Dry run failed");
+                        return Err(e);
+                    }
                 }
-                Err(e) => {
-                    spinner.error("Restore failed");
-                    return Err(e);
+            } else {
+                match core.restore_with_result(restore_options).await {
+                    Ok(result) if !result.row_count_mismatches.is_empty() => {
+                        spinner.success(format!(
+                            "Restore completed with {} row count mismatches: {}",
+                            result.row_count_mismatches.len(),
+                            backup_name
+                        ));
+                        for mismatch in &result.row_count_mismatches {
+                            eprintln!("{}", format!("[WARN] {}", mismatch).yellow());
+                        }
+                    }
+                    Ok(result) if result.verified => {
+                        spinner.success(format!(
+                            "Restore completed and row counts verified: {}",
+                            backup_name
+                        ));
+                    }
+                    Ok(_) => {
+                        spinner.success(format!("Restore completed successfully: {}", backup_name));
+                    }
+                    Err(e) => {
+                        spinner.error("Restore failed");
+                        return Err(e);
+                    }
                 }
+
+                if let Some(query) = &args.smoke_query {
+                    match &args.target_database {
+                        Some(target) => {
+                            run_smoke_query(smoke_query_in(database_config, target, query)).await?
+                        }
+                        None => run_smoke_query(core.smoke_query(query)).await?,
+                    }
+                }
+            }
+        }
+        Commands::TestStorage(args) => test_storage(args).await?,
+        Commands::TestDb(args) => test_database(args).await?,
+        Commands::Test(args) => test_connections(args).await?,
+        Commands::Copy(args) => copy_backups(args).await?,
+        Commands::Verify(args) => verify(args).await?,
+        Commands::Cleanup(args)
+            if args.retention.is_none() && args.retention_policy().is_none() =>
+        {
+            return Err(anyhow!(
+                "Cleanup needs --retention, at least one of --keep-daily, --keep-weekly, --keep-monthly or --keep-yearly, or a workspace with a retention"
+            ));
+        }
+        Commands::Cleanup(args) if args.explain => {
+            if let Some(retention) = &args.retention {
+                let days = parse_retention(retention)?;
+                let cutoff = retention_cutoff(retention, Utc::now())?;
+
+                println!(
+                    "Retention '{}' ({} days) will delete backups older than {}",
+                    retention,
+                    days,
+                    cutoff.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+            }
+
+            if let Some(policy) = args.retention_policy() {
+                println!(
+                    "Keeping the newest backup of the last {} days, {} weeks, {} months and {} years",
+                    policy.daily, policy.weekly, policy.monthly, policy.yearly
+                );
             }
         }
         Commands::Cleanup(args) => {
+            // Resolved up front, a broken workspace file shouldn't fail a cleanup that ran
+            let mut notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+            // Read before the storage args are moved out of `args`
+            let policy = args.retention_policy();
+
             let mut spinner = Spinner::new("Resolving storage configuration...");
             spinner.start();
 
@@ -272,6 +582,19 @@ async fn main() -> Result<()> {
                     }
                 };
 
+            // Without --database the cleanup covers the whole storage, the samples are
+            // labeled after what was cleaned up instead of an empty name
+            let metrics_label = args
+                .database
+                .clone()
+                .or_else(|| args.workspace.clone())
+                .unwrap_or_else(|| storage_config.name().to_string());
+            notifiers.extend(metrics_notifier(
+                &args.metrics_file,
+                &metrics_label,
+                &storage_config,
+            ));
+
             let storage = match StorageProvider::new(storage_config) {
                 Ok(provider) => {
                     spinner.update_message("Storage connected, testing connection...");
@@ -299,22 +622,55 @@ async fn main() -> Result<()> {
                 }
             }
 
-            match storage
-                .cleanup(parse_retention(&args.retention)?, args.dry_run)
-                .await
-            {
-                Ok((entries_deleted, storage_reclaimed)) => {
-                    if args.dry_run {
+            let started_at = Instant::now();
+            let result = storage
+                .cleanup_with_options(CleanupOptions {
+                    retention_days: args
+                        .retention
+                        .as_deref()
+                        .map(parse_retention)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    dry_run: args.dry_run,
+                    allow_bucket_root: args.allow_bucket_root,
+                    policy,
+                    database: args.database.clone(),
+                    strict: args.strict,
+                })
+                .await;
+
+            if !args.dry_run {
+                let target = args
+                    .database
+                    .clone()
+                    .or_else(|| args.workspace.clone())
+                    .unwrap_or_else(|| "storage".to_string());
+                let reclaimed = result.as_ref().ok().map(|report| report.bytes_reclaimed);
+                let notification =
+                    Notification::new(Operation::Cleanup, target, started_at, &result)
+                        .with_size(reclaimed);
+                notify_all(&notifiers, &notification).await;
+            }
+
+            match result {
+                Ok(report) => {
+                    if report.dry_run {
                         spinner.success(format!(
                             "Dry run completed: {} entries would be deleted, {} storage would be reclaimed",
-                            entries_deleted, storage_reclaimed
+                            report.count,
+                            format_size(report.bytes_reclaimed)
                         ));
                     } else {
                         spinner.success(format!(
                             "Cleanup completed: {} entries deleted, {} storage reclaimed",
-                            entries_deleted, storage_reclaimed
+                            report.count,
+                            format_size(report.bytes_reclaimed)
                         ));
                     }
+
+                    for entry in &report.deleted {
+                        println!("  - {} ({})", entry.name, format_size(entry.size));
+                    }
                 }
                 Err(e) => {
                     spinner.error("Cleanup failed");
@@ -351,29 +707,90 @@ async fn handle_workspace_command(command: WorkspaceCommands) -> Result<()> {
     };
 
     match command {
-        WorkspaceCommands::List => {
+        WorkspaceCommands::List { sort_by_last_used } => {
             if collection.workspaces.is_empty() {
                 println!("{}", "[INFO] No workspaces found.".cyan());
             } else {
                 println!("\n{}:", "Available workspaces".green().bold());
-                for workspace in collection.list_workspaces() {
+                let workspaces = if sort_by_last_used {
+                    collection.list_workspaces_by_last_used()
+                } else {
+                    collection.list_workspaces()
+                };
+                for workspace in workspaces {
                     let active_marker =
                         if Some(&workspace.name) == collection.active_workspace.as_ref() {
                             " (active)".green().to_string()
                         } else {
                             "".to_string()
                         };
+                    let last_used = match workspace.last_used_at() {
+                        Some(last_used) if sort_by_last_used => {
+                            format!(" last used {}", last_used.format("%Y-%m-%d %H:%M:%S UTC"))
+                                .dimmed()
+                                .to_string()
+                        }
+                        None if sort_by_last_used => " never used".dimmed().to_string(),
+                        _ => "".to_string(),
+                    };
                     if active_marker.is_empty() {
-                        println!("  - {}", workspace.name);
+                        println!("  - {}{}", workspace.name, last_used);
                     } else {
-                        println!("  - {} {}", workspace.name.green().bold(), active_marker);
+                        println!(
+                            "  - {} {}{}",
+                            workspace.name.green().bold(),
+                            active_marker,
+                            last_used
+                        );
                     }
                 }
             }
         }
-        WorkspaceCommands::Create { name: _ } => {
-            println!("Interactive workspace creation not implemented yet.");
-            println!("Use 'dbkp interactive' for guided workspace setup.");
+        WorkspaceCommands::Create { name } => {
+            if collection.get_workspace(&name).is_some() {
+                let overwrite = Confirm::new(&format!(
+                    "Workspace '{}' already exists. Overwrite it?",
+                    name
+                ))
+                .with_default(false)
+                .prompt()?;
+
+                if !overwrite {
+                    println!("{}", "[INFO] Workspace creation cancelled.".cyan());
+                    return Ok(());
+                }
+            }
+
+            println!("Creating workspace '{}'...", name.green().bold());
+            let workspace = InteractiveSetup::new()?.prompt_workspace(name).await?;
+            let name = workspace.name.clone();
+            collection.add_workspace(workspace);
+
+            let activate = Confirm::new("Set it as the active workspace?")
+                .with_default(true)
+                .prompt()?;
+            if activate {
+                collection.set_active(&name)?;
+            }
+
+            let mut spinner = Spinner::new("Saving workspace configuration...");
+            spinner.start();
+            match workspace_manager.save(&collection) {
+                Ok(_) => {
+                    if activate {
+                        spinner.success(format!(
+                            "Workspace '{}' created and activated!",
+                            name.green().bold()
+                        ));
+                    } else {
+                        spinner.success(format!("Workspace '{}' created.", name.green().bold()));
+                    }
+                }
+                Err(e) => {
+                    spinner.error("Failed to save workspace configuration");
+                    return Err(e);
+                }
+            }
         }
         WorkspaceCommands::Delete { name } => {
             if collection.remove_workspace(&name).is_some() {
@@ -428,85 +845,1210 @@ async fn handle_workspace_command(command: WorkspaceCommands) -> Result<()> {
     Ok(())
 }
 
-async fn resolve_configs_for_backup(
-    args: &cli::BackupArgs,
-) -> Result<(
-    dbkp_core::databases::DatabaseConfig,
-    dbkp_core::storage::provider::StorageConfig,
-)> {
-    if let Some(workspace_name) = &args.workspace {
-        let workspace_manager = WorkspaceManager::new()?;
-        let collection = workspace_manager.load()?;
-        let workspace = collection
-            .get_workspace(workspace_name)
-            .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
-        Ok((workspace.database.clone(), workspace.storage.clone()))
-    } else {
-        // Check if we have direct CLI parameters
-        let database_config = if has_database_config(&args.database_config) {
-            database_config_from_cli(&args.database_config)?
-        } else {
-            return Err(anyhow!(
-                "Either --workspace or database configuration parameters are required.\n\
-                Database parameters: --database-type, --database, --host, --port, --username\n\
-                Use 'dbkp backup --help' for more details."
-            ));
-        };
-
-        let storage_config = if has_storage_config(&args.storage_config) {
-            storage_from_cli(&args.storage_config)?
-        } else {
-            return Err(anyhow!(
-                "Either --workspace or storage configuration parameters are required.\n\
-                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key)\n\
-                Use 'dbkp backup --help' for more details."
-            ));
-        };
+fn configure_colors() {
+    let force_color = std::env::var("CLICOLOR_FORCE").is_ok_and(|value| value != "0");
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
 
-        Ok((database_config, storage_config))
+    if no_color || (!force_color && !std::io::stdout().is_terminal()) {
+        colored::control::set_override(false);
     }
 }
 
-async fn resolve_configs_for_restore(
-    args: &cli::RestoreArgs,
-) -> Result<(
-    dbkp_core::databases::DatabaseConfig,
-    dbkp_core::storage::provider::StorageConfig,
-)> {
-    if let Some(workspace_name) = &args.workspace {
-        let workspace_manager = WorkspaceManager::new()?;
-        let collection = workspace_manager.load()?;
-        let workspace = collection
-            .get_workspace(workspace_name)
-            .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
-        Ok((workspace.database.clone(), workspace.storage.clone()))
-    } else {
-        // Check if we have direct CLI parameters
-        let database_config = if has_database_config(&args.database_config) {
-            database_config_from_cli(&args.database_config)?
-        } else {
-            return Err(anyhow!(
-                "Either --workspace or database configuration parameters are required.\n\
-                Database parameters: --database-type, --database, --host, --port, --username\n\
-                Use 'dbkp restore --help' for more details."
-            ));
-        };
-
-        let storage_config = if has_storage_config(&args.storage_config) {
-            storage_from_cli(&args.storage_config)?
-        } else {
-            return Err(anyhow!(
-                "Either --workspace or storage configuration parameters are required.\n\
-                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key)\n\
-                Use 'dbkp restore --help' for more details."
-            ));
-        };
+async fn handle_config_command(command: ConfigCommands) -> Result<()> {
+    match command {
+        ConfigCommands::Migrate => {
+            let mut spinner = Spinner::new("Migrating workspace configuration...");
+            spinner.start();
 
-        Ok((database_config, storage_config))
+            let workspace_manager = WorkspaceManager::new()?;
+            match workspace_manager.migrate() {
+                Ok(Some(from_version)) => spinner.success(format!(
+                    "Workspace configuration migrated from schema version {} to {}",
+                    from_version,
+                    workspace::WORKSPACE_SCHEMA_VERSION
+                )),
+                Ok(None) => spinner.success("Workspace configuration is already up to date"),
+                Err(e) => {
+                    spinner.error("Failed to migrate workspace configuration");
+                    return Err(e);
+                }
+            }
+        }
     }
+
+    Ok(())
 }
 
-async fn resolve_storage_config(
+async fn restore_from_input_dir(args: &cli::RestoreArgs) -> Result<()> {
+    let input_dir = args
+        .input_dir
+        .as_ref()
+        .ok_or_else(|| anyhow!("--input-dir is required"))?;
+
+    let tool_log = match &args.log_dir {
+        Some(dir) => Some(tool_log_path(dir, &input_dir.to_string_lossy())?),
+        None => None,
+    };
+
+    let mut spinner = Spinner::new("Resolving configuration...");
+    spinner.start();
+
+    let database_config =
+        match resolve_database_config(&args.workspace, &args.database_config).await {
+            Ok(config) => {
+                spinner.update_message("Configuration resolved, connecting to database...");
+                config
+            }
+            Err(e) => {
+                spinner.error("Failed to resolve configuration");
+                return Err(e);
+            }
+        };
+
+    let database_connection = match DatabaseConnection::new(database_config.clone()).await {
+        Ok(conn) => {
+            spinner.update_message(format!(
+                "Database connected, restoring from '{}'...",
+                input_dir.display()
+            ));
+            conn
+        }
+        Err(e) => {
+            spinner.error("Failed to connect to database");
+            return Err(e);
+        }
+    };
+
+    match database_connection
+        .connection
+        .restore_from_directory(
+            input_dir,
+            databases::RestoreOptions {
+                drop_database_first: args.drop_database,
+                jobs: args.jobs,
+                allow_version_mismatch: args.allow_version_mismatch,
+                tool_log,
+                extra_restore_args: args.extra_restore_args()?.unwrap_or_default(),
+                target_database: args.target_database.clone(),
+                skip_clean: args.no_clean,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Ok(_) => {
+            spinner.success(format!(
+                "Restore completed successfully: {}",
+                input_dir.display()
+            ));
+        }
+        Err(e) => {
+            spinner.error("Restore failed");
+            return Err(e);
+        }
+    }
+
+    if let Some(query) = &args.smoke_query {
+        match &args.target_database {
+            Some(target) => run_smoke_query(smoke_query_in(database_config, target, query)).await?,
+            None => run_smoke_query(database_connection.smoke_query(query)).await?,
+        }
+    }
+
+    Ok(())
+}
+
+async fn restore_from_file(args: &cli::RestoreArgs) -> Result<()> {
+    let path = args
+        .file
+        .as_ref()
+        .ok_or_else(|| anyhow!("--file is required"))?;
+
+    let file =
+        File::open(path).map_err(|e| anyhow!("Failed to open '{}': {}", path.display(), e))?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("'{}' is not a file", path.display()))?;
+
+    let mut spinner = Spinner::new("Resolving configuration...");
+    spinner.start();
+
+    let started_at = Instant::now();
+    let notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+
+    let database_config =
+        match resolve_database_config(&args.workspace, &args.database_config).await {
+            Ok(config) => {
+                spinner.update_message("Configuration resolved, connecting to database...");
+                config
+            }
+            Err(e) => {
+                spinner.error("Failed to resolve configuration");
+                notify_failure(&notifiers, Operation::Restore, &name, started_at, &e).await;
+                return Err(e);
+            }
+        };
+
+    let database_connection = match DatabaseConnection::new(database_config.clone()).await {
+        Ok(conn) => {
+            spinner.update_message(format!(
+                "Database connected, restoring from '{}'...",
+                path.display()
+            ));
+            conn
+        }
+        Err(e) => {
+            spinner.error("Failed to connect to database");
+            notify_failure(&notifiers, Operation::Restore, &name, started_at, &e).await;
+            return Err(e);
+        }
+    };
+
+    let restore_options = RestoreOptions {
+        name: name.clone(),
+        drop_database_first: Some(args.drop_database),
+        per_table_files: Some(args.per_table_files),
+        single_transaction: Some(args.single_transaction),
+        charset: args.charset.clone(),
+        collation: args.collation.clone(),
+        pipeline_buffer_size: args.buffer_size,
+        allow_version_mismatch: Some(args.allow_version_mismatch),
+        tool_log_dir: args.log_dir.clone(),
+        encryption: encryption_from_cli(&args.encryption)?,
+        extra_restore_args: args.extra_restore_args()?,
+        jobs: args.jobs,
+        target_database: args.target_database.clone(),
+        clean: Some(!args.no_clean),
+        progress: Some(spinner.progress("Restoring, read")),
+        ..Default::default()
+    };
+
+    let result = restore_reader(
+        &database_connection,
+        BufReader::with_capacity(
+            args.buffer_size.unwrap_or(DEFAULT_PIPELINE_BUFFER_SIZE),
+            file,
+        ),
+        &restore_options,
+    )
+    .await;
+
+    let notification = Notification::new(Operation::Restore, name.clone(), started_at, &result);
+    notify_all(&notifiers, &notification).await;
+
+    match result {
+        Ok(_) => {
+            spinner.success(format!("Restore completed successfully: {}", name));
+        }
+        Err(e) => {
+            spinner.error("Restore failed");
+            return Err(e);
+        }
+    }
+
+    if let Some(query) = &args.smoke_query {
+        match &args.target_database {
+            Some(target) => run_smoke_query(smoke_query_in(database_config, target, query)).await?,
+            None => run_smoke_query(database_connection.smoke_query(query)).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpacks a PostgreSQL WAL chain into `--data-dir`. Only the storage is read, the server
+/// the directory is for isn't running yet.
+async fn restore_to_data_directory(args: &cli::RestoreArgs) -> Result<()> {
+    let data_dir = args
+        .data_dir
+        .as_ref()
+        .ok_or_else(|| anyhow!("--data-dir is required"))?;
+
+    let mut spinner = Spinner::new("Resolving configuration...");
+    spinner.start();
+
+    let started_at = Instant::now();
+    let notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+    let target = args.workspace.as_deref().unwrap_or("the backup");
+
+    let (database_config, storage_config) = match resolve_configs_for_restore(args).await {
+        Ok(configs) => {
+            spinner.update_message("Configuration resolved, determining backup name...");
+            configs
+        }
+        Err(e) => {
+            spinner.error("Failed to resolve configuration");
+            notify_failure(&notifiers, Operation::Restore, target, started_at, &e).await;
+            return Err(e);
+        }
+    };
+
+    let backup_name = match resolve_backup_name(args, &storage_config).await {
+        Ok(name) => {
+            spinner.update_message("Backup identified, connecting to storage...");
+            name
+        }
+        Err(e) => {
+            spinner.error("Failed to resolve backup name");
+            notify_failure(&notifiers, Operation::Restore, target, started_at, &e).await;
+            return Err(e);
+        }
+    };
+
+    let storage_options = StorageProviderOptions {
+        rate_limiter: args.max_rate.map(RateLimiter::new),
+        ..Default::default()
+    };
+
+    let storage_provider = match StorageProvider::new_with_options(storage_config, storage_options)
+    {
+        Ok(provider) => provider,
+        Err(e) => {
+            spinner.error("Failed to connect to storage");
+            notify_failure(&notifiers, Operation::Restore, &backup_name, started_at, &e).await;
+            return Err(e);
+        }
+    };
+
+    match storage_provider.test().await {
+        Ok(_) => {
+            spinner.update_message(format!("Storage verified, unpacking '{}'...", backup_name))
+        }
+        Err(e) => {
+            spinner.error("Storage test failed");
+            notify_failure(&notifiers, Operation::Restore, &backup_name, started_at, &e).await;
+            return Err(e);
+        }
+    }
+
+    let restore_options = RestoreOptions {
+        name: backup_name,
+        download_chunk_size: args.download_chunk_size.map(|size| size as usize),
+        download_concurrency: args.download_concurrency,
+        encryption: encryption_from_cli(&args.encryption)?,
+        target_time: args.target_time,
+        progress: Some(spinner.progress("Unpacking, downloaded")),
+        ..Default::default()
+    };
+
+    match restore_data_directory(
+        &storage_provider,
+        &database_config,
+        restore_options,
+        data_dir,
+        &notifiers,
+    )
+    .await
+    {
+        Ok(_) => {
+            spinner.success(format!(
+                "Data directory ready: {}, start PostgreSQL on it to replay the WAL",
+                data_dir.display()
+            ));
+            Ok(())
+        }
+        Err(e) => {
+            spinner.error("Restore failed");
+            Err(e)
+        }
+    }
+}
+
+/// Runs the smoke query against the database a `--target-database` restore went to
+async fn smoke_query_in(
+    mut database_config: DatabaseConfig,
+    target_database: &str,
+    query: &str,
+) -> Result<String> {
+    database_config.database = target_database.to_string();
+    DatabaseConnection::new(database_config)
+        .await?
+        .smoke_query(query)
+        .await
+}
+
+async fn test_storage(args: cli::TestStorageArgs) -> Result<()> {
+    let mut spinner = Spinner::new("Testing storage connection...").with_enabled(!args.json);
+    spinner.start();
+
+    let result = async {
+        let storage_config = resolve_storage_config(&args.workspace, &Some(args.storage)).await?;
+        let storage = StorageProvider::new(storage_config)?;
+
+        if args.deep {
+            storage.test_readwrite().await
+        } else {
+            storage.test().await
+        }
+    }
+    .await;
+
+    let success_message = if args.deep {
+        "Storage is reachable, write, read and delete verified"
+    } else {
+        "Storage is reachable"
+    };
+
+    report_connection_test(&mut spinner, "storage", args.json, success_message, result)
+}
+
+async fn test_database(args: cli::TestDbArgs) -> Result<()> {
+    let mut spinner = Spinner::new("Testing database connection...").with_enabled(!args.json);
+    spinner.start();
+
+    let result = async {
+        let database_config =
+            resolve_database_config(&args.workspace, &args.database_config).await?;
+        let database_connection = DatabaseConnection::new(database_config).await?;
+
+        database_connection.connection.test().await
+    }
+    .await;
+
+    report_connection_test(
+        &mut spinner,
+        "database",
+        args.json,
+        "Database is reachable",
+        result,
+    )
+}
+
+/// Checks the database and the storage the way a backup would reach them. Both are
+/// always tested and reported, the command fails when either of them is unreachable.
+async fn test_connections(args: cli::TestArgs) -> Result<()> {
+    let database_config = resolve_database_config(&args.workspace, &args.database_config).await?;
+    let storage_config =
+        resolve_storage_config(&args.workspace, &Some(args.storage_config)).await?;
+
+    let mut spinner = Spinner::new("Testing connections...").with_enabled(!args.json);
+    spinner.start();
+
+    let database_connection = DatabaseConnection::new(database_config).await;
+    let storage_provider = StorageProvider::new(storage_config);
+
+    let (database_result, storage_result) = match (database_connection, storage_provider) {
+        (Ok(database_connection), Ok(storage_provider)) => {
+            let core = DbBkp::new(database_connection, storage_provider);
+            (core.test_database().await, core.test_storage().await)
+        }
+        // Without a connection to one side there's no DbBkp, the other is tested alone
+        (Err(e), Ok(storage_provider)) => (
+            Err(e),
+            storage_provider
+                .test()
+                .await
+                .and_then(|ok| reachable(ok, "storage provider")),
+        ),
+        (Ok(database_connection), Err(e)) => (
+            database_connection
+                .connection
+                .test()
+                .await
+                .and_then(|ok| reachable(ok, "database")),
+            Err(e),
+        ),
+        (Err(database_error), Err(storage_error)) => (Err(database_error), Err(storage_error)),
+    };
+
+    let mut failed = Vec::new();
+    for (target, success_message, result) in [
+        ("database", "Database is reachable", database_result),
+        ("storage", "Storage is reachable", storage_result),
+    ] {
+        if let Err(e) =
+            report_connection_test(&mut spinner, target, args.json, success_message, result)
+        {
+            if !args.json {
+                eprintln!("  {:#}", e);
+            }
+            failed.push(target);
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!("The {} test failed", failed.join(" and ")));
+    }
+
+    Ok(())
+}
+
+fn reachable(ok: bool, target: &str) -> Result<bool> {
+    if !ok {
+        return Err(anyhow!("Failed to connect to the {}", target));
+    }
+
+    Ok(true)
+}
+
+/// `--webhook-url` wins over the notifications configured on the workspace
+fn resolve_notifiers(
+    webhook_url: &Option<String>,
+    workspace_name: &Option<String>,
+) -> Result<Vec<Arc<dyn Notifier>>> {
+    let config = match (webhook_url, workspace_name) {
+        (Some(url), _) => NotificationConfig {
+            webhook_url: Some(url.clone()),
+        },
+        (None, Some(workspace_name)) => WorkspaceManager::new()?
+            .load()?
+            .get_workspace(workspace_name)
+            .and_then(|workspace| workspace.notifications.clone())
+            .unwrap_or_default(),
+        (None, None) => NotificationConfig::default(),
+    };
+
+    Ok(config.notifiers())
+}
+
+/// Reports a backup or restore that failed before reaching the core, which notifies on
+/// its own from then on
+async fn notify_failure(
+    notifiers: &[Arc<dyn Notifier>],
+    operation: Operation,
+    target: &str,
+    started_at: Instant,
+    error: &anyhow::Error,
+) {
+    let result = Err::<(), _>(anyhow!("{:#}", error));
+    notify_all(
+        notifiers,
+        &Notification::new(operation, target, started_at, &result),
+    )
+    .await;
+}
+
+/// Writes the metrics of a run of `database` on `storage` to `--metrics-file`, if set
+fn metrics_notifier(
+    metrics_file: &Option<PathBuf>,
+    database: &str,
+    storage: &StorageConfig,
+) -> Option<Arc<dyn Notifier>> {
+    metrics_file.as_ref().map(|path| {
+        Arc::new(MetricsFileNotifier::new(path, database, storage.name())) as Arc<dyn Notifier>
+    })
+}
+
+/// What `backup` passes to the core, without progress reporting
+fn backup_options(args: &cli::BackupArgs) -> Result<BackupOptions> {
+    if args.manifest {
+        eprintln!(
+            "{}",
+            "[WARN] --manifest is no longer needed, manifests are written by default".yellow()
+        );
+    }
+
+    Ok(BackupOptions {
+        name_template: args.name_template.clone(),
+        per_table_files: Some(args.per_table_files),
+        update_latest: Some(args.update_latest),
+        incremental: Some(args.mode == cli::BackupMode::Incremental),
+        pipeline_buffer_size: args.buffer_size,
+        dump_retries: Some(args.dump_retries),
+        tool_log_dir: args.log_dir.clone(),
+        snapshot: args.snapshot.clone(),
+        compression_format: args.compression_format.map(Into::into),
+        compression_level: args.compression_level,
+        compression_threads: args.compression_threads,
+        upload_concurrency: args.upload_concurrency,
+        encryption: encryption_from_cli(&args.encryption)?,
+        extra_dump_args: args.extra_dump_args()?,
+        write_manifest: Some(!args.no_manifest && args.output.is_none()),
+        jobs: args.jobs,
+        scope: Some(args.scope()),
+        include_tables: Some(args.include_table.clone()),
+        exclude_tables: Some(args.exclude_table.clone()),
+        record_row_counts: Some(args.record_row_counts),
+        lock: Some(args.output.is_none()),
+        force_lock: Some(args.force),
+        ..Default::default()
+    })
+}
+
+fn backup_storage_options(args: &cli::BackupArgs) -> StorageProviderOptions {
+    StorageProviderOptions {
+        rate_limiter: args.max_rate.map(RateLimiter::new),
+        visibility_retries: args.visibility_retries,
+        retry: RetryConfig {
+            max_attempts: args.storage_attempts,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Backs up every workspace, `--concurrency` of them at a time. A failed workspace
+/// doesn't stop the others: every outcome is listed in the summary, and the command
+/// fails once all are done if any of them did.
+async fn backup_all(args: cli::BackupArgs, json: bool) -> Result<()> {
+    let workspace_manager = WorkspaceManager::new()?;
+    let collection = workspace_manager.load()?;
+    let mut workspaces: Vec<Workspace> =
+        collection.list_workspaces().into_iter().cloned().collect();
+    workspaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if workspaces.is_empty() {
+        return Err(anyhow!("No workspaces configured"));
+    }
+
+    // Recorded once up front, the backups run concurrently
+    let names: Vec<&str> = workspaces
+        .iter()
+        .map(|workspace| workspace.name.as_str())
+        .collect();
+    record_workspace_use(&workspace_manager, &names);
+
+    if !json {
+        eprintln!(
+            "{} Backing up {} workspaces, {} at a time",
+            "[INFO]".cyan(),
+            workspaces.len(),
+            args.concurrency
+        );
+    }
+
+    // Built once so --max-rate is shared by every workspace
+    let storage_options = backup_storage_options(&args);
+    let options = backup_options(&args)?;
+    let targets = workspaces
+        .into_iter()
+        .map(|workspace| (workspace.name.clone(), workspace))
+        .collect();
+
+    let results = run_batch(targets, args.concurrency, |workspace| {
+        let webhook_url = args.webhook_url.clone();
+        let metrics_file = args.metrics_file.clone();
+        let storage_options = storage_options.clone();
+        let options = workspace.backup_defaults.apply(options.clone());
+
+        async move {
+            if !json {
+                eprintln!("{} Backing up '{}'...", "[INFO]".cyan(), workspace.name);
+            }
+
+            let notifiers = resolve_notifiers(&webhook_url, &Some(workspace.name))?;
+
+            backup_to_storage(
+                workspace.database,
+                workspace.storage,
+                storage_options,
+                options,
+                notifiers,
+                &metrics_file,
+            )
+            .await
+        }
+    })
+    .await?;
+
+    let reports: Vec<_> = results
+        .into_iter()
+        .map(|(workspace, result)| batch_backup_report(workspace, result))
+        .collect();
+
+    finish_batch_backup(&reports, json, "workspace")
+}
+
+async fn backup_all_databases(args: cli::BackupArgs, json: bool) -> Result<()> {
+    // Binlogs and WAL cover the whole server, each database would copy the same changes
+    if args.mode == cli::BackupMode::Incremental {
+        return Err(anyhow!(
+            "Incremental backups follow the binlogs or WAL of the whole server, run them without --all-databases"
+        ));
+    }
+
+    let (database_config, storage_config) = resolve_configs_for_backup(&args).await?;
+
+    let databases = DatabaseConnection::new(database_config.clone())
+        .await?
+        .connection
+        .list_databases()
+        .await?;
+    let databases = databases::tables::select_databases(
+        &databases,
+        &args.include_database,
+        &args.exclude_database,
+    )?;
+
+    if databases.is_empty() {
+        return Err(anyhow!(
+            "No database to back up on {}",
+            database_config.host
+        ));
+    }
+
+    if !json {
+        eprintln!(
+            "{} Backing up {} databases: {}",
+            "[INFO]".cyan(),
+            databases.len(),
+            databases.join(", ")
+        );
+    }
+
+    let storage_options = backup_storage_options(&args);
+    let options = resolve_backup_defaults(&args.workspace)?.apply(backup_options(&args)?);
+    let notifiers = resolve_notifiers(&args.webhook_url, &args.workspace)?;
+    let mut reports = Vec::new();
+
+    // One after the other, they all run against the same server
+    for database in databases {
+        if !json {
+            eprintln!("{} Backing up '{}'...", "[INFO]".cyan(), database);
+        }
+
+        // Named after the database so every backup can be told apart and cleaned up
+        // on its own
+        let config = DatabaseConfig {
+            name: database.clone(),
+            database: database.clone(),
+            ..database_config.clone()
+        };
+
+        let result = backup_to_storage(
+            config,
+            storage_config.clone(),
+            storage_options.clone(),
+            options.clone(),
+            notifiers.clone(),
+            &args.metrics_file,
+        )
+        .await;
+
+        reports.push(batch_backup_report(database, result));
+    }
+
+    finish_batch_backup(&reports, json, "database")
+}
+
+async fn backup_to_storage(
+    database_config: DatabaseConfig,
+    storage_config: StorageConfig,
+    storage_options: StorageProviderOptions,
+    options: BackupOptions,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    metrics_file: &Option<PathBuf>,
+) -> Result<BackupResult> {
+    let metrics = metrics_notifier(metrics_file, &database_config.name, &storage_config);
+    let database_connection = DatabaseConnection::new(database_config).await?;
+    let storage_provider = StorageProvider::new_with_options(storage_config, storage_options)?;
+
+    let mut core = DbBkp::new(database_connection, storage_provider);
+
+    for notifier in notifiers.into_iter().chain(metrics) {
+        core = core.with_notifier(notifier);
+    }
+
+    core.test().await?;
+    core.backup_with_result(Some(options)).await
+}
+
+fn batch_backup_report(target: String, result: Result<BackupResult>) -> BatchBackupReport {
+    match result {
+        Ok(result) => BatchBackupReport {
+            target,
+            success: true,
+            name: Some(result.name),
+            size: Some(result.compressed_size),
+            error: None,
+        },
+        Err(e) => BatchBackupReport {
+            target,
+            success: false,
+            name: None,
+            size: None,
+            error: Some(format!("{:#}", e)),
+        },
+    }
+}
+
+/// Prints the summary of a batch and fails when any of its backups did, `kind` is what
+/// the targets are
+fn finish_batch_backup(reports: &[BatchBackupReport], json: bool, kind: &str) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(reports)?);
+    } else {
+        print_backup_summary(reports);
+    }
+
+    let failed = reports.iter().filter(|report| !report.success).count();
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} {} backups failed",
+            failed,
+            reports.len(),
+            kind
+        ));
+    }
+
+    Ok(())
+}
+
+fn print_backup_summary(reports: &[BatchBackupReport]) {
+    let width = reports
+        .iter()
+        .map(|report| report.target.len())
+        .max()
+        .unwrap_or_default();
+
+    println!("\n{}:", "Backup summary".green().bold());
+
+    for report in reports {
+        let (status, details) = match (&report.name, &report.error) {
+            (Some(name), _) => (
+                "ok    ".green(),
+                format!(
+                    "{} ({})",
+                    name,
+                    format_size(report.size.unwrap_or_default())
+                ),
+            ),
+            (None, error) => ("failed".red(), error.clone().unwrap_or_default()),
+        };
+
+        println!(
+            "  {:<width$}  {}  {}",
+            report.target,
+            status,
+            details,
+            width = width
+        );
+    }
+}
+
+fn print_backup_report(result: &BackupResult) -> Result<()> {
+    let report = BackupReport {
+        name: result.name.clone(),
+        size: result.compressed_size,
+        sha256: result.uncompressed_sha256.clone(),
+    };
+    println!("{}", serde_json::to_string(&report)?);
+    Ok(())
+}
+
+fn report_connection_test(
+    spinner: &mut Spinner,
+    target: &str,
+    json: bool,
+    success_message: &str,
+    result: Result<bool>,
+) -> Result<()> {
+    if json {
+        let report = ConnectionTestReport {
+            target: target.to_string(),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        };
+        println!("{}", serde_json::to_string(&report)?);
+    }
+
+    match result {
+        Ok(_) => {
+            spinner.success(success_message);
+            Ok(())
+        }
+        Err(e) => {
+            spinner.error(format!("The {} test failed", target));
+            Err(e)
+        }
+    }
+}
+
+async fn copy_backups(args: cli::CopyArgs) -> Result<()> {
+    let mut spinner = Spinner::new("Resolving storage configuration...");
+    spinner.start();
+
+    let older_than_days = args
+        .older_than
+        .as_deref()
+        .map(parse_retention)
+        .transpose()?;
+
+    let providers = async {
+        let source_config = resolve_storage_config(&args.workspace, &Some(args.storage)).await?;
+        let destination_config = resolve_storage_config(&Some(args.to_workspace), &None).await?;
+
+        let source = StorageProvider::new(source_config)?;
+        let destination = StorageProvider::new(destination_config)?;
+        source.test().await?;
+        destination.test().await?;
+
+        Ok::<_, anyhow::Error>((source, destination))
+    }
+    .await;
+
+    let (source, destination) = match providers {
+        Ok(providers) => providers,
+        Err(e) => {
+            spinner.error("Failed to connect to storage");
+            return Err(e);
+        }
+    };
+
+    let action = if args.delete_source_after_copy {
+        "Moving"
+    } else {
+        "Copying"
+    };
+    spinner.update_message(format!("{} backups...", action));
+
+    let report = match transfer_backups(
+        &source,
+        &destination,
+        TransferOptions {
+            older_than_days,
+            delete_source_after_copy: args.delete_source_after_copy,
+            dry_run: args.dry_run,
+        },
+    )
+    .await
+    {
+        Ok(report) => report,
+        Err(e) => {
+            spinner.error("Copy failed");
+            return Err(e);
+        }
+    };
+
+    if args.dry_run {
+        spinner.success(format!(
+            "Dry run completed: {} backups would be copied",
+            report.pending()
+        ));
+        for backup in &report.backups {
+            println!("  {}", backup.path);
+        }
+        return Ok(());
+    }
+
+    if report.failed() > 0 {
+        spinner.error(format!(
+            "{} backups failed to transfer, see the warnings above",
+            report.failed()
+        ));
+        return Err(anyhow!("{} backups failed to copy", report.failed()));
+    }
+
+    spinner.success(format!(
+        "Copy completed: {} copied, {} moved",
+        report.copied(),
+        report.moved()
+    ));
+
+    Ok(())
+}
+
+fn describe_manifest(manifest: &BackupManifest) -> String {
+    let server = match &manifest.database {
+        Some(metadata) => format!(
+            "{} {}",
+            get_db_name(&metadata.version),
+            get_version_name(&metadata.version)
+        ),
+        None => "unknown server".to_string(),
+    };
+
+    let mut description = format!(
+        "{} | {} uncompressed",
+        server,
+        format_size(manifest.uncompressed_size)
+    );
+
+    if manifest.scope != BackupScope::Full {
+        description.push_str(&format!(" | {}", manifest.scope));
+    }
+
+    description
+}
+
+async fn list_failed_verifications(
+    storage_provider: &StorageProvider,
+    filename_only: bool,
+    local_time: bool,
+) -> Result<()> {
+    let failures = list_verification_failures(storage_provider).await?;
+
+    if filename_only {
+        for failure in &failures {
+            println!("{}", failure.name);
+        }
+        return Ok(());
+    }
+
+    if failures.is_empty() {
+        println!("{}", "[INFO] No backups failed verification".cyan());
+        return Ok(());
+    }
+
+    println!("\n{}:", "Backups that failed verification".red().bold());
+
+    for (index, failure) in failures.iter().enumerate() {
+        println!(
+            "  {:2}. {} | {} | {}",
+            index + 1,
+            format_backup_timestamp(failure.failed_at, local_time),
+            failure.name,
+            failure.error
+        );
+    }
+
+    Ok(())
+}
+
+async fn verify(args: cli::VerifyArgs) -> Result<()> {
+    let mut spinner = Spinner::new(format!("Verifying '{}'...", args.name));
+    spinner.start();
+
+    let storage = async {
+        let storage_config = resolve_storage_config(&args.workspace, &Some(args.storage)).await?;
+        let storage = StorageProvider::new(storage_config)?;
+
+        if !storage.exists(&args.name).await? {
+            return Err(anyhow!("Backup not found: {}", args.name));
+        }
+
+        Ok((storage, encryption_from_cli(&args.encryption)?))
+    }
+    .await;
+
+    let (storage, encryption) = match storage {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            spinner.error("Failed to access the backup");
+            return Err(e);
+        }
+    };
+
+    let result = verify_backup(
+        &storage,
+        VerifyOptions {
+            name: args.name.clone(),
+            compression_format: None,
+            encryption,
+            uncompressed: args.uncompressed,
+        },
+    )
+    .await;
+
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            spinner.error("Verification failed");
+            // A wrong key or a storage error says nothing about the backup itself
+            if is_integrity_failure(&e) {
+                flag_failed_backup(&storage, &args.name, args.quarantine, &e).await;
+            }
+            return Err(e);
+        }
+    };
+
+    // A later success clears the flag, a transient read error shouldn't stick forever
+    clear_verification_failure(&storage, &args.name).await?;
+
+    if report.matched_manifest {
+        spinner.success(format!(
+            "{} is intact, the uncompressed dump matches its manifest ({} bytes, SHA-256 {})",
+            args.name, report.digest.size, report.digest.sha256
+        ));
+    } else {
+        spinner.success(format!(
+            "{} is readable ({} bytes uncompressed, SHA-256 {})",
+            args.name, report.digest.size, report.digest.sha256
+        ));
+    }
+
+    Ok(())
+}
+
+/// Records the failure so `list` flags the backup, and quarantines it if asked to. Errors
+/// are only reported, the verification error is what the command fails with.
+async fn flag_failed_backup(
+    storage: &StorageProvider,
+    name: &str,
+    quarantine: bool,
+    error: &anyhow::Error,
+) {
+    if let Err(e) = record_verification_failure(storage, name, &error.to_string()).await {
+        eprintln!(
+            "{}",
+            format!("[WARN] Failed to record the verification failure: {}", e).yellow()
+        );
+    }
+
+    if !quarantine {
+        return;
+    }
+
+    match quarantine_backup(storage, name).await {
+        Ok(quarantined) => println!(
+            "{}",
+            format!("[INFO] Moved {} to {}", name, quarantined).cyan()
+        ),
+        Err(e) => eprintln!(
+            "{}",
+            format!("[WARN] Failed to quarantine {}: {}", name, e).yellow()
+        ),
+    }
+}
+
+fn print_restore_plan(plan: &RestorePlan) {
+    for object in &plan.objects {
+        let summary = &object.summary;
+
+        println!(
+            "{} | dumped from {} | {} objects, {} tables",
+            object.name,
+            summary.dump_version.as_deref().unwrap_or("unknown version"),
+            summary.object_count,
+            summary.tables.len()
+        );
+
+        for table in &summary.tables {
+            println!("  {}", table);
+        }
+    }
+
+    println!("{}", "[INFO] Dry run, nothing was restored".cyan());
+}
+
+async fn run_smoke_query(query: impl Future<Output = Result<String>>) -> Result<()> {
+    let mut spinner = Spinner::new("Running smoke query...");
+    spinner.start();
+
+    match query.await {
+        Ok(output) => {
+            let rows: Vec<&str> = output.lines().collect();
+            spinner.success(format!(
+                "Smoke query passed ({} rows): {}",
+                rows.len(),
+                rows.first().unwrap_or(&"")
+            ));
+            Ok(())
+        }
+        Err(e) => {
+            spinner.error("Smoke query failed");
+            Err(e)
+        }
+    }
+}
+
+async fn resolve_database_config(
+    workspace_name: &Option<String>,
+    database_args: &cli::DatabaseArgs,
+) -> Result<dbkp_core::databases::DatabaseConfig> {
+    if let Some(workspace_name) = workspace_name {
+        let workspace_manager = WorkspaceManager::new()?;
+        let collection = workspace_manager.load()?;
+        let workspace = collection
+            .get_workspace(workspace_name)
+            .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+        Ok(workspace.database.clone())
+    } else if has_database_config(database_args) {
+        database_config_from_cli(database_args)
+    } else {
+        Err(anyhow!(
+            "Either --workspace or database configuration parameters are required.\n\
+            Database parameters: --database-type, --database, --host, --port, --username\n\
+            Use command --help for more details."
+        ))
+    }
+}
+
+async fn resolve_configs_for_backup(
+    args: &cli::BackupArgs,
+) -> Result<(
+    dbkp_core::databases::DatabaseConfig,
+    dbkp_core::storage::provider::StorageConfig,
+)> {
+    if args.output.is_some() && args.mode == cli::BackupMode::Incremental {
+        return Err(anyhow!(
+            "Incremental backups keep their state in the storage and can't be written to stdout"
+        ));
+    }
+
+    if let Some(workspace_name) = &args.workspace {
+        let workspace_manager = WorkspaceManager::new()?;
+        let collection = workspace_manager.load()?;
+        let workspace = collection
+            .get_workspace(workspace_name)
+            .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+        let storage_config = match &args.output {
+            Some(_) => StorageConfig::Stdout,
+            None => workspace.storage.clone(),
+        };
+        record_workspace_use(&workspace_manager, &[workspace_name.as_str()]);
+        Ok((workspace.database.clone(), storage_config))
+    } else {
+        // Check if we have direct CLI parameters
+        let database_config = if has_database_config(&args.database_config) {
+            database_config_from_cli(&args.database_config)?
+        } else {
+            return Err(anyhow!(
+                "Either --workspace or database configuration parameters are required.\n\
+                Database parameters: --database-type, --database, --host, --port, --username\n\
+                Use 'dbkp backup --help' for more details."
+            ));
+        };
+
+        let storage_config = if args.output.is_some() {
+            StorageConfig::Stdout
+        } else if has_storage_config(&args.storage_config) {
+            storage_from_cli(&args.storage_config)?
+        } else {
+            return Err(anyhow!(
+                "Either --workspace, --output or storage configuration parameters are required.\n\
+                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key; for GCS: --bucket, --gcs-credential-path; for Azure: --azure-account, --azure-account-key, --container; for SFTP: --sftp-host, --sftp-username, --sftp-key-path)\n\
+                Use 'dbkp backup --help' for more details."
+            ));
+        };
+
+        Ok((database_config, storage_config))
+    }
+}
+
+async fn resolve_configs_for_restore(
+    args: &cli::RestoreArgs,
+) -> Result<(
+    dbkp_core::databases::DatabaseConfig,
+    dbkp_core::storage::provider::StorageConfig,
+)> {
+    if let Some(workspace_name) = &args.workspace {
+        let workspace_manager = WorkspaceManager::new()?;
+        let collection = workspace_manager.load()?;
+        let workspace = collection
+            .get_workspace(workspace_name)
+            .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+        record_workspace_use(&workspace_manager, &[workspace_name.as_str()]);
+        Ok((workspace.database.clone(), workspace.storage.clone()))
+    } else {
+        // Check if we have direct CLI parameters
+        let database_config = if has_database_config(&args.database_config) {
+            database_config_from_cli(&args.database_config)?
+        } else {
+            return Err(anyhow!(
+                "Either --workspace or database configuration parameters are required.\n\
+                Database parameters: --database-type, --database, --host, --port, --username\n\
+                Use 'dbkp restore --help' for more details."
+            ));
+        };
+
+        let storage_config = if has_storage_config(&args.storage_config) {
+            storage_from_cli(&args.storage_config)?
+        } else {
+            return Err(anyhow!(
+                "Either --workspace or storage configuration parameters are required.\n\
+                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key; for GCS: --bucket, --gcs-credential-path; for Azure: --azure-account, --azure-account-key, --container; for SFTP: --sftp-host, --sftp-username, --sftp-key-path)\n\
+                Use 'dbkp restore --help' for more details."
+            ));
+        };
+
+        Ok((database_config, storage_config))
+    }
+}
+
+async fn resolve_storage_config(
     workspace_name: &Option<String>,
     storage_args: &Option<cli::StorageArgs>,
 ) -> Result<dbkp_core::storage::provider::StorageConfig> {
@@ -524,20 +2066,56 @@ async fn resolve_storage_config(
             } else {
                 Err(anyhow!(
                     "Either --workspace or storage configuration parameters are required.\n\
-                    Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key)\n\
+                    Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key; for GCS: --bucket, --gcs-credential-path; for Azure: --azure-account, --azure-account-key, --container; for SFTP: --sftp-host, --sftp-username, --sftp-key-path)\n\
                     Use command --help for more details."
                 ))
             }
         } else {
             Err(anyhow!(
                 "Either --workspace or storage configuration parameters are required.\n\
-                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key)\n\
+                Storage parameters: --storage-type, --location (and for S3: --bucket, --endpoint, --access-key, --secret-key; for GCS: --bucket, --gcs-credential-path; for Azure: --azure-account, --azure-account-key, --container; for SFTP: --sftp-host, --sftp-username, --sftp-key-path)\n\
                 Use command --help for more details."
             ))
         }
     }
 }
 
+/// Updates `last_used` of the workspaces, a failure only warns as the backup or restore
+/// can go on without it
+fn record_workspace_use(workspace_manager: &WorkspaceManager, names: &[&str]) {
+    if let Err(e) = workspace_manager.mark_used(names) {
+        eprintln!(
+            "{}",
+            format!("[WARN] Failed to record the use of the workspace: {}", e).yellow()
+        );
+    }
+}
+
+/// Backup defaults saved with the workspace, none without one
+fn resolve_backup_defaults(workspace_name: &Option<String>) -> Result<BackupDefaults> {
+    let Some(workspace_name) = workspace_name else {
+        return Ok(BackupDefaults::default());
+    };
+
+    let collection = WorkspaceManager::new()?.load()?;
+    let workspace = collection
+        .get_workspace(workspace_name)
+        .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+    Ok(workspace.backup_defaults.clone())
+}
+
+fn resolve_workspace_retention(workspace_name: &Option<String>) -> Result<Option<String>> {
+    let Some(workspace_name) = workspace_name else {
+        return Ok(None);
+    };
+
+    let collection = WorkspaceManager::new()?.load()?;
+    let workspace = collection
+        .get_workspace(workspace_name)
+        .ok_or_else(|| anyhow!("Workspace '{}' not found", workspace_name))?;
+    Ok(workspace.retention.clone())
+}
+
 async fn resolve_backup_name(
     args: &cli::RestoreArgs,
     storage_config: &dbkp_core::storage::provider::StorageConfig,
@@ -551,6 +2129,11 @@ async fn resolve_backup_name(
             .list_with_options(ListOptions {
                 latest_only: Some(true),
                 limit: Some(1),
+                recursive: None,
+                database: None,
+                before: None,
+                after: None,
+                include_quarantined: None,
             })
             .await?;
 
@@ -559,12 +2142,26 @@ async fn resolve_backup_name(
         } else {
             Err(anyhow!("No backups found"))
         }
+    } else if let Some(pattern) = &args.newest_of {
+        let storage_provider = StorageProvider::new(storage_config.clone())?;
+        let entries = storage_provider.list().await?;
+
+        match newest_matching(&entries, pattern)? {
+            Some(entry) => Ok(entry.metadata.name.clone()),
+            None => Err(anyhow!("No backups match '{}'", pattern)),
+        }
     } else {
-        Err(anyhow!("Either --name or --latest must be specified"))
+        Err(anyhow!(
+            "Either --name, --latest or --newest-of must be specified"
+        ))
     }
 }
 
 fn has_database_config(args: &cli::DatabaseArgs) -> bool {
+    if args.database_type.as_deref() == Some("sqlite") {
+        return args.database.is_some();
+    }
+
     args.database_type.is_some()
         && args.database.is_some()
         && args.host.is_some()
@@ -583,6 +2180,19 @@ fn has_storage_config(args: &cli::StorageArgs) -> bool {
             && args.access_key.is_some()
             && args.secret_key.is_some()
             && args.location.is_some()
+    } else if args.storage_type.as_deref() == Some("gcs") {
+        // Credentials may come from the environment
+        args.bucket.is_some() && args.location.is_some()
+    } else if args.storage_type.as_deref() == Some("azure") {
+        args.azure_account.is_some()
+            && (args.azure_account_key.is_some() || args.azure_sas_token.is_some())
+            && args.container.is_some()
+            && args.location.is_some()
+    } else if args.storage_type.as_deref() == Some("sftp") {
+        args.sftp_host.is_some()
+            && args.sftp_username.is_some()
+            && args.sftp_key_path.is_some()
+            && args.location.is_some()
     } else {
         false
     }
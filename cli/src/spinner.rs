@@ -1,15 +1,19 @@
 use colored::*;
-use std::io::{self, Write};
+use dbkp_core::progress::ProgressReporter;
+use std::io::{self, IsTerminal, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-/// Animated spinner with color changes
+/// Animated spinner with color changes. Status goes to stderr so stdout only carries
+/// results; when stderr isn't a terminal every status is printed once on its own line.
 pub struct Spinner {
     running: Arc<AtomicBool>,
     handle: Option<thread::JoinHandle<()>>,
-    message: String,
+    message: Arc<Mutex<String>>,
+    enabled: bool,
+    animated: bool,
 }
 
 impl Spinner {
@@ -18,17 +22,34 @@ impl Spinner {
         Self {
             running: Arc::new(AtomicBool::new(false)),
             handle: None,
-            message: message.into(),
+            message: Arc::new(Mutex::new(message.into())),
+            enabled: true,
+            animated: io::stderr().is_terminal(),
         }
     }
 
+    /// A disabled spinner doesn't animate or print status lines, errors are still printed.
+    /// Used when stdout is meant to be consumed by another program.
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
     /// Start the spinner animation
     pub fn start(&mut self) {
-        if self.running.load(Ordering::Relaxed) {
+        if !self.enabled || self.running.load(Ordering::Relaxed) {
             return; // Already running
         }
 
         self.running.store(true, Ordering::Relaxed);
+
+        if !self.animated {
+            if let Ok(text) = self.message.lock() {
+                eprintln!("{}", text);
+            }
+            return;
+        }
+
         let running = self.running.clone();
         let message = self.message.clone();
 
@@ -38,24 +59,25 @@ impl Spinner {
             let mut frame_index = 0;
 
             // Hide cursor
-            print!("\x1B[?25l");
-            io::stdout().flush().unwrap();
+            eprint!("\x1B[?25l");
+            io::stderr().flush().ok();
 
             while running.load(Ordering::Relaxed) {
                 let frame = frames[frame_index % frames.len()];
                 let color = colors[frame_index % colors.len()];
 
                 // Move to beginning of line, clear it, and print spinner
-                print!("\r{} {}", frame.to_string().color(color), message);
-                io::stdout().flush().unwrap();
+                let text = message.lock().map(|text| text.clone()).unwrap_or_default();
+                eprint!("\r\x1B[K{} {}", frame.to_string().color(color), text);
+                io::stderr().flush().ok();
 
                 frame_index += 1;
                 thread::sleep(Duration::from_millis(100));
             }
 
             // Clear the line and show cursor again
-            print!("\r\x1B[K\x1B[?25h");
-            io::stdout().flush().unwrap();
+            eprint!("\r\x1B[K\x1B[?25h");
+            io::stderr().flush().ok();
         });
 
         self.handle = Some(handle);
@@ -77,24 +99,77 @@ impl Spinner {
     /// Stop the spinner and print a success message
     pub fn success(&mut self, message: impl Into<String>) {
         self.stop();
-        println!("{} {}", "[SUCCESS]".green(), message.into());
+        if self.enabled {
+            println!("{} {}", "[SUCCESS]".green(), message.into());
+        }
     }
 
     /// Stop the spinner and print an error message
     pub fn error(&mut self, message: impl Into<String>) {
         self.stop();
-        println!("{} {}", "[ERROR]".red(), message.into());
+        eprintln!("{} {}", "[ERROR]".red(), message.into());
     }
 
     /// Stop the spinner and print an info message
     pub fn info(&mut self, message: impl Into<String>) {
         self.stop();
-        println!("{} {}", "[INFO]".cyan(), message.into());
+        if self.enabled {
+            println!("{} {}", "[INFO]".cyan(), message.into());
+        }
     }
 
     /// Update the spinner message while it's running
     pub fn update_message(&mut self, message: impl Into<String>) {
-        self.message = message.into();
+        if let Ok(mut text) = self.message.lock() {
+            *text = message.into();
+
+            if !self.animated && self.running.load(Ordering::Relaxed) {
+                eprintln!("{}", text);
+            }
+        }
+    }
+
+    /// A progress reporter that shows the transferred bytes after `label` in the message
+    pub fn progress(&self, label: impl Into<String>) -> Arc<dyn ProgressReporter> {
+        Arc::new(SpinnerProgress {
+            message: self.message.clone(),
+            label: label.into(),
+        })
+    }
+}
+
+struct SpinnerProgress {
+    message: Arc<Mutex<String>>,
+    label: String,
+}
+
+impl ProgressReporter for SpinnerProgress {
+    fn on_bytes(&self, transferred: u64, total: Option<u64>) {
+        let text = match total {
+            Some(total) => format!(
+                "{} {} / {}",
+                self.label,
+                format_size(transferred),
+                format_size(total)
+            ),
+            None => format!("{} {}", self.label, format_size(transferred)),
+        };
+
+        if let Ok(mut message) = self.message.lock() {
+            *message = text;
+        }
+    }
+}
+
+pub(crate) fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{}B", size)
+    } else if size < 1024 * 1024 {
+        format!("{:.2}KB", size as f64 / 1024.0)
+    } else if size < 1024 * 1024 * 1024 {
+        format!("{:.2}MB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.2}GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
     }
 }
 
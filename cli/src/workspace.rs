@@ -1,21 +1,68 @@
 use anyhow::{anyhow, Result};
-use dbkp_core::{databases::DatabaseConfig, storage::provider::StorageConfig};
+use chrono::{DateTime, Utc};
+use dbkp_core::{
+    common::{generate_id, write_atomically},
+    compression::CompressionFormat,
+    databases::DatabaseConfig,
+    notifications::NotificationConfig,
+    storage::provider::StorageConfig,
+    BackupOptions,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+pub const WORKSPACE_SCHEMA_VERSION: u64 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workspace {
     pub name: String,
     pub database: DatabaseConfig,
     pub storage: StorageConfig,
     pub created_at: String,
+    /// RFC 3339 time the workspace was last activated or used for a backup or restore
     pub last_used: Option<String>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    /// Used by `backup` for the options its flags leave unset
+    #[serde(default)]
+    pub backup_defaults: BackupDefaults,
+    /// Used by `cleanup` when neither --retention nor a --keep-* policy is given
+    #[serde(default)]
+    pub retention: Option<String>,
+}
+
+/// Backup options saved with a workspace. Only tuning options are kept, the backup name,
+/// the lock and the encryption secrets are given on each run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupDefaults {
+    pub compression_format: Option<CompressionFormat>,
+    pub compression_level: Option<u32>,
+    pub compression_threads: Option<usize>,
+    pub upload_concurrency: Option<usize>,
+    pub jobs: Option<u8>,
+}
+
+impl BackupDefaults {
+    /// Fills the options `options` leaves unset
+    pub fn apply(&self, options: BackupOptions) -> BackupOptions {
+        options.with_defaults(&BackupOptions {
+            compression_format: self.compression_format.clone(),
+            compression_level: self.compression_level,
+            compression_threads: self.compression_threads,
+            upload_concurrency: self.upload_concurrency,
+            jobs: self.jobs,
+            ..Default::default()
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WorkspaceCollection {
+    #[serde(default)]
+    pub schema_version: u64,
     pub workspaces: HashMap<String, Workspace>,
     pub active_workspace: Option<String>,
 }
@@ -23,6 +70,7 @@ pub struct WorkspaceCollection {
 impl WorkspaceCollection {
     pub fn new() -> Self {
         Self {
+            schema_version: WORKSPACE_SCHEMA_VERSION,
             workspaces: HashMap::new(),
             active_workspace: None,
         }
@@ -47,6 +95,7 @@ impl WorkspaceCollection {
     pub fn set_active(&mut self, name: &str) -> Result<()> {
         if self.workspaces.contains_key(name) {
             self.active_workspace = Some(name.to_string());
+            self.mark_used(name);
             Ok(())
         } else {
             Err(anyhow!("Workspace '{}' does not exist", name))
@@ -62,6 +111,32 @@ impl WorkspaceCollection {
     pub fn list_workspaces(&self) -> Vec<&Workspace> {
         self.workspaces.values().collect()
     }
+
+    /// Most recently used first, workspaces never used last, by name
+    pub fn list_workspaces_by_last_used(&self) -> Vec<&Workspace> {
+        let mut workspaces = self.list_workspaces();
+        workspaces.sort_by(|a, b| {
+            b.last_used_at()
+                .cmp(&a.last_used_at())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        workspaces
+    }
+
+    pub fn mark_used(&mut self, name: &str) {
+        if let Some(workspace) = self.workspaces.get_mut(name) {
+            workspace.last_used = Some(Utc::now().to_rfc3339());
+        }
+    }
+}
+
+impl Workspace {
+    pub fn last_used_at(&self) -> Option<DateTime<Utc>> {
+        self.last_used
+            .as_deref()
+            .and_then(|last_used| DateTime::parse_from_rfc3339(last_used).ok())
+            .map(|last_used| last_used.with_timezone(&Utc))
+    }
 }
 
 pub struct WorkspaceManager {
@@ -76,9 +151,11 @@ impl WorkspaceManager {
 
         fs::create_dir_all(&config_dir)?;
 
-        Ok(Self {
-            config_path: config_dir.join("workspaces.json"),
-        })
+        Ok(Self::with_path(config_dir.join("workspaces.json")))
+    }
+
+    pub fn with_path(config_path: PathBuf) -> Self {
+        Self { config_path }
     }
 
     pub fn load(&self) -> Result<WorkspaceCollection> {
@@ -87,15 +164,125 @@ impl WorkspaceManager {
         }
 
         let content = fs::read_to_string(&self.config_path)?;
-        let collection: WorkspaceCollection = serde_json::from_str(&content)?;
+        let (value, from_version) = migrate_workspace_value(serde_json::from_str(&content)?)?;
+        let collection: WorkspaceCollection = serde_json::from_value(value)?;
+
+        if from_version < WORKSPACE_SCHEMA_VERSION {
+            self.save(&collection)?;
+        }
+
         Ok(collection)
     }
 
+    /// Upgrades the workspace file in place and returns the version it was migrated from,
+    /// or `None` when it was already up to date.
+    pub fn migrate(&self) -> Result<Option<u64>> {
+        if !self.config_path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&self.config_path)?;
+        let (_, from_version) = migrate_workspace_value(serde_json::from_str(&content)?)?;
+
+        if from_version == WORKSPACE_SCHEMA_VERSION {
+            return Ok(None);
+        }
+
+        self.load()?;
+        Ok(Some(from_version))
+    }
+
+    /// Replaces the file in one step, so a concurrent `load`, e.g. from another run
+    /// recording its use, never reads a half-written file
     pub fn save(&self, collection: &WorkspaceCollection) -> Result<()> {
         let content = serde_json::to_string_pretty(collection)?;
-        fs::write(&self.config_path, content)?;
+        write_atomically(&self.config_path, &content)?;
         Ok(())
     }
+
+    /// Records in the saved workspaces that `names` were just used
+    pub fn mark_used(&self, names: &[&str]) -> Result<()> {
+        let mut collection = self.load()?;
+        for name in names {
+            collection.mark_used(name);
+        }
+        self.save(&collection)
+    }
+}
+
+fn migrate_workspace_value(mut value: Value) -> Result<(Value, u64)> {
+    let from_version = value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    if from_version > WORKSPACE_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Workspace file uses schema version {} but this version of dbkp only supports up to {}",
+            from_version,
+            WORKSPACE_SCHEMA_VERSION
+        ));
+    }
+
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid workspace file: expected a JSON object"))?;
+
+    let mut version = from_version;
+    while version < WORKSPACE_SCHEMA_VERSION {
+        match version {
+            // Version 0 files predate schema versioning and need no structural changes
+            0 => {}
+            // Version 1 configs created by the CLI had empty ids
+            1 => backfill_config_ids(object),
+            _ => unreachable!(),
+        }
+        version += 1;
+    }
+
+    object.insert(
+        "schema_version".into(),
+        Value::from(WORKSPACE_SCHEMA_VERSION),
+    );
+
+    Ok((value, from_version))
+}
+
+fn backfill_config_ids(collection: &mut Map<String, Value>) {
+    let Some(workspaces) = collection
+        .get_mut("workspaces")
+        .and_then(Value::as_object_mut)
+    else {
+        return;
+    };
+
+    for workspace in workspaces.values_mut() {
+        if let Some(database) = workspace.get_mut("database") {
+            backfill_config_id(database);
+        }
+
+        // Storage configs are externally tagged enums, e.g. {"Local": {...}}
+        if let Some(storage) = workspace
+            .get_mut("storage")
+            .and_then(Value::as_object_mut)
+            .and_then(|storage| storage.values_mut().next())
+        {
+            backfill_config_id(storage);
+        }
+    }
+}
+
+fn backfill_config_id(config: &mut Value) {
+    if let Some(config) = config.as_object_mut() {
+        let is_missing = config
+            .get("id")
+            .and_then(Value::as_str)
+            .is_none_or(str::is_empty);
+
+        if is_missing {
+            config.insert("id".into(), Value::from(generate_id()));
+        }
+    }
 }
 
 impl Default for WorkspaceManager {
@@ -103,3 +290,194 @@ impl Default for WorkspaceManager {
         Self::new().expect("Failed to create workspace manager")
     }
 }
+
+#[cfg(test)]
+mod workspace_tests {
+    use std::fs;
+
+    use dbkp_core::{compression::CompressionFormat, BackupOptions};
+    use tempfile::tempdir;
+
+    use super::{StorageConfig, WorkspaceCollection, WorkspaceManager, WORKSPACE_SCHEMA_VERSION};
+
+    const LEGACY_WORKSPACES: &str = r#"{
+        "workspaces": {
+            "production": {
+                "name": "production",
+                "database": {
+                    "id": "",
+                    "name": "app",
+                    "connection_type": "PostgreSql",
+                    "host": "localhost",
+                    "port": 5432,
+                    "database": "app",
+                    "username": "postgres",
+                    "password": null,
+                    "ssh_tunnel": null
+                },
+                "storage": {
+                    "Local": {
+                        "id": "",
+                        "name": "default",
+                        "location": "/var/backups"
+                    }
+                },
+                "created_at": "2024-01-01T00:00:00Z",
+                "last_used": null
+            }
+        },
+        "active_workspace": "production"
+    }"#;
+
+    #[test]
+    fn test_load_migrates_legacy_file() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join("workspaces.json");
+        fs::write(&config_path, LEGACY_WORKSPACES).expect("Failed to write fixture");
+
+        let manager = WorkspaceManager::with_path(config_path.clone());
+        let collection = manager.load().expect("Failed to load legacy workspaces");
+
+        assert_eq!(collection.schema_version, WORKSPACE_SCHEMA_VERSION);
+        assert_eq!(collection.active_workspace.as_deref(), Some("production"));
+        let workspace = collection
+            .get_workspace("production")
+            .expect("Workspace should survive migration");
+        assert!(
+            !workspace.database.id.is_empty(),
+            "Database id should be backfilled"
+        );
+        assert!(workspace.backup_defaults.compression_format.is_none());
+        assert!(workspace.retention.is_none());
+        match &workspace.storage {
+            StorageConfig::Local(storage) => {
+                assert!(!storage.id.is_empty(), "Storage id should be backfilled")
+            }
+            _ => panic!("Expected local storage"),
+        }
+
+        let content = fs::read_to_string(&config_path).expect("Failed to read migrated file");
+        let value: serde_json::Value =
+            serde_json::from_str(&content).expect("Failed to parse migrated file");
+        assert_eq!(
+            value["schema_version"].as_u64(),
+            Some(WORKSPACE_SCHEMA_VERSION),
+            "Migrated file should be rewritten in place"
+        );
+
+        assert_eq!(
+            manager.migrate().expect("Failed to migrate"),
+            None,
+            "Second migration should be a no-op"
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_newer_schema() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join("workspaces.json");
+        fs::write(
+            &config_path,
+            format!(
+                r#"{{"schema_version": {}, "workspaces": {{}}, "active_workspace": null}}"#,
+                WORKSPACE_SCHEMA_VERSION + 1
+            ),
+        )
+        .expect("Failed to write fixture");
+
+        let manager = WorkspaceManager::with_path(config_path);
+        assert!(manager.load().is_err());
+    }
+
+    #[test]
+    fn test_backup_defaults_round_trip() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join("workspaces.json");
+        fs::write(&config_path, LEGACY_WORKSPACES).expect("Failed to write fixture");
+
+        let manager = WorkspaceManager::with_path(config_path);
+        let mut collection = manager.load().expect("Failed to load workspaces");
+        let workspace = collection
+            .workspaces
+            .get_mut("production")
+            .expect("Missing workspace");
+        workspace.backup_defaults.compression_format = Some(CompressionFormat::Zstd);
+        workspace.backup_defaults.compression_level = Some(19);
+        workspace.retention = Some("30d".to_string());
+        manager
+            .save(&collection)
+            .expect("Failed to save workspaces");
+
+        let collection = manager.load().expect("Failed to reload workspaces");
+        let workspace = collection.get_workspace("production").unwrap();
+        assert!(matches!(
+            workspace.backup_defaults.compression_format,
+            Some(CompressionFormat::Zstd)
+        ));
+        assert_eq!(workspace.retention.as_deref(), Some("30d"));
+
+        // Flags given on the command line win over the defaults
+        let options = workspace.backup_defaults.apply(BackupOptions {
+            compression_level: Some(3),
+            ..Default::default()
+        });
+        assert!(matches!(
+            options.compression_format,
+            Some(CompressionFormat::Zstd)
+        ));
+        assert_eq!(options.compression_level, Some(3));
+    }
+
+    #[test]
+    fn test_last_used() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join("workspaces.json");
+        fs::write(&config_path, LEGACY_WORKSPACES).expect("Failed to write fixture");
+
+        let manager = WorkspaceManager::with_path(config_path);
+        let mut collection = manager.load().expect("Failed to load workspaces");
+        for name in ["staging", "analytics"] {
+            let mut workspace = collection.get_workspace("production").unwrap().clone();
+            workspace.name = name.to_string();
+            collection.add_workspace(workspace);
+        }
+        collection.workspaces.get_mut("staging").unwrap().last_used =
+            Some("2024-03-01T10:00:00+02:00".to_string());
+        collection
+            .workspaces
+            .get_mut("analytics")
+            .unwrap()
+            .last_used = Some("2024-03-01T09:00:00Z".to_string());
+        manager
+            .save(&collection)
+            .expect("Failed to save workspaces");
+
+        let names = |collection: &WorkspaceCollection| {
+            collection
+                .list_workspaces_by_last_used()
+                .iter()
+                .map(|workspace| workspace.name.clone())
+                .collect::<Vec<_>>()
+        };
+        // Times are compared, not strings, production was never used
+        assert_eq!(names(&collection), ["analytics", "staging", "production"]);
+
+        manager
+            .mark_used(&["production"])
+            .expect("Failed to record the use");
+        let mut collection = manager.load().expect("Failed to reload workspaces");
+        assert_eq!(names(&collection), ["production", "analytics", "staging"]);
+
+        // Saved through a temporary file renamed over the old one, nothing is left behind
+        let files: Vec<_> = fs::read_dir(temp_dir.path())
+            .expect("Failed to read temp dir")
+            .map(|entry| entry.expect("Failed to read entry").file_name())
+            .collect();
+        assert_eq!(files, ["workspaces.json"]);
+
+        collection
+            .set_active("staging")
+            .expect("Failed to activate workspace");
+        assert_eq!(names(&collection)[0], "staging");
+    }
+}
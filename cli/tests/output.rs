@@ -0,0 +1,50 @@
+use assert_cmd::Command;
+use tempfile::tempdir;
+
+#[test]
+fn test_01_status_goes_to_stderr() {
+    let location = tempdir().expect("Failed to create temp dir");
+
+    let output = Command::cargo_bin("dbkp")
+        .expect("Failed to find the dbkp binary")
+        .args(["list", "--storage-type", "local", "--location"])
+        .arg(location.path())
+        .output()
+        .expect("Failed to run dbkp");
+
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "[INFO] No backups found\n");
+
+    // Redirected stderr gets one plain line per status, without the animation
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Resolving storage configuration...\n"));
+    assert!(
+        !stderr.contains('\x1B'),
+        "Unexpected escape codes: {:?}",
+        stderr
+    );
+}
+
+#[test]
+fn test_02_json_list_only_prints_json() {
+    let location = tempdir().expect("Failed to create temp dir");
+
+    let output = Command::cargo_bin("dbkp")
+        .expect("Failed to find the dbkp binary")
+        .args([
+            "list",
+            "--format",
+            "json",
+            "--storage-type",
+            "local",
+            "--location",
+        ])
+        .arg(location.path())
+        .output()
+        .expect("Failed to run dbkp");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+}
@@ -37,12 +37,19 @@ impl ArchiveInstaller {
 
         let (major_version, _, string_version) = match &self.database_version {
             Version::PostgreSQL(version) => (version.major, version.major, version.to_string()),
-            Version::MySql(version) => (version.major, version.minor, version.to_string()),
+            Version::MySql(version) | Version::MariaDB(version) => {
+                (version.major, version.minor, version.to_string())
+            }
+            Version::MongoDB(version) => (version.major, version.minor, version.to_string()),
+            Version::SQLite(_) => return Err(anyhow!("SQLite doesn't use any external tools")),
         };
 
         let database_name = match self.database_version {
             Version::PostgreSQL(_) => "postgresql",
             Version::MySql(_) => "mysql",
+            Version::MariaDB(_) => "mariadb",
+            Version::SQLite(_) => "sqlite",
+            Version::MongoDB(_) => "mongodb",
         };
 
         let databases = match metadata
@@ -0,0 +1,30 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for backup names and cleanup. Everything defaults to
+/// [`SystemClock`]; tests swap in a [`FixedClock`] to get the same names and the same
+/// backups removed on every run.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always returns the same time
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
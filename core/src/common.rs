@@ -4,17 +4,99 @@ use dirs::cache_dir;
 use regex::Regex;
 use std::{
     borrow::Borrow,
-    env,
+    env, fs,
     path::{Path, PathBuf},
 };
 
 use uuid::Uuid;
 
 use crate::{
+    clock::{Clock, SystemClock},
     compression::CompressionFormat,
-    databases::{version::Version, DatabaseConfig},
+    databases::{version::Version, ConnectionType, DatabaseConfig},
 };
 
+/// Default size of the buffers used to move data between the database tools, compression
+/// and storage. Larger buffers mean fewer round-trips to the storage worker at the cost of
+/// memory per running backup or restore.
+pub const DEFAULT_PIPELINE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Suffix of the manifest recording the digest of a backup's uncompressed dump
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+pub fn manifest_name(backup_name: &str) -> String {
+    format!("{}{}", backup_name, MANIFEST_SUFFIX)
+}
+
+/// Suffix of the state of a database's incremental chain, see [`crate::BinlogState`]
+pub const BINLOG_STATE_SUFFIX: &str = ".binlog.json";
+
+/// Incremental backups of a database keep their chain in `<database name>.binlog.json`
+pub fn binlog_state_name(database_name: &str) -> String {
+    format!("{}{}", database_name, BINLOG_STATE_SUFFIX)
+}
+
+/// Extensions a backup name ends with, one per compression format. Uncompressed backups
+/// end with a bare dot, or `.sql` when named by hand.
+pub const BACKUP_EXTENSIONS: [&str; 7] = ["gz", "zst", "xz", "zip", "zz", "sql", ""];
+
+/// Name of a backup without its compression extension, which stays the same when the
+/// backup is re-compressed into another format
+pub fn backup_base_name(backup_name: &str) -> &str {
+    match backup_name.rsplit_once('.') {
+        Some((base, extension))
+            if !base.is_empty()
+                && !base.ends_with('/')
+                && BACKUP_EXTENSIONS.contains(&extension) =>
+        {
+            base
+        }
+        _ => backup_name,
+    }
+}
+
+/// Suffix of the record left next to a backup that failed verification
+pub const VERIFICATION_FAILURE_SUFFIX: &str = ".verify-failed.json";
+
+pub fn verification_failure_name(backup_name: &str) -> String {
+    format!("{}{}", backup_name, VERIFICATION_FAILURE_SUFFIX)
+}
+
+/// Objects stored alongside a backup that should be removed together with it.
+pub fn sidecar_paths(backup_name: &str) -> Vec<String> {
+    vec![
+        manifest_name(backup_name),
+        verification_failure_name(backup_name),
+    ]
+}
+
+/// Format of the UTC timestamp [`get_default_backup_name`] puts in backup names
+pub const BACKUP_TIMESTAMP_FORMAT: &str = "%Y-%m-%d-%H%M%S";
+
+/// Matches the end of a backup name: the timestamp (first group), the id and the
+/// extension. It's anchored to the end of the name, so a date elsewhere in it, e.g. in a
+/// `{date}` prefix, is never taken for the timestamp.
+pub const BACKUP_TIMESTAMP_PATTERN: &str =
+    r"(\d{4}-\d{2}-\d{2}-\d{6})-[a-f0-9]+\.(gz|zst|xz|zz|dump|tar|zip|sql)?$";
+
+/// Path of the log that collects the output of the database tools for one run, named
+/// after the backup. Creates `log_dir` if needed.
+pub fn tool_log_path(log_dir: &Path, backup_name: &str) -> Result<PathBuf> {
+    fs::create_dir_all(log_dir)?;
+
+    let file_name = Path::new(backup_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| backup_name.to_string());
+
+    Ok(log_dir.join(format!("{}.log", file_name)))
+}
+
+/// Identifier for new database and storage configs
+pub fn generate_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
 pub fn slugify(input: &str) -> String {
     let mut slug = String::new();
     let mut prev_is_separator = false;
@@ -36,30 +118,146 @@ pub fn slugify(input: &str) -> String {
     slug.to_string()
 }
 
+/// Builds `<prefix>-<YYYY-MM-DD-HHMMSS>-<id>.<ext>`, where the prefix is the config name
+/// or `name_template` rendered by [`render_name_template`]. The embedded timestamp is
+/// always UTC, regardless of the machine's local timezone.
 pub fn get_default_backup_name<B>(
     database_config: B,
     compression_format: &CompressionFormat,
-) -> String
+    name_template: Option<&str>,
+) -> Result<String>
+where
+    B: Borrow<DatabaseConfig>,
+{
+    get_default_backup_name_with_clock(
+        database_config,
+        compression_format,
+        name_template,
+        &SystemClock,
+    )
+}
+
+/// [`get_default_backup_name`] with the timestamp taken from `clock`
+pub fn get_default_backup_name_with_clock<B>(
+    database_config: B,
+    compression_format: &CompressionFormat,
+    name_template: Option<&str>,
+    clock: &dyn Clock,
+) -> Result<String>
 where
     B: Borrow<DatabaseConfig>,
 {
     let borrowed_config: &DatabaseConfig = database_config.borrow();
-    let now = Utc::now();
-    let date_str = now.format("%Y-%m-%d-%H%M%S");
+    let now = clock.now();
+    let date_str = now.format(BACKUP_TIMESTAMP_FORMAT);
     let uuid_string = Uuid::new_v4().to_string();
     let uuid = uuid_string.split('-').next().unwrap_or("backup");
 
-    let extension = match compression_format {
-        CompressionFormat::Zlib => "zip",
-        CompressionFormat::Deflate => "zz",
-        CompressionFormat::Gzip => "gz",
-        CompressionFormat::None => "",
+    let extension = compression_format.extension();
+
+    let prefix = match name_template {
+        Some(template) => render_name_template(template, borrowed_config, now)?,
+        None => borrowed_config.name.clone(),
     };
 
-    format!(
-        "{}-{}-{}.{}",
-        borrowed_config.name, date_str, uuid, extension
-    )
+    Ok(format!("{}-{}-{}.{}", prefix, date_str, uuid, extension))
+}
+
+/// Placeholders of a backup name template, e.g. `{database}/{year}/{month}/{database}`
+pub const NAME_TEMPLATE_PLACEHOLDERS: [&str; 9] = [
+    "name", "database", "type", "host", "date", "time", "year", "month", "day",
+];
+
+/// Renders the prefix of a backup name. The timestamp and id are always appended to the
+/// last path component, so listing and cleanup can still read the timestamp, and `/`
+/// in the template creates nested prefixes in storage. Dates and times are UTC.
+pub fn render_name_template(
+    template: &str,
+    database_config: &DatabaseConfig,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    expand_name_template(template, |placeholder| {
+        let value = match placeholder {
+            "name" => database_config.name.clone(),
+            "database" => database_config.database.clone(),
+            "type" => match database_config.connection_type {
+                ConnectionType::PostgreSql => "postgresql",
+                ConnectionType::MySql => "mysql",
+                ConnectionType::SQLite => "sqlite",
+                ConnectionType::MariaDB => "mariadb",
+                ConnectionType::MongoDB => "mongodb",
+            }
+            .to_string(),
+            "host" => database_config.host.clone(),
+            "date" => now.format("%Y-%m-%d").to_string(),
+            "time" => now.format("%H%M%S").to_string(),
+            "year" => now.format("%Y").to_string(),
+            "month" => now.format("%m").to_string(),
+            "day" => now.format("%d").to_string(),
+            _ => return None,
+        };
+
+        // A value must not add path components of its own, e.g. a SQLite file path
+        Some(value.replace(['/', '\\'], "-"))
+    })
+}
+
+/// Checks a template without rendering it, to reject a bad one before any backup runs
+pub fn validate_name_template(template: &str) -> Result<()> {
+    expand_name_template(template, |placeholder| {
+        NAME_TEMPLATE_PLACEHOLDERS
+            .contains(&placeholder)
+            .then(|| placeholder.to_string())
+    })
+    .map(|_| ())
+}
+
+fn expand_name_template(
+    template: &str,
+    value_of: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(anyhow!("Unmatched '}}' in name template: {}", template));
+        }
+
+        rendered.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed '{{' in name template: {}", template))?;
+        let placeholder = &rest[start + 1..start + end];
+
+        rendered.push_str(&value_of(placeholder).ok_or_else(|| {
+            anyhow!(
+                "Unknown placeholder {{{}}} in name template, use one of {}",
+                placeholder,
+                NAME_TEMPLATE_PLACEHOLDERS
+                    .map(|placeholder| format!("{{{}}}", placeholder))
+                    .join(", ")
+            )
+        })?);
+
+        rest = &rest[start + end + 1..];
+    }
+
+    rendered.push_str(rest);
+
+    if rendered
+        .split('/')
+        .any(|component| component.is_empty() || component == "." || component == "..")
+    {
+        return Err(anyhow!(
+            "Name template '{}' renders to an invalid path: {}",
+            template,
+            rendered
+        ));
+    }
+
+    Ok(rendered)
 }
 
 pub fn get_binaries_base_path(version: &Version) -> PathBuf {
@@ -77,18 +275,25 @@ pub fn get_db_name(version: &Version) -> String {
     match version {
         Version::PostgreSQL(_) => "postgresql".into(),
         Version::MySql(_) => "mysql".into(),
+        Version::MariaDB(_) => "mariadb".into(),
+        Version::SQLite(_) => "sqlite".into(),
+        Version::MongoDB(_) => "mongodb".into(),
     }
 }
 
 pub fn get_version_name(version: &Version) -> String {
     match version {
         Version::PostgreSQL(version) => version.to_string(),
-        Version::MySql(version) => version.to_string(),
+        Version::MySql(version) | Version::MariaDB(version) => version.to_string(),
+        Version::SQLite(version) => version.clone(),
+        Version::MongoDB(version) => version.to_string(),
     }
 }
 
+/// Parses the UTC timestamp embedded by [`get_default_backup_name`], see
+/// [`BACKUP_TIMESTAMP_PATTERN`].
 pub fn extract_timestamp_from_filename(filename: &str) -> Result<DateTime<Utc>> {
-    let re = Regex::new(r"(\d{4}-\d{2}-\d{2}-\d{6})-[a-f0-9]+\.(gz|dump|tar|zip|sql)$")
+    let re = Regex::new(BACKUP_TIMESTAMP_PATTERN)
         .map_err(|e| anyhow!("Failed to compile regex: {}", e))?;
 
     let caps = re.captures(filename).ok_or_else(|| {
@@ -103,12 +308,42 @@ pub fn extract_timestamp_from_filename(filename: &str) -> Result<DateTime<Utc>>
         .ok_or_else(|| anyhow!("Failed to extract timestamp from filename: {}", filename))?
         .as_str();
 
-    let naive_datetime = NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%d-%H%M%S")
+    let naive_datetime = NaiveDateTime::parse_from_str(timestamp_str, BACKUP_TIMESTAMP_FORMAT)
         .map_err(|e| anyhow!("Failed to parse timestamp {}: {}", timestamp_str, e))?;
 
-    let datetime = DateTime::<Utc>::from_utc(naive_datetime, Utc);
+    Ok(naive_datetime.and_utc())
+}
+
+/// Returns the name prefix [`get_default_backup_name`] put in front of the timestamp,
+/// i.e. the database config name the backup was taken from.
+pub fn extract_database_from_filename(filename: &str) -> Result<String> {
+    let filename = filename.rsplit('/').next().unwrap_or(filename);
+    let re = Regex::new(&format!("^(.+)-{}", BACKUP_TIMESTAMP_PATTERN))
+        .map_err(|e| anyhow!("Failed to compile regex: {}", e))?;
+
+    let caps = re.captures(filename).ok_or_else(|| {
+        anyhow!(
+            "Filename doesn't match expected backup name format: {}",
+            filename
+        )
+    })?;
+
+    Ok(caps
+        .get(1)
+        .ok_or_else(|| anyhow!("Failed to extract database from filename: {}", filename))?
+        .as_str()
+        .to_string())
+}
 
-    Ok(datetime)
+/// Whether `filename` is a backup of `database`. Every name matches when no database is
+/// given, names that don't follow the backup name format never match one.
+pub fn is_backup_of(filename: &str, database: Option<&str>) -> bool {
+    match database {
+        Some(database) => {
+            extract_database_from_filename(filename).is_ok_and(|name| name == database)
+        }
+        None => true,
+    }
 }
 
 pub fn get_arch() -> Result<String> {
@@ -168,3 +403,242 @@ pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()>
     }
     Ok(())
 }
+
+/// Parses a size such as `10MB`, `1.5GiB`, `500K` or `1048576` (bytes). KB, MB, GB and
+/// TB, or just their first letter, are powers of 1000, KiB, MiB, GiB and TiB powers of
+/// 1024. Units are case insensitive and may be separated from the value by spaces.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+
+    let value: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{}', use a format like '10MB'", size))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "kib" => 1 << 10,
+        "m" | "mb" => 1_000_000,
+        "mib" => 1 << 20,
+        "g" | "gb" => 1_000_000_000,
+        "gib" => 1 << 30,
+        "t" | "tb" => 1_000_000_000_000,
+        "tib" => 1 << 40,
+        unit => {
+            return Err(anyhow!(
+                "Invalid size unit '{}', use B, KB, MB, GB, TB, KiB, MiB, GiB or TiB",
+                unit
+            ))
+        }
+    };
+
+    let bytes = value * multiplier as f64;
+
+    if bytes < 1.0 {
+        return Err(anyhow!("Size must be at least 1 byte"));
+    }
+
+    if bytes >= u64::MAX as f64 {
+        return Err(anyhow!("Size '{}' is too large", size));
+    }
+
+    Ok(bytes as u64)
+}
+
+/// Writes next to `path` and renames, which replaces the file in one step, so
+/// readers see either the old content or the new one
+pub fn write_atomically(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, content)?;
+
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod common_tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::{
+        clock::FixedClock,
+        compression::CompressionFormat,
+        databases::{ConnectionType, DatabaseConfig},
+    };
+
+    use super::{
+        backup_base_name, extract_database_from_filename, extract_timestamp_from_filename,
+        get_default_backup_name, get_default_backup_name_with_clock, is_backup_of, parse_size,
+        render_name_template, validate_name_template,
+    };
+
+    fn database_config() -> DatabaseConfig {
+        DatabaseConfig {
+            id: "id".to_string(),
+            name: "shop".to_string(),
+            connection_type: ConnectionType::PostgreSql,
+            host: "db.internal".to_string(),
+            port: 5432,
+            database: "shop_production".to_string(),
+            username: "backup".to_string(),
+            password: None,
+            ssh_tunnel: None,
+            connect_timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_extract_database_from_filename() {
+        assert_eq!(
+            extract_database_from_filename("prod-orders-2024-01-15-120000-abc123.gz")
+                .expect("Failed to extract database"),
+            "prod-orders"
+        );
+        assert!(extract_database_from_filename("latest.json").is_err());
+
+        // zstd, xz and uncompressed backups
+        for name in [
+            "prod-orders-2024-01-15-120000-abc123.zst",
+            "prod-orders-2024-01-15-120000-abc123.xz",
+            "prod-orders-2024-01-15-120000-abc123.",
+        ] {
+            assert_eq!(extract_database_from_filename(name).unwrap(), "prod-orders");
+        }
+    }
+
+    #[test]
+    fn test_extract_timestamp_from_filename() {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+
+        for name in [
+            "shop-2024-01-15-120000-abc123.gz",
+            "shop/2024/01/shop-2024-01-15-120000-abc123.zst",
+            // A date earlier in the name doesn't count, only the one at the end
+            "shop/2023-06-01-120000-aaa111/shop-2024-01-15-120000-abc123.gz",
+            "shop-2023-06-01-120000-aaa111-2024-01-15-120000-abc123.gz",
+        ] {
+            assert_eq!(extract_timestamp_from_filename(name).unwrap(), timestamp);
+        }
+
+        for name in [
+            "shop.gz",
+            "latest.json",
+            // Sidecars and copies carry a backup timestamp, but aren't backups
+            "shop-2024-01-15-120000-abc123.gz.manifest.json",
+            "shop-2024-01-15-120000-abc123.gz.partial",
+            "shop-2024-01-15-120000-abc123.gz.old",
+            // Not a hexadecimal id, or not a date at all
+            "shop-2024-01-15-120000-backup.gz",
+            "shop-2024-13-45-120000-abc123.gz",
+        ] {
+            assert!(extract_timestamp_from_filename(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_backup_base_name() {
+        for name in [
+            "shop-2024-01-15-120000-abc123.gz",
+            "shop-2024-01-15-120000-abc123.zst",
+            "shop-2024-01-15-120000-abc123.",
+        ] {
+            assert_eq!(backup_base_name(name), "shop-2024-01-15-120000-abc123");
+        }
+
+        assert_eq!(backup_base_name("shop/dump.sql"), "shop/dump");
+        assert_eq!(backup_base_name("shop.tar"), "shop.tar");
+        assert_eq!(backup_base_name("shop"), "shop");
+        assert_eq!(backup_base_name(".gz"), ".gz");
+    }
+
+    #[test]
+    fn test_is_backup_of() {
+        let name = "prod-orders-2024-01-15-120000-abc123.gz";
+
+        assert!(is_backup_of(name, Some("prod-orders")));
+        assert!(!is_backup_of(name, Some("prod")));
+        assert!(is_backup_of(name, None));
+        assert!(!is_backup_of("latest.json", Some("prod-orders")));
+    }
+
+    #[test]
+    fn test_name_template() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 5, 14, 30, 0).unwrap();
+
+        assert_eq!(
+            render_name_template(
+                "{name}/{year}/{month}/{database}-{type}",
+                &database_config(),
+                now
+            )
+            .unwrap(),
+            "shop/2024/03/shop_production-postgresql"
+        );
+
+        let name = get_default_backup_name(
+            database_config(),
+            &CompressionFormat::Gzip,
+            Some("{name}/{date}/{name}"),
+        )
+        .unwrap();
+        assert!(name.starts_with("shop/"));
+        assert!(extract_timestamp_from_filename(&name).is_ok());
+        assert_eq!(extract_database_from_filename(&name).unwrap(), "shop");
+
+        let name = get_default_backup_name_with_clock(
+            database_config(),
+            &CompressionFormat::Gzip,
+            Some("{name}/{date}/{name}"),
+            &FixedClock(now),
+        )
+        .unwrap();
+        assert!(name.starts_with("shop/2024-03-05/shop-2024-03-05-143000-"));
+        assert_eq!(extract_timestamp_from_filename(&name).unwrap(), now);
+
+        assert!(validate_name_template("{host}/{name}").is_ok());
+        assert!(validate_name_template("{name}/{weekday}").is_err());
+        assert!(validate_name_template("{name").is_err());
+        assert!(validate_name_template("name}").is_err());
+        assert!(validate_name_template("/{name}").is_err());
+        assert!(validate_name_template("{name}/../other").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576").unwrap(), 1_048_576);
+        assert_eq!(parse_size("1048576B").unwrap(), 1_048_576);
+
+        // Decimal units
+        assert_eq!(parse_size("500K").unwrap(), 500_000);
+        assert_eq!(parse_size("10MB").unwrap(), 10_000_000);
+        assert_eq!(parse_size("2gb").unwrap(), 2_000_000_000);
+        assert_eq!(parse_size("1T").unwrap(), 1_000_000_000_000);
+
+        // Binary units
+        assert_eq!(parse_size("512KiB").unwrap(), 524_288);
+        assert_eq!(parse_size("10MiB").unwrap(), 10_485_760);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 1_610_612_736);
+        assert_eq!(parse_size(" 1.5 gib ").unwrap(), 1_610_612_736);
+        assert_eq!(parse_size("2TiB").unwrap(), 2_199_023_255_552);
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("-10MB").is_err());
+        assert!(parse_size("1.2.3MB").is_err());
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("10 MB/s").is_err());
+        assert!(parse_size("0MB").is_err());
+        assert!(parse_size("0.1").is_err());
+        assert!(parse_size("100000000TB").is_err());
+    }
+}
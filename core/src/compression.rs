@@ -1,31 +1,155 @@
+use anyhow::anyhow;
 use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
 use flate2::Compression;
 use serde::{Deserialize, Serialize};
-use std::io::{self, Seek, SeekFrom, Write};
+use std::io::{self, BufReader, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use xz2::{
+    read::XzDecoder,
+    stream::{Check, MtStreamBuilder},
+    write::XzEncoder,
+};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompressionFormat {
     Gzip,
     Zlib,
     Deflate,
+    Zstd,
+    Xz,
     None,
 }
 
+impl CompressionFormat {
+    /// Extension used in backup names, without the dot. Uncompressed backups have none.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionFormat::Zlib => "zip",
+            CompressionFormat::Deflate => "zz",
+            CompressionFormat::Gzip => "gz",
+            CompressionFormat::Zstd => "zst",
+            CompressionFormat::Xz => "xz",
+            CompressionFormat::None => "",
+        }
+    }
+
+    /// Compression levels the format accepts, `None` when it has no levels
+    pub fn level_range(&self) -> Option<RangeInclusive<u32>> {
+        match self {
+            CompressionFormat::Gzip
+            | CompressionFormat::Zlib
+            | CompressionFormat::Deflate
+            | CompressionFormat::Xz => Some(0..=9),
+            CompressionFormat::Zstd => Some(1..=22),
+            CompressionFormat::None => None,
+        }
+    }
+
+    pub fn validate_level(&self, level: u32) -> anyhow::Result<()> {
+        let name = match self {
+            CompressionFormat::Gzip => "gzip",
+            CompressionFormat::Zlib => "zlib",
+            CompressionFormat::Deflate => "deflate",
+            CompressionFormat::Zstd => "zstd",
+            CompressionFormat::Xz => "xz",
+            CompressionFormat::None => "none",
+        };
+
+        match self.level_range() {
+            Some(range) if range.contains(&level) => Ok(()),
+            Some(range) => Err(anyhow!(
+                "Invalid {} compression level {}, use a level from {} to {}",
+                name,
+                level,
+                range.start(),
+                range.end()
+            )),
+            None => Err(anyhow!(
+                "Uncompressed backups don't take a compression level"
+            )),
+        }
+    }
+
+    /// Guesses the format from the extension of a backup name. Names that end with a dot,
+    /// as generated for uncompressed backups, or with `.sql` are uncompressed.
+    pub fn from_filename(name: &str) -> Option<CompressionFormat> {
+        let (_, extension) = name.rsplit_once('.')?;
+
+        match extension.to_lowercase().as_str() {
+            "gz" => Some(CompressionFormat::Gzip),
+            "zip" => Some(CompressionFormat::Zlib),
+            "zz" => Some(CompressionFormat::Deflate),
+            "zst" => Some(CompressionFormat::Zstd),
+            "xz" => Some(CompressionFormat::Xz),
+            "" | "sql" => Some(CompressionFormat::None),
+            _ => None,
+        }
+    }
+
+    /// Recognizes the formats that have a magic number from the first bytes of a stream.
+    /// Deflate streams and uncompressed dumps can't be told apart this way.
+    pub fn from_magic_bytes(header: &[u8]) -> Option<CompressionFormat> {
+        match header {
+            [0x1F, 0x8B, ..] => Some(CompressionFormat::Gzip),
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Some(CompressionFormat::Zstd),
+            [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, ..] => Some(CompressionFormat::Xz),
+            [0x78, 0x01 | 0x9C | 0xDA, ..] => Some(CompressionFormat::Zlib),
+            _ => None,
+        }
+    }
+}
+
+/// Longest magic number `CompressionFormat::from_magic_bytes` looks at
+pub const MAGIC_BYTES_LEN: usize = 6;
+
 pub enum Compressor<W: Write + Send + Unpin> {
     Gzip(GzEncoder<W>),
     Zlib(ZlibEncoder<W>),
     Deflate(DeflateEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Xz(XzEncoder<W>),
     None(W),
 }
 
 impl<W: Write + Send + Unpin> Compressor<W> {
-    pub fn new(writer: W, format: CompressionFormat, level: Compression) -> Self {
-        match format {
+    pub fn new(writer: W, format: CompressionFormat, level: Compression) -> io::Result<Self> {
+        Self::new_with_threads(writer, format, level, 1)
+    }
+
+    /// `threads` only applies to zstd and xz, the other formats always compress on the
+    /// calling thread. Multithreaded output is still readable by any decoder, but isn't
+    /// byte-for-byte identical to the single-threaded output.
+    pub fn new_with_threads(
+        writer: W,
+        format: CompressionFormat,
+        level: Compression,
+        threads: usize,
+    ) -> io::Result<Self> {
+        let compressor = match format {
             CompressionFormat::Gzip => Compressor::Gzip(GzEncoder::new(writer, level)),
             CompressionFormat::Zlib => Compressor::Zlib(ZlibEncoder::new(writer, level)),
             CompressionFormat::Deflate => Compressor::Deflate(DeflateEncoder::new(writer, level)),
+            CompressionFormat::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level.level() as i32)?;
+                if threads > 1 {
+                    encoder.multithread(threads as u32)?;
+                }
+                Compressor::Zstd(encoder)
+            }
+            CompressionFormat::Xz if threads > 1 => {
+                let stream = MtStreamBuilder::new()
+                    .threads(threads as u32)
+                    .preset(level.level())
+                    .check(Check::Crc64)
+                    .encoder()
+                    .map_err(io::Error::from)?;
+                Compressor::Xz(XzEncoder::new_stream(writer, stream))
+            }
+            CompressionFormat::Xz => Compressor::Xz(XzEncoder::new(writer, level.level())),
             CompressionFormat::None => Compressor::None(writer),
-        }
+        };
+
+        Ok(compressor)
     }
 
     pub fn finish(self) -> io::Result<W> {
@@ -33,6 +157,8 @@ impl<W: Write + Send + Unpin> Compressor<W> {
             Compressor::Gzip(encoder) => encoder.finish(),
             Compressor::Zlib(encoder) => encoder.finish(),
             Compressor::Deflate(encoder) => encoder.finish(),
+            Compressor::Zstd(encoder) => encoder.finish(),
+            Compressor::Xz(encoder) => encoder.finish(),
             Compressor::None(writer) => Ok(writer),
         }
     }
@@ -44,6 +170,8 @@ impl<W: Write + Send + Unpin> Write for Compressor<W> {
             Compressor::Gzip(ref mut encoder) => encoder.write(buf),
             Compressor::Zlib(ref mut encoder) => encoder.write(buf),
             Compressor::Deflate(ref mut encoder) => encoder.write(buf),
+            Compressor::Zstd(ref mut encoder) => encoder.write(buf),
+            Compressor::Xz(ref mut encoder) => encoder.write(buf),
             Compressor::None(ref mut writer) => writer.write(buf),
         }
     }
@@ -53,53 +181,59 @@ impl<W: Write + Send + Unpin> Write for Compressor<W> {
             Compressor::Gzip(ref mut encoder) => encoder.flush(),
             Compressor::Zlib(ref mut encoder) => encoder.flush(),
             Compressor::Deflate(ref mut encoder) => encoder.flush(),
+            Compressor::Zstd(ref mut encoder) => encoder.flush(),
+            Compressor::Xz(ref mut encoder) => encoder.flush(),
             Compressor::None(ref mut writer) => writer.flush(),
         }
     }
 }
 
 use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
-use std::io::Read;
+use std::io::{Chain, Cursor, Read};
+
+/// A reader whose first bytes were consumed to sniff its format, then put back in front
+pub type SniffedReader<R> = Chain<Cursor<Vec<u8>>, R>;
 
 pub enum Decompressor<R: Read + Send + Unpin> {
     Gzip(GzDecoder<R>),
     Zlib(ZlibDecoder<R>),
     Deflate(DeflateDecoder<R>),
+    Zstd(zstd::stream::read::Decoder<'static, BufReader<R>>),
+    Xz(XzDecoder<R>),
     None(R),
 }
 
 impl<R: Read + Send + Unpin> Decompressor<R> {
-    pub fn new(reader: R, format: CompressionFormat) -> Self {
-        match format {
+    pub fn new(reader: R, format: CompressionFormat) -> io::Result<Self> {
+        let decompressor = match format {
             CompressionFormat::Gzip => Decompressor::Gzip(GzDecoder::new(reader)),
             CompressionFormat::Zlib => Decompressor::Zlib(ZlibDecoder::new(reader)),
             CompressionFormat::Deflate => Decompressor::Deflate(DeflateDecoder::new(reader)),
+            CompressionFormat::Zstd => {
+                Decompressor::Zstd(zstd::stream::read::Decoder::new(reader)?)
+            }
+            // Threaded xz encoders may write several streams
+            CompressionFormat::Xz => Decompressor::Xz(XzDecoder::new_multi_decoder(reader)),
             CompressionFormat::None => Decompressor::None(reader),
-        }
+        };
+
+        Ok(decompressor)
     }
 
     pub fn detect_format(mut reader: R) -> io::Result<(CompressionFormat, R)>
     where
         R: Read + Seek,
     {
-        let mut signature = [0u8; 3];
+        let mut signature = Vec::with_capacity(MAGIC_BYTES_LEN);
         let start_pos = reader.stream_position()?;
-        let bytes_read = reader.read(&mut signature)?;
+        (&mut reader)
+            .take(MAGIC_BYTES_LEN as u64)
+            .read_to_end(&mut signature)?;
         reader.seek(SeekFrom::Start(start_pos))?;
 
-        if bytes_read < 2 {
-            return Ok((CompressionFormat::None, reader));
-        }
-
-        if signature[0] == 0x1F && signature[1] == 0x8B {
-            Ok((CompressionFormat::Gzip, reader))
-        } else if signature[0] == 0x78
-            && (signature[1] == 0x01 || signature[1] == 0x9C || signature[1] == 0xDA)
-        {
-            Ok((CompressionFormat::Zlib, reader))
-        } else {
-            Ok((CompressionFormat::None, reader))
-        }
+        let format =
+            CompressionFormat::from_magic_bytes(&signature).unwrap_or(CompressionFormat::None);
+        Ok((format, reader))
     }
 
     pub fn into_inner(self) -> R {
@@ -107,17 +241,52 @@ impl<R: Read + Send + Unpin> Decompressor<R> {
             Decompressor::Gzip(decoder) => decoder.into_inner(),
             Decompressor::Zlib(decoder) => decoder.into_inner(),
             Decompressor::Deflate(decoder) => decoder.into_inner(),
+            Decompressor::Zstd(decoder) => decoder.finish().into_inner(),
+            Decompressor::Xz(decoder) => decoder.into_inner(),
             Decompressor::None(reader) => reader,
         }
     }
 }
 
+impl<R: Read + Send + Unpin> Decompressor<SniffedReader<R>> {
+    /// Picks the format from the magic bytes at the start of the stream, falling back to
+    /// the extension of `name`, then to `fallback`. Unlike `detect_format`, this works on
+    /// readers that can't seek, such as storage readers.
+    pub fn sniff(
+        mut reader: R,
+        name: Option<&str>,
+        fallback: Option<CompressionFormat>,
+    ) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(MAGIC_BYTES_LEN);
+        (&mut reader)
+            .take(MAGIC_BYTES_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        let format = CompressionFormat::from_magic_bytes(&header)
+            .or_else(|| name.and_then(CompressionFormat::from_filename))
+            .or(fallback)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Can't tell the compression format of {}, specify it explicitly",
+                        name.unwrap_or("the backup")
+                    ),
+                )
+            })?;
+
+        Decompressor::new(Cursor::new(header).chain(reader), format)
+    }
+}
+
 impl<R: Read + Send + Unpin> Read for Decompressor<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             Decompressor::Gzip(ref mut decoder) => decoder.read(buf),
             Decompressor::Zlib(ref mut decoder) => decoder.read(buf),
             Decompressor::Deflate(ref mut decoder) => decoder.read(buf),
+            Decompressor::Zstd(ref mut decoder) => decoder.read(buf),
+            Decompressor::Xz(ref mut decoder) => decoder.read(buf),
             Decompressor::None(ref mut reader) => reader.read(buf),
         }
     }
@@ -137,7 +306,8 @@ mod compression_test {
     fn compress() {
         let message = "Ceci est un texte test";
         let bytes = vec![];
-        let mut compressor = Compressor::new(bytes, CompressionFormat::Zlib, Compression::best());
+        let mut compressor = Compressor::new(bytes, CompressionFormat::Zlib, Compression::best())
+            .expect("Failed to create compressor");
 
         compressor
             .write_all(message.as_bytes())
@@ -147,7 +317,8 @@ mod compression_test {
         res.flush().expect("Failed to flush");
 
         let reader = Cursor::new(res);
-        let mut decompressor = Decompressor::new(reader, CompressionFormat::Zlib);
+        let mut decompressor = Decompressor::new(reader, CompressionFormat::Zlib)
+            .expect("Failed to create decompressor");
 
         let mut buf = [0u8; 512];
         let n = decompressor.read(&mut buf).expect("Failed to read bytes");
@@ -155,4 +326,148 @@ mod compression_test {
 
         assert_eq!(message.as_bytes(), decompressed_bytes);
     }
+
+    #[test]
+    fn validate_level() {
+        assert!(CompressionFormat::Gzip.validate_level(9).is_ok());
+        assert!(CompressionFormat::Zstd.validate_level(19).is_ok());
+
+        let error = CompressionFormat::Gzip.validate_level(19).unwrap_err();
+        assert!(error.to_string().contains("from 0 to 9"));
+        assert!(CompressionFormat::Zstd.validate_level(0).is_err());
+        assert!(CompressionFormat::None.validate_level(1).is_err());
+    }
+
+    #[test]
+    fn format_from_filename() {
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Zlib,
+            CompressionFormat::Deflate,
+            CompressionFormat::Zstd,
+            CompressionFormat::Xz,
+            CompressionFormat::None,
+        ] {
+            let name = format!("db-2024-01-15-120000-abc123.{}", format.extension());
+            assert_eq!(
+                CompressionFormat::from_filename(&name).map(|f| f.extension()),
+                Some(format.extension())
+            );
+        }
+
+        assert!(CompressionFormat::from_filename("db-2024-01-15-120000-abc123.bak").is_none());
+        assert!(CompressionFormat::from_filename("backup").is_none());
+    }
+
+    fn compressed(format: CompressionFormat, message: &[u8]) -> Vec<u8> {
+        let mut compressor = Compressor::new(vec![], format, Compression::default())
+            .expect("Failed to create compressor");
+        compressor
+            .write_all(message)
+            .expect("Failed to write bytes");
+        compressor.finish().expect("Unable to finish compressor")
+    }
+
+    #[test]
+    fn format_from_magic_bytes() {
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Zlib,
+            CompressionFormat::Zstd,
+            CompressionFormat::Xz,
+        ] {
+            let bytes = compressed(format.clone(), b"SELECT 1;");
+            assert_eq!(
+                CompressionFormat::from_magic_bytes(&bytes).map(|f| f.extension()),
+                Some(format.extension())
+            );
+        }
+
+        assert!(CompressionFormat::from_magic_bytes(b"-- PostgreSQL database dump").is_none());
+        assert!(CompressionFormat::from_magic_bytes(&[0x1F]).is_none());
+        assert!(CompressionFormat::from_magic_bytes(&[]).is_none());
+    }
+
+    #[test]
+    fn sniff_ignores_misleading_name() {
+        let message = b"Ceci est un texte test";
+
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Zstd,
+            CompressionFormat::Xz,
+        ] {
+            let bytes = compressed(format, message);
+            let mut decompressor =
+                Decompressor::sniff(Cursor::new(bytes), Some("backup.sql"), None)
+                    .expect("Failed to create decompressor");
+
+            let mut decompressed = Vec::new();
+            decompressor
+                .read_to_end(&mut decompressed)
+                .expect("Failed to read bytes");
+            assert_eq!(decompressed, message);
+        }
+    }
+
+    #[test]
+    fn sniff_falls_back_to_name_then_option() {
+        let message = b"Ceci est un texte test";
+        let bytes = compressed(CompressionFormat::Deflate, message);
+
+        for (name, fallback) in [
+            (Some("backup.zz"), None),
+            (None, Some(CompressionFormat::Deflate)),
+        ] {
+            let mut decompressor = Decompressor::sniff(Cursor::new(bytes.clone()), name, fallback)
+                .expect("Failed to create decompressor");
+
+            let mut decompressed = Vec::new();
+            decompressor
+                .read_to_end(&mut decompressed)
+                .expect("Failed to read bytes");
+            assert_eq!(decompressed, message);
+        }
+
+        assert!(Decompressor::sniff(Cursor::new(bytes), Some("backup.bak"), None).is_err());
+
+        // Short uncompressed input is passed through untouched
+        let mut decompressor = Decompressor::sniff(
+            Cursor::new(b"x".to_vec()),
+            None,
+            Some(CompressionFormat::None),
+        )
+        .expect("Failed to create decompressor");
+        let mut decompressed = Vec::new();
+        decompressor
+            .read_to_end(&mut decompressed)
+            .expect("Failed to read bytes");
+        assert_eq!(decompressed, b"x");
+    }
+
+    #[test]
+    fn threaded_roundtrip() {
+        let message = "Ceci est un texte test ".repeat(10_000);
+
+        for format in [CompressionFormat::Zstd, CompressionFormat::Xz] {
+            let mut compressor =
+                Compressor::new_with_threads(vec![], format.clone(), Compression::new(3), 2)
+                    .expect("Failed to create compressor");
+
+            compressor
+                .write_all(message.as_bytes())
+                .expect("Failed to write bytes");
+
+            let compressed = compressor.finish().expect("Unable to finish compressor");
+
+            let mut decompressor = Decompressor::new(Cursor::new(compressed), format)
+                .expect("Failed to create decompressor");
+            let mut decompressed = String::new();
+            decompressor
+                .read_to_string(&mut decompressed)
+                .expect("Failed to read bytes");
+
+            assert_eq!(message, decompressed);
+        }
+    }
 }
@@ -1,35 +1,485 @@
 use std::{
-    io::{Read, Write},
-    path::PathBuf,
+    collections::BTreeMap,
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
     sync::Arc,
+    time::Duration,
 };
 
-use anyhow::Result;
+use crate::common::DEFAULT_PIPELINE_BUFFER_SIZE;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use log::warn;
+use mongo::connection::MongoConnection;
 use mysql::connection::MySqlConnection;
 use postgres::connection::PostgreSqlConnection;
 use serde::{Deserialize, Serialize};
+use sqlite::connection::SqliteConnection;
 use ssh_tunnel::SshTunnelConfig;
-use tokio::process::Command;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{Child, Command},
+    task::JoinHandle,
+};
 use version::Version;
 
+pub mod mongo;
 pub mod mysql;
 pub mod postgres;
+pub mod sqlite;
 pub mod ssh_tunnel;
+pub mod tables;
 pub mod version;
 
+/// Where an incremental chain continues from. For MySQL a binlog file and offset, for
+/// PostgreSQL the chain's replication slot and a WAL location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinlogPosition {
+    pub file: String,
+    pub position: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum BackupMode {
+    #[default]
+    Full,
+    /// A full dump that also records the binlog position it is consistent with (MySQL),
+    /// or a `pg_basebackup` of the cluster with a replication slot for its WAL (PostgreSQL)
+    IncrementalBase,
+    /// Only the binlog events (MySQL) or WAL segments (PostgreSQL) written since `since`
+    Incremental { since: BinlogPosition },
+}
+
+/// Which parts of the database a backup contains
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackupScope {
+    #[default]
+    Full,
+    /// Tables and other objects without their rows
+    SchemaOnly,
+    /// Rows only, restored into tables that must already exist
+    DataOnly,
+}
+
+impl fmt::Display for BackupScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupScope::Full => write!(f, "full"),
+            BackupScope::SchemaOnly => write!(f, "schema-only"),
+            BackupScope::DataOnly => write!(f, "data-only"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BackupOptions {
-    // compression: Option<u16>,
+    pub per_table_files: bool,
+    pub mode: BackupMode,
+    pub scope: BackupScope,
+    /// Size of the buffer used to copy dump output, defaults to `DEFAULT_PIPELINE_BUFFER_SIZE`
+    pub buffer_size: Option<usize>,
+    /// File the complete stderr of the dump tools is appended to
+    pub tool_log: Option<PathBuf>,
+    /// Dump as of a snapshot exported with `pg_export_snapshot()` (PostgreSQL). The
+    /// exporting transaction must stay open until the dump has started.
+    pub snapshot: Option<String>,
+    /// Passed to pg_dump or mysqldump after the arguments we set ourselves
+    pub extra_dump_args: Vec<String>,
+    /// Dump with this many parallel `pg_dump` jobs in directory format, archived as a tar
+    /// stream (PostgreSQL). Unset keeps the single-stream plain SQL dump.
+    pub jobs: Option<u8>,
+    /// Glob patterns of the tables to dump, all tables when empty
+    pub include_tables: Vec<String>,
+    /// Glob patterns of the tables to leave out
+    pub exclude_tables: Vec<String>,
+    /// Count the rows of every table as of the snapshot the dump reads, see
+    /// `BackupSummary::row_counts` (PostgreSQL, full backups)
+    pub count_rows: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl BackupOptions {
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_PIPELINE_BUFFER_SIZE)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupSummary {
+    /// Binlog position the backup ends at, for incremental bases and increments
+    pub binlog_position: Option<BinlogPosition>,
+    /// Rows per table as of the dump, when `BackupOptions::count_rows` is set and the
+    /// connection can count them in the dump's snapshot
+    pub row_counts: Option<RowCounts>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RestoreOptions {
     pub drop_database_first: bool,
+    pub jobs: Option<u8>,
+    pub per_table_files: bool,
+    /// Apply a plain-SQL PostgreSQL restore atomically, rolling back on the first error
+    pub single_transaction: bool,
+    pub charset: Option<String>,
+    pub collation: Option<String>,
+    /// Size of the buffer used to feed the restore tool, defaults to `DEFAULT_PIPELINE_BUFFER_SIZE`
+    pub buffer_size: Option<usize>,
+    /// Restore a dump taken from a different server version instead of refusing it
+    pub allow_version_mismatch: bool,
+    /// File the complete stderr of the restore tools is appended to
+    pub tool_log: Option<PathBuf>,
+    /// Passed to psql, pg_restore or mysql after the arguments we set ourselves
+    pub extra_restore_args: Vec<String>,
+    /// Restore into this database instead of the configured one, e.g. to clone
+    /// production into staging. The dump itself doesn't name its database.
+    pub target_database: Option<String>,
+    /// Don't drop the objects of the dump before creating them (PostgreSQL), for restores
+    /// into an empty database
+    pub skip_clean: bool,
+}
+
+impl RestoreOptions {
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_PIPELINE_BUFFER_SIZE)
+    }
+}
+
+/// Exact number of rows per table, named like the table filters match them
+/// (`schema.table` for PostgreSQL, collections for MongoDB)
+pub type RowCounts = BTreeMap<String, u64>;
+
+/// What restoring a dump would load, found by `DatabaseConnectionTrait::inspect_dump`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DumpSummary {
+    /// Server version the dump was taken from, when the dump records it
+    pub dump_version: Option<String>,
+    /// Tables the dump creates, in dump order
+    pub tables: Vec<String>,
+    /// Every object the dump creates, tables included
+    pub object_count: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseMetadata {
-    version: Version,
+    pub version: Version,
+}
+
+/// Charset, collation and locale names are interpolated into `CREATE DATABASE` statements,
+/// so only allow the characters they are actually made of.
+pub(crate) fn validate_charset_name(name: &str) -> Result<()> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '@'))
+    {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid charset or collation name: {}", name))
+    }
+}
+
+/// Error messages from the dump tools and drivers that describe a temporary condition, so
+/// running the dump again may succeed. Authentication and permission failures are
+/// deliberately absent: retrying those can't help.
+const TRANSIENT_DUMP_ERRORS: &[&str] = &[
+    "lock wait timeout",
+    "deadlock found",
+    "lock timeout",
+    "could not obtain lock",
+    "could not serialize access",
+    "lost connection to mysql server",
+    "server has gone away",
+    "server closed the connection unexpectedly",
+    "connection reset by peer",
+    "connection refused",
+    "too many connections",
+    "the database system is starting up",
+    "the database system is shutting down",
+];
+
+pub fn is_transient_dump_error(error: &anyhow::Error) -> bool {
+    let message = format!("{:#}", error).to_lowercase();
+
+    TRANSIENT_DUMP_ERRORS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// Reads a child's output pipe in the background, so a tool that logs a lot can't fill the
+/// pipe and stall while we are busy streaming its other end.
+pub(crate) fn collect_output<R>(pipe: Option<R>) -> JoinHandle<Vec<u8>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output).await;
+        }
+        output
+    })
+}
+
+/// How much of a dump tool's output `stream_tool_output` keeps for inspection, enough to
+/// reach the binlog coordinates mysqldump writes after its header comments
+pub(crate) const CAPTURED_HEADER_SIZE: usize = 64 * 1024;
+
+/// Runs a dump tool and copies its stdout into `writer` through one `buffer_size` buffer,
+/// so memory use doesn't grow with the size of the dump. When `header` is given, the first
+/// `CAPTURED_HEADER_SIZE` bytes are also collected into it. Stderr is collected on the
+/// side: it ends up in the error message, where the transient error detection looks for
+/// it, and in `tool_log` if set.
+pub(crate) async fn stream_tool_output(
+    mut cmd: Command,
+    bin_name: &str,
+    writer: &mut (dyn Write + Send + Unpin),
+    buffer_size: usize,
+    tool_log: Option<&Path>,
+    mut header: Option<&mut Vec<u8>>,
+) -> Result<()> {
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start {}: {}", bin_name, e))?;
+
+    let stderr = collect_output(child.stderr.take());
+
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture {} stdout", bin_name))?;
+
+    let mut buffer = vec![0u8; buffer_size];
+
+    loop {
+        match stdout.read(&mut buffer).await {
+            Ok(0) => break, // EOF
+            Ok(n) => {
+                if let Some(header) = header.as_deref_mut() {
+                    let remaining = CAPTURED_HEADER_SIZE.saturating_sub(header.len());
+                    header.extend_from_slice(&buffer[..n.min(remaining)]);
+                }
+
+                writer
+                    .write_all(&buffer[..n])
+                    .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
+            }
+            Err(e) => {
+                return Err(anyhow!("Failed to read from {}: {}", bin_name, e));
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| anyhow!("{} process failed: {}", bin_name, e))?;
+
+    let stderr = stderr.await.unwrap_or_default();
+    let log_note = save_tool_log(tool_log, bin_name, &stderr);
+
+    if !status.success() {
+        return Err(anyhow!(
+            "{} failed: {}{}",
+            bin_name,
+            String::from_utf8_lossy(&stderr),
+            log_note
+        ));
+    }
+
+    Ok(())
+}
+
+/// Streams a dump straight into the stdin of a restore tool, starting with the first
+/// `filled` bytes already in `buffer`. If the tool exits before consuming everything,
+/// feeding stops and its output is returned, so the caller reports the tool's own error
+/// instead of a broken pipe.
+pub(crate) async fn feed_restore_tool(
+    mut child: Child,
+    bin_name: &str,
+    reader: &mut (dyn Read + Send + Unpin),
+    buffer: &mut [u8],
+    filled: usize,
+) -> Result<Output> {
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture {} stdin", bin_name))?;
+    let stdout = collect_output(child.stdout.take());
+    let stderr = collect_output(child.stderr.take());
+
+    let mut fed = stdin.write_all(&buffer[..filled]).await;
+
+    while fed.is_ok() {
+        match reader.read(buffer) {
+            Ok(0) => break, // EOF
+            Ok(n) => fed = stdin.write_all(&buffer[..n]).await,
+            Err(e) => return Err(anyhow!("Failed to read backup data: {}", e)),
+        }
+    }
+
+    drop(stdin);
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| anyhow!("{} process failed: {}", bin_name, e))?;
+
+    if let Err(e) = fed {
+        if status.success() {
+            return Err(anyhow!("Failed to write to {}: {}", bin_name, e));
+        }
+    }
+
+    Ok(Output {
+        status,
+        stdout: stdout.await.unwrap_or_default(),
+        stderr: stderr.await.unwrap_or_default(),
+    })
+}
+
+/// Appends the stderr of `bin_name` to `tool_log` when one is configured. Returns a note
+/// pointing at the log, meant to be appended to error messages.
+pub(crate) fn save_tool_log(tool_log: Option<&Path>, bin_name: &str, stderr: &[u8]) -> String {
+    let Some(path) = tool_log else {
+        return String::new();
+    };
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            writeln!(file, "==> {} <==", bin_name)?;
+            file.write_all(stderr)?;
+            writeln!(file)
+        });
+
+    match result {
+        Ok(()) => format!(" (full {} output in {})", bin_name, path.display()),
+        Err(e) => {
+            warn!(
+                "Failed to write {} log to {}: {}",
+                bin_name,
+                path.display(),
+                e
+            );
+            String::new()
+        }
+    }
+}
+
+/// Tar archives carry a `ustar` magic 257 bytes into their first header
+pub(crate) fn is_tar_archive(header: &[u8]) -> bool {
+    header.get(257..262) == Some(&b"ustar"[..])
+}
+
+/// The version comments written by pg_dump and mysqldump sit in the first few lines
+const DUMP_HEADER_SIZE: usize = 8 * 1024;
+
+/// Reads the beginning of a dump into `buffer` so its header can be inspected before
+/// anything is sent to the restore tool. Returns the number of bytes read.
+pub(crate) fn read_dump_header(
+    reader: &mut (dyn Read + Send + Unpin),
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    let limit = buffer.len().min(DUMP_HEADER_SIZE);
+    let mut filled = 0;
+
+    while filled < limit {
+        match reader.read(&mut buffer[filled..limit]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Counts the `CREATE` statements of a plain SQL dump and collects the tables among them.
+/// pg_dump and mysqldump both start every statement on a new line.
+pub(crate) fn scan_sql_dump(reader: impl Read, buffer_size: usize) -> io::Result<DumpSummary> {
+    let mut reader = BufReader::with_capacity(buffer_size, reader);
+    let mut summary = DumpSummary::default();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        if !line.starts_with(b"CREATE ") {
+            continue;
+        }
+
+        summary.object_count += 1;
+
+        if let Some(definition) = line.strip_prefix(b"CREATE TABLE ") {
+            let definition = String::from_utf8_lossy(definition);
+            let definition = definition
+                .strip_prefix("IF NOT EXISTS ")
+                .unwrap_or(&definition);
+
+            if let Some(name) = definition.split_whitespace().next() {
+                summary.tables.push(name.trim_matches('`').to_string());
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Finds the `major.minor` version on the header line starting with `marker`, e.g.
+/// `-- Dumped from database version 16.2` for pg_dump.
+pub(crate) fn parse_dump_version(header: &[u8], marker: &str) -> Option<(u16, u16)> {
+    let header = String::from_utf8_lossy(header);
+    let line = header.lines().find(|line| line.starts_with(marker))?;
+    let mut parts = line[marker.len()..]
+        .trim()
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty());
+
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+
+    Some((major, minor))
+}
+
+/// Refuses to restore a dump taken from a different server version, unless the user
+/// explicitly allowed it, in which case the mismatch is only logged.
+pub(crate) fn check_dump_version(
+    engine: &str,
+    dump_version: &str,
+    server_version: &str,
+    allow_version_mismatch: bool,
+) -> Result<()> {
+    if dump_version == server_version {
+        return Ok(());
+    }
+
+    if allow_version_mismatch {
+        warn!(
+            "!!! VERSION MISMATCH: restoring a {} {} dump into a {} {} server. \
+             Proceeding because the version check was overridden, review the result carefully !!!",
+            engine, dump_version, engine, server_version
+        );
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "The backup was taken from {} {} but the target server runs {} {}. \
+         Use --allow-version-mismatch to restore it anyway",
+        engine,
+        dump_version,
+        engine,
+        server_version
+    ))
 }
 
 #[async_trait]
@@ -37,12 +487,34 @@ pub trait DatabaseConnectionTrait: Send + Sync + Unpin {
     async fn test(&self) -> Result<bool>;
     async fn get_metadata(&self) -> Result<DatabaseMetadata>;
     async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()>;
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<BackupSummary>;
     async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()>;
     async fn restore_with_options(
         &self,
         reader: &mut (dyn Read + Send + Unpin),
         options: RestoreOptions,
     ) -> Result<()>;
+    async fn restore_from_directory(&self, path: &Path, options: RestoreOptions) -> Result<()>;
+    /// Reads a dump the way `restore_with_options` would and checks it against the server,
+    /// version included, without changing anything
+    async fn inspect_dump(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<DumpSummary>;
+    /// Runs `query` against the configured database with the command-line client and
+    /// returns its rows, one per line.
+    async fn run_query(&self, query: &str) -> Result<String>;
+    /// Databases of the server the connection can back up, system databases left out,
+    /// sorted by name
+    async fn list_databases(&self) -> Result<Vec<String>>;
+    /// Counts the rows of every table of the configured database, or of `database` when
+    /// given, to check a restore against the counts recorded at backup time
+    async fn table_row_counts(&self, database: Option<&str>) -> Result<RowCounts>;
 }
 
 #[async_trait]
@@ -55,7 +527,13 @@ pub trait UtilitiesTrait: Send + Sync + Unpin {
 pub enum ConnectionType {
     PostgreSql,
     MySql,
-    // MariaDB,
+    /// `DatabaseConfig::database` is the path of the database file
+    SQLite,
+    /// Handled by the MySQL connection, which runs the `mariadb-*` tools once it sees a
+    /// MariaDB server
+    MariaDB,
+    /// Backed up with `mongodump --archive`, users authenticate against `admin`
+    MongoDB,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +547,59 @@ pub struct DatabaseConfig {
     pub username: String,
     pub password: Option<String>,
     pub ssh_tunnel: Option<SshTunnelConfig>,
+    /// Defaults to [`DEFAULT_CONNECT_TIMEOUT`]
+    #[serde(default)]
+    pub connect_timeout: Option<Duration>,
+}
+
+impl DatabaseConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)
+    }
+}
+
+/// How long a database server gets to accept a connection or answer a connection test
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returned when the database server didn't answer within the connect timeout, typically
+/// because a firewall silently drops the connection attempts
+#[derive(Debug)]
+pub struct ConnectionTimedOut(pub Duration);
+
+impl fmt::Display for ConnectionTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Database server didn't answer within {}s",
+            self.0.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for ConnectionTimedOut {}
+
+/// Prefixes failed connection tests, leaving timeouts recognizable
+pub(crate) fn connection_test_error(error: anyhow::Error) -> anyhow::Error {
+    if error.is::<ConnectionTimedOut>() {
+        error
+    } else {
+        anyhow!("Connection test failed: {}", error)
+    }
+}
+
+/// Bounds connecting or running a connection check by `timeout`, whether it's sqlx or the
+/// server that gives up first
+pub(crate) async fn with_connect_timeout<T>(
+    timeout: Duration,
+    future: impl std::future::Future<Output = Result<T, sqlx::Error>>,
+) -> Result<T> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(sqlx::Error::PoolTimedOut)) | Err(_) => {
+            Err(anyhow::Error::new(ConnectionTimedOut(timeout)))
+        }
+        Ok(Err(e)) => Err(e.into()),
+    }
 }
 
 pub struct DatabaseConnection {
@@ -82,9 +613,216 @@ impl DatabaseConnection {
             ConnectionType::PostgreSql => {
                 Arc::new(PostgreSqlConnection::new(config.clone()).await?)
             }
-            ConnectionType::MySql => Arc::new(MySqlConnection::new(config.clone()).await?),
+            ConnectionType::MySql | ConnectionType::MariaDB => {
+                Arc::new(MySqlConnection::new(config.clone()).await?)
+            }
+            ConnectionType::SQLite => Arc::new(SqliteConnection::new(config.clone()).await?),
+            ConnectionType::MongoDB => Arc::new(MongoConnection::new(config.clone()).await?),
         };
 
         Ok(Self { config, connection })
     }
+
+    /// Runs a query after a restore as a quick check that the database is usable. Fails
+    /// when the query errors or returns no rows.
+    pub async fn smoke_query(&self, query: &str) -> Result<String> {
+        let output = self.connection.run_query(query).await?;
+        let output = output.trim();
+
+        if output.is_empty() {
+            return Err(anyhow!("Smoke query returned no rows: {}", query));
+        }
+
+        Ok(output.to_string())
+    }
+}
+
+#[cfg(test)]
+mod databases_tests {
+    use std::{process::Stdio, time::Duration};
+
+    use anyhow::anyhow;
+
+    use tokio::process::Command;
+
+    use super::{
+        check_dump_version, feed_restore_tool, is_tar_archive, is_transient_dump_error,
+        parse_dump_version, scan_sql_dump, stream_tool_output, validate_charset_name,
+        with_connect_timeout, ConnectionTimedOut, CAPTURED_HEADER_SIZE,
+    };
+
+    #[test]
+    fn test_transient_dump_errors() {
+        assert!(is_transient_dump_error(&anyhow!(
+            "mysqldump failed: Lock wait timeout exceeded; try restarting transaction"
+        )));
+        assert!(is_transient_dump_error(&anyhow!(
+            "pg_dump failed: server closed the connection unexpectedly"
+        )));
+
+        assert!(!is_transient_dump_error(&anyhow!(
+            "mysqldump failed: Access denied for user 'backup'@'%'"
+        )));
+        assert!(!is_transient_dump_error(&anyhow!(
+            "pg_dump failed: FATAL: password authentication failed for user \"backup\""
+        )));
+    }
+
+    #[test]
+    fn test_dump_version_check() {
+        let pg_header = b"--\n-- PostgreSQL database dump\n--\n\n-- Dumped from database version 15.4 (Debian 15.4-1)\n-- Dumped by pg_dump version 16.2\n";
+        let mysql_header = b"-- MySQL dump 10.13  Distrib 9.1.0, for Linux (x86_64)\n--\n-- Server version\t8.0.36\n";
+
+        assert_eq!(
+            parse_dump_version(pg_header, "-- Dumped from database version"),
+            Some((15, 4))
+        );
+        assert_eq!(
+            parse_dump_version(mysql_header, "-- Server version"),
+            Some((8, 0))
+        );
+        assert_eq!(
+            parse_dump_version(b"SELECT 1;\n", "-- Server version"),
+            None
+        );
+
+        assert!(check_dump_version("PostgreSQL", "15", "15", false).is_ok());
+        assert!(check_dump_version("PostgreSQL", "15", "16", false).is_err());
+        assert!(check_dump_version("PostgreSQL", "15", "16", true).is_ok());
+    }
+
+    #[test]
+    fn test_validate_charset_name() {
+        for name in [
+            "utf8mb4",
+            "utf8mb4_0900_ai_ci",
+            "UTF8",
+            "en_US.UTF-8",
+            "de_DE@euro",
+        ] {
+            assert!(validate_charset_name(name).is_ok(), "{}", name);
+        }
+
+        // They end up unquoted in CREATE DATABASE (MySQL) or in a string literal (PostgreSQL)
+        for name in [
+            "",
+            "utf8mb4; DROP DATABASE shop",
+            "UTF8' TEMPLATE template1",
+            "utf8 ",
+        ] {
+            assert!(validate_charset_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_scan_sql_dump() {
+        let pg_dump = b"SET statement_timeout = 0;\nCREATE SCHEMA sales;\nCREATE TABLE public.orders (\n    id integer NOT NULL\n);\nCOPY public.orders (id) FROM stdin;\n1\n\\.\nCREATE INDEX orders_idx ON public.orders USING btree (id);\n";
+        let summary = scan_sql_dump(&pg_dump[..], 16).expect("Failed to scan dump");
+        assert_eq!(summary.tables, vec!["public.orders"]);
+        assert_eq!(summary.object_count, 3);
+
+        let mysql_dump = b"DROP TABLE IF EXISTS `orders`;\nCREATE TABLE `orders` (\n  `id` int NOT NULL\n) ENGINE=InnoDB;\nINSERT INTO `orders` VALUES (1),(2);\n";
+        let summary = scan_sql_dump(&mysql_dump[..], 4096).expect("Failed to scan dump");
+        assert_eq!(summary.tables, vec!["orders"]);
+        assert_eq!(summary.object_count, 1);
+    }
+
+    #[test]
+    fn test_is_tar_archive() {
+        let mut archive = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "toc.dat", &b"PGDM"[..])
+            .expect("Failed to append entry");
+        let archive = archive.into_inner().expect("Failed to finish archive");
+
+        assert!(is_tar_archive(&archive));
+        assert!(!is_tar_archive(
+            b"--\n-- PostgreSQL database dump\n--\n\nSET statement_timeout = 0;\n"
+        ));
+        assert!(!is_tar_archive(&[0u8; 100]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stream_tool_output() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg("head -c 1000000 /dev/zero; echo 'still running' >&2");
+
+        let mut output = Vec::new();
+        let mut header = Vec::new();
+        stream_tool_output(cmd, "sh", &mut output, 4096, None, Some(&mut header))
+            .await
+            .expect("Failed to stream tool output");
+
+        assert_eq!(output.len(), 1_000_000);
+        assert_eq!(header.len(), CAPTURED_HEADER_SIZE);
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg("echo 'Lock wait timeout exceeded' >&2; exit 2");
+
+        let error = stream_tool_output(cmd, "sh", &mut Vec::new(), 4096, None, None)
+            .await
+            .expect_err("Failing tool should return an error");
+
+        assert!(is_transient_dump_error(&error));
+    }
+
+    /// Restore tools are spawned directly with the dump on their stdin, so neither a tool
+    /// installed under a path with a space nor shell syntax in the dump is interpreted
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_feed_restore_tool_without_shell() {
+        let dir = tempfile::tempdir().unwrap();
+        let tools_dir = dir.path().join("dbkp tools");
+        std::fs::create_dir(&tools_dir).unwrap();
+        let tool = tools_dir.join("mariadb client");
+        std::os::unix::fs::symlink("/bin/cat", &tool).unwrap();
+
+        let dump = b"-- MariaDB dump\nINSERT INTO t VALUES ('$(touch pwned)', '; exit 1');\n";
+        let mut buffer = vec![0u8; 16];
+        buffer.copy_from_slice(&dump[..16]);
+
+        let child = Command::new(&tool)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to spawn the tool");
+        let output = feed_restore_tool(child, "mariadb", &mut &dump[16..], &mut buffer, 16)
+            .await
+            .expect("Failed to feed the tool");
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, dump);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        let timeout = Duration::from_millis(50);
+
+        let error = with_connect_timeout(timeout, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert!(error.downcast_ref::<ConnectionTimedOut>().is_some());
+
+        // sqlx giving up on the pool first means the same
+        let error =
+            with_connect_timeout(timeout, async { Err::<(), _>(sqlx::Error::PoolTimedOut) })
+                .await
+                .unwrap_err();
+        assert!(error.downcast_ref::<ConnectionTimedOut>().is_some());
+
+        let error = with_connect_timeout(timeout, async { Err::<(), _>(sqlx::Error::RowNotFound) })
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<ConnectionTimedOut>().is_none());
+    }
 }
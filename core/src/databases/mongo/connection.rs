@@ -0,0 +1,478 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::Stdio,
+};
+
+use crate::databases::{
+    connection_test_error, feed_restore_tool, read_dump_header, save_tool_log,
+    ssh_tunnel::{SshRemoteConfig, SshTunnel},
+    stream_tool_output,
+    version::{Version, VersionTrait},
+    BackupMode, BackupOptions, BackupScope, BackupSummary, ConnectionTimedOut, DatabaseConfig,
+    DatabaseConnectionTrait, DatabaseMetadata, DumpSummary, RestoreOptions, RowCounts,
+    UtilitiesTrait,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, Document},
+    error::ErrorKind,
+    options::{ClientOptions, Credential, ServerAddress},
+    Client,
+};
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+
+use super::{utilities::MongoDbUtilities, version::MongoDbVersion};
+
+/// Users are looked up in the `admin` database, where backup users normally live
+const AUTH_SOURCE: &str = "admin";
+
+/// Every archive written by `mongodump --archive` starts with this magic number
+const ARCHIVE_MAGIC: [u8; 4] = [0x6d, 0xe2, 0x99, 0x81];
+
+/// Databases of the server itself, left out by `list_databases`
+const SYSTEM_DATABASES: [&str; 3] = ["admin", "config", "local"];
+
+pub struct MongoConnection {
+    pub config: DatabaseConfig,
+    pub client: Client,
+    _ssh_tunnel: Option<SshTunnel>,
+}
+
+impl MongoConnection {
+    pub async fn new(config: DatabaseConfig) -> Result<Self> {
+        let mut config = config.clone();
+        let ssh_tunnel = match &config.ssh_tunnel {
+            Some(ssh_config) => {
+                let tunnel = SshTunnel::new(
+                    ssh_config.clone(),
+                    SshRemoteConfig {
+                        host: config.host.clone(),
+                        port: config.port,
+                    },
+                )?;
+
+                Some(tunnel)
+            }
+            None => None,
+        };
+
+        if let Some(ssh_tunnel) = &ssh_tunnel {
+            config.host = "localhost".into();
+            config.port = ssh_tunnel.local_port;
+        }
+
+        let mut client_options = ClientOptions::default();
+        client_options.hosts = vec![ServerAddress::Tcp {
+            host: config.host.clone(),
+            port: Some(config.port),
+        }];
+        // The other members of a replica set may not be reachable from here, e.g.
+        // through an SSH tunnel, the tools are pointed at this server only as well
+        client_options.direct_connection = Some(true);
+        client_options.connect_timeout = Some(config.connect_timeout());
+        client_options.server_selection_timeout = Some(config.connect_timeout());
+
+        if !config.username.is_empty() {
+            let mut credential = Credential::default();
+            credential.username = Some(config.username.clone());
+            credential.password = config.password.clone();
+            credential.source = Some(AUTH_SOURCE.to_string());
+            client_options.credential = Some(credential);
+        }
+
+        let client = Client::with_options(client_options)
+            .map_err(|e| anyhow!("Failed to create MongoDB client: {}", e))?;
+
+        Ok(Self {
+            config,
+            client,
+            _ssh_tunnel: ssh_tunnel,
+        })
+    }
+
+    /// The driver connects lazily, so this is where an unreachable server shows up
+    async fn run_command(&self, database: &str, command: Document) -> Result<Document> {
+        let timeout = self.config.connect_timeout();
+
+        let database = self.client.database(database);
+
+        match tokio::time::timeout(timeout, async { database.run_command(command).await }).await {
+            Ok(Ok(document)) => Ok(document),
+            Ok(Err(e)) if matches!(*e.kind, ErrorKind::ServerSelection { .. }) => {
+                Err(anyhow::Error::new(ConnectionTimedOut(timeout)))
+            }
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(anyhow::Error::new(ConnectionTimedOut(timeout))),
+        }
+    }
+
+    async fn get_version(&self) -> Result<MongoDbVersion> {
+        match self.get_metadata().await?.version {
+            Version::MongoDB(version) => Ok(version),
+            _ => Err(anyhow!("Wrong version type")),
+        }
+    }
+
+    /// A tool with the connection arguments. The password goes through a config file
+    /// rather than the command line, which other users could read; the file has to be
+    /// kept until the tool has exited.
+    async fn get_command(&self, bin_name: &str) -> Result<(Command, Option<NamedTempFile>)> {
+        let version = self.get_version().await?;
+        let utilities = MongoDbUtilities::new(version);
+        let mut cmd = utilities.get_command(bin_name).await?;
+
+        cmd.arg(format!("--host={}", self.config.host))
+            .arg(format!("--port={}", self.config.port));
+
+        if !self.config.username.is_empty() {
+            cmd.arg(format!("--username={}", self.config.username))
+                .arg(format!("--authenticationDatabase={}", AUTH_SOURCE));
+        }
+
+        let config_file = match &self.config.password {
+            Some(password) => {
+                let mut file =
+                    NamedTempFile::new().context("Failed to create the tool config file")?;
+                writeln!(file, "password: {}", serde_json::to_string(password)?)
+                    .context("Failed to write the tool config file")?;
+                cmd.arg(format!("--config={}", file.path().display()));
+                Some(file)
+            }
+            None => None,
+        };
+
+        Ok((cmd, config_file))
+    }
+
+    /// `--nsInclude` for the configured database, renamed when restoring somewhere else
+    fn namespace_args(&self, options: &RestoreOptions) -> Vec<String> {
+        let source = &self.config.database;
+        let mut args = vec![format!("--nsInclude={}.*", source)];
+
+        if let Some(target) = &options.target_database {
+            args.push(format!("--nsFrom={}.*", source));
+            args.push(format!("--nsTo={}.*", target));
+        }
+
+        args
+    }
+
+    fn check_restore_options(options: &RestoreOptions) -> Result<()> {
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        if options.single_transaction {
+            return Err(anyhow!(
+                "Single transaction restores are not supported for MongoDB"
+            ));
+        }
+
+        if options.charset.is_some() || options.collation.is_some() {
+            return Err(anyhow!(
+                "Charset and collation options don't apply to MongoDB"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Runs mongorestore on an archive, reading it from `reader` after the header
+    /// already in `buffer`
+    async fn run_mongorestore(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        buffer: &mut [u8],
+        header_length: usize,
+        extra_args: Vec<String>,
+        options: &RestoreOptions,
+    ) -> Result<()> {
+        if !buffer[..header_length].starts_with(&ARCHIVE_MAGIC) {
+            return Err(anyhow!("Backup is not a mongodump archive"));
+        }
+
+        let (mut cmd, _config_file) = self.get_command("mongorestore").await?;
+
+        cmd.arg("--archive")
+            .args(self.namespace_args(options))
+            .args(extra_args);
+
+        let child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to start mongorestore")?;
+
+        let output =
+            feed_restore_tool(child, "mongorestore", reader, buffer, header_length).await?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "mongorestore", &output.stderr);
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let exit_code = output.status.code().unwrap_or(-1);
+
+            return Err(anyhow!(
+                "mongorestore failed with exit code {}{}.\nError: {}",
+                exit_code,
+                log_note,
+                stderr.trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Arguments shared by mongorestore restores from an archive or a directory
+fn restore_args(options: &RestoreOptions) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if options.drop_database_first {
+        args.push("--drop".to_string());
+    }
+
+    if let Some(jobs) = options.jobs {
+        args.push(format!("--numParallelCollections={}", jobs.max(1)));
+    }
+
+    args.extend(options.extra_restore_args.iter().cloned());
+    args
+}
+
+#[async_trait]
+impl DatabaseConnectionTrait for MongoConnection {
+    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
+        let build_info = self
+            .run_command("admin", doc! { "buildInfo": 1 })
+            .await
+            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+
+        let version = build_info
+            .get_str("version")
+            .ok()
+            .and_then(MongoDbVersion::parse_string_version)
+            .ok_or_else(|| anyhow!("Failed to parse MongoDB version string"))?;
+
+        Ok(DatabaseMetadata {
+            version: Version::MongoDB(version),
+        })
+    }
+
+    async fn test(&self) -> Result<bool> {
+        self.run_command("admin", doc! { "ping": 1 })
+            .await
+            .map(|_| true)
+            .map_err(connection_test_error)
+    }
+
+    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<BackupSummary> {
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        if !matches!(options.mode, BackupMode::Full) {
+            return Err(anyhow!(
+                "Incremental backups are only supported for MySQL and PostgreSQL"
+            ));
+        }
+
+        if options.snapshot.is_some() {
+            return Err(anyhow!(
+                "Exported snapshots are only supported for PostgreSQL"
+            ));
+        }
+
+        if !options.include_tables.is_empty() || !options.exclude_tables.is_empty() {
+            return Err(anyhow!("Table filters are not supported for MongoDB"));
+        }
+
+        if options.scope != BackupScope::Full {
+            return Err(anyhow!(
+                "Schema-only and data-only backups are not supported for MongoDB"
+            ));
+        }
+
+        let (mut cmd, _config_file) = self.get_command("mongodump").await?;
+
+        cmd.arg(format!("--db={}", self.config.database))
+            .arg("--archive");
+
+        if let Some(jobs) = options.jobs {
+            cmd.arg(format!("--numParallelCollections={}", jobs.max(1)));
+        }
+
+        cmd.args(&options.extra_dump_args);
+
+        stream_tool_output(
+            cmd,
+            "mongodump",
+            writer,
+            options.buffer_size(),
+            options.tool_log.as_deref(),
+            None,
+        )
+        .await?;
+
+        Ok(BackupSummary::default())
+    }
+
+    async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
+        self.restore_with_options(
+            reader,
+            RestoreOptions {
+                drop_database_first: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn restore_with_options(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<()> {
+        Self::check_restore_options(&options)?;
+
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        self.run_mongorestore(
+            reader,
+            &mut buffer,
+            header_length,
+            restore_args(&options),
+            &options,
+        )
+        .await
+    }
+
+    async fn run_query(&self, query: &str) -> Result<String> {
+        // There's no shell among the database tools, queries are database commands
+        // written as JSON, e.g. `{"count": "users"}`
+        let command: Document = serde_json::from_str(query)
+            .map_err(|e| anyhow!("MongoDB queries must be a JSON command document: {}", e))?;
+
+        let result = self
+            .run_command(&self.config.database, command)
+            .await
+            .map_err(|e| anyhow!("Query failed: {}", e))?;
+
+        Ok(serde_json::to_string(&result)?)
+    }
+
+    async fn table_row_counts(&self, database: Option<&str>) -> Result<RowCounts> {
+        let database = self
+            .client
+            .database(database.unwrap_or(&self.config.database));
+        let mut counts = RowCounts::new();
+
+        // Views are computed from collections, they'd be counted twice
+        let collections = database
+            .list_collection_names()
+            .filter(doc! { "type": "collection" })
+            .await
+            .map_err(|e| anyhow!("Failed to list collections: {}", e))?;
+
+        for name in collections {
+            let count = database
+                .collection::<Document>(&name)
+                .count_documents(doc! {})
+                .await
+                .map_err(|e| anyhow!("Failed to count documents of {}: {}", name, e))?;
+            counts.insert(name, count);
+        }
+
+        Ok(counts)
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let result = self
+            .run_command("admin", doc! { "listDatabases": 1, "nameOnly": true })
+            .await
+            .map_err(|e| anyhow!("Failed to list databases: {}", e))?;
+
+        let mut databases: Vec<String> = result
+            .get_array("databases")?
+            .iter()
+            .filter_map(|database| database.as_document()?.get_str("name").ok())
+            .filter(|name| !SYSTEM_DATABASES.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+        databases.sort();
+
+        Ok(databases)
+    }
+
+    async fn restore_from_directory(&self, path: &Path, options: RestoreOptions) -> Result<()> {
+        Self::check_restore_options(&options)?;
+
+        if !path.is_dir() {
+            return Err(anyhow!("{} is not a directory", path.display()));
+        }
+
+        let (mut cmd, _config_file) = self.get_command("mongorestore").await?;
+
+        cmd.args(self.namespace_args(&options))
+            .args(restore_args(&options))
+            .arg(format!("--dir={}", path.display()));
+
+        let output = cmd
+            .output()
+            .await
+            .context("Failed to execute mongorestore")?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "mongorestore", &output.stderr);
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let exit_code = output.status.code().unwrap_or(-1);
+
+            return Err(anyhow!(
+                "mongorestore failed with exit code {}{}.\nError: {}",
+                exit_code,
+                log_note,
+                stderr.trim()
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn inspect_dump(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<DumpSummary> {
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        // Reads the whole archive without writing anything. The archive format keeps
+        // no table of contents that could be summarized without the tools.
+        self.run_mongorestore(
+            reader,
+            &mut buffer,
+            header_length,
+            vec!["--dryRun".to_string()],
+            &options,
+        )
+        .await?;
+
+        Ok(DumpSummary::default())
+    }
+}
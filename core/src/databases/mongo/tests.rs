@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod mongodb_connection_tests {
+    use std::env;
+
+    use crate::databases::{
+        mongo::{connection::MongoConnection, version::MongoDbVersion},
+        version::{Version, VersionTrait},
+        ConnectionType, DatabaseConfig, DatabaseConnectionTrait,
+    };
+    use anyhow::Result;
+    use dotenv::dotenv;
+    use mongodb::bson::{doc, Document};
+
+    fn get_mongodb_config() -> Result<DatabaseConfig> {
+        dotenv().ok();
+
+        let port: u16 = env::var("MONGODB_PORT").unwrap_or("0".into()).parse()?;
+
+        let config = DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::MongoDB,
+            host: env::var("MONGODB_HOST").unwrap_or_default(),
+            password: env::var("MONGODB_PASSWORD").ok(),
+            username: env::var("MONGODB_USERNAME").unwrap_or_default(),
+            database: env::var("MONGODB_NAME").unwrap_or_default(),
+            port,
+            ssh_tunnel: None,
+            connect_timeout: None,
+        };
+
+        Ok(config)
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let version = MongoDbVersion::parse_string_version("7.0.12").unwrap();
+        assert_eq!((version.major, version.minor), (7, 0));
+        assert_eq!(version.to_string(), "7");
+
+        assert!(MongoDbVersion::parse_string_version("unknown").is_none());
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn test_01_mongodb_connection() {
+        let config = get_mongodb_config().expect("Failed to get config");
+        let connection = MongoConnection::new(config)
+            .await
+            .expect("Failed to get connection");
+
+        let is_connected = connection.test().await.expect("Failed to test connection");
+        assert!(is_connected);
+
+        let metadata = connection
+            .get_metadata()
+            .await
+            .expect("Failed to get metadata");
+
+        assert!(matches!(metadata.version, Version::MongoDB(_)));
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn test_02_mongodb_backup_and_restore() {
+        let collection_name = format!("test_restore_{}", chrono::Utc::now().timestamp());
+        let config = get_mongodb_config().expect("Failed to get config");
+
+        let connection = MongoConnection::new(config.clone())
+            .await
+            .expect("Failed to get connection");
+
+        let collection = connection
+            .client
+            .database(&config.database)
+            .collection::<Document>(&collection_name);
+
+        collection
+            .insert_many(vec![
+                doc! { "name": "test1", "value": 100 },
+                doc! { "name": "test2", "value": 200 },
+            ])
+            .await
+            .expect("Failed to insert test data");
+
+        let mut backup_buffer = Vec::new();
+        connection
+            .backup(&mut backup_buffer)
+            .await
+            .expect("Failed to backup database");
+
+        assert!(!backup_buffer.is_empty(), "Backup should not be empty");
+
+        collection
+            .update_one(doc! { "name": "test1" }, doc! { "$set": { "value": 999 } })
+            .await
+            .expect("Failed to modify test data");
+
+        connection
+            .restore(&mut backup_buffer.as_slice())
+            .await
+            .expect("Failed to restore database");
+
+        let restored = collection
+            .find_one(doc! { "name": "test1" })
+            .await
+            .expect("Failed to fetch test data")
+            .expect("Restored document should exist");
+
+        assert_eq!(restored.get_i32("value").unwrap(), 100);
+
+        collection
+            .drop()
+            .await
+            .expect("Failed to drop test collection");
+    }
+}
@@ -0,0 +1,77 @@
+use std::{env, path::PathBuf};
+
+use crate::{
+    archives::installer::ArchiveInstaller,
+    common::get_binaries_base_path,
+    databases::{version::Version, UtilitiesTrait},
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::debug;
+use tokio::process::Command;
+
+use super::version::MongoDbVersion;
+
+pub struct MongoDbUtilities {
+    version: MongoDbVersion,
+}
+
+impl MongoDbUtilities {
+    pub fn new(version: MongoDbVersion) -> Self {
+        MongoDbUtilities { version }
+    }
+
+    async fn install(&self) -> Result<()> {
+        let archives_installer = ArchiveInstaller::new(Version::MongoDB(self.version.clone()));
+        let path = archives_installer.download_and_install().await?;
+
+        debug!(
+            "Successfully installed MongoDB utilities at {}",
+            path.display()
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UtilitiesTrait for MongoDbUtilities {
+    fn get_base_path(&self) -> Result<PathBuf> {
+        let path = get_binaries_base_path(&Version::MongoDB(self.version.clone())).join("bin");
+        Ok(path)
+    }
+
+    async fn get_command(&self, bin_name: &str) -> Result<Command> {
+        let base_path = self.get_base_path()?;
+        let bin_path = base_path.join(bin_name);
+
+        if !bin_path.exists() {
+            debug!("MongoDB utilities not found, attempting to download and install");
+
+            // The database tools are released separately from the server and are often
+            // installed system-wide already
+            if let Err(e) = self.install().await {
+                return match find_in_path(bin_name) {
+                    Some(path) => {
+                        debug!("Using {} from PATH", path.display());
+                        Ok(Command::new(path))
+                    }
+                    None => Err(e),
+                };
+            }
+
+            if !bin_path.exists() {
+                return Err(anyhow!("Binary {} not found after installation", bin_name));
+            }
+        }
+
+        let command = Command::new(&bin_path);
+        Ok(command)
+    }
+}
+
+fn find_in_path(bin_name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(bin_name))
+        .find(|path| path.is_file())
+}
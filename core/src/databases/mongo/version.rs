@@ -0,0 +1,49 @@
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::databases::version::VersionTrait;
+
+/// Server version as reported by `buildInfo`, e.g. `7.0.12`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MongoDbVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl VersionTrait for MongoDbVersion {
+    fn from_str(string: &str) -> Option<Self> {
+        let res: Vec<&str> = string.split(".").collect();
+
+        let major = res.first()?.parse::<u16>().ok()?;
+        let minor = res.get(1)?.parse::<u16>().ok()?;
+
+        Some(MongoDbVersion { major, minor })
+    }
+
+    fn parse_string_version(version_string: &str) -> Option<Self> {
+        let regex = Regex::new(r"(\d+)\.(\d+)").ok()?;
+        let captures = regex.captures(version_string)?;
+
+        let major = captures.get(1)?.as_str().parse::<u16>().ok()?;
+        let minor = captures.get(2)?.as_str().parse::<u16>().ok()?;
+
+        Some(MongoDbVersion { major, minor })
+    }
+}
+
+impl fmt::Display for MongoDbVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.major)
+    }
+}
+
+impl FromStr for MongoDbVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        <MongoDbVersion as VersionTrait>::from_str(s)
+            .ok_or_else(|| format!("Unsupported MongoDB version: {}", s))
+    }
+}
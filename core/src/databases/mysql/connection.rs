@@ -1,26 +1,39 @@
 use std::{
-    io::{Read, Write},
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
     process::Stdio,
-    time::Duration,
 };
 
 use crate::databases::{
+    check_dump_version, connection_test_error, feed_restore_tool, parse_dump_version,
+    read_dump_header, save_tool_log, scan_sql_dump, stream_tool_output,
+    tables::select_tables,
+    validate_charset_name,
     version::{Version, VersionTrait},
-    DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, RestoreOptions, UtilitiesTrait,
+    with_connect_timeout, BackupMode, BackupOptions, BackupScope, BackupSummary, BinlogPosition,
+    ConnectionTimedOut, DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, DumpSummary,
+    RestoreOptions, RowCounts, UtilitiesTrait,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use log::warn;
+use regex::Regex;
 use sqlx::{
-    mysql::{MySqlConnectOptions, MySqlPoolOptions},
+    mysql::{MySqlConnectOptions, MySqlDatabaseError, MySqlPoolOptions},
     MySql, Pool,
 };
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    process::Command,
-};
+use tokio::process::Command;
 
 use super::{utilities::MySqlUtilities, version::MySqlVersion};
 
+const DEFAULT_CHARSET: &str = "utf8mb4";
+const DEFAULT_COLLATION: &str = "utf8mb4_unicode_ci";
+const ER_BAD_DB_ERROR: u16 = 1049;
+
+/// Databases of the server itself, left out by `list_databases`
+const SYSTEM_DATABASES: [&str; 4] = ["information_schema", "mysql", "performance_schema", "sys"];
+
 pub struct MySqlConnection {
     pub config: DatabaseConfig,
     pub pool: Pool<MySql>,
@@ -28,35 +41,76 @@ pub struct MySqlConnection {
 
 impl MySqlConnection {
     pub async fn new(config: DatabaseConfig) -> Result<Self> {
+        let pool = match Self::connect(&config, Some(&config.database)).await {
+            Ok(pool) => pool,
+            Err(sqlx::Error::Database(e))
+                if e.try_downcast_ref::<MySqlDatabaseError>()
+                    .is_some_and(|e| e.number() == ER_BAD_DB_ERROR) =>
+            {
+                // The database doesn't exist yet, e.g. when restoring to a fresh server
+                with_connect_timeout(config.connect_timeout(), Self::connect(&config, None)).await?
+            }
+            Err(sqlx::Error::PoolTimedOut) => {
+                return Err(ConnectionTimedOut(config.connect_timeout()).into())
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { config, pool })
+    }
+
+    async fn connect(
+        config: &DatabaseConfig,
+        database: Option<&str>,
+    ) -> Result<Pool<MySql>, sqlx::Error> {
         let mut connect_options = MySqlConnectOptions::new()
             .host(&config.host)
             .username(&config.username)
-            .database(&config.database)
             .port(config.port);
 
+        if let Some(database) = database {
+            connect_options = connect_options.database(database);
+        }
+
         connect_options = match &config.password {
             Some(password) => connect_options.password(&password),
             None => connect_options,
         };
 
-        let pool = MySqlPoolOptions::new()
+        MySqlPoolOptions::new()
             .max_connections(5)
-            .acquire_timeout(Duration::from_secs(5))
+            .acquire_timeout(config.connect_timeout())
             .connect_with(connect_options)
-            .await?;
+            .await
+    }
 
-        Ok(Self { config, pool })
+    /// The same server with `database` as the target of commands
+    fn with_database(&self, database: &str) -> Self {
+        let mut config = self.config.clone();
+        config.database = database.to_string();
+
+        Self {
+            config,
+            pool: self.pool.clone(),
+        }
     }
 
-    async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
-        let metadata = self.get_metadata().await?;
+    async fn get_version(&self) -> Result<MySqlVersion> {
+        match self.get_metadata().await?.version {
+            Version::MySql(version) | Version::MariaDB(version) => Ok(version),
+            _ => Err(anyhow!("Wrong version type")),
+        }
+    }
 
-        let version = match metadata.version {
-            Version::MySql(version) => version,
-            _ => return Err(anyhow!("Wrong version type")),
-        };
+    async fn is_mariadb(&self) -> Result<bool> {
+        Ok(matches!(
+            self.get_metadata().await?.version,
+            Version::MariaDB(_)
+        ))
+    }
 
-        let utilities = MySqlUtilities::new(version);
+    async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
+        let utilities = MySqlUtilities::new(self.get_metadata().await?.version);
         let mut cmd = utilities.get_command(bin_name).await?;
 
         if let Some(password) = &self.config.password {
@@ -77,6 +131,327 @@ impl MySqlConnection {
 
         Ok(cmd)
     }
+
+    async fn execute_statement(&self, statement: &str) -> Result<String> {
+        let mut cmd = self.get_base_command("mysql").await?;
+
+        cmd.arg(format!("--host={}", self.config.host))
+            .arg(format!("--port={}", self.config.port))
+            .arg(format!("--user={}", self.config.username))
+            .arg("--protocol=TCP")
+            .arg("--skip-column-names")
+            .arg("--batch")
+            .arg("-e")
+            .arg(statement);
+
+        let output = cmd
+            .output()
+            .await
+            .context("Failed to execute mysql statement")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let exit_code = output.status.code().unwrap_or(-1);
+
+            return Err(anyhow!(
+                "mysql statement failed with exit code {}.\nError details: {}",
+                exit_code,
+                stderr.trim()
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    async fn prepare_restore(&self, options: &RestoreOptions) -> Result<()> {
+        let kill_statements = self
+            .execute_statement(&format!(
+                "SELECT CONCAT('KILL ', id, ';') FROM information_schema.processlist 
+                WHERE user = '{}' AND db = '{}' AND id != CONNECTION_ID();",
+                self.config.username, self.config.database
+            ))
+            .await
+            .context("Failed to list database connections")?;
+
+        for kill_statement in kill_statements.lines().filter(|line| !line.is_empty()) {
+            // The connection may already be gone by the time we get to it
+            if let Err(e) = self.execute_statement(kill_statement).await {
+                warn!("Failed to terminate connection: {}", e);
+            }
+        }
+
+        let database = quote_identifier(&self.config.database);
+
+        // Preserve the existing charset unless one was requested explicitly
+        let (charset, collation) = match (&options.charset, &options.collation) {
+            (None, None) => match self.get_database_charset().await? {
+                Some((charset, collation)) => (charset, Some(collation)),
+                None => (
+                    DEFAULT_CHARSET.to_string(),
+                    Some(DEFAULT_COLLATION.to_string()),
+                ),
+            },
+            (charset, collation) => (
+                charset
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_CHARSET.to_string()),
+                collation.clone(),
+            ),
+        };
+
+        validate_charset_name(&charset)?;
+        let mut create_statement = format!(
+            "CREATE DATABASE IF NOT EXISTS {} CHARACTER SET {}",
+            database, charset
+        );
+
+        if let Some(collation) = collation {
+            validate_charset_name(&collation)?;
+            create_statement.push_str(&format!(" COLLATE {}", collation));
+        }
+
+        if options.drop_database_first {
+            self.execute_statement(&format!("DROP DATABASE IF EXISTS {};", database))
+                .await
+                .context("Failed to drop database")?;
+        }
+
+        self.execute_statement(&format!("{};", create_statement))
+            .await
+            .context("Failed to create database")?;
+
+        Ok(())
+    }
+
+    async fn get_database_charset(&self) -> Result<Option<(String, String)>> {
+        let output = self
+            .execute_statement(&format!(
+                "SELECT DEFAULT_CHARACTER_SET_NAME, DEFAULT_COLLATION_NAME
+                FROM information_schema.SCHEMATA WHERE SCHEMA_NAME = '{}';",
+                self.config.database.replace('\'', "''")
+            ))
+            .await
+            .context("Failed to read database charset")?;
+
+        Ok(output.lines().next().and_then(|line| {
+            let (charset, collation) = line.split_once('\t')?;
+            Some((charset.to_string(), collation.to_string()))
+        }))
+    }
+
+    async fn current_binlog_position(&self) -> Result<BinlogPosition> {
+        let version = self.get_version().await?;
+
+        // SHOW MASTER STATUS was replaced in MySQL 8.2 and removed in 8.4, MariaDB still
+        // supports it
+        let statement = if (version.major, version.minor) >= (8, 2) && !self.is_mariadb().await? {
+            "SHOW BINARY LOG STATUS"
+        } else {
+            "SHOW MASTER STATUS"
+        };
+
+        let output = self.execute_statement(statement).await?;
+        let mut columns = output.lines().next().unwrap_or_default().split('\t');
+
+        match (
+            columns.next(),
+            columns.next().and_then(|position| position.parse().ok()),
+        ) {
+            (Some(file), Some(position)) if !file.is_empty() => Ok(BinlogPosition {
+                file: file.to_string(),
+                position,
+            }),
+            _ => Err(anyhow!("Binary logging is not enabled on the MySQL server")),
+        }
+    }
+
+    /// Streams the binlog events for this database between `since` and the current
+    /// position as SQL, using `mysqlbinlog --read-from-remote-server`. The output can be
+    /// replayed with the `mysql` client on top of the base backup.
+    async fn backup_binlog(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        since: &BinlogPosition,
+        options: &BackupOptions,
+    ) -> Result<BinlogPosition> {
+        let until = self.current_binlog_position().await?;
+
+        let binary_logs = self.execute_statement("SHOW BINARY LOGS").await?;
+        let files: Vec<&str> = binary_logs
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .filter(|file| *file >= since.file.as_str() && *file <= until.file.as_str())
+            .collect();
+
+        if files.first() != Some(&since.file.as_str()) {
+            return Err(anyhow!(
+                "Binary log {} is no longer available on the server, take a new base backup",
+                since.file
+            ));
+        }
+
+        let mut cmd = self.get_base_command("mysqlbinlog").await?;
+
+        cmd.arg("--read-from-remote-server")
+            .arg(format!("--host={}", self.config.host))
+            .arg(format!("--port={}", self.config.port))
+            .arg(format!("--user={}", self.config.username))
+            .arg("--protocol=TCP")
+            .arg(format!("--database={}", self.config.database))
+            .arg("--skip-gtids")
+            .arg(format!("--start-position={}", since.position))
+            .arg(format!("--stop-position={}", until.position))
+            .args(&files);
+
+        stream_tool_output(
+            cmd,
+            "mysqlbinlog",
+            writer,
+            options.buffer_size(),
+            options.tool_log.as_deref(),
+            None,
+        )
+        .await?;
+
+        Ok(until)
+    }
+
+    /// Table names to dump and `--ignore-table` arguments for the table filters of
+    /// `options`. mysqldump has no patterns of its own, so they are expanded here.
+    async fn table_args(&self, options: &BackupOptions) -> Result<Vec<String>> {
+        if options.include_tables.is_empty() && options.exclude_tables.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tables: Vec<String> = self
+            .run_query("SHOW TABLES")
+            .await?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let selection = select_tables(&tables, &options.include_tables, &options.exclude_tables)?;
+
+        let mut args = selection.include;
+        args.extend(
+            selection
+                .exclude
+                .iter()
+                .map(|table| format!("--ignore-table={}.{}", self.config.database, table)),
+        );
+
+        Ok(args)
+    }
+
+    /// Dumps every table into its own schema (`.sql`) and data (`.txt`) file using
+    /// `mysqldump --tab`, then archives the directory as a single tar stream. The data
+    /// files are written by the server itself, so this mode requires the server to run
+    /// on the same host and the user to hold the `FILE` privilege.
+    async fn backup_per_table(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: &BackupOptions,
+    ) -> Result<()> {
+        let dump_dir = create_tab_directory()?;
+
+        let mut cmd = self.get_command("mysqldump").await?;
+
+        cmd.arg(format!("--tab={}", dump_dir.path().display()))
+            .arg("--single-transaction")
+            .arg("--skip-lock-tables")
+            .arg("--no-tablespaces")
+            .arg("--skip-triggers")
+            .args(self.table_args(options).await?);
+
+        let output = cmd.output().await.context("Failed to execute mysqldump")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "mysqldump failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let mut archive = tar::Builder::new(writer);
+        archive
+            .append_dir_all(".", dump_dir.path())
+            .context("Failed to archive per-table files")?;
+        archive
+            .finish()
+            .context("Failed to finalize per-table archive")?;
+
+        Ok(())
+    }
+
+    /// Restores an archive produced by `backup_per_table`: schema files are replayed
+    /// with `mysql` first, then data files are bulk loaded with `mysqlimport --local`.
+    async fn restore_per_table(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<()> {
+        let dump_dir = tempfile::tempdir().context("Failed to create restore directory")?;
+
+        tar::Archive::new(reader)
+            .unpack(dump_dir.path())
+            .context("Failed to extract per-table archive")?;
+
+        let mut schema_files: Vec<PathBuf> = Vec::new();
+        let mut data_files: Vec<PathBuf> = Vec::new();
+
+        for entry in fs::read_dir(dump_dir.path())? {
+            let path = entry?.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("sql") => schema_files.push(path),
+                Some("txt") => data_files.push(path),
+                _ => {}
+            }
+        }
+
+        schema_files.sort();
+        data_files.sort();
+
+        self.prepare_restore(&options).await?;
+
+        for schema_file in &schema_files {
+            let mut cmd = self.get_command("mysql").await?;
+            let output = cmd
+                .stdin(Stdio::from(fs::File::open(schema_file)?))
+                .output()
+                .await
+                .context("Failed to execute mysql")?;
+
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Failed to restore schema from {}: {}",
+                    schema_file.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+
+        if data_files.is_empty() {
+            return Ok(());
+        }
+
+        let mut cmd = self.get_command("mysqlimport").await?;
+        cmd.arg("--local").args(&data_files);
+
+        let output = cmd
+            .output()
+            .await
+            .context("Failed to execute mysqlimport")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "mysqlimport failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -87,25 +462,80 @@ impl DatabaseConnectionTrait for MySqlConnection {
             .await
             .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
 
-        let version = match MySqlVersion::parse_string_version(version_string.0.as_str()) {
-            Some(version) => version,
-            None => return Err(anyhow!("Failed to parse MySQL version string")),
-        };
+        let version = parse_server_version(&version_string.0)
+            .ok_or_else(|| anyhow!("Failed to parse MySQL version string"))?;
 
-        Ok(DatabaseMetadata {
-            version: Version::MySql(version),
-        })
+        Ok(DatabaseMetadata { version })
     }
 
     async fn test(&self) -> Result<bool> {
-        sqlx::query("SELECT 1")
-            .execute(&self.pool)
-            .await
-            .map(|_| true)
-            .map_err(|e| anyhow!("Connection test failed: {}", e))
+        with_connect_timeout(
+            self.config.connect_timeout(),
+            sqlx::query("SELECT 1").execute(&self.pool),
+        )
+        .await
+        .map(|_| true)
+        .map_err(connection_test_error)
     }
 
     async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<BackupSummary> {
+        if options.scope != BackupScope::Full
+            && (options.per_table_files || !matches!(options.mode, BackupMode::Full))
+        {
+            return Err(anyhow!(
+                "Schema-only and data-only backups can't be incremental or per-table"
+            ));
+        }
+
+        let record_position = match &options.mode {
+            BackupMode::Full => false,
+            BackupMode::IncrementalBase => true,
+            BackupMode::Incremental { since } => {
+                if options.per_table_files {
+                    return Err(anyhow!(
+                        "Incremental backups can't be combined with per-table files"
+                    ));
+                }
+
+                let until = self.backup_binlog(writer, since, &options).await?;
+                return Ok(BackupSummary {
+                    binlog_position: Some(until),
+                    row_counts: None,
+                });
+            }
+        };
+
+        if options.snapshot.is_some() {
+            return Err(anyhow!(
+                "Exported snapshots are only supported for PostgreSQL"
+            ));
+        }
+
+        if options.jobs.is_some() {
+            return Err(anyhow!("Parallel dumps are only supported for PostgreSQL"));
+        }
+
+        if options.per_table_files {
+            if record_position {
+                return Err(anyhow!(
+                    "Incremental backups can't be combined with per-table files"
+                ));
+            }
+
+            self.backup_per_table(writer, &options).await?;
+            return Ok(BackupSummary::default());
+        }
+
         let mut cmd = self.get_command("mysqldump").await?;
 
         cmd.arg("--opt")
@@ -117,123 +547,109 @@ impl DatabaseConnectionTrait for MySqlConnection {
             .arg("--no-tablespaces")
             .arg("--skip-triggers");
 
-        let mut child = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| anyhow!("Failed to start mysqldump: {}", e))?;
-
-        let mut stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture mysqldump stdout".to_string()))?;
-
-        let mut buffer = [0u8; 16384];
-
-        loop {
-            match stdout.read(&mut buffer).await {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    writer
-                        .write_all(&buffer[..n])
-                        .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from mysqldump: {}", e));
-                }
+        match options.scope {
+            BackupScope::Full => {}
+            BackupScope::SchemaOnly => {
+                cmd.arg("--no-data");
+            }
+            BackupScope::DataOnly => {
+                cmd.arg("--no-create-info");
             }
         }
 
-        let status = child
-            .wait()
-            .await
-            .map_err(|e| anyhow!("mysqldump process failed: {}", e))?;
-
-        if !status.success() {
-            let mut stderr = child
-                .stderr
-                .take()
-                .ok_or_else(|| anyhow!("Failed to capture mysqldump stderr".to_string()))?;
+        cmd.args(self.table_args(&options).await?);
+
+        if record_position {
+            // Writes the binlog coordinates of the dump's snapshot as a comment near the
+            // top of the output, which we pick up while streaming
+            let version = self.get_version().await?;
+            if (version.major, version.minor, version.patch) >= (8, 0, 26)
+                && !self.is_mariadb().await?
+            {
+                cmd.arg("--source-data=2");
+            } else {
+                cmd.arg("--master-data=2");
+            }
+        }
 
-            let mut error_message = String::new();
-            stderr
-                .read_to_string(&mut error_message)
-                .await
-                .map_err(|e| anyhow!("Failed to read mysqldump stderr: {}", e))?;
+        cmd.args(&options.extra_dump_args);
 
-            return Err(anyhow!("mysqldump failed: {}", error_message));
-        }
+        let mut header = Vec::new();
+        stream_tool_output(
+            cmd,
+            "mysqldump",
+            writer,
+            options.buffer_size(),
+            options.tool_log.as_deref(),
+            record_position.then_some(&mut header),
+        )
+        .await?;
+
+        let binlog_position = if record_position {
+            Some(parse_dump_binlog_position(&header).ok_or_else(|| {
+                anyhow!("mysqldump did not report a binlog position, is binary logging enabled?")
+            })?)
+        } else {
+            None
+        };
 
-        Ok(())
+        Ok(BackupSummary {
+            binlog_position,
+            row_counts: None,
+        })
     }
 
     async fn restore_with_options(
         &self,
         reader: &mut (dyn Read + Send + Unpin),
-        _options: RestoreOptions,
+        mut options: RestoreOptions,
     ) -> Result<()> {
-        let mut cmd = self.get_base_command("mysql").await?;
-
-        cmd.arg(format!("--host={}", self.config.host))
-            .arg(format!("--port={}", self.config.port))
-            .arg(format!("--user={}", self.config.username))
-            .arg("--protocol=TCP")
-            .arg("-e")
-            .arg(format!(
-                "SELECT CONCAT('KILL ', id, ';') FROM information_schema.processlist 
-                WHERE user = '{}' AND db = '{}' AND id != CONNECTION_ID();",
-                self.config.username, self.config.database
-            ));
-
-        let drop_connections_output = cmd
-            .output()
-            .await
-            .context(format!("Failed to execute connection termination command"))?;
-
-        if !drop_connections_output.status.success() {
-            let stderr = String::from_utf8_lossy(&drop_connections_output.stderr);
-            let exit_code = drop_connections_output.status.code().unwrap_or(-1);
-
-            return Err(anyhow!(
-                "Failed to drop connections with exit code {}.\nError details: {}",
-                exit_code,
-                stderr.trim()
-            ));
+        if let Some(target) = options.target_database.take() {
+            return self
+                .with_database(&target)
+                .restore_with_options(reader, options)
+                .await;
         }
 
-        let mut cmd = self.get_command("mysql").await?;
-        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        if options.per_table_files {
+            return self.restore_per_table(reader, options).await;
+        }
 
-        let mut stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture psql stdin".to_string()))?;
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        if let Some((dump_major, dump_minor)) =
+            parse_dump_version(&buffer[..header_length], "-- Server version")
+        {
+            let server_version = self.get_version().await?;
+            check_dump_version(
+                "MySQL",
+                &format!("{}.{}", dump_major, dump_minor),
+                &format!("{}.{}", server_version.major, server_version.minor),
+                options.allow_version_mismatch,
+            )?;
+        }
 
-        let mut buffer = [0u8; 16384];
+        self.prepare_restore(&options).await?;
 
-        loop {
-            match reader.read(&mut buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    stdin.write_all(&buffer[..n]).await?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from pg_dump: {}", e));
-                }
-            }
-        }
+        let mut cmd = self.get_command("mysql").await?;
+        let child = cmd
+            .args(&options.extra_restore_args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
 
-        drop(stdin);
+        let output = feed_restore_tool(child, "mysql", reader, &mut buffer, header_length).await?;
 
-        let output = child
-            .wait_with_output()
-            .await
-            .map_err(|e| anyhow!("mysql process failed: {}", e))?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "mysql", &output.stderr);
 
         if !output.status.success() {
             return Err(anyhow!(
-                "mysql restore failed: {}",
-                String::from_utf8_lossy(&output.stderr)
+                "mysql restore failed: {}{}",
+                String::from_utf8_lossy(&output.stderr),
+                log_note
             ));
         }
 
@@ -245,8 +661,191 @@ impl DatabaseConnectionTrait for MySqlConnection {
             reader,
             RestoreOptions {
                 drop_database_first: true,
+                ..Default::default()
             },
         )
         .await
     }
+
+    async fn run_query(&self, query: &str) -> Result<String> {
+        self.execute_statement(&format!(
+            "USE {}; {}",
+            quote_identifier(&self.config.database),
+            query
+        ))
+        .await
+    }
+
+    async fn table_row_counts(&self, database: Option<&str>) -> Result<RowCounts> {
+        let database = quote_identifier(database.unwrap_or(&self.config.database));
+
+        let tables: Vec<String> = self
+            .execute_statement(&format!(
+                "SHOW FULL TABLES FROM {} WHERE Table_type = 'BASE TABLE'",
+                database
+            ))
+            .await?
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .filter(|table| !table.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if tables.is_empty() {
+            return Ok(RowCounts::new());
+        }
+
+        let statement = tables
+            .iter()
+            .map(|table| {
+                format!(
+                    "SELECT {}, COUNT(*) FROM {}.{}",
+                    quote_string(table),
+                    database,
+                    quote_identifier(table)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+
+        self.execute_statement(&statement)
+            .await
+            .map_err(|e| anyhow!("Failed to count rows: {}", e))?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (table, count) = line
+                    .rsplit_once('\t')
+                    .ok_or_else(|| anyhow!("Unexpected row count output: {}", line))?;
+                Ok((table.to_string(), count.trim().parse()?))
+            })
+            .collect()
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let output = self.execute_statement("SHOW DATABASES").await?;
+
+        let mut databases: Vec<String> = output
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|name| !name.is_empty() && !SYSTEM_DATABASES.contains(&name.as_str()))
+            .collect();
+        databases.sort();
+
+        Ok(databases)
+    }
+
+    async fn restore_from_directory(&self, _path: &Path, _options: RestoreOptions) -> Result<()> {
+        Err(anyhow!(
+            "Directory-format restore is only supported for PostgreSQL"
+        ))
+    }
+
+    async fn inspect_dump(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<DumpSummary> {
+        if options.per_table_files {
+            let dump_dir = tempfile::tempdir().context("Failed to create restore directory")?;
+
+            tar::Archive::new(reader)
+                .unpack(dump_dir.path())
+                .context("Failed to extract per-table archive")?;
+
+            let mut summary = DumpSummary::default();
+
+            for entry in fs::read_dir(dump_dir.path())? {
+                let path = entry?.path();
+
+                if path.extension().and_then(|extension| extension.to_str()) == Some("sql") {
+                    if let Some(table) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        summary.tables.push(table.to_string());
+                    }
+                }
+            }
+
+            summary.tables.sort();
+            summary.object_count = summary.tables.len();
+
+            return Ok(summary);
+        }
+
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        let dump_version = parse_dump_version(&buffer[..header_length], "-- Server version");
+
+        if let Some((dump_major, dump_minor)) = dump_version {
+            let server_version = self.get_version().await?;
+            check_dump_version(
+                "MySQL",
+                &format!("{}.{}", dump_major, dump_minor),
+                &format!("{}.{}", server_version.major, server_version.minor),
+                options.allow_version_mismatch,
+            )?;
+        }
+
+        let mut summary = scan_sql_dump(
+            io::Cursor::new(&buffer[..header_length]).chain(reader),
+            options.buffer_size(),
+        )
+        .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        summary.dump_version = dump_version.map(|(major, minor)| format!("{}.{}", major, minor));
+
+        Ok(summary)
+    }
+}
+
+/// Directory `mysqldump --tab` writes into. The server writes the data files as its own
+/// user, so other users get write and search access, but can't list the directory, and
+/// the sticky bit keeps them from removing or replacing the files of others.
+pub(crate) fn create_tab_directory() -> Result<tempfile::TempDir> {
+    let dump_dir = tempfile::tempdir().context("Failed to create dump directory")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dump_dir.path(), fs::Permissions::from_mode(0o1733))
+            .context("Failed to make dump directory writable by the server")?;
+    }
+
+    Ok(dump_dir)
+}
+
+/// Parses the output of `SELECT version()`, MariaDB servers report themselves as e.g.
+/// `10.11.6-MariaDB-1:10.11.6+maria~ubu2204`
+pub(crate) fn parse_server_version(version_string: &str) -> Option<Version> {
+    let version = MySqlVersion::parse_string_version(version_string)?;
+
+    if version_string.contains("MariaDB") {
+        Some(Version::MariaDB(version))
+    } else {
+        Some(Version::MySql(version))
+    }
+}
+
+/// Extracts the coordinates written by `mysqldump --source-data=2` (or `--master-data=2`
+/// on older servers) from the beginning of a dump.
+pub(crate) fn parse_dump_binlog_position(header: &[u8]) -> Option<BinlogPosition> {
+    let header = String::from_utf8_lossy(header);
+    let regex =
+        Regex::new(r"(?:MASTER|SOURCE)_LOG_FILE='([^']+)',\s*(?:MASTER|SOURCE)_LOG_POS=(\d+)")
+            .ok()?;
+    let captures = regex.captures(&header)?;
+
+    Some(BinlogPosition {
+        file: captures.get(1)?.as_str().to_string(),
+        position: captures.get(2)?.as_str().parse().ok()?,
+    })
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("`{}`", identifier.replace('`', "``"))
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
 }
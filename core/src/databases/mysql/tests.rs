@@ -3,8 +3,16 @@ mod mysql_connection_tests {
     use std::{env, thread::sleep, time::Duration};
 
     use crate::databases::{
-        mysql::connection::MySqlConnection, version::Version, ConnectionType, DatabaseConfig,
-        DatabaseConnectionTrait,
+        mysql::{
+            connection::{
+                create_tab_directory, parse_dump_binlog_position, parse_server_version,
+                MySqlConnection,
+            },
+            utilities::binary_name,
+        },
+        version::Version,
+        BackupOptions, BackupScope, BinlogPosition, ConnectionType, DatabaseConfig,
+        DatabaseConnectionTrait, RestoreOptions,
     };
     use anyhow::Result;
     use dotenv::dotenv;
@@ -25,6 +33,7 @@ mod mysql_connection_tests {
             database: env::var("MYSQL_NAME").unwrap_or_default(),
             port,
             ssh_tunnel: None,
+            connect_timeout: None,
         };
 
         Ok(config)
@@ -191,4 +200,221 @@ mod mysql_connection_tests {
         let test3_exists = restored_rows.iter().any(|(name, _)| name == "test3");
         assert!(test3_exists, "test3 should be restored");
     }
+
+    #[ignore]
+    #[tokio::test]
+    async fn test_04_mysql_schema_only_backup() {
+        let test_table_name = format!("test_scope_{}", chrono::Utc::now().timestamp());
+        let config = get_mysql_config().expect("Failed to get config");
+
+        let connection = MySqlConnection::new(config)
+            .await
+            .expect("Failed to get connection");
+
+        sqlx::query(
+            format!(
+                "CREATE TABLE {} (id SERIAL PRIMARY KEY, name TEXT)",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&connection.pool)
+        .await
+        .expect("Failed to create test table");
+
+        sqlx::query(
+            format!(
+                "INSERT INTO {} (name) VALUES ('test1'), ('test2')",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&connection.pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let mut buffer = Vec::new();
+        connection
+            .backup_with_options(
+                &mut buffer,
+                BackupOptions {
+                    scope: BackupScope::SchemaOnly,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to backup database");
+        let dump = String::from_utf8(buffer).expect("Dump should be UTF-8");
+
+        assert!(dump.contains(&format!("CREATE TABLE `{}`", test_table_name)));
+        assert!(!dump.contains("INSERT INTO"));
+
+        sqlx::query(format!("DROP TABLE {}", test_table_name).as_str())
+            .execute(&connection.pool)
+            .await
+            .expect("Failed to drop test table");
+    }
+
+    /// Needs the server on the same host with the FILE privilege, see `backup_per_table`
+    #[ignore]
+    #[tokio::test]
+    async fn test_05_mysql_per_table_backup_and_restore() {
+        let test_table_name = format!("test_tab_{}", chrono::Utc::now().timestamp());
+        let config = get_mysql_config().expect("Failed to get config");
+
+        let connection = MySqlConnection::new(config)
+            .await
+            .expect("Failed to get connection");
+
+        sqlx::query(
+            format!(
+                "CREATE TABLE {} (id SERIAL PRIMARY KEY, name TEXT)",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&connection.pool)
+        .await
+        .expect("Failed to create test table");
+
+        sqlx::query(
+            format!(
+                "INSERT INTO {} (name) VALUES ('test1'), ('test2')",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&connection.pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let mut buffer = Vec::new();
+        connection
+            .backup_with_options(
+                &mut buffer,
+                BackupOptions {
+                    per_table_files: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to backup database");
+
+        let mut archive = tar::Archive::new(buffer.as_slice());
+        let files: Vec<String> = archive
+            .entries()
+            .expect("Failed to read archive")
+            .map(|entry| {
+                let entry = entry.expect("Failed to read archive entry");
+                entry.path().unwrap().to_string_lossy().to_string()
+            })
+            .collect();
+        for extension in ["sql", "txt"] {
+            let file = format!("{}.{}", test_table_name, extension);
+            assert!(
+                files.iter().any(|path| path.ends_with(&file)),
+                "{} is missing from {:?}",
+                file,
+                files
+            );
+        }
+
+        sqlx::query(format!("DELETE FROM {}", test_table_name).as_str())
+            .execute(&connection.pool)
+            .await
+            .expect("Failed to delete test data");
+
+        connection
+            .restore_with_options(
+                &mut buffer.as_slice(),
+                RestoreOptions {
+                    per_table_files: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to restore database");
+
+        let rows: Vec<(String,)> =
+            sqlx::query_as(format!("SELECT name FROM {} ORDER BY id", test_table_name).as_str())
+                .fetch_all(&connection.pool)
+                .await
+                .expect("Failed to fetch restored data");
+        assert_eq!(rows.len(), 2);
+
+        sqlx::query(format!("DROP TABLE {}", test_table_name).as_str())
+            .execute(&connection.pool)
+            .await
+            .expect("Failed to drop test table");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tab_directory_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dump_dir = create_tab_directory().expect("Failed to create dump directory");
+        let mode = std::fs::metadata(dump_dir.path())
+            .expect("Failed to read dump directory")
+            .permissions()
+            .mode();
+
+        // Others can't list the directory or remove the files in it
+        assert_eq!(mode & 0o7777, 0o1733);
+    }
+
+    #[test]
+    fn test_parse_dump_binlog_position() {
+        let header = b"-- MySQL dump 10.13\n--\n-- Position to start replication or point-in-time recovery from\n--\n\n-- CHANGE REPLICATION SOURCE TO SOURCE_LOG_FILE='binlog.000042', SOURCE_LOG_POS=157;\n";
+
+        assert_eq!(
+            parse_dump_binlog_position(header),
+            Some(BinlogPosition {
+                file: "binlog.000042".to_string(),
+                position: 157,
+            })
+        );
+
+        let legacy = b"-- CHANGE MASTER TO MASTER_LOG_FILE='mysql-bin.000003', MASTER_LOG_POS=73;";
+        assert_eq!(
+            parse_dump_binlog_position(legacy).map(|position| position.position),
+            Some(73)
+        );
+
+        assert_eq!(parse_dump_binlog_position(b"-- MySQL dump 10.13"), None);
+    }
+
+    #[test]
+    fn test_parse_server_version() {
+        let mariadb = parse_server_version("10.11.6-MariaDB-1:10.11.6+maria~ubu2204")
+            .expect("Failed to parse MariaDB version");
+        assert!(matches!(
+            mariadb,
+            Version::MariaDB(ref version) if (version.major, version.minor, version.patch) == (10, 11, 6)
+        ));
+
+        let mysql =
+            parse_server_version("8.0.36-0ubuntu0.22.04.1").expect("Failed to parse MySQL version");
+        assert!(matches!(
+            mysql,
+            Version::MySql(ref version) if (version.major, version.minor, version.patch) == (8, 0, 36)
+        ));
+
+        assert!(parse_server_version("unknown").is_none());
+    }
+
+    #[test]
+    fn test_binary_name() {
+        let mariadb =
+            parse_server_version("11.4.2-MariaDB").expect("Failed to parse MariaDB version");
+        assert_eq!(binary_name(&mariadb, "mysql"), "mariadb");
+        assert_eq!(binary_name(&mariadb, "mysqldump"), "mariadb-dump");
+        assert_eq!(binary_name(&mariadb, "mysqlbinlog"), "mariadb-binlog");
+        assert_eq!(binary_name(&mariadb, "mysqlimport"), "mariadb-import");
+        assert_eq!(binary_name(&mariadb, "mysqladmin"), "mysqladmin");
+
+        let mysql = parse_server_version("8.4.0").expect("Failed to parse MySQL version");
+        assert_eq!(binary_name(&mysql, "mysqldump"), "mysqldump");
+        assert_eq!(binary_name(&mysql, "mysql"), "mysql");
+    }
 }
@@ -9,20 +9,21 @@ use anyhow::{anyhow, Result};
 use log::debug;
 use tokio::process::Command;
 
-use super::version::MySqlVersion;
 use async_trait::async_trait;
 
+/// Tools for a MySQL or MariaDB server, `version` is either `Version::MySql` or
+/// `Version::MariaDB`
 pub struct MySqlUtilities {
-    version: MySqlVersion,
+    version: Version,
 }
 
 impl MySqlUtilities {
-    pub fn new(version: MySqlVersion) -> Self {
+    pub fn new(version: Version) -> Self {
         MySqlUtilities { version }
     }
 
     pub async fn install(&self) -> Result<()> {
-        let archives_installer = ArchiveInstaller::new(Version::MySql(self.version.clone()));
+        let archives_installer = ArchiveInstaller::new(self.version.clone());
         let path = archives_installer.download_and_install().await?;
 
         debug!(
@@ -37,11 +38,12 @@ impl MySqlUtilities {
 #[async_trait]
 impl UtilitiesTrait for MySqlUtilities {
     fn get_base_path(&self) -> Result<PathBuf> {
-        let path = get_binaries_base_path(&Version::MySql(self.version.clone())).join("bin");
+        let path = get_binaries_base_path(&self.version).join("bin");
         Ok(path)
     }
 
     async fn get_command(&self, bin_name: &str) -> Result<Command> {
+        let bin_name = binary_name(&self.version, bin_name);
         let base_path = self.get_base_path()?;
         let bin_path = base_path.join(bin_name);
 
@@ -58,3 +60,18 @@ impl UtilitiesTrait for MySqlUtilities {
         Ok(command)
     }
 }
+
+/// Recent MariaDB releases only ship the tools under their `mariadb-*` names
+pub(crate) fn binary_name<'a>(version: &Version, bin_name: &'a str) -> &'a str {
+    if !matches!(version, Version::MariaDB(_)) {
+        return bin_name;
+    }
+
+    match bin_name {
+        "mysql" => "mariadb",
+        "mysqldump" => "mariadb-dump",
+        "mysqlbinlog" => "mariadb-binlog",
+        "mysqlimport" => "mariadb-import",
+        _ => bin_name,
+    }
+}
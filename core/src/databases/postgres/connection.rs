@@ -1,26 +1,36 @@
 use std::{
-    io::{Read, Write},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
     process::Stdio,
     time::Duration,
 };
 
 use crate::databases::{
+    check_dump_version, connection_test_error, feed_restore_tool, is_tar_archive,
+    parse_dump_version, read_dump_header, save_tool_log, scan_sql_dump,
     ssh_tunnel::{SshRemoteConfig, SshTunnel},
+    stream_tool_output,
+    tables::select_tables,
+    validate_charset_name,
     version::{Version, VersionTrait},
-    DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, RestoreOptions, UtilitiesTrait,
+    with_connect_timeout, BackupMode, BackupOptions, BackupScope, BackupSummary, BinlogPosition,
+    DatabaseConfig, DatabaseConnectionTrait, DatabaseMetadata, DumpSummary, RestoreOptions,
+    RowCounts, UtilitiesTrait,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use log::warn;
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
-    Pool, Postgres,
-};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    process::Command,
+    Connection, PgConnection, Pool, Postgres,
 };
+use tokio::process::Command;
 
-use super::{utilities::PostgreSqlUtilities, version::PostgreSQLVersion};
+use super::{
+    utilities::PostgreSqlUtilities,
+    version::PostgreSQLVersion,
+    wal::{archive_wal_segments, format_lsn, parse_lsn, replication_slot_name},
+};
 
 pub struct PostgreSqlConnection {
     pub config: DatabaseConfig,
@@ -28,6 +38,18 @@ pub struct PostgreSqlConnection {
     _ssh_tunnel: Option<SshTunnel>,
 }
 
+/// A snapshot exported by an open `REPEATABLE READ` transaction. Dumps started with
+/// `BackupOptions::snapshot` set to `id` all see the data as of the same instant, as long
+/// as this value is kept alive until they have started.
+///
+/// PostgreSQL only lets a snapshot be imported by transactions in the database that
+/// exported it, so this gives consistency across several dumps of one database, not
+/// across databases.
+pub struct ExportedSnapshot {
+    pub id: String,
+    _connection: PgConnection,
+}
+
 impl PostgreSqlConnection {
     pub async fn new(config: DatabaseConfig) -> Result<Self> {
         let mut config = config.clone();
@@ -62,11 +84,14 @@ impl PostgreSqlConnection {
             None => connect_options,
         };
 
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(Duration::from_secs(30))
-            .connect_with(connect_options)
-            .await?;
+        let pool = with_connect_timeout(
+            config.connect_timeout(),
+            PgPoolOptions::new()
+                .max_connections(5)
+                .acquire_timeout(Duration::from_secs(30))
+                .connect_with(connect_options),
+        )
+        .await?;
 
         Ok(Self {
             config,
@@ -75,13 +100,106 @@ impl PostgreSqlConnection {
         })
     }
 
-    async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
-        let metadata = self.get_metadata().await?;
-        let version = match metadata.version {
-            Version::PostgreSQL(version) => version,
-            _ => return Err(anyhow!("Wrong version type")),
-        };
+    /// A connection to the configured database. The pool is connected to the `postgres`
+    /// database, snapshots have to be exported and imported in the one being dumped.
+    async fn connect_to_database(&self) -> Result<PgConnection> {
+        let mut connect_options = PgConnectOptions::new()
+            .host(&self.config.host)
+            .username(&self.config.username)
+            .database(&self.config.database)
+            .port(self.config.port);
+
+        if let Some(password) = &self.config.password {
+            connect_options = connect_options.password(password);
+        }
+
+        with_connect_timeout(
+            self.config.connect_timeout(),
+            PgConnection::connect_with(&connect_options),
+        )
+        .await
+    }
+
+    pub async fn export_snapshot(&self) -> Result<ExportedSnapshot> {
+        let mut connection = self.connect_to_database().await?;
+
+        sqlx::query("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut connection)
+            .await?;
+
+        let (id,): (String,) = sqlx::query_as("SELECT pg_export_snapshot()")
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to export snapshot: {}", e))?;
+
+        Ok(ExportedSnapshot {
+            id,
+            _connection: connection,
+        })
+    }
+
+    /// Counts the rows of every table as `snapshot` sees them
+    async fn row_counts_as_of(&self, snapshot: &str) -> Result<RowCounts> {
+        validate_snapshot_id(snapshot)?;
+        let mut connection = self.connect_to_database().await?;
+
+        sqlx::query("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut connection)
+            .await?;
+        sqlx::query(&format!("SET TRANSACTION SNAPSHOT '{}'", snapshot))
+            .execute(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to import snapshot {}: {}", snapshot, e))?;
+
+        let rows: Vec<(String, String)> = sqlx::query_as(ROW_COUNTS_QUERY)
+            .fetch_all(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to count rows: {}", e))?;
+
+        rows.into_iter()
+            .map(|(table, count)| Ok((table, count.parse()?)))
+            .collect()
+    }
+
+    /// Row counts matching the dump exactly: they are taken in the snapshot the dump then
+    /// reads, exported here unless `options` brings its own. The returned snapshot has to
+    /// be kept until the dump has started.
+    async fn count_rows_for_dump(
+        &self,
+        options: &BackupOptions,
+    ) -> Result<(Option<ExportedSnapshot>, RowCounts)> {
+        if let Some(snapshot) = &options.snapshot {
+            return Ok((None, self.row_counts_as_of(snapshot).await?));
+        }
+
+        let exported = self.export_snapshot().await?;
+        let row_counts = self.row_counts_as_of(&exported.id).await?;
+
+        Ok((Some(exported), row_counts))
+    }
+
+    /// The same server with `database` as the target of commands. The SSH tunnel, if any,
+    /// stays owned by `self`, so the result must not outlive it.
+    fn with_database(&self, database: &str) -> Self {
+        let mut config = self.config.clone();
+        config.database = database.to_string();
 
+        Self {
+            config,
+            pool: self.pool.clone(),
+            _ssh_tunnel: None,
+        }
+    }
+
+    async fn get_version(&self) -> Result<PostgreSQLVersion> {
+        match self.get_metadata().await?.version {
+            Version::PostgreSQL(version) => Ok(version),
+            _ => Err(anyhow!("Wrong version type")),
+        }
+    }
+
+    async fn get_base_command(&self, bin_name: &str) -> Result<Command> {
+        let version = self.get_version().await?;
         let utilities = PostgreSqlUtilities::new(version);
         let mut cmd = utilities.get_command(bin_name).await?;
 
@@ -106,43 +224,13 @@ impl PostgreSqlConnection {
 
         Ok(cmd)
     }
-}
 
-#[async_trait]
-impl DatabaseConnectionTrait for PostgreSqlConnection {
-    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
-        let version_string: (String,) = sqlx::query_as("SELECT version()")
-            .fetch_one(&self.pool)
-            .await
-            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
-
-        let version = match PostgreSQLVersion::parse_string_version(version_string.0.as_str()) {
-            Some(version) => version,
-            None => return Err(anyhow!("Fauiled to parse PostgreSQL version string")),
-        };
-
-        Ok(DatabaseMetadata {
-            version: Version::PostgreSQL(version),
-        })
-    }
-
-    async fn test(&self) -> Result<bool> {
-        sqlx::query("SELECT 1")
-            .execute(&self.pool)
-            .await
-            .map(|_| true)
-            .map_err(|e| anyhow!("Connection test failed: {}", e))
-    }
-
-    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+    /// pg_dump with the arguments shared by the plain and directory formats
+    async fn get_dump_command(&self, options: &BackupOptions) -> Result<Command> {
         let mut cmd = self.get_command("pg_dump").await?;
 
-        cmd.arg("--format=plain")
-            .arg("--encoding=UTF8")
+        cmd.arg("--encoding=UTF8")
             .arg("--schema=*")
-            .arg("--clean")
-            .arg("--if-exists")
-            .arg("--no-owner")
             .arg("--blobs")
             .arg("--exclude-schema=information_schema")
             .arg("--exclude-schema=pg_catalog")
@@ -150,71 +238,287 @@ impl DatabaseConnectionTrait for PostgreSqlConnection {
             .arg("--exclude-schema=pg_temp*")
             .arg("--exclude-schema=pg_toast_temp*");
 
-        let mut child = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| anyhow!("Failed to start pg_dump: {}", e))?;
+        if let Some(snapshot) = &options.snapshot {
+            validate_snapshot_id(snapshot)?;
+            cmd.arg(format!("--snapshot={}", snapshot));
+        }
 
-        let mut stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture pg_dump stdout".to_string()))?;
+        match options.scope {
+            BackupScope::Full => {}
+            BackupScope::SchemaOnly => {
+                cmd.arg("--schema-only");
+            }
+            BackupScope::DataOnly => {
+                cmd.arg("--data-only");
+            }
+        }
 
-        let mut buffer = [0u8; 16384];
+        cmd.args(self.table_args(options).await?);
 
-        loop {
-            match stdout.read(&mut buffer).await {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    writer
-                        .write_all(&buffer[..n])
-                        .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read from pg_dump: {}", e));
-                }
-            }
+        Ok(cmd)
+    }
+
+    /// `--table` and `--exclude-table` arguments for the table filters of `options`. The
+    /// patterns are expanded here rather than by pg_dump, so a table can't silently be
+    /// both included and excluded.
+    async fn table_args(&self, options: &BackupOptions) -> Result<Vec<String>> {
+        if options.include_tables.is_empty() && options.exclude_tables.is_empty() {
+            return Ok(Vec::new());
         }
 
-        let status = child
-            .wait()
-            .await
-            .map_err(|e| anyhow!("pg_dump process failed: {}", e))?;
+        let tables: Vec<String> = self
+            .run_query(
+                "SELECT schemaname || '.' || tablename FROM pg_tables \
+                 WHERE schemaname NOT IN ('pg_catalog', 'information_schema') ORDER BY 1",
+            )
+            .await?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
 
-        if !status.success() {
-            let mut stderr = child
-                .stderr
-                .take()
-                .ok_or_else(|| anyhow!("Failed to capture pg_dump stderr".to_string()))?;
+        let selection = select_tables(&tables, &options.include_tables, &options.exclude_tables)?;
 
-            let mut error_message = String::new();
-            stderr
-                .read_to_string(&mut error_message)
-                .await
-                .map_err(|e| anyhow!("Failed to read pg_dump stderr: {}", e))?;
+        let mut args: Vec<String> = selection
+            .include
+            .iter()
+            .map(|table| format!("--table={}", quote_table_name(table)))
+            .collect();
+        args.extend(
+            selection
+                .exclude
+                .iter()
+                .map(|table| format!("--exclude-table={}", quote_table_name(table))),
+        );
+
+        Ok(args)
+    }
+
+    /// Dumps with `pg_dump --format=directory --jobs`, which writes the tables in parallel
+    /// into one file each, then archives the directory as a single tar stream. pg_dump's
+    /// own compression is disabled, the backup pipeline compresses the archive.
+    async fn backup_parallel(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: &BackupOptions,
+        jobs: u8,
+    ) -> Result<()> {
+        let dump_dir = tempfile::tempdir().context("Failed to create dump directory")?;
+        // pg_dump creates the directory itself and refuses an existing one
+        let dump_path = dump_dir.path().join("dump");
+
+        let mut cmd = self.get_dump_command(options).await?;
+
+        cmd.arg("--format=directory")
+            .arg(format!("--jobs={}", jobs.max(1)))
+            .arg("--compress=0")
+            .arg("--file")
+            .arg(&dump_path);
+
+        cmd.args(&options.extra_dump_args);
+
+        let output = cmd.output().await.context("Failed to execute pg_dump")?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "pg_dump", &output.stderr);
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pg_dump failed: {}{}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+                log_note
+            ));
+        }
+
+        let mut archive = tar::Builder::new(writer);
+        archive
+            .append_dir_all(".", &dump_path)
+            .context("Failed to archive directory-format dump")?;
+        archive
+            .finish()
+            .context("Failed to finalize directory-format archive")?;
+
+        Ok(())
+    }
+
+    /// `pg_receivewal` resumes from the slot's position only since PostgreSQL 15, older
+    /// versions would start at the current WAL position and leave a gap in the chain
+    async fn check_wal_backup_support(&self) -> Result<()> {
+        let version = self.get_version().await?;
 
-            return Err(anyhow!("pg_dump failed: {}", error_message));
+        if version.major < 15 {
+            return Err(anyhow!(
+                "Incremental PostgreSQL backups need PostgreSQL 15 or later, the server runs {}.{}",
+                version.major,
+                version.minor
+            ));
         }
 
         Ok(())
     }
 
-    async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
-        self.restore_with_options(
-            reader,
-            RestoreOptions {
-                drop_database_first: true,
-            },
+    async fn current_wal_lsn(&self) -> Result<u64> {
+        let (lsn,): (String,) = sqlx::query_as("SELECT pg_current_wal_lsn()::text")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| anyhow!("Failed to read the current WAL position: {}", e))?;
+
+        parse_lsn(&lsn).ok_or_else(|| anyhow!("Invalid WAL position: {}", lsn))
+    }
+
+    /// Takes a physical base backup of the whole cluster with `pg_basebackup`, streamed as
+    /// a single tar with the WAL needed to make it consistent. A replication slot created
+    /// first keeps every later WAL segment on the server until an increment fetches it,
+    /// so a chain that is abandoned must have its slot dropped by hand.
+    async fn backup_wal_base(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: &BackupOptions,
+    ) -> Result<BinlogPosition> {
+        self.check_wal_backup_support().await?;
+
+        let slot = replication_slot_name(&self.config.name);
+
+        // A new base starts a new chain, the WAL kept for the previous one isn't needed
+        sqlx::query(
+            "SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots \
+             WHERE slot_name = $1",
         )
+        .bind(&slot)
+        .execute(&self.pool)
         .await
+        .map_err(|e| anyhow!("Failed to drop replication slot {}: {}", slot, e))?;
+
+        sqlx::query("SELECT pg_create_physical_replication_slot($1, true)")
+            .bind(&slot)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!("Failed to create replication slot {}: {}", slot, e))?;
+
+        let mut cmd = self.get_base_command("pg_basebackup").await?;
+
+        cmd.arg("-h")
+            .arg(&self.config.host)
+            .arg("-p")
+            .arg(self.config.port.to_string())
+            .arg("-U")
+            .arg(&self.config.username)
+            .arg("--pgdata=-")
+            .arg("--format=tar")
+            .arg("--wal-method=fetch")
+            .arg("--checkpoint=fast")
+            .args(&options.extra_dump_args);
+
+        stream_tool_output(
+            cmd,
+            "pg_basebackup",
+            writer,
+            options.buffer_size(),
+            options.tool_log.as_deref(),
+            None,
+        )
+        .await?;
+
+        Ok(BinlogPosition {
+            file: slot,
+            position: self.current_wal_lsn().await?,
+        })
     }
 
-    async fn restore_with_options(
+    /// Archives the WAL segments written since the previous backup of the chain with
+    /// `pg_receivewal`, which resumes from the chain's replication slot. The current
+    /// segment is switched first so the increment ends on a complete one.
+    async fn backup_wal(
         &self,
-        reader: &mut (dyn Read + Send + Unpin),
-        options: RestoreOptions,
-    ) -> Result<()> {
+        writer: &mut (dyn Write + Send + Unpin),
+        since: &BinlogPosition,
+        options: &BackupOptions,
+    ) -> Result<BinlogPosition> {
+        self.check_wal_backup_support().await?;
+
+        let slot: Option<(String,)> =
+            sqlx::query_as("SELECT slot_name::text FROM pg_replication_slots WHERE slot_name = $1")
+                .bind(&since.file)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| anyhow!("Failed to read replication slots: {}", e))?;
+
+        if slot.is_none() {
+            return Err(anyhow!(
+                "Replication slot {} no longer exists, take a new base backup",
+                since.file
+            ));
+        }
+
+        sqlx::query("SELECT pg_switch_wal()")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow!("Failed to switch WAL segment: {}", e))?;
+
+        let until = self.current_wal_lsn().await?;
+        let wal_dir = tempfile::tempdir().context("Failed to create WAL directory")?;
+
+        let mut cmd = self.get_base_command("pg_receivewal").await?;
+
+        cmd.arg("-h")
+            .arg(&self.config.host)
+            .arg("-p")
+            .arg(self.config.port.to_string())
+            .arg("-U")
+            .arg(&self.config.username)
+            .arg("--directory")
+            .arg(wal_dir.path())
+            .arg(format!("--slot={}", since.file))
+            .arg(format!("--endpos={}", format_lsn(until)))
+            .arg("--no-loop")
+            .arg("--no-password")
+            .args(&options.extra_dump_args);
+
+        let output = cmd
+            .output()
+            .await
+            .context("Failed to execute pg_receivewal")?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "pg_receivewal", &output.stderr);
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pg_receivewal failed: {}{}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+                log_note
+            ));
+        }
+
+        archive_wal_segments(wal_dir.path(), writer)?;
+
+        Ok(BinlogPosition {
+            file: since.file.clone(),
+            position: until,
+        })
+    }
+
+    fn create_database_statement(&self, options: &RestoreOptions) -> Result<String> {
+        let mut statement = format!("CREATE DATABASE \"{}\"", self.config.database);
+
+        if let Some(charset) = &options.charset {
+            validate_charset_name(charset)?;
+            statement.push_str(&format!(" ENCODING '{}' TEMPLATE template0", charset));
+        }
+
+        if let Some(collation) = &options.collation {
+            validate_charset_name(collation)?;
+            statement.push_str(&format!(
+                " LC_COLLATE '{}' LC_CTYPE '{}'",
+                collation, collation
+            ));
+
+            if options.charset.is_none() {
+                statement.push_str(" TEMPLATE template0");
+            }
+        }
+
+        statement.push(';');
+        Ok(statement)
+    }
+
+    async fn prepare_restore(&self, options: &RestoreOptions) -> Result<()> {
         let mut cmd = self.get_base_command("psql").await?;
 
         cmd.arg("-h")
@@ -295,7 +599,7 @@ impl DatabaseConnectionTrait for PostgreSqlConnection {
                 .arg("-d")
                 .arg("postgres")
                 .arg("-c")
-                .arg(format!("CREATE DATABASE \"{}\";", self.config.database));
+                .arg(self.create_database_statement(options)?);
 
             let create_output = create_cmd
                 .output()
@@ -314,6 +618,194 @@ impl DatabaseConnectionTrait for PostgreSqlConnection {
             }
         }
 
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DatabaseConnectionTrait for PostgreSqlConnection {
+    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
+        let version_string: (String,) = sqlx::query_as("SELECT version()")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+
+        let version = match PostgreSQLVersion::parse_string_version(version_string.0.as_str()) {
+            Some(version) => version,
+            None => return Err(anyhow!("Fauiled to parse PostgreSQL version string")),
+        };
+
+        Ok(DatabaseMetadata {
+            version: Version::PostgreSQL(version),
+        })
+    }
+
+    async fn test(&self) -> Result<bool> {
+        with_connect_timeout(
+            self.config.connect_timeout(),
+            sqlx::query("SELECT 1").execute(&self.pool),
+        )
+        .await
+        .map(|_| true)
+        .map_err(connection_test_error)
+    }
+
+    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        mut options: BackupOptions,
+    ) -> Result<BackupSummary> {
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        // pg_basebackup copies the whole cluster, none of the dump filters apply
+        if !matches!(options.mode, BackupMode::Full)
+            && (options.jobs.is_some()
+                || options.scope != BackupScope::Full
+                || options.snapshot.is_some()
+                || !options.include_tables.is_empty()
+                || !options.exclude_tables.is_empty())
+        {
+            return Err(anyhow!(
+                "Incremental PostgreSQL backups can't be combined with jobs, scopes, snapshots or table filters"
+            ));
+        }
+
+        match &options.mode {
+            BackupMode::Full => {}
+            BackupMode::IncrementalBase => {
+                let position = self.backup_wal_base(writer, &options).await?;
+                return Ok(BackupSummary {
+                    binlog_position: Some(position),
+                    row_counts: None,
+                });
+            }
+            BackupMode::Incremental { since } => {
+                let position = self.backup_wal(writer, since, &options).await?;
+                return Ok(BackupSummary {
+                    binlog_position: Some(position),
+                    row_counts: None,
+                });
+            }
+        }
+
+        // Counting is best effort, the backup goes on without the counts
+        let (exported, row_counts) = match options.count_rows {
+            true => match self.count_rows_for_dump(&options).await {
+                Ok((exported, row_counts)) => (exported, Some(row_counts)),
+                Err(e) => {
+                    warn!("Failed to count rows in the snapshot of the dump: {}", e);
+                    (None, None)
+                }
+            },
+            false => (None, None),
+        };
+
+        if let Some(exported) = &exported {
+            options.snapshot = Some(exported.id.clone());
+        }
+
+        let summary = BackupSummary {
+            binlog_position: None,
+            row_counts,
+        };
+
+        if let Some(jobs) = options.jobs {
+            self.backup_parallel(writer, &options, jobs).await?;
+            drop(exported);
+            return Ok(summary);
+        }
+
+        let mut cmd = self.get_dump_command(&options).await?;
+
+        cmd.arg("--format=plain").arg("--no-owner");
+
+        // pg_dump refuses --clean for data-only dumps, they don't create the tables
+        if options.scope != BackupScope::DataOnly {
+            cmd.arg("--clean").arg("--if-exists");
+        }
+
+        cmd.args(&options.extra_dump_args);
+
+        stream_tool_output(
+            cmd,
+            "pg_dump",
+            writer,
+            options.buffer_size(),
+            options.tool_log.as_deref(),
+            None,
+        )
+        .await?;
+
+        drop(exported);
+        Ok(summary)
+    }
+
+    async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
+        self.restore_with_options(
+            reader,
+            RestoreOptions {
+                drop_database_first: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn restore_with_options(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        mut options: RestoreOptions,
+    ) -> Result<()> {
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        if let Some(target) = options.target_database.take() {
+            return self
+                .with_database(&target)
+                .restore_with_options(reader, options)
+                .await;
+        }
+
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        // Written by a parallel backup, pg_restore checks the version of those itself. The
+        // directory format can't be read from stdin, so it's the only dump extracted to
+        // disk, plain dumps are piped into psql as they are decompressed.
+        if is_tar_archive(&buffer[..header_length]) {
+            let dump_dir = tempfile::tempdir().context("Failed to create restore directory")?;
+
+            tar::Archive::new(io::Cursor::new(&buffer[..header_length]).chain(reader))
+                .unpack(dump_dir.path())
+                .context("Failed to extract directory-format archive")?;
+
+            return self.restore_from_directory(dump_dir.path(), options).await;
+        }
+
+        // Checked before prepare_restore, which may drop the existing database
+        if let Some((dump_major, _)) =
+            parse_dump_version(&buffer[..header_length], "-- Dumped from database version")
+        {
+            let server_version = self.get_version().await?;
+            check_postgres_dump_version(
+                dump_major,
+                server_version.major,
+                options.allow_version_mismatch,
+            )?;
+        }
+
+        self.prepare_restore(&options).await?;
+
         let mut cmd = self.get_base_command("psql").await?;
 
         cmd.arg("-h")
@@ -325,51 +817,349 @@ impl DatabaseConnectionTrait for PostgreSqlConnection {
             .arg("-d")
             .arg(&self.config.database);
 
-        let mut child = cmd
+        if options.single_transaction {
+            cmd.arg("--single-transaction")
+                .arg("-v")
+                .arg("ON_ERROR_STOP=1");
+        }
+
+        cmd.args(&options.extra_restore_args);
+
+        let child = cmd
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
 
-        let mut stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("Failed to capture psql stdin"))?;
+        let output = if options.skip_clean {
+            let mut reader = SkipCleanStatements::new(
+                io::Cursor::new(buffer[..header_length].to_vec()).chain(reader),
+            );
+            feed_restore_tool(child, "psql", &mut reader, &mut buffer, 0).await?
+        } else {
+            feed_restore_tool(child, "psql", reader, &mut buffer, header_length).await?
+        };
 
-        let mut buffer = [0u8; 16384];
+        let log_note = save_tool_log(options.tool_log.as_deref(), "psql", &output.stderr);
 
-        loop {
-            match reader.read(&mut buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    stdin.write_all(&buffer[..n]).await?;
-                }
-                Err(e) => {
-                    return Err(anyhow!("Failed to read backup data: {}", e));
-                }
-            }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let exit_code = output.status.code().unwrap_or(-1);
+
+            return Err(anyhow!(
+                "psql restore failed with exit code {}{}.\nStderr: {}\nStdout: {}",
+                exit_code,
+                log_note,
+                stderr.trim(),
+                stdout.trim()
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn run_query(&self, query: &str) -> Result<String> {
+        let mut cmd = self.get_command("psql").await?;
+
+        cmd.arg("--no-align")
+            .arg("--tuples-only")
+            .arg("-v")
+            .arg("ON_ERROR_STOP=1")
+            .arg("-c")
+            .arg(query);
+
+        let output = cmd.output().await.context("Failed to execute psql")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Query failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
         }
 
-        drop(stdin);
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    async fn table_row_counts(&self, database: Option<&str>) -> Result<RowCounts> {
+        if let Some(database) = database {
+            return self.with_database(database).table_row_counts(None).await;
+        }
 
-        let output = child
-            .wait_with_output()
+        let output = self
+            .run_query(ROW_COUNTS_QUERY)
             .await
-            .map_err(|e| anyhow!("psql process failed: {}", e))?;
+            .map_err(|e| anyhow!("Failed to count rows: {}", e))?;
+
+        output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (table, count) = line
+                    .rsplit_once('|')
+                    .ok_or_else(|| anyhow!("Unexpected row count output: {}", line))?;
+                Ok((table.to_string(), count.trim().parse()?))
+            })
+            .collect()
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let databases: Vec<(String,)> = sqlx::query_as(
+            "SELECT datname::text FROM pg_database \
+             WHERE NOT datistemplate AND datallowconn AND datname <> 'postgres' \
+             ORDER BY datname",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!("Failed to list databases: {}", e))?;
+
+        Ok(databases.into_iter().map(|(name,)| name).collect())
+    }
+
+    async fn restore_from_directory(&self, path: &Path, mut options: RestoreOptions) -> Result<()> {
+        if let Some(target) = options.target_database.take() {
+            return self
+                .with_database(&target)
+                .restore_from_directory(path, options)
+                .await;
+        }
+
+        if !path.join("toc.dat").is_file() {
+            return Err(anyhow!(
+                "{} is not a directory-format dump (missing toc.dat)",
+                path.display()
+            ));
+        }
+
+        self.prepare_restore(&options).await?;
+
+        let mut cmd = self.get_command("pg_restore").await?;
+
+        cmd.arg("--format=directory")
+            .arg(format!("--jobs={}", options.jobs.unwrap_or(1).max(1)))
+            .arg("--no-owner");
+
+        if !options.drop_database_first && !options.skip_clean {
+            cmd.arg("--clean").arg("--if-exists");
+        }
+
+        cmd.args(&options.extra_restore_args).arg(path);
+
+        let output = cmd.output().await.context("Failed to execute pg_restore")?;
+        let log_note = save_tool_log(options.tool_log.as_deref(), "pg_restore", &output.stderr);
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
             let exit_code = output.status.code().unwrap_or(-1);
 
             return Err(anyhow!(
-                "psql restore failed with exit code {}.\nStderr: {}\nStdout: {}",
+                "pg_restore failed with exit code {}{}.\nError: {}",
                 exit_code,
-                stderr.trim(),
-                stdout.trim()
+                log_note,
+                stderr.trim()
             ));
         }
 
         Ok(())
     }
+
+    async fn inspect_dump(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<DumpSummary> {
+        let mut buffer = vec![0u8; options.buffer_size()];
+        let header_length = read_dump_header(reader, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+        let (mut summary, dump_version) = if is_tar_archive(&buffer[..header_length]) {
+            let dump_dir = tempfile::tempdir().context("Failed to create restore directory")?;
+
+            tar::Archive::new(io::Cursor::new(&buffer[..header_length]).chain(reader))
+                .unpack(dump_dir.path())
+                .context("Failed to extract directory-format archive")?;
+
+            let mut cmd = self.get_base_command("pg_restore").await?;
+            cmd.arg("--list").arg(dump_dir.path());
+
+            let output = cmd.output().await.context("Failed to execute pg_restore")?;
+
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "pg_restore --list failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+
+            parse_restore_list(&String::from_utf8_lossy(&output.stdout))
+        } else {
+            let dump_version =
+                parse_dump_version(&buffer[..header_length], "-- Dumped from database version");
+            let summary = scan_sql_dump(
+                io::Cursor::new(&buffer[..header_length]).chain(reader),
+                options.buffer_size(),
+            )
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+
+            (summary, dump_version)
+        };
+
+        if let Some((dump_major, _)) = dump_version {
+            let server_version = self.get_version().await?;
+            check_postgres_dump_version(
+                dump_major,
+                server_version.major,
+                options.allow_version_mismatch,
+            )?;
+        }
+
+        summary.dump_version = dump_version.map(|(major, minor)| format!("{}.{}", major, minor));
+
+        Ok(summary)
+    }
+}
+
+/// Leaves out the statements `pg_dump --clean --if-exists` writes to drop the objects of a
+/// plain dump before creating them. pg_dump writes them all between the preamble and the
+/// first object, so nothing past the first `-- Name:` comment is looked at, COPY data
+/// included.
+pub(crate) struct SkipCleanStatements<R> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+    position: usize,
+    past_clean_section: bool,
+}
+
+impl<R: Read> SkipCleanStatements<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+            position: 0,
+            past_clean_section: false,
+        }
+    }
+}
+
+impl<R: Read> Read for SkipCleanStatements<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.position < self.line.len() {
+                let n = buf.len().min(self.line.len() - self.position);
+                buf[..n].copy_from_slice(&self.line[self.position..self.position + n]);
+                self.position += n;
+                return Ok(n);
+            }
+
+            if self.past_clean_section {
+                return self.reader.read(buf);
+            }
+
+            self.line.clear();
+            self.position = 0;
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+
+            if self.line.starts_with(b"-- Name: ")
+                || self.line.starts_with(b"-- Data for Name: ")
+                || self.line.starts_with(b"-- TOC entry ")
+            {
+                self.past_clean_section = true;
+            } else if is_clean_statement(&self.line) {
+                self.line.clear();
+            }
+        }
+    }
+}
+
+/// `DROP ... IF EXISTS` and the `ALTER TABLE IF EXISTS ... DROP ...` that remove
+/// constraints and defaults first
+fn is_clean_statement(line: &[u8]) -> bool {
+    line.starts_with(b"DROP ")
+        || (line.starts_with(b"ALTER ") && line.windows(6).any(|word| word == b" DROP "))
+}
+
+/// Reads the table of contents printed by `pg_restore --list`. The header is made of `;`
+/// comments, every other line is an entry such as `215; 1259 16386 TABLE public orders app`.
+pub(crate) fn parse_restore_list(toc: &str) -> (DumpSummary, Option<(u16, u16)>) {
+    let mut summary = DumpSummary::default();
+    let mut header = String::new();
+
+    for line in toc.lines() {
+        if let Some(comment) = line.strip_prefix(';') {
+            header.push_str(comment.trim());
+            header.push('\n');
+            continue;
+        }
+
+        let Some((_, entry)) = line.split_once("; ") else {
+            continue;
+        };
+
+        summary.object_count += 1;
+
+        let fields: Vec<&str> = entry.split_whitespace().collect();
+
+        if let [_, _, "TABLE", schema, name, ..] = fields.as_slice() {
+            if *schema != "DATA" {
+                summary.tables.push(format!("{}.{}", schema, name));
+            }
+        }
+    }
+
+    let dump_version = parse_dump_version(header.as_bytes(), "Dumped from database version:");
+
+    (summary, dump_version)
+}
+
+/// Quotes both parts of `schema.table` so pg_dump matches the name exactly instead of
+/// treating it as a pattern
+fn quote_table_name(table: &str) -> String {
+    let quote = |identifier: &str| format!("\"{}\"", identifier.replace('"', "\"\""));
+
+    match table.split_once('.') {
+        Some((schema, name)) => format!("{}.{}", quote(schema), quote(name)),
+        None => quote(table),
+    }
+}
+
+/// Every table with its row count. query_to_xml runs a count per table within this single
+/// query, so they all see the same snapshot.
+const ROW_COUNTS_QUERY: &str = "SELECT schemaname || '.' || tablename, \
+     (xpath('/row/c/text()', query_to_xml(format('SELECT count(*) AS c FROM %I.%I', \
+     schemaname, tablename), false, true, '')))[1]::text \
+     FROM pg_tables WHERE schemaname NOT IN ('pg_catalog', 'information_schema') \
+     ORDER BY 1";
+
+/// A newer PostgreSQL restores the dumps of older ones, so only a dump from a newer major
+/// version is refused, see `check_dump_version`. An older dump is restored with a warning.
+pub(crate) fn check_postgres_dump_version(
+    dump_major: u16,
+    server_major: u16,
+    allow_version_mismatch: bool,
+) -> Result<()> {
+    if dump_major < server_major {
+        warn!(
+            "Restoring a PostgreSQL {} dump into a PostgreSQL {} server",
+            dump_major, server_major
+        );
+        return Ok(());
+    }
+
+    check_dump_version(
+        "PostgreSQL",
+        &dump_major.to_string(),
+        &server_major.to_string(),
+        allow_version_mismatch,
+    )
+}
+
+/// Snapshot ids look like `00000003-0000001B-1`
+pub(crate) fn validate_snapshot_id(snapshot: &str) -> Result<()> {
+    if !snapshot.is_empty() && snapshot.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid snapshot id: {}", snapshot))
+    }
 }
@@ -2,3 +2,4 @@ pub mod connection;
 mod tests;
 pub mod utilities;
 pub mod version;
+pub mod wal;
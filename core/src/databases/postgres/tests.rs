@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod postgresql_connection_test {
-    use crate::databases::postgres::connection::PostgreSqlConnection;
+    use crate::databases::postgres::connection::{
+        check_postgres_dump_version, parse_restore_list, validate_snapshot_id,
+        PostgreSqlConnection, SkipCleanStatements,
+    };
     use crate::databases::ssh_tunnel::{SshAuthMethod, SshTunnelConfig};
     use crate::databases::version::Version;
     use crate::databases::{
-        ConnectionType, DatabaseConfig, DatabaseConnectionTrait, RestoreOptions,
+        BackupOptions, BackupScope, ConnectionType, DatabaseConfig, DatabaseConnectionTrait,
+        RestoreOptions,
     };
     use crate::test_utils::test_utils::{
         get_postgresql_connection, get_postgresql_pool, initialize_test,
@@ -13,6 +17,7 @@ mod postgresql_connection_test {
     use anyhow::Result;
     use dotenv::dotenv;
     use std::env;
+    use std::io::Read;
     use std::thread::sleep;
     use std::time::Duration;
 
@@ -39,7 +44,9 @@ mod postgresql_connection_test {
                     key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                     passphrase_key: None,
                 },
+                jump_hosts: Vec::new(),
             }),
+            connect_timeout: None,
         };
 
         let connection = PostgreSqlConnection::new(config).await?;
@@ -188,6 +195,7 @@ mod postgresql_connection_test {
                 &mut backup_cursor,
                 RestoreOptions {
                     drop_database_first: false,
+                    ..Default::default()
                 },
             )
             .await
@@ -250,4 +258,248 @@ mod postgresql_connection_test {
 
         assert!(buf.len() > 0);
     }
+
+    #[tokio::test]
+    async fn test_06_schema_and_data_only_dumps() {
+        initialize_test();
+        let test_table_name = format!("test_scope_{}", chrono::Utc::now().timestamp());
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db_pool");
+        let connection = get_postgresql_connection(false)
+            .await
+            .expect("Failed to get connection");
+
+        sqlx::query(
+            format!(
+                "CREATE TABLE {} (id SERIAL PRIMARY KEY, name TEXT)",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&db_pool)
+        .await
+        .expect("Failed to create test table");
+
+        sqlx::query(
+            format!(
+                "INSERT INTO {} (name) VALUES ('test1'), ('test2')",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&db_pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let mut schema_dump = Vec::new();
+        connection
+            .backup_with_options(
+                &mut schema_dump,
+                BackupOptions {
+                    scope: BackupScope::SchemaOnly,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to dump schema");
+        let schema_dump = String::from_utf8(schema_dump).expect("Dump should be UTF-8");
+
+        assert!(schema_dump.contains(&format!("CREATE TABLE public.{}", test_table_name)));
+        assert!(!schema_dump.contains("INSERT INTO"));
+        assert!(!schema_dump.contains(&format!("COPY public.{}", test_table_name)));
+
+        let mut data_dump = Vec::new();
+        connection
+            .backup_with_options(
+                &mut data_dump,
+                BackupOptions {
+                    scope: BackupScope::DataOnly,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to dump data");
+        let data_dump = String::from_utf8(data_dump).expect("Dump should be UTF-8");
+
+        assert!(!data_dump.contains("CREATE TABLE"));
+        assert!(data_dump.contains(&format!("COPY public.{}", test_table_name)));
+
+        sqlx::query(format!("DROP TABLE {}", test_table_name).as_str())
+            .execute(&db_pool)
+            .await
+            .expect("Failed to drop test table");
+    }
+
+    #[tokio::test]
+    async fn test_07_restore_to_target_database() {
+        initialize_test();
+        let timestamp = chrono::Utc::now().timestamp();
+        let test_table_name = format!("test_target_{}", timestamp);
+        let target_database = format!("dbkp_target_{}", timestamp);
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db_pool");
+        let connection = get_postgresql_connection(false)
+            .await
+            .expect("Failed to get connection");
+
+        sqlx::query(
+            format!(
+                "CREATE TABLE {} (id SERIAL PRIMARY KEY, name TEXT)",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&db_pool)
+        .await
+        .expect("Failed to create test table");
+
+        sqlx::query(
+            format!(
+                "INSERT INTO {} (name) VALUES ('test1'), ('test2')",
+                test_table_name
+            )
+            .as_str(),
+        )
+        .execute(&db_pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let mut backup_buffer = Vec::new();
+        connection
+            .backup(&mut backup_buffer)
+            .await
+            .expect("Failed to backup database");
+
+        connection
+            .restore_with_options(
+                &mut std::io::Cursor::new(backup_buffer),
+                RestoreOptions {
+                    drop_database_first: true,
+                    target_database: Some(target_database.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to restore into target database");
+
+        let mut target_config = connection.config.clone();
+        target_config.database = target_database.clone();
+        let target_connection = PostgreSqlConnection::new(target_config)
+            .await
+            .expect("Failed to connect to target database");
+
+        let count = target_connection
+            .run_query(&format!("SELECT count(*) FROM {}", test_table_name))
+            .await
+            .expect("Failed to query target database");
+        assert_eq!(count.trim(), "2");
+
+        sqlx::query(format!("DROP TABLE {}", test_table_name).as_str())
+            .execute(&db_pool)
+            .await
+            .expect("Failed to drop test table");
+
+        get_postgresql_connection(true)
+            .await
+            .expect("Failed to get admin connection")
+            .run_query(&format!("DROP DATABASE \"{}\"", target_database))
+            .await
+            .expect("Failed to drop target database");
+    }
+
+    #[test]
+    fn test_08_parse_restore_list() {
+        let toc = "\
+;
+; Archive created at 2024-03-01 10:00:00 UTC
+;     dbname: app
+;     Dumped from database version: 16.2
+;     Dumped by pg_dump version: 16.2
+;
+; Selected TOC Entries:
+;
+215; 1259 16386 TABLE public orders app
+216; 1259 16390 SEQUENCE public orders_id_seq app
+217; 1259 16392 TABLE sales invoices app
+3350; 0 16386 TABLE DATA public orders app
+";
+
+        let (summary, dump_version) = parse_restore_list(toc);
+
+        assert_eq!(dump_version, Some((16, 2)));
+        assert_eq!(summary.object_count, 4);
+        assert_eq!(summary.tables, vec!["public.orders", "sales.invoices"]);
+    }
+
+    #[test]
+    fn test_09_skip_clean_statements() {
+        let dump = "\
+SET client_encoding = 'UTF8';
+SELECT pg_catalog.set_config('search_path', '', false);
+
+ALTER TABLE IF EXISTS ONLY public.orders DROP CONSTRAINT IF EXISTS orders_pkey;
+ALTER TABLE IF EXISTS public.orders ALTER COLUMN id DROP DEFAULT;
+DROP SEQUENCE IF EXISTS public.orders_id_seq;
+DROP TABLE IF EXISTS public.orders;
+--
+-- Name: orders; Type: TABLE; Schema: public; Owner: -
+--
+
+CREATE TABLE public.orders (
+    id integer NOT NULL,
+    note text
+);
+
+COPY public.orders (id, note) FROM stdin;
+1\tDROP TABLE IF EXISTS public.orders;
+\\.
+
+ALTER TABLE ONLY public.orders
+    ADD CONSTRAINT orders_pkey PRIMARY KEY (id);
+";
+
+        let mut restored = String::new();
+        // A tiny buffer so lines are read in several pieces
+        let mut reader = SkipCleanStatements::new(dump.as_bytes());
+        let mut chunk = [0u8; 7];
+        loop {
+            let n = reader.read(&mut chunk).expect("Failed to read dump");
+            if n == 0 {
+                break;
+            }
+            restored.push_str(std::str::from_utf8(&chunk[..n]).unwrap());
+        }
+
+        let (_, objects) = dump.split_once("--\n-- Name").unwrap();
+        assert_eq!(
+            restored,
+            format!(
+                "SET client_encoding = 'UTF8';\n\
+                 SELECT pg_catalog.set_config('search_path', '', false);\n\n\
+                 --\n-- Name{}",
+                objects
+            )
+        );
+        // COPY data past the first object is left alone
+        assert!(restored.contains("1\tDROP TABLE IF EXISTS public.orders;"));
+    }
+
+    #[test]
+    fn test_10_validate_snapshot_id() {
+        assert!(validate_snapshot_id("00000003-0000001B-1").is_ok());
+        assert!(validate_snapshot_id("0000000A-00000F2C-12").is_ok());
+
+        assert!(validate_snapshot_id("").is_err());
+        // It ends up quoted in SET TRANSACTION SNAPSHOT and on the pg_dump command line
+        assert!(validate_snapshot_id("00000003-0000001B-1'; DROP TABLE orders; --").is_err());
+        assert!(validate_snapshot_id("00000003 0000001B").is_err());
+        assert!(validate_snapshot_id("snapshot").is_err());
+    }
+
+    #[test]
+    fn test_11_check_postgres_dump_version() {
+        assert!(check_postgres_dump_version(16, 16, false).is_ok());
+        // Older dumps restore into a newer server
+        assert!(check_postgres_dump_version(15, 16, false).is_ok());
+        assert!(check_postgres_dump_version(17, 16, false).is_err());
+        assert!(check_postgres_dump_version(17, 16, true).is_ok());
+    }
 }
@@ -0,0 +1,242 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+
+/// Directory of a restored data directory the WAL of the increments is unpacked into,
+/// `restore_command` copies the segments from there during recovery
+pub const WAL_ARCHIVE_DIR: &str = "dbkp_wal";
+
+/// Name of the physical replication slot that keeps the WAL of an incremental chain on
+/// the server until the next increment has fetched it. Slot names are limited to 63
+/// lowercase letters, digits and underscores.
+pub fn replication_slot_name(config_name: &str) -> String {
+    let sanitized: String = config_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("dbkp_{}", sanitized).chars().take(63).collect()
+}
+
+/// Parses a WAL location as printed by PostgreSQL, e.g. `0/16B3748`
+pub fn parse_lsn(lsn: &str) -> Option<u64> {
+    let (high, low) = lsn.trim().split_once('/')?;
+    let high = u32::from_str_radix(high, 16).ok()?;
+    let low = u32::from_str_radix(low, 16).ok()?;
+
+    Some(((high as u64) << 32) | low as u64)
+}
+
+pub fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+/// Archives the WAL segments `pg_receivewal` wrote to `dir`. The segment it was still
+/// writing is left out, the next increment fetches it again in full.
+pub fn archive_wal_segments(dir: &Path, writer: &mut (dyn Write + Send + Unpin)) -> Result<usize> {
+    let mut segments: Vec<_> = fs::read_dir(dir)
+        .context("Failed to read WAL directory")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| !entry.file_name().to_string_lossy().ends_with(".partial"))
+        .collect();
+    segments.sort_by_key(|entry| entry.file_name());
+
+    let mut archive = tar::Builder::new(writer);
+
+    for segment in &segments {
+        archive
+            .append_path_with_name(segment.path(), segment.file_name())
+            .with_context(|| {
+                format!(
+                    "Failed to archive WAL segment {}",
+                    segment.file_name().to_string_lossy()
+                )
+            })?;
+    }
+
+    archive.finish().context("Failed to finalize WAL archive")?;
+
+    Ok(segments.len())
+}
+
+/// Unpacks a `pg_basebackup` tar into `data_dir`, which must be empty or not exist yet
+pub fn unpack_base_backup(data_dir: &Path, reader: impl Read) -> Result<()> {
+    if data_dir.exists()
+        && fs::read_dir(data_dir)
+            .context("Failed to read data directory")?
+            .next()
+            .is_some()
+    {
+        return Err(anyhow!(
+            "Data directory {} is not empty",
+            data_dir.display()
+        ));
+    }
+
+    fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+
+    // PostgreSQL refuses to start on a data directory other users can read
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(data_dir, fs::Permissions::from_mode(0o700))
+            .context("Failed to restrict data directory permissions")?;
+    }
+
+    tar::Archive::new(reader)
+        .unpack(data_dir)
+        .context("Failed to extract base backup")
+}
+
+/// Unpacks the WAL segments of an increment next to the ones already restored. A segment
+/// fetched again by a later increment replaces the earlier copy.
+pub fn unpack_wal_segments(data_dir: &Path, reader: impl Read) -> Result<()> {
+    let wal_dir = data_dir.join(WAL_ARCHIVE_DIR);
+    fs::create_dir_all(&wal_dir).context("Failed to create WAL directory")?;
+
+    tar::Archive::new(reader)
+        .unpack(&wal_dir)
+        .context("Failed to extract WAL segments")
+}
+
+/// Settings that make the server replay the restored WAL on its next start, up to
+/// `target_time` when set and to the end of the last increment otherwise
+pub fn recovery_settings(wal_dir: &Path, target_time: Option<DateTime<Utc>>) -> String {
+    // The path goes through the `%` placeholders of restore_command, then the shell that
+    // runs it, both inside a postgresql.auto.conf string literal
+    let wal_dir = wal_dir.to_string_lossy().replace('%', "%%");
+    let restore_command = format!("cp '{}'/%f \"%p\"", wal_dir.replace('\'', r"'\''"));
+    let mut settings = format!(
+        "\n# Added by dbkp to replay the restored WAL\nrestore_command = '{}'\n",
+        restore_command.replace('\\', r"\\").replace('\'', "''")
+    );
+
+    if let Some(target_time) = target_time {
+        settings.push_str(&format!(
+            "recovery_target_time = '{}'\n",
+            target_time.format("%Y-%m-%d %H:%M:%S%.f+00")
+        ));
+    }
+
+    settings.push_str("recovery_target_action = 'promote'\n");
+    settings
+}
+
+/// Puts a restored data directory in recovery mode, see `recovery_settings`
+pub fn write_recovery_settings(data_dir: &Path, target_time: Option<DateTime<Utc>>) -> Result<()> {
+    let data_dir = data_dir
+        .canonicalize()
+        .context("Failed to resolve data directory")?;
+    let wal_dir = data_dir.join(WAL_ARCHIVE_DIR);
+    fs::create_dir_all(&wal_dir).context("Failed to create WAL directory")?;
+
+    let mut auto_conf = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_dir.join("postgresql.auto.conf"))
+        .context("Failed to open postgresql.auto.conf")?;
+    auto_conf
+        .write_all(recovery_settings(&wal_dir, target_time).as_bytes())
+        .context("Failed to write recovery settings")?;
+
+    fs::write(data_dir.join("recovery.signal"), b"").context("Failed to write recovery.signal")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod wal_tests {
+    use std::{fs, io::Cursor, path::Path};
+
+    use chrono::{TimeZone, Utc};
+    use tempfile::tempdir;
+
+    use super::{
+        archive_wal_segments, format_lsn, parse_lsn, recovery_settings, replication_slot_name,
+        unpack_base_backup, unpack_wal_segments, write_recovery_settings, WAL_ARCHIVE_DIR,
+    };
+
+    #[test]
+    fn test_lsn() {
+        assert_eq!(parse_lsn("0/16B3748"), Some(0x16B3748));
+        assert_eq!(parse_lsn("1A/0\n"), Some(0x1A_0000_0000));
+        assert_eq!(parse_lsn("16B3748"), None);
+        assert_eq!(format_lsn(0x1A_016B_3748), "1A/16B3748");
+        assert_eq!(parse_lsn(&format_lsn(u64::MAX)), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_replication_slot_name() {
+        assert_eq!(replication_slot_name("Prod-Orders"), "dbkp_prod_orders");
+        assert_eq!(replication_slot_name(&"a".repeat(100)).len(), 63);
+    }
+
+    #[test]
+    fn test_recovery_settings() {
+        let settings = recovery_settings(Path::new("/data/dbkp_wal"), None);
+        assert!(settings.contains("restore_command = 'cp ''/data/dbkp_wal''/%f \"%p\"'"));
+        assert!(!settings.contains("recovery_target_time"));
+
+        // Quotes, expansions and placeholders in the path stay literal
+        let settings = recovery_settings(Path::new("/data/it's \"$HOME\" `id` 100%"), None);
+        assert!(settings.contains(
+            r#"restore_command = 'cp ''/data/it''\\''''s "$HOME" `id` 100%%''/%f "%p"'"#
+        ));
+
+        let target_time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap();
+        let settings = recovery_settings(Path::new("/data/dbkp_wal"), Some(target_time));
+        assert!(settings.contains("recovery_target_time = '2024-03-01 12:30:00+00'"));
+        assert!(settings.contains("recovery_target_action = 'promote'"));
+    }
+
+    #[test]
+    fn test_restore_data_directory() {
+        let wal = tempdir().unwrap();
+        fs::write(wal.path().join("000000010000000000000001"), b"segment 1").unwrap();
+        fs::write(wal.path().join("000000010000000000000002.partial"), b"half").unwrap();
+
+        let mut increment = Vec::new();
+        assert_eq!(archive_wal_segments(wal.path(), &mut increment).unwrap(), 1);
+
+        let base_files = tempdir().unwrap();
+        fs::write(base_files.path().join("PG_VERSION"), b"16\n").unwrap();
+        let mut base = tar::Builder::new(Vec::new());
+        base.append_dir_all(".", base_files.path()).unwrap();
+        let base = base.into_inner().unwrap();
+
+        let root = tempdir().unwrap();
+        let data_dir = root.path().join("data");
+        unpack_base_backup(&data_dir, Cursor::new(&base)).unwrap();
+        unpack_wal_segments(&data_dir, Cursor::new(&increment)).unwrap();
+        write_recovery_settings(&data_dir, None).unwrap();
+
+        assert!(data_dir.join("PG_VERSION").is_file());
+        assert!(data_dir.join("recovery.signal").is_file());
+        assert!(data_dir
+            .join(WAL_ARCHIVE_DIR)
+            .join("000000010000000000000001")
+            .is_file());
+        assert!(!data_dir
+            .join(WAL_ARCHIVE_DIR)
+            .join("000000010000000000000002.partial")
+            .exists());
+        assert!(fs::read_to_string(data_dir.join("postgresql.auto.conf"))
+            .unwrap()
+            .contains("restore_command"));
+
+        // A second restore into the same directory would mix two clusters
+        assert!(unpack_base_backup(&data_dir, Cursor::new(&base)).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::databases::{
+    version::Version, BackupMode, BackupOptions, BackupScope, BackupSummary, DatabaseConfig,
+    DatabaseConnectionTrait, DatabaseMetadata, DumpSummary, RestoreOptions, RowCounts,
+};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteRow},
+    Column, Connection, Row, SqliteConnection as SqlxSqliteConnection,
+};
+
+/// A SQLite database file. `DatabaseConfig::database` holds its path, the host, port and
+/// credentials are ignored.
+pub struct SqliteConnection {
+    pub config: DatabaseConfig,
+}
+
+impl SqliteConnection {
+    pub async fn new(config: DatabaseConfig) -> Result<Self> {
+        if config.ssh_tunnel.is_some() {
+            return Err(anyhow!("SSH tunnels are not supported for SQLite"));
+        }
+
+        Ok(Self { config })
+    }
+
+    fn path(&self) -> PathBuf {
+        PathBuf::from(&self.config.database)
+    }
+
+    async fn connect(path: &Path, read_only: bool) -> Result<SqlxSqliteConnection> {
+        if !path.is_file() {
+            return Err(anyhow!("SQLite database not found: {}", path.display()));
+        }
+
+        let connect_options = SqliteConnectOptions::new()
+            .filename(path)
+            .read_only(read_only)
+            .create_if_missing(false);
+
+        SqlxSqliteConnection::connect_with(&connect_options)
+            .await
+            .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))
+    }
+
+    /// Opens the file and runs `PRAGMA quick_check`, which fails on anything that isn't
+    /// an intact SQLite database.
+    async fn check_database(path: &Path) -> Result<()> {
+        let mut connection = Self::connect(path, true).await?;
+
+        let (result,): (String,) = sqlx::query_as("PRAGMA quick_check")
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| anyhow!("{} is not a valid SQLite database: {}", path.display(), e))?;
+
+        connection.close().await.ok();
+
+        if result != "ok" {
+            return Err(anyhow!(
+                "Integrity check of {} failed: {}",
+                path.display(),
+                result
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DatabaseConnectionTrait for SqliteConnection {
+    async fn get_metadata(&self) -> Result<DatabaseMetadata> {
+        let mut connection = Self::connect(&self.path(), true).await?;
+
+        let (version,): (String,) = sqlx::query_as("SELECT sqlite_version()")
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to get database version: {}", e))?;
+
+        Ok(DatabaseMetadata {
+            version: Version::SQLite(version),
+        })
+    }
+
+    async fn test(&self) -> Result<bool> {
+        Self::check_database(&self.path())
+            .await
+            .map(|_| true)
+            .map_err(|e| anyhow!("Connection test failed: {}", e))
+    }
+
+    async fn backup(&self, writer: &mut (dyn Write + Send + Unpin)) -> Result<()> {
+        self.backup_with_options(writer, BackupOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    async fn backup_with_options(
+        &self,
+        writer: &mut (dyn Write + Send + Unpin),
+        options: BackupOptions,
+    ) -> Result<BackupSummary> {
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        if !matches!(options.mode, BackupMode::Full) {
+            return Err(anyhow!("Incremental backups are only supported for MySQL"));
+        }
+
+        if options.snapshot.is_some() {
+            return Err(anyhow!(
+                "Exported snapshots are only supported for PostgreSQL"
+            ));
+        }
+
+        if options.jobs.is_some() {
+            return Err(anyhow!("Parallel dumps are only supported for PostgreSQL"));
+        }
+
+        if !options.include_tables.is_empty() || !options.exclude_tables.is_empty() {
+            return Err(anyhow!("Table filters are not supported for SQLite"));
+        }
+
+        if options.scope != BackupScope::Full {
+            return Err(anyhow!(
+                "Schema-only and data-only backups are not supported for SQLite"
+            ));
+        }
+
+        if !options.extra_dump_args.is_empty() {
+            return Err(anyhow!("SQLite backups don't run an external dump tool"));
+        }
+
+        // VACUUM INTO writes a consistent copy even while other processes use the
+        // database, copying the file itself could catch a write halfway through
+        let snapshot_dir = tempfile::tempdir().context("Failed to create backup directory")?;
+        let snapshot_path = snapshot_dir.path().join("backup.sqlite");
+
+        let mut connection = Self::connect(&self.path(), false).await?;
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(snapshot_path.to_string_lossy().to_string())
+            .execute(&mut connection)
+            .await
+            .map_err(|e| anyhow!("SQLite backup failed: {}", e))?;
+
+        connection.close().await.ok();
+
+        let mut snapshot = File::open(&snapshot_path).context("Failed to open SQLite backup")?;
+        copy_with_buffer(&mut snapshot, writer, options.buffer_size())
+            .map_err(|e| anyhow!("Failed to write backup data: {}", e))?;
+
+        Ok(BackupSummary::default())
+    }
+
+    async fn restore(&self, reader: &mut (dyn Read + Send + Unpin)) -> Result<()> {
+        self.restore_with_options(
+            reader,
+            RestoreOptions {
+                drop_database_first: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn restore_with_options(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<()> {
+        if options.target_database.is_some() {
+            return Err(anyhow!(
+                "SQLite restores go to the configured database file, change its path instead"
+            ));
+        }
+
+        if options.per_table_files {
+            return Err(anyhow!("Per-table files mode is only supported for MySQL"));
+        }
+
+        if !options.extra_restore_args.is_empty() {
+            return Err(anyhow!(
+                "SQLite restores don't run an external restore tool"
+            ));
+        }
+
+        let path = self.path();
+
+        if path.exists() && !options.drop_database_first {
+            return Err(anyhow!(
+                "{} already exists, use --drop-database to overwrite it",
+                path.display()
+            ));
+        }
+
+        // The backup is checked in a scratch file first, so a corrupt or truncated
+        // backup never replaces a working database
+        let mut staged = tempfile::NamedTempFile::new().context("Failed to create restore file")?;
+        copy_with_buffer(reader, staged.as_file_mut(), options.buffer_size())
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+        staged.as_file_mut().flush()?;
+
+        Self::check_database(staged.path()).await?;
+
+        let mut target = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        let mut staged = File::open(staged.path())?;
+        copy_with_buffer(&mut staged, &mut target, options.buffer_size())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        target.sync_all()?;
+
+        // A journal left over from the previous database would be replayed on top of the
+        // restored file
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut journal = path.clone().into_os_string();
+            journal.push(suffix);
+            match fs::remove_file(&journal) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!("Failed to remove {:?}: {}", journal, e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn restore_from_directory(&self, _path: &Path, _options: RestoreOptions) -> Result<()> {
+        Err(anyhow!("Directory restores are not supported for SQLite"))
+    }
+
+    async fn table_row_counts(&self, _database: Option<&str>) -> Result<RowCounts> {
+        let mut connection = Self::connect(&self.path(), true).await?;
+
+        let tables: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )
+        .fetch_all(&mut connection)
+        .await
+        .map_err(|e| anyhow!("Failed to list tables: {}", e))?;
+
+        let mut counts = RowCounts::new();
+
+        for (table,) in tables {
+            let (count,): (i64,) = sqlx::query_as(&format!(
+                "SELECT COUNT(*) FROM \"{}\"",
+                table.replace('"', "\"\"")
+            ))
+            .fetch_one(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Failed to count rows of {}: {}", table, e))?;
+            counts.insert(table, count as u64);
+        }
+
+        connection.close().await.ok();
+
+        Ok(counts)
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        Err(anyhow!("A SQLite file holds a single database"))
+    }
+
+    async fn run_query(&self, query: &str) -> Result<String> {
+        let mut connection = Self::connect(&self.path(), false).await?;
+
+        let rows = sqlx::query(query)
+            .fetch_all(&mut connection)
+            .await
+            .map_err(|e| anyhow!("Query failed: {}", e))?;
+
+        connection.close().await.ok();
+
+        Ok(rows.iter().map(format_row).collect::<Vec<_>>().join("\n"))
+    }
+
+    async fn inspect_dump(
+        &self,
+        reader: &mut (dyn Read + Send + Unpin),
+        options: RestoreOptions,
+    ) -> Result<DumpSummary> {
+        let mut staged = tempfile::NamedTempFile::new().context("Failed to create restore file")?;
+        copy_with_buffer(reader, staged.as_file_mut(), options.buffer_size())
+            .map_err(|e| anyhow!("Failed to read backup data: {}", e))?;
+        staged.as_file_mut().flush()?;
+
+        Self::check_database(staged.path()).await?;
+
+        let mut connection = Self::connect(staged.path(), true).await?;
+
+        let objects: Vec<(String, String)> = sqlx::query_as(
+            "SELECT type, name FROM sqlite_master WHERE name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .fetch_all(&mut connection)
+        .await
+        .map_err(|e| anyhow!("Failed to list backup contents: {}", e))?;
+
+        connection.close().await.ok();
+
+        Ok(DumpSummary {
+            dump_version: None,
+            object_count: objects.len(),
+            tables: objects
+                .into_iter()
+                .filter(|(object_type, _)| object_type == "table")
+                .map(|(_, name)| name)
+                .collect(),
+        })
+    }
+}
+
+/// Renders a row the way `psql --no-align` does, columns separated by `|`
+fn format_row(row: &SqliteRow) -> String {
+    (0..row.columns().len())
+        .map(|index| {
+            if let Ok(value) = row.try_get::<Option<String>, _>(index) {
+                value.unwrap_or_default()
+            } else if let Ok(Some(value)) = row.try_get::<Option<i64>, _>(index) {
+                value.to_string()
+            } else if let Ok(Some(value)) = row.try_get::<Option<f64>, _>(index) {
+                value.to_string()
+            } else {
+                format!("<{}>", row.column(index).name())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn copy_with_buffer(
+    reader: &mut (impl Read + ?Sized),
+    writer: &mut (impl Write + ?Sized),
+    buffer_size: usize,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; buffer_size];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => writer.write_all(&buffer[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod connection;
+mod tests;
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod sqlite_connection_test {
+    use std::path::Path;
+
+    use sqlx::{sqlite::SqliteConnectOptions, Connection, SqliteConnection as SqlxConnection};
+    use tempfile::tempdir;
+
+    use crate::databases::sqlite::connection::SqliteConnection;
+    use crate::databases::version::Version;
+    use crate::databases::{ConnectionType, DatabaseConfig, DatabaseConnectionTrait};
+    use crate::test_utils::test_utils::initialize_test;
+
+    fn get_config(path: &Path) -> DatabaseConfig {
+        DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::SQLite,
+            host: String::new(),
+            port: 0,
+            database: path.to_string_lossy().to_string(),
+            username: String::new(),
+            password: None,
+            ssh_tunnel: None,
+            connect_timeout: None,
+        }
+    }
+
+    async fn create_database(path: &Path, value: &str) {
+        let connect_options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let mut connection = SqlxConnection::connect_with(&connect_options)
+            .await
+            .expect("Failed to create database");
+
+        sqlx::query("CREATE TABLE items (name TEXT NOT NULL)")
+            .execute(&mut connection)
+            .await
+            .expect("Failed to create table");
+        sqlx::query("INSERT INTO items (name) VALUES (?)")
+            .bind(value)
+            .execute(&mut connection)
+            .await
+            .expect("Failed to insert row");
+
+        connection.close().await.expect("Failed to close database");
+    }
+
+    #[tokio::test]
+    async fn test_01_connection_test() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let path = dir.path().join("app.db");
+
+        let connection = SqliteConnection::new(get_config(&path))
+            .await
+            .expect("Failed to get connection");
+        assert!(connection.test().await.is_err());
+
+        create_database(&path, "first").await;
+        assert!(connection.test().await.expect("Failed to test connection"));
+
+        let metadata = connection
+            .get_metadata()
+            .await
+            .expect("Failed to get metadata");
+        assert!(matches!(metadata.version, Version::SQLite(_)));
+
+        std::fs::write(&path, b"not a database").expect("Failed to overwrite database");
+        assert!(connection.test().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_02_backup_and_restore() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let path = dir.path().join("app.db");
+        create_database(&path, "first").await;
+
+        let connection = SqliteConnection::new(get_config(&path))
+            .await
+            .expect("Failed to get connection");
+
+        let mut backup = Vec::new();
+        connection
+            .backup(&mut backup)
+            .await
+            .expect("Failed to backup database");
+        assert!(backup.starts_with(b"SQLite format 3\0"));
+
+        std::fs::remove_file(&path).expect("Failed to remove database");
+        create_database(&path, "second").await;
+
+        connection
+            .restore(&mut backup.as_slice())
+            .await
+            .expect("Failed to restore database");
+
+        let rows = connection
+            .run_query("SELECT name FROM items")
+            .await
+            .expect("Failed to query restored database");
+        assert_eq!(rows, "first");
+    }
+
+    #[tokio::test]
+    async fn test_03_invalid_backup_keeps_database() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let path = dir.path().join("app.db");
+        create_database(&path, "first").await;
+
+        let connection = SqliteConnection::new(get_config(&path))
+            .await
+            .expect("Failed to get connection");
+
+        let result = connection.restore(&mut &b"garbage"[..]).await;
+        assert!(result.is_err());
+
+        let rows = connection
+            .run_query("SELECT name FROM items")
+            .await
+            .expect("Failed to query database");
+        assert_eq!(rows, "first");
+    }
+}
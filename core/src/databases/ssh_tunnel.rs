@@ -1,6 +1,7 @@
 use std::{
     io::{Read, Write},
     net::{Shutdown, TcpListener, TcpStream},
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
@@ -21,6 +22,19 @@ pub struct SshTunnelConfig {
     pub port: u16,
     pub username: String,
     pub auth_method: SshAuthMethod,
+    /// Bastions to hop through, in order, before reaching `host`
+    #[serde(default)]
+    pub jump_hosts: Vec<SshJumpHost>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshJumpHost {
+    pub host: String,
+    pub port: u16,
+    /// Defaults to the username of the tunnel
+    pub username: Option<String>,
+    /// Defaults to the auth method of the tunnel
+    pub auth_method: Option<SshAuthMethod>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,16 +52,26 @@ pub enum SshAuthMethod {
         key_path: String,
         passphrase_key: Option<String>,
     },
+    /// Use the identities loaded in the running ssh-agent (`SSH_AUTH_SOCK`)
+    Agent,
 }
 
 pub struct SshTunnel {
     pub local_port: u16,
     shutdown_signal: Arc<AtomicBool>,
     thread_handle: Option<JoinHandle<()>>,
+    jump_tunnels: Vec<SshTunnel>,
 }
 
 impl SshTunnel {
     pub fn new(ssh_config: SshTunnelConfig, remote_config: SshRemoteConfig) -> Result<Self> {
+        if let SshAuthMethod::PrivateKey { key_path, .. } = &ssh_config.auth_method {
+            if !Path::new(key_path).is_file() {
+                return Err(anyhow!("SSH key file '{}' does not exist", key_path));
+            }
+        }
+
+        let (jump_tunnels, ssh_config) = Self::open_jump_hosts(ssh_config)?;
         let shutdown_signal = Arc::new(AtomicBool::new(false));
         let local_port = Self::find_available_port()?;
         let (setup_tx, setup_rx) = channel();
@@ -78,9 +102,72 @@ impl SshTunnel {
             thread_handle: Some(thread_handle),
             shutdown_signal,
             local_port,
+            jump_tunnels,
         })
     }
 
+    /// Forwards a local port to each hop through the previous one and returns the
+    /// config reaching the final host through the last of them
+    fn open_jump_hosts(
+        mut ssh_config: SshTunnelConfig,
+    ) -> Result<(Vec<SshTunnel>, SshTunnelConfig)> {
+        let jump_hosts = std::mem::take(&mut ssh_config.jump_hosts);
+        let mut tunnels: Vec<SshTunnel> = Vec::new();
+
+        for (index, jump_host) in jump_hosts.iter().enumerate() {
+            let next_hop = match jump_hosts.get(index + 1) {
+                Some(next) => SshRemoteConfig {
+                    host: next.host.clone(),
+                    port: next.port,
+                },
+                None => SshRemoteConfig {
+                    host: ssh_config.host.clone(),
+                    port: ssh_config.port,
+                },
+            };
+
+            let (host, port) = match tunnels.last() {
+                Some(previous) => ("127.0.0.1".to_string(), previous.local_port),
+                None => (jump_host.host.clone(), jump_host.port),
+            };
+
+            let hop_config = SshTunnelConfig {
+                host,
+                port,
+                username: jump_host
+                    .username
+                    .clone()
+                    .unwrap_or_else(|| ssh_config.username.clone()),
+                auth_method: jump_host
+                    .auth_method
+                    .clone()
+                    .unwrap_or_else(|| ssh_config.auth_method.clone()),
+                jump_hosts: Vec::new(),
+            };
+
+            let tunnel = SshTunnel::new(hop_config, next_hop).with_context(|| {
+                format!(
+                    "Failed to hop through {}:{}",
+                    jump_host.host, jump_host.port
+                )
+            })?;
+
+            debug!(
+                "Jump host {}:{} forwarded to local port {}",
+                jump_host.host, jump_host.port, tunnel.local_port
+            );
+
+            tunnels.push(tunnel);
+        }
+
+        if let Some(last) = tunnels.last() {
+            ssh_config.host = "127.0.0.1".into();
+            ssh_config.port = last.local_port;
+        }
+
+        Ok((tunnels, ssh_config))
+    }
+
     fn find_available_port() -> Result<u16> {
         let listener = TcpListener::bind("127.0.0.1:0")
             .map_err(|e| anyhow!("Failed to find available port: {}", e))?;
@@ -164,7 +251,7 @@ impl SshTunnel {
                 if let Err(e) = session.userauth_pubkey_file(
                     &ssh_config.username,
                     None,
-                    std::path::Path::new(key_path),
+                    Path::new(key_path),
                     passphrase_key,
                 ) {
                     shutdown_signal.store(true, Ordering::Relaxed);
@@ -176,6 +263,17 @@ impl SshTunnel {
                     return;
                 };
             }
+            SshAuthMethod::Agent => {
+                if let Err(e) = session.userauth_agent(&ssh_config.username) {
+                    shutdown_signal.store(true, Ordering::Relaxed);
+                    if let Err(e) =
+                        setup_tx.send(Err(anyhow!("SSH agent authentication failed: {}", e)))
+                    {
+                        warn!("Failed to send setup message: {}", e);
+                    };
+                    return;
+                };
+            }
         }
 
         let listener = match TcpListener::bind(format!("127.0.0.1:{}", local_port)) {
@@ -405,6 +503,11 @@ impl Drop for SshTunnel {
                 );
             }
         }
+
+        // Each hop runs through the previous one, close them from the inside out
+        while let Some(jump_tunnel) = self.jump_tunnels.pop() {
+            drop(jump_tunnel);
+        }
     }
 }
 
@@ -416,7 +519,7 @@ mod ssh_tunnel_tests {
 
     use crate::databases::{
         postgres::connection::PostgreSqlConnection,
-        ssh_tunnel::{SshAuthMethod, SshRemoteConfig, SshTunnel, SshTunnelConfig},
+        ssh_tunnel::{SshAuthMethod, SshJumpHost, SshRemoteConfig, SshTunnel, SshTunnelConfig},
         ConnectionType, DatabaseConfig, DatabaseConnectionTrait,
     };
 
@@ -438,6 +541,7 @@ mod ssh_tunnel_tests {
                 key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                 passphrase_key: None,
             },
+            jump_hosts: Vec::new(),
         };
 
         let remote_port: u16 = env::var("POSTGRESQL_PORT")
@@ -464,6 +568,77 @@ mod ssh_tunnel_tests {
             database: env::var("DB_NAME").unwrap_or_default(),
             password: Some(password),
             ssh_tunnel: None,
+            connect_timeout: None,
+        };
+
+        let postgres_connection = PostgreSqlConnection::new(database_config)
+            .await
+            .expect("Unable to get postgresql connection");
+
+        let is_connected = postgres_connection
+            .test()
+            .await
+            .expect("Failed to test database connection");
+
+        assert!(is_connected);
+    }
+
+    /// Needs `SSH_JUMP_HOST_1` and `SSH_JUMP_HOST_2`, two bastions the tunnel host is
+    /// only reachable through, accepting the same credentials as the tunnel host
+    #[ignore]
+    #[tokio::test]
+    async fn test_02_run_tunnel_through_jump_hosts() {
+        dotenv().ok();
+
+        let (Ok(first_hop), Ok(second_hop)) =
+            (env::var("SSH_JUMP_HOST_1"), env::var("SSH_JUMP_HOST_2"))
+        else {
+            return;
+        };
+
+        let jump_host = |host: String| SshJumpHost {
+            host,
+            port: 22,
+            username: None,
+            auth_method: None,
+        };
+
+        let ssh_config = SshTunnelConfig {
+            host: env::var("SSH_HOST").unwrap_or_default(),
+            username: env::var("SSH_USERNAME").unwrap_or_default(),
+            port: 22,
+            auth_method: SshAuthMethod::PrivateKey {
+                key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
+                passphrase_key: None,
+            },
+            jump_hosts: vec![jump_host(first_hop), jump_host(second_hop)],
+        };
+
+        let remote_port: u16 = env::var("POSTGRESQL_PORT")
+            .unwrap_or("0".into())
+            .parse()
+            .expect("Unable to parse remote port");
+
+        let tunnel = SshTunnel::new(
+            ssh_config,
+            SshRemoteConfig {
+                host: "localhost".into(),
+                port: remote_port,
+            },
+        )
+        .expect("Failed to get ssh tunnel through jump hosts");
+
+        let database_config = DatabaseConfig {
+            id: "test".into(),
+            name: "test".into(),
+            connection_type: ConnectionType::PostgreSql,
+            host: "localhost".into(),
+            port: tunnel.local_port,
+            username: env::var("DB_USERNAME").unwrap_or_default(),
+            database: env::var("DB_NAME").unwrap_or_default(),
+            password: Some(env::var("DB_PASSWORD").unwrap_or_default()),
+            ssh_tunnel: None,
+            connect_timeout: None,
         };
 
         let postgres_connection = PostgreSqlConnection::new(database_config)
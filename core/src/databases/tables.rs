@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Context, Result};
+use globset::{Glob, GlobMatcher};
+
+/// Tables picked by the `include_tables` and `exclude_tables` patterns of `BackupOptions`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TableSelection {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Expands the include and exclude glob patterns against the tables of the database.
+///
+/// Table names may be qualified by their schema (`public.orders`). A pattern without a
+/// dot matches the unqualified name, so `audit_*` matches `public.audit_log`.
+pub(crate) fn select_tables(
+    tables: &[String],
+    include: &[String],
+    exclude: &[String],
+) -> Result<TableSelection> {
+    let mut selection = TableSelection::default();
+
+    for pattern in include {
+        let matched = matching_tables(tables, pattern)?;
+
+        if matched.is_empty() {
+            return Err(anyhow!(
+                "No table matches the include pattern '{}'",
+                pattern
+            ));
+        }
+
+        selection.include.extend(matched);
+    }
+
+    for pattern in exclude {
+        selection.exclude.extend(matching_tables(tables, pattern)?);
+    }
+
+    selection.include.sort();
+    selection.include.dedup();
+    selection.exclude.sort();
+    selection.exclude.dedup();
+
+    if let Some(table) = selection
+        .include
+        .iter()
+        .find(|table| selection.exclude.contains(table))
+    {
+        return Err(anyhow!(
+            "Table {} is matched by both an include and an exclude pattern",
+            table
+        ));
+    }
+
+    Ok(selection)
+}
+
+fn matching_tables(tables: &[String], pattern: &str) -> Result<Vec<String>> {
+    let matcher = build_matcher(pattern)?;
+    let qualified = pattern.contains('.');
+
+    Ok(tables
+        .iter()
+        .filter(|table| {
+            let name = match table.rsplit_once('.') {
+                Some((_, name)) if !qualified => name,
+                _ => table.as_str(),
+            };
+
+            matcher.is_match(name)
+        })
+        .cloned()
+        .collect())
+}
+
+fn build_matcher(pattern: &str) -> Result<GlobMatcher> {
+    let glob = Glob::new(pattern).context(format!("Invalid table pattern: {}", pattern))?;
+    Ok(glob.compile_matcher())
+}
+
+/// Databases of a server picked by include and exclude glob patterns, for backing up
+/// every database at once. Without include patterns every database is picked.
+pub fn select_databases(
+    databases: &[String],
+    include: &[String],
+    exclude: &[String],
+) -> Result<Vec<String>> {
+    let build = |patterns: &[String]| -> Result<Vec<GlobMatcher>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Glob::new(pattern)
+                    .map(|glob| glob.compile_matcher())
+                    .context(format!("Invalid database pattern: {}", pattern))
+            })
+            .collect()
+    };
+    let include = build(include)?;
+    let exclude = build(exclude)?;
+
+    Ok(databases
+        .iter()
+        .filter(|database| {
+            include.is_empty() || include.iter().any(|matcher| matcher.is_match(database))
+        })
+        .filter(|database| !exclude.iter().any(|matcher| matcher.is_match(database)))
+        .cloned()
+        .collect())
+}
+
+#[cfg(test)]
+mod tables_tests {
+    use super::{select_databases, select_tables};
+
+    fn tables() -> Vec<String> {
+        [
+            "public.orders",
+            "public.order_items",
+            "public.audit_log",
+            "sales.orders",
+        ]
+        .iter()
+        .map(|table| table.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_select_tables() {
+        let selection = select_tables(&tables(), &["order*".to_string()], &[]).unwrap();
+        assert_eq!(
+            selection.include,
+            vec!["public.order_items", "public.orders", "sales.orders"]
+        );
+
+        let selection = select_tables(&tables(), &["public.orders".to_string()], &[]).unwrap();
+        assert_eq!(selection.include, vec!["public.orders"]);
+
+        let selection = select_tables(&tables(), &[], &["audit_*".to_string()]).unwrap();
+        assert!(selection.include.is_empty());
+        assert_eq!(selection.exclude, vec!["public.audit_log"]);
+    }
+
+    #[test]
+    fn test_select_tables_errors() {
+        assert!(select_tables(&tables(), &["missing_*".to_string()], &[]).is_err());
+        assert!(select_tables(&tables(), &["[".to_string()], &[]).is_err());
+
+        let error = select_tables(&tables(), &["orders".to_string()], &["sales.*".to_string()])
+            .unwrap_err();
+        assert!(error.to_string().contains("sales.orders"));
+    }
+
+    #[test]
+    fn test_select_databases() {
+        let databases: Vec<String> = ["shop", "shop_staging", "analytics"]
+            .iter()
+            .map(|database| database.to_string())
+            .collect();
+
+        assert_eq!(select_databases(&databases, &[], &[]).unwrap(), databases);
+        assert_eq!(
+            select_databases(
+                &databases,
+                &["shop*".to_string()],
+                &["*_staging".to_string()]
+            )
+            .unwrap(),
+            vec!["shop"]
+        );
+        assert!(select_databases(&databases, &["[".to_string()], &[]).is_err());
+    }
+}
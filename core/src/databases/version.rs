@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
 
-use super::{mysql::version::MySqlVersion, postgres::version::PostgreSQLVersion};
+use super::{
+    mongo::version::MongoDbVersion, mysql::version::MySqlVersion,
+    postgres::version::PostgreSQLVersion,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Version {
     PostgreSQL(PostgreSQLVersion),
     MySql(MySqlVersion),
+    MariaDB(MySqlVersion),
+    /// Reported by `sqlite_version()`. SQLite needs no external tools, so this never
+    /// selects an archive to install.
+    SQLite(String),
+    MongoDB(MongoDbVersion),
 }
 
 pub trait VersionTrait: Sized + ToString {
@@ -0,0 +1,410 @@
+use aes_gcm::{
+    aead::{
+        generic_array::GenericArray,
+        rand_core::RngCore,
+        stream::{DecryptorBE32, EncryptorBE32},
+        OsRng,
+    },
+    Aes256Gcm, KeyInit,
+};
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor, Read, Write};
+
+/// Written at the start of every encrypted object, followed by the key derivation byte,
+/// the salt and the nonce prefix
+const MAGIC: &[u8; 8] = b"DBKPENC1";
+const SALT_SIZE: usize = 16;
+/// Nonce size of AES-GCM minus the 5 bytes used by the STREAM counter and last-block flag
+const NONCE_PREFIX_SIZE: usize = 7;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + SALT_SIZE + NONCE_PREFIX_SIZE;
+const TAG_SIZE: usize = 16;
+/// Plaintext size of every chunk but the last
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const KDF_NONE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum EncryptionKey {
+    /// A 256-bit key used as is
+    Key(Vec<u8>),
+    /// A passphrase the key is derived from with Argon2id, using a random salt per object
+    Passphrase(String),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncryptionOptions {
+    pub key: EncryptionKey,
+}
+
+impl EncryptionKey {
+    fn kdf(&self) -> u8 {
+        match self {
+            EncryptionKey::Key(_) => KDF_NONE,
+            EncryptionKey::Passphrase(_) => KDF_ARGON2ID,
+        }
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Result<Aes256Gcm> {
+        match self {
+            EncryptionKey::Key(key) => Aes256Gcm::new_from_slice(key)
+                .map_err(|_| anyhow!("Encryption keys must be 32 bytes long")),
+            EncryptionKey::Passphrase(passphrase) => {
+                let mut key = [0u8; 32];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|e| anyhow!("Failed to derive encryption key: {}", e))?;
+                Ok(Aes256Gcm::new(&key.into()))
+            }
+        }
+    }
+}
+
+fn crypto_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Decryption failed, the key is wrong or the backup is corrupted",
+    )
+}
+
+/// The first chunk failing to authenticate is most likely a wrong key, so it is reported
+/// as invalid input rather than invalid data, which marks a backup as corrupted.
+fn wrong_key_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Decryption failed, the key is wrong or the backup is corrupted",
+    )
+}
+
+fn corrupted_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Decryption failed, the backup is corrupted",
+    )
+}
+
+/// Encrypts everything written to it with AES-256-GCM in the STREAM construction, so
+/// backups of any size can be encrypted chunk by chunk and truncation is detected.
+pub enum Encryptor<W: Write + Send + Unpin> {
+    Aes256Gcm {
+        writer: W,
+        // Boxed, the cipher state dwarfs the plain variant
+        encryptor: Option<Box<EncryptorBE32<Aes256Gcm>>>,
+        buffer: Vec<u8>,
+    },
+    None(W),
+}
+
+impl<W: Write + Send + Unpin> Encryptor<W> {
+    pub fn new(mut writer: W, options: Option<&EncryptionOptions>) -> Result<Self> {
+        let Some(options) = options else {
+            return Ok(Encryptor::None(writer));
+        };
+
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce_prefix);
+
+        let cipher = options.key.cipher(&salt)?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[options.key.kdf()])?;
+        writer.write_all(&salt)?;
+        writer.write_all(&nonce_prefix)?;
+
+        Ok(Encryptor::Aes256Gcm {
+            writer,
+            encryptor: Some(Box::new(EncryptorBE32::from_aead(
+                cipher,
+                GenericArray::from_slice(&nonce_prefix),
+            ))),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encryptor::Aes256Gcm {
+                mut writer,
+                encryptor,
+                buffer,
+            } => {
+                let encryptor = encryptor.ok_or_else(crypto_error)?;
+                let chunk = encryptor
+                    .encrypt_last(buffer.as_slice())
+                    .map_err(|_| crypto_error())?;
+                writer.write_all(&chunk)?;
+                Ok(writer)
+            }
+            Encryptor::None(writer) => Ok(writer),
+        }
+    }
+}
+
+impl<W: Write + Send + Unpin> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encryptor::Aes256Gcm {
+                writer,
+                encryptor,
+                buffer,
+            } => {
+                // A full chunk is only sealed once more data arrives, the final one has to
+                // be sealed as last in `finish`
+                if buffer.len() == CHUNK_SIZE {
+                    let chunk = encryptor
+                        .as_mut()
+                        .ok_or_else(crypto_error)?
+                        .encrypt_next(buffer.as_slice())
+                        .map_err(|_| crypto_error())?;
+                    writer.write_all(&chunk)?;
+                    buffer.clear();
+                }
+
+                let n = buf.len().min(CHUNK_SIZE - buffer.len());
+                buffer.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+            Encryptor::None(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encryptor::Aes256Gcm { writer, .. } => writer.flush(),
+            Encryptor::None(writer) => writer.flush(),
+        }
+    }
+}
+
+pub enum Decryptor<R: Read + Send + Unpin> {
+    Aes256Gcm {
+        reader: R,
+        // Boxed, the cipher state dwarfs the plain variant
+        decryptor: Option<Box<DecryptorBE32<Aes256Gcm>>>,
+        chunk: Vec<u8>,
+        lookahead: Option<u8>,
+        plaintext: Cursor<Vec<u8>>,
+        /// Whether a chunk has been decrypted, which proves the key is right
+        authenticated: bool,
+    },
+    None(io::Chain<Cursor<Vec<u8>>, R>),
+}
+
+impl<R: Read + Send + Unpin> Decryptor<R> {
+    /// Reads the header of the object to tell whether it is encrypted. Fails when an
+    /// encrypted backup is read without a key, or a key is given for a plain one.
+    pub fn new(mut reader: R, options: Option<&EncryptionOptions>) -> Result<Self> {
+        let mut header = vec![0u8; HEADER_SIZE];
+        let read = read_full(&mut reader, &mut header)?;
+        header.truncate(read);
+
+        let is_encrypted = header.len() == HEADER_SIZE && header.starts_with(MAGIC);
+
+        let options = match (is_encrypted, options) {
+            (false, None) => return Ok(Decryptor::None(Cursor::new(header).chain(reader))),
+            (false, Some(_)) => return Err(anyhow!("The backup is not encrypted")),
+            (true, None) => {
+                return Err(anyhow!(
+                    "The backup is encrypted, an encryption key or passphrase is required"
+                ))
+            }
+            (true, Some(options)) => options,
+        };
+
+        let kdf = header[MAGIC.len()];
+        if kdf != options.key.kdf() {
+            return Err(anyhow!(match kdf {
+                KDF_ARGON2ID => "The backup was encrypted with a passphrase, not a key",
+                _ => "The backup was encrypted with a key, not a passphrase",
+            }));
+        }
+
+        let salt = &header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_SIZE];
+        let nonce_prefix = &header[HEADER_SIZE - NONCE_PREFIX_SIZE..];
+        let cipher = options.key.cipher(salt)?;
+
+        Ok(Decryptor::Aes256Gcm {
+            reader,
+            decryptor: Some(Box::new(DecryptorBE32::from_aead(
+                cipher,
+                GenericArray::from_slice(nonce_prefix),
+            ))),
+            chunk: Vec::with_capacity(CHUNK_SIZE + TAG_SIZE),
+            lookahead: None,
+            plaintext: Cursor::new(Vec::new()),
+            authenticated: false,
+        })
+    }
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+impl<R: Read + Send + Unpin> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (reader, decryptor, chunk, lookahead, plaintext, authenticated) = match self {
+            Decryptor::Aes256Gcm {
+                reader,
+                decryptor,
+                chunk,
+                lookahead,
+                plaintext,
+                authenticated,
+            } => (
+                reader,
+                decryptor,
+                chunk,
+                lookahead,
+                plaintext,
+                authenticated,
+            ),
+            Decryptor::None(reader) => return reader.read(buf),
+        };
+
+        loop {
+            let n = plaintext.read(buf)?;
+            if n > 0 || buf.is_empty() || decryptor.is_none() {
+                return Ok(n);
+            }
+
+            // Read the next sealed chunk, plus one byte to tell whether it is the last one
+            chunk.clear();
+            chunk.extend(lookahead.take());
+            let start = chunk.len();
+            chunk.resize(CHUNK_SIZE + TAG_SIZE, 0);
+            let read = read_full(reader, &mut chunk[start..])?;
+            chunk.truncate(start + read);
+
+            let mut next = [0u8; 1];
+            let is_last = chunk.len() < CHUNK_SIZE + TAG_SIZE || read_full(reader, &mut next)? == 0;
+
+            let decrypted = if is_last {
+                decryptor
+                    .take()
+                    .ok_or_else(crypto_error)?
+                    .decrypt_last(chunk.as_slice())
+            } else {
+                *lookahead = Some(next[0]);
+                decryptor
+                    .as_mut()
+                    .ok_or_else(crypto_error)?
+                    .decrypt_next(chunk.as_slice())
+            };
+
+            let decrypted = decrypted.map_err(|_| match authenticated {
+                true => corrupted_error(),
+                false => wrong_key_error(),
+            })?;
+            *authenticated = true;
+            *plaintext = Cursor::new(decrypted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod encryption_test {
+    use std::io::{Read, Write};
+
+    use super::{Decryptor, EncryptionKey, EncryptionOptions, Encryptor, CHUNK_SIZE};
+
+    fn encrypt(data: &[u8], options: &EncryptionOptions) -> Vec<u8> {
+        let mut encryptor =
+            Encryptor::new(Vec::new(), Some(options)).expect("Failed to create encryptor");
+        encryptor.write_all(data).expect("Failed to write bytes");
+        encryptor.finish().expect("Unable to finish encryptor")
+    }
+
+    fn decrypt(data: &[u8], options: &EncryptionOptions) -> std::io::Result<Vec<u8>> {
+        let mut decryptor =
+            Decryptor::new(data, Some(options)).expect("Failed to create decryptor");
+        let mut decrypted = Vec::new();
+        decryptor.read_to_end(&mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn roundtrip() {
+        // Spans several chunks and ends with a partial one
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| i as u8).collect();
+
+        for key in [
+            EncryptionKey::Key(vec![7u8; 32]),
+            EncryptionKey::Passphrase("correct horse battery staple".into()),
+        ] {
+            let options = EncryptionOptions { key };
+            let encrypted = encrypt(&data, &options);
+
+            assert_ne!(
+                &encrypted[encrypted.len() - 100..],
+                &data[data.len() - 100..]
+            );
+            assert_eq!(
+                decrypt(&encrypted, &options).expect("Failed to decrypt"),
+                data
+            );
+        }
+
+        let options = EncryptionOptions {
+            key: EncryptionKey::Key(vec![7u8; 32]),
+        };
+        assert!(decrypt(&encrypt(b"", &options), &options)
+            .expect("Failed to decrypt")
+            .is_empty());
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let data = b"Ceci est un texte test";
+        let encrypted = encrypt(
+            data,
+            &EncryptionOptions {
+                key: EncryptionKey::Passphrase("right".into()),
+            },
+        );
+
+        let result = decrypt(
+            &encrypted,
+            &EncryptionOptions {
+                key: EncryptionKey::Passphrase("wrong".into()),
+            },
+        );
+        assert_eq!(
+            result.expect_err("Decrypted with the wrong key").kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+
+        assert!(Decryptor::new(encrypted.as_slice(), None).is_err());
+    }
+
+    #[test]
+    fn truncated_backup_fails() {
+        let options = EncryptionOptions {
+            key: EncryptionKey::Key(vec![1u8; 32]),
+        };
+        let data = vec![42u8; CHUNK_SIZE * 2];
+        let encrypted = encrypt(&data, &options);
+
+        assert_eq!(
+            decrypt(&encrypted[..encrypted.len() - 10], &options)
+                .expect_err("Decrypted a truncated backup")
+                .kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::provider::StorageProvider;
+
+/// Name of the state object written under the target prefix. It records the size and
+/// modification time of every uploaded file so later runs only upload what changed.
+pub const FOLDER_STATE_NAME: &str = ".dbkp-folder-state.json";
+
+/// Persist the state after this many uploads, so an interrupted run loses little progress
+const STATE_SAVE_INTERVAL: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    pub size: u64,
+    pub modified_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderState {
+    pub files: BTreeMap<String, FileFingerprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Uploaded,
+    Unchanged,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub path: String,
+    pub size: u64,
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FolderBackupReport {
+    pub files: Vec<FileResult>,
+}
+
+impl FolderBackupReport {
+    fn count(&self, predicate: impl Fn(&FileStatus) -> bool) -> usize {
+        self.files
+            .iter()
+            .filter(|file| predicate(&file.status))
+            .count()
+    }
+
+    pub fn uploaded(&self) -> usize {
+        self.count(|status| *status == FileStatus::Uploaded)
+    }
+
+    pub fn unchanged(&self) -> usize {
+        self.count(|status| *status == FileStatus::Unchanged)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Failed(_)))
+    }
+}
+
+/// Uploads every file under `folder` to `prefix`, skipping files whose size and
+/// modification time match the previous run. Failed files are reported and retried on
+/// the next run rather than aborting the whole backup.
+pub async fn backup_folder(
+    provider: &StorageProvider,
+    folder: &Path,
+    prefix: &str,
+) -> Result<FolderBackupReport> {
+    if !folder.is_dir() {
+        return Err(anyhow!("Not a directory: {}", folder.display()));
+    }
+
+    let state_key = object_key(prefix, FOLDER_STATE_NAME);
+    let previous = load_state(provider, &state_key).await?;
+
+    let mut files = Vec::new();
+    collect_files(folder, &mut files)?;
+    files.sort();
+
+    let mut state = FolderState::default();
+    let mut report = FolderBackupReport::default();
+    let mut uploads_since_save = 0;
+
+    for path in files {
+        let relative_path = path
+            .strip_prefix(folder)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        let fingerprint = match fingerprint(&path) {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                warn!("Failed to read metadata of {}: {}", path.display(), e);
+                report.files.push(FileResult {
+                    path: relative_path,
+                    size: 0,
+                    status: FileStatus::Failed(e.to_string()),
+                });
+                continue;
+            }
+        };
+
+        let size = fingerprint.size;
+
+        if previous.files.get(&relative_path) == Some(&fingerprint) {
+            debug!("Skipping unchanged file {}", relative_path);
+            state.files.insert(relative_path.clone(), fingerprint);
+            report.files.push(FileResult {
+                path: relative_path,
+                size,
+                status: FileStatus::Unchanged,
+            });
+            continue;
+        }
+
+        let status = match upload_file(provider, &path, &object_key(prefix, &relative_path)).await {
+            Ok(()) => {
+                state.files.insert(relative_path.clone(), fingerprint);
+                uploads_since_save += 1;
+                FileStatus::Uploaded
+            }
+            Err(e) => {
+                warn!("Failed to upload {}: {}", relative_path, e);
+                FileStatus::Failed(e.to_string())
+            }
+        };
+
+        report.files.push(FileResult {
+            path: relative_path,
+            size,
+            status,
+        });
+
+        if uploads_since_save >= STATE_SAVE_INTERVAL {
+            save_state(provider, &state_key, &merged(&previous, &state)).await?;
+            uploads_since_save = 0;
+        }
+    }
+
+    // Files that disappeared from the folder drop out of the state here
+    save_state(provider, &state_key, &state).await?;
+
+    Ok(report)
+}
+
+fn object_key(prefix: &str, relative_path: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+
+    if prefix.is_empty() {
+        relative_path.to_string()
+    } else {
+        format!("{}/{}", prefix, relative_path)
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+fn fingerprint(path: &Path) -> io::Result<FileFingerprint> {
+    let metadata = fs::metadata(path)?;
+    let modified_ms = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+
+    Ok(FileFingerprint {
+        size: metadata.len(),
+        modified_ms,
+    })
+}
+
+async fn upload_file(provider: &StorageProvider, path: &Path, key: &str) -> Result<()> {
+    let mut file = File::open(path)?;
+    let mut writer = provider.create_writer(key).await?;
+
+    io::copy(&mut file, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Intermediate saves keep entries from the previous run that haven't been visited yet,
+/// otherwise an interrupted run would forget about them and upload them again.
+fn merged(previous: &FolderState, current: &FolderState) -> FolderState {
+    let mut files = previous.files.clone();
+    files.extend(current.files.clone());
+    FolderState { files }
+}
+
+async fn load_state(provider: &StorageProvider, key: &str) -> Result<FolderState> {
+    if !provider.exists(key).await? {
+        return Ok(FolderState::default());
+    }
+
+    let mut reader = provider.create_reader(key).await?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+async fn save_state(provider: &StorageProvider, key: &str, state: &FolderState) -> Result<()> {
+    let mut writer = provider.create_writer(key).await?;
+    writer.write_all(&serde_json::to_vec(state)?)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod folder_backup_tests {
+    use std::fs;
+
+    use crate::test_utils::test_utils::{get_local_provider, initialize_test};
+
+    use super::backup_folder;
+
+    #[tokio::test]
+    async fn test_rerun_skips_unchanged_files() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let folder = tempfile::tempdir().expect("Failed to create folder");
+
+        fs::create_dir(folder.path().join("nested")).expect("Failed to create nested folder");
+        fs::write(folder.path().join("a.txt"), b"first").expect("Failed to write file");
+        fs::write(folder.path().join("nested/b.txt"), b"second").expect("Failed to write file");
+
+        let report = backup_folder(&provider, folder.path(), "assets")
+            .await
+            .expect("Failed to back up folder");
+        assert_eq!(report.uploaded(), 2);
+        assert_eq!(report.failed(), 0);
+
+        let report = backup_folder(&provider, folder.path(), "assets")
+            .await
+            .expect("Failed to back up folder");
+        assert_eq!(report.uploaded(), 0);
+        assert_eq!(report.unchanged(), 2);
+
+        fs::write(folder.path().join("a.txt"), b"first, changed").expect("Failed to write file");
+
+        let report = backup_folder(&provider, folder.path(), "assets")
+            .await
+            .expect("Failed to back up folder");
+        assert_eq!(report.uploaded(), 1);
+        assert_eq!(report.unchanged(), 1);
+        assert!(provider
+            .exists("assets/nested/b.txt")
+            .await
+            .expect("Failed to check existence"));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod backup;
+
 // use anyhow::{Context, Result};
 // use aws_sdk_s3::{primitives::ByteStream, Client as S3Client};
 // use chrono::Utc;
@@ -31,7 +33,7 @@
 //     concurrency: u8,
 //     include_patterns: Option<Vec<String>>,
 //     exclude_patterns: Option<Vec<String>>,
-//     skip_larger_than: Option<u32>,
+//     skip_larger_than: Option<u64>,
 //     add_timestamp: bool,
 // ) -> Result<BackupStats> {
 //     // Validate the folder path
@@ -65,8 +67,8 @@
 //     let include_set = build_glob_set(include_patterns)?;
 //     let exclude_set = build_glob_set(exclude_patterns)?;
 
-//     // Calculate max file size in bytes if specified
-//     let max_file_size = skip_larger_than.map(|size| size as u64 * 1024 * 1024);
+//     // Size in bytes, see `common::parse_size`
+//     let max_file_size = skip_larger_than;
 
 //     // List all files in the directory recursively
 //     let mut files = Vec::new();
@@ -1,41 +1,313 @@
-use std::io::Write;
+use std::{
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Result};
-use common::get_default_backup_name;
+use chrono::{DateTime, Utc};
+use common::{
+    binlog_state_name, extract_database_from_filename, get_default_backup_name, manifest_name,
+    sidecar_paths, tool_log_path, DEFAULT_PIPELINE_BUFFER_SIZE,
+};
 use compression::{CompressionFormat, Compressor, Decompressor};
-use databases::DatabaseConnection;
+use databases::{
+    is_transient_dump_error, postgres::wal, tables::select_tables, BackupMode, BackupScope,
+    BackupSummary, BinlogPosition, ConnectionType, DatabaseConfig, DatabaseConnection, DumpSummary,
+    RowCounts,
+};
+use encryption::{Decryptor, EncryptionOptions, Encryptor};
 use flate2::Compression;
-use serde::{Deserialize, Serialize};
-use storage::provider::{ListOptions, StorageProvider};
+use lock::{BackupLock, DEFAULT_LOCK_TTL};
+use log::{info, warn};
+use manifest::{compare_row_counts, BackupManifest, DigestWriter, DumpDigest, RowCountMismatch};
+use notifications::{notify_all, Notification, Notifier, Operation};
+use progress::{ProgressReader, ProgressReporter, ProgressWriter};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use storage::{
+    provider::{
+        CleanupOptions, CleanupReport, ListOptions, ReaderOptions, RetentionPolicy, StorageProvider,
+    },
+    transfer::copy_object,
+};
 
 use crate::storage::Entry;
 
 pub mod archives;
+pub mod clock;
 pub mod common;
 pub mod compression;
 pub mod databases;
+pub mod encryption;
 pub mod folders;
+pub mod lock;
+pub mod manifest;
+pub mod metrics;
+pub mod notifications;
+pub mod progress;
+pub mod quarantine;
 pub mod storage;
 mod test_utils;
 mod tests;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct BackupOptions {
-    name: Option<String>,
-    compression_format: Option<CompressionFormat>,
-    compression_level: Option<u32>,
+    pub name: Option<String>,
+    /// Prefix of generated names, e.g. `{name}/{year}/{month}/{name}`, see
+    /// `common::render_name_template`. Ignored when `name` is set.
+    pub name_template: Option<String>,
+    pub compression_format: Option<CompressionFormat>,
+    pub compression_level: Option<u32>,
+    pub per_table_files: Option<bool>,
+    pub update_latest: Option<bool>,
+    /// Back up only the changes since the previous backup of the chain (MySQL binlogs,
+    /// PostgreSQL WAL). The first incremental run takes a full base backup.
+    pub incremental: Option<bool>,
+    /// Buffer size for the dump -> compress -> upload pipeline, see
+    /// `DEFAULT_PIPELINE_BUFFER_SIZE`. Larger values trade memory for fewer, bigger
+    /// storage writes, which mostly pays off on fast links.
+    pub pipeline_buffer_size: Option<usize>,
+    /// Re-run the dump up to this many times when it fails with a transient database
+    /// error such as a lock timeout or a dropped connection
+    pub dump_retries: Option<u32>,
+    /// Keep the full stderr of the dump tools in `<dir>/<backup name>.log`
+    pub tool_log_dir: Option<PathBuf>,
+    /// Dump as of an exported PostgreSQL snapshot, see `ExportedSnapshot`
+    pub snapshot: Option<String>,
+    /// Compression threads for zstd and xz, defaults to 1 so the output is reproducible
+    pub compression_threads: Option<usize>,
+    /// Parts uploaded in parallel, overrides the `upload_concurrency` of the storage
+    pub upload_concurrency: Option<usize>,
+    /// Encrypt the compressed backup before it leaves the machine
+    pub encryption: Option<EncryptionOptions>,
+    /// Additional arguments for the dump tool
+    pub extra_dump_args: Option<Vec<String>>,
+    /// Write a `<name>.manifest.json` describing the backup, see `BackupManifest`.
+    /// Defaults to true. Failing to write it only logs a warning.
+    pub write_manifest: Option<bool>,
+    /// Dump PostgreSQL with this many parallel jobs in directory format, archived as a
+    /// tar stream. Unset keeps the single-stream dump.
+    pub jobs: Option<u8>,
+    /// Dump only the schema or only the data, recorded in the manifest
+    pub scope: Option<BackupScope>,
+    /// Glob patterns of the tables to back up, all tables when unset
+    pub include_tables: Option<Vec<String>>,
+    /// Glob patterns of the tables to leave out of the backup
+    pub exclude_tables: Option<Vec<String>>,
+    /// Count the rows of every backed up table and record them in the manifest, see
+    /// `RestoreOptions::verify_row_counts`. PostgreSQL counts them in the snapshot the
+    /// dump reads, other databases once the dump is done, where rows written in between
+    /// show up as mismatches on restore. Only full backups with data are counted.
+    pub record_row_counts: Option<bool>,
+    /// Hold the `<database name>.lock` object for the duration of the backup, see
+    /// `lock::BackupLock`. Defaults to true.
+    pub lock: Option<bool>,
+    /// Take the lock even when another backup holds it
+    pub force_lock: Option<bool>,
+    #[serde(skip)]
+    pub progress: Option<Arc<dyn ProgressReporter>>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl BackupOptions {
+    /// Fills the options left unset with those of `defaults`, e.g. the backup defaults
+    /// saved with a workspace
+    pub fn with_defaults(self, defaults: &BackupOptions) -> Self {
+        Self {
+            name: self.name.or_else(|| defaults.name.clone()),
+            name_template: self
+                .name_template
+                .or_else(|| defaults.name_template.clone()),
+            compression_format: self
+                .compression_format
+                .or_else(|| defaults.compression_format.clone()),
+            compression_level: self.compression_level.or(defaults.compression_level),
+            per_table_files: self.per_table_files.or(defaults.per_table_files),
+            update_latest: self.update_latest.or(defaults.update_latest),
+            incremental: self.incremental.or(defaults.incremental),
+            pipeline_buffer_size: self.pipeline_buffer_size.or(defaults.pipeline_buffer_size),
+            dump_retries: self.dump_retries.or(defaults.dump_retries),
+            tool_log_dir: self.tool_log_dir.or_else(|| defaults.tool_log_dir.clone()),
+            snapshot: self.snapshot.or_else(|| defaults.snapshot.clone()),
+            compression_threads: self.compression_threads.or(defaults.compression_threads),
+            upload_concurrency: self.upload_concurrency.or(defaults.upload_concurrency),
+            encryption: self.encryption.or_else(|| defaults.encryption.clone()),
+            extra_dump_args: self
+                .extra_dump_args
+                .or_else(|| defaults.extra_dump_args.clone()),
+            write_manifest: self.write_manifest.or(defaults.write_manifest),
+            jobs: self.jobs.or(defaults.jobs),
+            scope: self.scope.or(defaults.scope),
+            include_tables: self
+                .include_tables
+                .or_else(|| defaults.include_tables.clone()),
+            exclude_tables: self
+                .exclude_tables
+                .or_else(|| defaults.exclude_tables.clone()),
+            record_row_counts: self.record_row_counts.or(defaults.record_row_counts),
+            lock: self.lock.or(defaults.lock),
+            force_lock: self.force_lock.or(defaults.force_lock),
+            progress: self.progress.or_else(|| defaults.progress.clone()),
+        }
+    }
+}
+
+/// Name of the pointer object written next to the backups when `update_latest` is set.
+pub const LATEST_POINTER_NAME: &str = "latest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatestPointer {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Tracks an incremental backup chain, MySQL binlogs or PostgreSQL WAL. Stored as
+/// `<database name>.binlog.json` next to the backups. Cleanup keeps or removes the base
+/// and its increments together, and drops the state with them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinlogState {
+    pub base: String,
+    pub increments: Vec<String>,
+    pub position: BinlogPosition,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct RestoreOptions {
     pub name: String,
     pub compression_format: Option<CompressionFormat>,
     pub drop_database_first: Option<bool>,
+    pub per_table_files: Option<bool>,
+    pub single_transaction: Option<bool>,
+    pub charset: Option<String>,
+    pub collation: Option<String>,
+    /// After restoring an incremental base, replay the increments recorded for it in order
+    pub apply_increments: Option<bool>,
+    /// Buffer size for the download -> decompress -> restore pipeline
+    pub pipeline_buffer_size: Option<usize>,
+    /// Bytes fetched from storage per request, see `ReaderOptions`
+    pub download_chunk_size: Option<usize>,
+    /// Chunks fetched from storage in parallel, see `ReaderOptions`
+    pub download_concurrency: Option<usize>,
+    /// Restore even when the backup was taken from a server version the target may not
+    /// support: a different MySQL version or a newer PostgreSQL major version
+    pub allow_version_mismatch: Option<bool>,
+    /// Keep the full stderr of the restore tools in `<dir>/<backup name>.log`
+    pub tool_log_dir: Option<PathBuf>,
+    /// Key or passphrase of an encrypted backup
+    pub encryption: Option<EncryptionOptions>,
+    /// Additional arguments for the restore tool, applied to the main dump only
+    pub extra_restore_args: Option<Vec<String>>,
+    /// Parallel pg_restore jobs for backups taken with `BackupOptions::jobs`
+    pub jobs: Option<u8>,
+    /// Restore into this database instead of the one the connection is configured for
+    pub target_database: Option<String>,
+    /// Only check that the backup can be restored, see `DbBkp::plan_restore`
+    pub dry_run: Option<bool>,
+    /// Stop replaying the WAL at this time, see `restore_data_directory`
+    pub target_time: Option<DateTime<Utc>>,
+    /// Once restored, compare the row count of every table with the counts recorded in
+    /// the manifest, see `BackupOptions::record_row_counts`
+    pub verify_row_counts: Option<bool>,
+    /// Fail the restore when the row counts don't match or weren't recorded, instead of
+    /// only reporting it in `RestoreResult`. Mismatches with counts taken after the dump
+    /// are only reported, see `BackupManifest::row_counts_exact`.
+    pub strict_verify: Option<bool>,
+    /// Drop the objects of a PostgreSQL dump before creating them again, defaults to true.
+    /// Turn it off to restore into an empty database without the DROP statements.
+    pub clean: Option<bool>,
+    #[serde(skip)]
+    pub progress: Option<Arc<dyn ProgressReporter>>,
+}
+
+impl RestoreOptions {
+    /// What the main dump of a backup is restored with
+    fn main_restore_options(&self, tool_log: Option<PathBuf>) -> databases::RestoreOptions {
+        databases::RestoreOptions {
+            drop_database_first: self.drop_database_first.unwrap_or(false),
+            per_table_files: self.per_table_files.unwrap_or(false),
+            single_transaction: self.single_transaction.unwrap_or(false),
+            charset: self.charset.clone(),
+            collation: self.collation.clone(),
+            buffer_size: self.pipeline_buffer_size,
+            allow_version_mismatch: self.allow_version_mismatch.unwrap_or(false),
+            tool_log,
+            extra_restore_args: self.extra_restore_args.clone().unwrap_or_default(),
+            jobs: self.jobs,
+            target_database: self.target_database.clone(),
+            skip_clean: !self.clean.unwrap_or(true),
+        }
+    }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct DestinationStatus {
+    pub destination: String,
+    pub error: Option<String>,
+}
+
+/// Outcome of a backup across the primary storage and its mirrors. The primary always
+/// succeeded, otherwise the backup returns an error instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupResult {
+    pub name: String,
+    /// Size of the stored object, after compression and encryption
+    pub compressed_size: u64,
+    /// SHA-256 of the dump before compression, as recorded in the manifest
+    pub uncompressed_sha256: String,
+    pub destinations: Vec<DestinationStatus>,
+}
+
+impl BackupResult {
+    pub fn failed_destinations(&self) -> Vec<&DestinationStatus> {
+        self.destinations
+            .iter()
+            .filter(|destination| destination.error.is_some())
+            .collect()
+    }
+
+    /// The backup exists, but not on every destination
+    pub fn is_partial_success(&self) -> bool {
+        !self.failed_destinations().is_empty()
+    }
+}
+
+/// Outcome of `DbBkp::restore_with_result`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreResult {
+    /// Whether the row counts were compared with the manifest
+    pub verified: bool,
+    /// Empty when the counts matched or weren't compared
+    pub row_count_mismatches: Vec<RowCountMismatch>,
+}
+
+/// What `DbBkp::restore` would apply, found by `DbBkp::plan_restore`
+#[derive(Debug, Clone, Serialize)]
+pub struct RestorePlan {
+    pub name: String,
+    /// In the order they would be restored
+    pub objects: Vec<PlannedObject>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedObject {
+    pub name: String,
+    pub summary: DumpSummary,
+}
+
+struct RestoreStep {
+    name: String,
+    increment: bool,
+    options: databases::RestoreOptions,
+}
+
+/// Label of the main storage in `BackupResult::destinations`
+pub const PRIMARY_DESTINATION: &str = "primary";
+
 pub struct DbBkp {
     database_connection: DatabaseConnection,
     storage_provider: StorageProvider,
+    mirrors: Vec<(String, StorageProvider)>,
+    notifiers: Vec<Arc<dyn Notifier>>,
 }
 
 impl DbBkp {
@@ -43,57 +315,428 @@ impl DbBkp {
         Self {
             database_connection,
             storage_provider,
+            mirrors: Vec::new(),
+            notifiers: Vec::new(),
         }
     }
 
+    /// Reports the outcome of every backup and restore to `notifier`
+    pub fn with_notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    /// Copies every new backup, with its sidecars, to `storage_provider` once it is
+    /// complete on the primary storage. A failed copy doesn't fail the backup, it is
+    /// reported in the `BackupResult` under `name`.
+    pub fn with_mirror(
+        mut self,
+        name: impl Into<String>,
+        storage_provider: StorageProvider,
+    ) -> Self {
+        self.mirrors.push((name.into(), storage_provider));
+        self
+    }
+
     pub async fn test(&self) -> Result<bool> {
-        let is_database_connected = self.database_connection.connection.test().await?;
-        let is_storage_connected = self.storage_provider.test().await?;
+        self.test_database().await?;
+        self.test_storage().await
+    }
 
-        if !is_database_connected {
+    pub async fn test_database(&self) -> Result<bool> {
+        if !self.database_connection.connection.test().await? {
             return Err(anyhow!("Failed to connect to the database"));
-        } else if !is_storage_connected {
+        }
+
+        Ok(true)
+    }
+
+    pub async fn test_storage(&self) -> Result<bool> {
+        if !self.storage_provider.test().await? {
             return Err(anyhow!("Failed to connect to the storage provider"));
         }
 
-        return Ok(true);
+        Ok(true)
     }
 
+    /// Backs up and returns the backup name. Failed mirror copies are only logged, use
+    /// `backup_with_result` to act on them.
     pub async fn backup_with(&self, options: Option<BackupOptions>) -> Result<String> {
-        let options = match options {
-            Some(options) => options,
-            None => BackupOptions {
-                name: None,
-                compression_format: None,
-                compression_level: None,
-            },
+        let result = self.backup_with_result(options).await?;
+
+        for destination in result.failed_destinations() {
+            warn!(
+                "Backup {} was not copied to {}: {}",
+                result.name,
+                destination.destination,
+                destination.error.as_deref().unwrap_or_default()
+            );
+        }
+
+        Ok(result.name)
+    }
+
+    pub async fn backup_with_result(&self, options: Option<BackupOptions>) -> Result<BackupResult> {
+        let started_at = Instant::now();
+        let result = self.run_backup(options).await;
+
+        let target = match &result {
+            Ok(backup) => backup.name.clone(),
+            Err(_) => self.database_connection.config.name.clone(),
+        };
+        let size = result.as_ref().ok().map(|backup| backup.compressed_size);
+        let notification =
+            Notification::new(Operation::Backup, target, started_at, &result).with_size(size);
+        notify_all(&self.notifiers, &notification).await;
+
+        result
+    }
+
+    async fn run_backup(&self, options: Option<BackupOptions>) -> Result<BackupResult> {
+        let options = options.unwrap_or_default();
+
+        let lock = if options.lock.unwrap_or(true) {
+            Some(
+                BackupLock::acquire(
+                    &self.storage_provider,
+                    &self.database_connection.config.name,
+                    DEFAULT_LOCK_TTL,
+                    options.force_lock.unwrap_or(false),
+                )
+                .await?,
+            )
+        } else {
+            None
         };
 
+        let result = self.run_locked_backup(options).await;
+
+        if let Some(lock) = lock {
+            if let Err(e) = lock.release().await {
+                warn!("Failed to release the backup lock: {}", e);
+            }
+        }
+
+        result
+    }
+
+    async fn run_locked_backup(&self, options: BackupOptions) -> Result<BackupResult> {
         let compression_format = options
             .compression_format
+            .clone()
             .unwrap_or(CompressionFormat::Gzip);
-        let compression_level = options.compression_level.unwrap_or(9);
-        let name = match options.name {
+
+        // Checked up front so a bad level fails before anything is dumped
+        if let Some(level) = options.compression_level {
+            compression_format.validate_level(level)?;
+        }
+
+        let binlog_state = if options.incremental.unwrap_or(false) {
+            read_json::<BinlogState>(&self.storage_provider, &self.binlog_state_name()).await?
+        } else {
+            None
+        };
+
+        let mode = match (options.incremental.unwrap_or(false), &binlog_state) {
+            (false, _) => BackupMode::Full,
+            (true, None) => BackupMode::IncrementalBase,
+            (true, Some(state)) => BackupMode::Incremental {
+                since: state.position.clone(),
+            },
+        };
+
+        let name = match options.name.clone() {
             Some(name) => name,
-            None => get_default_backup_name(&self.database_connection.config, &compression_format),
+            None if matches!(mode, BackupMode::Incremental { .. }) => {
+                let mut config = self.database_connection.config.clone();
+                config.name = format!("{}-incremental", config.name);
+                get_default_backup_name(
+                    &config,
+                    &compression_format,
+                    options.name_template.as_deref(),
+                )?
+            }
+            None => get_default_backup_name(
+                &self.database_connection.config,
+                &compression_format,
+                options.name_template.as_deref(),
+            )?,
+        };
+
+        let buffer_size = options
+            .pipeline_buffer_size
+            .unwrap_or(DEFAULT_PIPELINE_BUFFER_SIZE);
+
+        let tool_log = match &options.tool_log_dir {
+            Some(dir) => Some(tool_log_path(dir, &name)?),
+            None => None,
+        };
+
+        let database_options = databases::BackupOptions {
+            per_table_files: options.per_table_files.unwrap_or(false),
+            mode: mode.clone(),
+            scope: options.scope.unwrap_or_default(),
+            buffer_size: Some(buffer_size),
+            tool_log,
+            snapshot: options.snapshot.clone(),
+            extra_dump_args: options.extra_dump_args.clone().unwrap_or_default(),
+            jobs: options.jobs,
+            include_tables: options.include_tables.clone().unwrap_or_default(),
+            exclude_tables: options.exclude_tables.clone().unwrap_or_default(),
+            count_rows: matches!(mode, BackupMode::Full)
+                && options.record_row_counts.unwrap_or(false)
+                && options.write_manifest.unwrap_or(true)
+                && options.scope.unwrap_or_default() != BackupScope::SchemaOnly,
+        };
+
+        let dump_retries = options.dump_retries.unwrap_or(0);
+        let mut attempt = 0;
+
+        // A failed attempt drops its writer, which discards the partial upload, so each
+        // retry re-runs the dump from scratch into a fresh object
+        let (summary, digest, compressed_size) = loop {
+            let result = self
+                .write_backup(&name, &options, database_options.clone())
+                .await;
+
+            match result {
+                Ok(written) => break written,
+                Err(e) if attempt < dump_retries && is_transient_dump_error(&e) => {
+                    attempt += 1;
+                    let delay = Duration::from_secs(1 << attempt.min(6));
+                    warn!(
+                        "Backup attempt {} failed with a transient error, retrying in {}s: {}",
+                        attempt,
+                        delay.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
         };
 
-        let writer = self.storage_provider.create_writer(&name).await?;
-        let mut compressed_writed = Compressor::new(
-            writer,
+        if let Some(position) = summary.binlog_position {
+            let state = match (mode, binlog_state) {
+                (BackupMode::Incremental { .. }, Some(mut state)) => {
+                    state.increments.push(name.clone());
+                    state.position = position;
+                    state
+                }
+                _ => BinlogState {
+                    base: name.clone(),
+                    increments: Vec::new(),
+                    position,
+                },
+            };
+
+            self.write_json(&self.binlog_state_name(), &state).await?;
+        }
+
+        if options.write_manifest.unwrap_or(true) {
+            // Counted in the dump's snapshot when the database can, after the dump otherwise
+            let (row_counts, row_counts_exact) = match summary.row_counts {
+                Some(row_counts) => (
+                    Some(filter_row_counts(row_counts, &database_options)?),
+                    true,
+                ),
+                None if database_options.count_rows => {
+                    match self.count_backed_up_rows(&database_options).await {
+                        Ok(row_counts) => (Some(row_counts), false),
+                        Err(e) => {
+                            warn!("Failed to count the rows of {}: {}", name, e);
+                            (None, false)
+                        }
+                    }
+                }
+                None => (None, false),
+            };
+
+            let manifest = BackupManifest {
+                name: name.clone(),
+                created_at: Utc::now(),
+                compression_format: compression_format.clone(),
+                scope: options.scope.unwrap_or_default(),
+                uncompressed_size: digest.size,
+                uncompressed_sha256: digest.sha256.clone(),
+                compressed_size: Some(compressed_size),
+                encrypted: options.encryption.is_some(),
+                database_type: Some(self.database_connection.config.connection_type.clone()),
+                database: self
+                    .database_connection
+                    .connection
+                    .get_metadata()
+                    .await
+                    .ok(),
+                tool_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                // Extra dump arguments may carry credentials, e.g. a password option
+                options: Some(BackupOptions {
+                    encryption: None,
+                    extra_dump_args: None,
+                    ..options.clone()
+                }),
+                row_counts,
+                row_counts_exact,
+            };
+
+            // The backup itself is complete, a missing manifest only costs the metadata
+            if let Err(e) = self.write_json(&manifest_name(&name), &manifest).await {
+                warn!("Failed to write the manifest of {}: {}", name, e);
+            }
+        }
+
+        if options.update_latest.unwrap_or(false) {
+            // Don't point readers at a backup they can't see yet
+            self.storage_provider.wait_until_visible(&name).await?;
+            self.update_latest_pointer(&name).await?;
+        }
+
+        let mut destinations = vec![DestinationStatus {
+            destination: PRIMARY_DESTINATION.to_string(),
+            error: None,
+        }];
+        destinations.extend(self.mirror_backup(&name).await?);
+
+        Ok(BackupResult {
+            name,
+            compressed_size,
+            uncompressed_sha256: digest.sha256,
+            destinations,
+        })
+    }
+
+    /// Row counts of the tables the table filters of `options` kept in the backup, counted
+    /// once the dump is done
+    async fn count_backed_up_rows(&self, options: &databases::BackupOptions) -> Result<RowCounts> {
+        let row_counts = self
+            .database_connection
+            .connection
+            .table_row_counts(None)
+            .await?;
+
+        filter_row_counts(row_counts, options)
+    }
+
+    async fn mirror_backup(&self, name: &str) -> Result<Vec<DestinationStatus>> {
+        if self.mirrors.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut objects = vec![name.to_string()];
+        for sidecar in sidecar_paths(name) {
+            if self.storage_provider.exists(&sidecar).await? {
+                objects.push(sidecar);
+            }
+        }
+
+        let mut statuses = Vec::new();
+
+        for (mirror_name, mirror) in &self.mirrors {
+            let mut error = None;
+
+            for object in &objects {
+                if let Err(e) = copy_object(&self.storage_provider, mirror, object).await {
+                    error = Some(format!("{:#}", e));
+                    break;
+                }
+            }
+
+            statuses.push(DestinationStatus {
+                destination: mirror_name.clone(),
+                error,
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    async fn write_backup(
+        &self,
+        name: &str,
+        options: &BackupOptions,
+        database_options: databases::BackupOptions,
+    ) -> Result<(BackupSummary, DumpDigest, u64)> {
+        let compression_format = options
+            .compression_format
+            .clone()
+            .unwrap_or(CompressionFormat::Gzip);
+        let compression = Compression::new(options.compression_level.unwrap_or(9));
+        let compression_threads = options.compression_threads.unwrap_or(1).max(1);
+
+        let buffer_size = database_options.buffer_size();
+        let writer = self
+            .storage_provider
+            .create_writer_with_concurrency(name, options.upload_concurrency)
+            .await?
+            .with_progress(options.progress.clone());
+        let encrypted_writer = Encryptor::new(
+            BufWriter::with_capacity(buffer_size, writer),
+            options.encryption.as_ref(),
+        )?;
+        let compressed_writed = Compressor::new_with_threads(
+            encrypted_writer,
             compression_format,
-            Compression::new(compression_level),
+            compression,
+            compression_threads,
+        )?;
+        let mut progress_writer = ProgressWriter::new(
+            DigestWriter::new(compressed_writed),
+            options.progress.clone(),
         );
 
-        self.database_connection
+        let summary = self
+            .database_connection
             .connection
-            .backup(&mut compressed_writed)
+            .backup_with_options(&mut progress_writer, database_options)
             .await?;
 
-        let mut writer = compressed_writed.finish()?;
+        let (compressed_writed, digest) = progress_writer.into_inner().into_parts();
+        let mut writer = compressed_writed
+            .finish()?
+            .finish()?
+            .into_inner()
+            .map_err(|e| e.into_error())?;
         writer.flush()?;
 
-        Ok(name)
+        Ok((summary, digest, writer.written()))
+    }
+
+    async fn update_latest_pointer(&self, name: &str) -> Result<()> {
+        let pointer = LatestPointer {
+            name: name.to_string(),
+            created_at: Utc::now(),
+        };
+
+        self.write_json(LATEST_POINTER_NAME, &pointer).await
+    }
+
+    /// See [`manifest::read_manifest`]
+    pub async fn read_manifest(&self, name: &str) -> Result<Option<BackupManifest>> {
+        manifest::read_manifest(&self.storage_provider, name).await
+    }
+
+    pub async fn read_latest_pointer(&self) -> Result<Option<LatestPointer>> {
+        read_json(&self.storage_provider, LATEST_POINTER_NAME).await
+    }
+
+    pub async fn read_binlog_state(&self) -> Result<Option<BinlogState>> {
+        read_json(&self.storage_provider, &self.binlog_state_name()).await
+    }
+
+    fn binlog_state_name(&self) -> String {
+        binlog_state_name(&self.database_connection.config.name)
+    }
+
+    async fn write_json<T: Serialize>(&self, path: &str, value: &T) -> Result<()> {
+        let mut writer = self.storage_provider.create_writer(path).await?;
+        writer.write_all(&serde_json::to_vec_pretty(value)?)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub async fn smoke_query(&self, query: &str) -> Result<String> {
+        self.database_connection.smoke_query(query).await
     }
 
     pub async fn backup(&self) -> Result<String> {
@@ -101,27 +744,277 @@ impl DbBkp {
     }
 
     pub async fn restore(&self, options: RestoreOptions) -> Result<()> {
-        let compression_format = options
-            .compression_format
-            .unwrap_or(CompressionFormat::Gzip);
+        self.restore_with_result(options).await.map(|_| ())
+    }
+
+    /// Restores like `restore` and reports how the restored data compares with the
+    /// row counts recorded at backup time, when `options.verify_row_counts` is set
+    pub async fn restore_with_result(&self, options: RestoreOptions) -> Result<RestoreResult> {
+        if options.dry_run.unwrap_or(false) {
+            let plan = self.plan_restore(options).await?;
+
+            for object in &plan.objects {
+                info!(
+                    "Dry run: would restore {} ({} objects, {} tables)",
+                    object.name,
+                    object.summary.object_count,
+                    object.summary.tables.len()
+                );
+            }
+
+            return Ok(RestoreResult::default());
+        }
+
+        let started_at = Instant::now();
+        let target = options.name.clone();
+        let result = self.run_restore(options).await;
+
+        let notification = Notification::new(Operation::Restore, target, started_at, &result);
+        notify_all(&self.notifiers, &notification).await;
+
+        result
+    }
+
+    /// Restores a backup that doesn't come from the storage, e.g. a local file.
+    /// `options.name` is only used to tell the compression format from its extension
+    /// and to name the tool log.
+    pub async fn restore_from_reader<R: Read + Send + Unpin>(
+        &self,
+        reader: R,
+        options: RestoreOptions,
+    ) -> Result<()> {
+        let started_at = Instant::now();
+        let result = restore_reader(&self.database_connection, reader, &options).await;
+
+        let notification = Notification::new(Operation::Restore, options.name, started_at, &result);
+        notify_all(&self.notifiers, &notification).await;
+
+        result
+    }
+
+    /// See [`restore_data_directory`], which needs no connection to the server
+    pub async fn restore_data_directory(
+        &self,
+        options: RestoreOptions,
+        data_dir: &Path,
+    ) -> Result<()> {
+        restore_data_directory(
+            &self.storage_provider,
+            &self.database_connection.config,
+            options,
+            data_dir,
+            &self.notifiers,
+        )
+        .await
+    }
+
+    async fn run_restore(&self, options: RestoreOptions) -> Result<RestoreResult> {
+        let mut increments_applied = false;
+
+        for step in self.restore_steps(&options).await? {
+            increments_applied |= step.increment;
+
+            let context = if step.increment {
+                format!("Failed to apply increment {}", step.name)
+            } else {
+                format!("Failed to restore {}", step.name)
+            };
+
+            let mut reader = open_object(&self.storage_provider, &step.name, &options)
+                .await
+                .map_err(|e| anyhow!("{}: {}", context, e))?;
+
+            self.database_connection
+                .connection
+                .restore_with_options(&mut reader, step.options)
+                .await
+                .map_err(|e| anyhow!("{}: {}", context, e))?;
+        }
+
+        if !options.verify_row_counts.unwrap_or(false) {
+            return Ok(RestoreResult::default());
+        }
+
+        self.verify_row_counts(&options, increments_applied).await
+    }
 
-        let reader = self.storage_provider.create_reader(&options.name).await?;
-        let mut compressed_reader = Decompressor::new(reader, compression_format);
+    /// Compares the restored tables with the row counts in the manifest of the backup
+    async fn verify_row_counts(
+        &self,
+        options: &RestoreOptions,
+        increments_applied: bool,
+    ) -> Result<RestoreResult> {
+        let strict = options.strict_verify.unwrap_or(false);
 
-        self.database_connection
+        let expected = match self.read_manifest(&options.name).await? {
+            // The counts are the ones of the base, the increments changed the rows since
+            Some(_) if increments_applied => Err(format!(
+                "The row counts of {} don't cover its increments",
+                options.name
+            )),
+            Some(BackupManifest {
+                row_counts: Some(row_counts),
+                row_counts_exact,
+                ..
+            }) => Ok((row_counts, row_counts_exact)),
+            _ => Err(format!("{} has no recorded row counts", options.name)),
+        };
+
+        let (expected, exact) = match expected {
+            Ok(expected) => expected,
+            Err(message) if strict => return Err(anyhow!("{}, can't verify the restore", message)),
+            Err(message) => {
+                warn!("{}, the restore is not verified", message);
+                return Ok(RestoreResult::default());
+            }
+        };
+
+        let actual = self
+            .database_connection
             .connection
-            .restore_with_options(
-                &mut compressed_reader,
-                databases::RestoreOptions {
-                    drop_database_first: match options.drop_database_first {
-                        Some(drop) => drop,
-                        None => false,
-                    },
-                },
-            )
+            .table_row_counts(options.target_database.as_deref())
             .await?;
+        let mismatches = compare_row_counts(&expected, &actual);
 
-        Ok(())
+        // Advisory counts may be off by the rows written while the dump ran
+        if strict && exact && !mismatches.is_empty() {
+            return Err(anyhow!(
+                "Restored row counts don't match the backup: {}",
+                mismatches
+                    .iter()
+                    .map(|mismatch| mismatch.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        for mismatch in &mismatches {
+            warn!(
+                "Row count mismatch after restoring {}: {}",
+                options.name, mismatch
+            );
+        }
+
+        Ok(RestoreResult {
+            verified: true,
+            row_count_mismatches: mismatches,
+        })
+    }
+
+    /// Downloads, decrypts and decompresses every object `restore` would apply, checks
+    /// them against the server and summarizes them, without changing the database.
+    pub async fn plan_restore(&self, options: RestoreOptions) -> Result<RestorePlan> {
+        let mut objects = Vec::new();
+
+        for step in self.restore_steps(&options).await? {
+            let mut reader = open_object(&self.storage_provider, &step.name, &options).await?;
+
+            let summary = self
+                .database_connection
+                .connection
+                .inspect_dump(&mut reader, step.options)
+                .await
+                .map_err(|e| anyhow!("Failed to inspect {}: {}", step.name, e))?;
+
+            objects.push(PlannedObject {
+                name: step.name,
+                summary,
+            });
+        }
+
+        Ok(RestorePlan {
+            name: options.name,
+            objects,
+        })
+    }
+
+    /// The objects a restore applies in order: the members of the backup set, then the
+    /// increments when requested, each with the options it is restored with.
+    async fn restore_steps(&self, options: &RestoreOptions) -> Result<Vec<RestoreStep>> {
+        if !self.storage_provider.exists(&options.name).await? {
+            return Err(anyhow!("Backup not found: {}", options.name));
+        }
+
+        // Only a hint, older backups have no manifest
+        if let Ok(Some(manifest)) = self.read_manifest(&options.name).await {
+            match manifest.scope {
+                BackupScope::Full => {}
+                BackupScope::SchemaOnly => warn!(
+                    "{} is a schema-only backup, the restored tables will be empty",
+                    options.name
+                ),
+                BackupScope::DataOnly => warn!(
+                    "{} is a data-only backup, its tables must already exist in the target database",
+                    options.name
+                ),
+            }
+        }
+
+        if options.target_time.is_some() {
+            return Err(anyhow!(
+                "A target time only applies to data directory restores"
+            ));
+        }
+
+        let binlog_state = self.read_binlog_state().await?;
+
+        // pg_basebackup and WAL archives can't be fed to psql
+        if self.database_connection.config.connection_type == ConnectionType::PostgreSql {
+            if let Some(state) = &binlog_state {
+                if state.base == options.name || state.increments.contains(&options.name) {
+                    return Err(anyhow!(
+                        "{} is part of a PostgreSQL WAL chain, restore it into a data directory instead",
+                        options.name
+                    ));
+                }
+            }
+        }
+
+        let increments = if options.apply_increments.unwrap_or(false) {
+            match binlog_state {
+                Some(state) if state.base == options.name => state.increments,
+                _ => {
+                    return Err(anyhow!(
+                        "{} is not the base of an incremental backup chain",
+                        options.name
+                    ))
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        // Binlog events switch to the database they were recorded in
+        if !increments.is_empty() && options.target_database.is_some() {
+            return Err(anyhow!(
+                "Incremental backups can't be restored into a different database"
+            ));
+        }
+
+        let tool_log = match &options.tool_log_dir {
+            Some(dir) => Some(tool_log_path(dir, &options.name)?),
+            None => None,
+        };
+
+        let mut steps = vec![RestoreStep {
+            name: options.name.clone(),
+            increment: false,
+            options: options.main_restore_options(tool_log.clone()),
+        }];
+
+        for increment in increments {
+            steps.push(RestoreStep {
+                name: increment,
+                increment: true,
+                options: databases::RestoreOptions {
+                    buffer_size: options.pipeline_buffer_size,
+                    tool_log: tool_log.clone(),
+                    ..Default::default()
+                },
+            });
+        }
+
+        Ok(steps)
     }
 
     pub async fn list_with_options(&self, options: ListOptions) -> Result<Vec<Entry>> {
@@ -129,9 +1022,258 @@ impl DbBkp {
         Ok(entries)
     }
 
+    /// Lists only the backups taken from this connection's database config, including its
+    /// incremental backups, which is what a workspace sharing a bucket wants to see.
+    pub async fn list_for_database(&self) -> Result<Vec<Entry>> {
+        let name = &self.database_connection.config.name;
+        let incremental_name = format!("{}-incremental", name);
+
+        let entries = self.storage_provider.list().await?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| {
+                extract_database_from_filename(&entry.metadata.name)
+                    .is_ok_and(|database| &database == name || database == incremental_name)
+            })
+            .collect())
+    }
+
     pub async fn list(&self) -> Result<Vec<Entry>> {
         let entries = self.storage_provider.list().await?;
 
         Ok(entries)
     }
+
+    /// Deletes a backup along with its manifest and other sidecars
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        delete_backup(&self.storage_provider, name).await
+    }
+
+    /// Deletes this database's backups older than `retention_days`, see
+    /// `StorageProvider::cleanup`. Backups of other databases sharing the storage are left
+    /// alone.
+    pub async fn cleanup(&self, retention_days: u64, dry_run: bool) -> Result<CleanupReport> {
+        self.cleanup_database(retention_days, None, dry_run).await
+    }
+
+    /// Deletes this database's backups the policy doesn't select, see
+    /// `StorageProvider::cleanup_with_policy`
+    pub async fn cleanup_with_policy(
+        &self,
+        policy: RetentionPolicy,
+        dry_run: bool,
+    ) -> Result<CleanupReport> {
+        self.cleanup_database(0, Some(policy), dry_run).await
+    }
+
+    async fn cleanup_database(
+        &self,
+        retention_days: u64,
+        policy: Option<RetentionPolicy>,
+        dry_run: bool,
+    ) -> Result<CleanupReport> {
+        let started_at = Instant::now();
+        let result = self
+            .storage_provider
+            .cleanup_with_options(CleanupOptions {
+                retention_days,
+                dry_run,
+                allow_bucket_root: false,
+                policy,
+                database: Some(self.database_connection.config.name.clone()),
+                strict: false,
+            })
+            .await;
+
+        if !dry_run {
+            self.notify_cleanup(started_at, &result).await;
+        }
+
+        result
+    }
+
+    async fn notify_cleanup(&self, started_at: Instant, result: &Result<CleanupReport>) {
+        let reclaimed = result.as_ref().ok().map(|report| report.bytes_reclaimed);
+        let notification = Notification::new(
+            Operation::Cleanup,
+            self.database_connection.config.name.clone(),
+            started_at,
+            result,
+        )
+        .with_size(reclaimed);
+        notify_all(&self.notifiers, &notification).await;
+    }
+}
+
+/// `DbBkp::restore_from_reader` for callers that have no storage configured at all.
+/// Increments are tracked in the storage, so they can't be applied this way.
+pub async fn restore_reader<R: Read + Send + Unpin>(
+    database_connection: &DatabaseConnection,
+    reader: R,
+    options: &RestoreOptions,
+) -> Result<()> {
+    if options.apply_increments.unwrap_or(false) {
+        return Err(anyhow!(
+            "Increments can only be applied when restoring from the storage"
+        ));
+    }
+
+    let tool_log = match &options.tool_log_dir {
+        Some(dir) => Some(tool_log_path(dir, &options.name)?),
+        None => None,
+    };
+
+    let decrypted_reader = Decryptor::new(reader, options.encryption.as_ref())?;
+    let compressed_reader = Decompressor::sniff(
+        decrypted_reader,
+        Some(&options.name),
+        options.compression_format.clone(),
+    )?;
+    let mut reader = ProgressReader::new(compressed_reader, options.progress.clone());
+
+    database_connection
+        .connection
+        .restore_with_options(&mut reader, options.main_restore_options(tool_log))
+        .await
+        .map_err(|e| anyhow!("Failed to restore {}: {}", options.name, e))
+}
+
+/// Deletes a backup along with its manifest and other sidecars. Only the storage is used.
+pub async fn delete_backup(storage_provider: &StorageProvider, name: &str) -> Result<()> {
+    storage_provider.delete(name).await?;
+
+    for sidecar in sidecar_paths(name) {
+        if let Err(e) = storage_provider.delete(&sidecar).await {
+            warn!("Failed to delete {}: {}", sidecar, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores a PostgreSQL WAL chain into `data_dir` instead of a running server: the base
+/// backup is unpacked there with the WAL of every increment, and the directory is set up
+/// to replay it up to `options.target_time` on the next start of a server of the same
+/// major version. `data_dir` must be empty or not exist yet. Only the storage is used,
+/// `database_config` names the chain.
+pub async fn restore_data_directory(
+    storage_provider: &StorageProvider,
+    database_config: &DatabaseConfig,
+    options: RestoreOptions,
+    data_dir: &Path,
+    notifiers: &[Arc<dyn Notifier>],
+) -> Result<()> {
+    let started_at = Instant::now();
+    let target = options.name.clone();
+    let result =
+        run_restore_data_directory(storage_provider, database_config, options, data_dir).await;
+
+    let notification = Notification::new(Operation::Restore, target, started_at, &result);
+    notify_all(notifiers, &notification).await;
+
+    result
+}
+
+async fn run_restore_data_directory(
+    storage_provider: &StorageProvider,
+    database_config: &DatabaseConfig,
+    options: RestoreOptions,
+    data_dir: &Path,
+) -> Result<()> {
+    if database_config.connection_type != ConnectionType::PostgreSql {
+        return Err(anyhow!(
+            "Data directory restores are only supported for PostgreSQL"
+        ));
+    }
+
+    let state_name = binlog_state_name(&database_config.name);
+    let increments = match read_json::<BinlogState>(storage_provider, &state_name).await? {
+        Some(state) if state.base == options.name => state.increments,
+        _ => {
+            return Err(anyhow!(
+                "{} is not the base of an incremental backup chain",
+                options.name
+            ))
+        }
+    };
+
+    let reader = open_object(storage_provider, &options.name, &options)
+        .await
+        .map_err(|e| anyhow!("Failed to restore {}: {}", options.name, e))?;
+    wal::unpack_base_backup(data_dir, reader)
+        .map_err(|e| anyhow!("Failed to restore {}: {}", options.name, e))?;
+
+    for increment in &increments {
+        let reader = open_object(storage_provider, increment, &options)
+            .await
+            .map_err(|e| anyhow!("Failed to apply increment {}: {}", increment, e))?;
+        wal::unpack_wal_segments(data_dir, reader)
+            .map_err(|e| anyhow!("Failed to apply increment {}: {}", increment, e))?;
+    }
+
+    wal::write_recovery_settings(data_dir, options.target_time)
+}
+
+/// Keeps the counts of the tables the table filters of `options` kept in the backup
+fn filter_row_counts(
+    mut row_counts: RowCounts,
+    options: &databases::BackupOptions,
+) -> Result<RowCounts> {
+    if !options.include_tables.is_empty() || !options.exclude_tables.is_empty() {
+        let tables: Vec<String> = row_counts.keys().cloned().collect();
+        let selection = select_tables(&tables, &options.include_tables, &options.exclude_tables)?;
+
+        row_counts.retain(|table, _| {
+            (selection.include.is_empty() || selection.include.contains(table))
+                && !selection.exclude.contains(table)
+        });
+    }
+
+    Ok(row_counts)
+}
+
+async fn read_json<T: DeserializeOwned>(
+    storage_provider: &StorageProvider,
+    path: &str,
+) -> Result<Option<T>> {
+    if !storage_provider.exists(path).await? {
+        return Ok(None);
+    }
+
+    let mut reader = storage_provider.create_reader(path).await?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+/// Reads an object back into the dump the database tool wrote
+async fn open_object(
+    storage_provider: &StorageProvider,
+    name: &str,
+    options: &RestoreOptions,
+) -> Result<Box<dyn Read + Send + Unpin>> {
+    let reader = storage_provider
+        .create_reader_with_options(
+            name,
+            ReaderOptions {
+                chunk_size: options.download_chunk_size,
+                concurrency: options.download_concurrency,
+            },
+        )
+        .await?
+        .with_progress(options.progress.clone());
+    let decrypted_reader = Decryptor::new(reader, options.encryption.as_ref())?;
+    // Left unset, the format of each object is taken from its extension
+    let compressed_reader = Decompressor::sniff(
+        decrypted_reader,
+        Some(name),
+        options.compression_format.clone(),
+    )?;
+
+    Ok(Box::new(ProgressReader::new(
+        compressed_reader,
+        options.progress.clone(),
+    )))
 }
@@ -0,0 +1,263 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{common::generate_id, storage::provider::StorageProvider};
+
+/// Suffix of the lock object written next to the backups, `<database name>.lock`
+pub const LOCK_SUFFIX: &str = ".lock";
+
+/// How long a lock keeps other backups of the database out. A backup running longer
+/// than this can be joined by another one, a crashed run blocks the next ones until then.
+pub const DEFAULT_LOCK_TTL: TimeDelta = TimeDelta::hours(6);
+
+pub fn lock_name(database_name: &str) -> String {
+    format!("{}{}", database_name, LOCK_SUFFIX)
+}
+
+/// Content of the lock object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    /// Tells this run's lock apart from one written by another run in the meantime
+    pub id: String,
+    pub pid: u32,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Advisory lock that keeps two backups of the same database from running at once, e.g.
+/// when a cron run overruns into the next one. Storage has no atomic create, so two runs
+/// starting at the same instant can both get it; it only guards against overlaps.
+///
+/// Released with [`BackupLock::release`], or when dropped if the backup failed or
+/// panicked before that.
+pub struct BackupLock {
+    storage_provider: StorageProvider,
+    path: String,
+    info: LockInfo,
+    /// What was written to the lock object, tells whether it was taken over on drop
+    content: Vec<u8>,
+    released: bool,
+}
+
+impl BackupLock {
+    /// Writes the lock of `database_name`, failing when another backup holds a lock that
+    /// hasn't expired yet, unless `force` is set. Expired locks are taken over.
+    pub async fn acquire(
+        storage_provider: &StorageProvider,
+        database_name: &str,
+        ttl: TimeDelta,
+        force: bool,
+    ) -> Result<Self> {
+        let path = lock_name(database_name);
+        let now = Utc::now();
+
+        match read_lock(storage_provider, &path).await {
+            Ok(Some(existing)) if existing.expires_at > now && !force => {
+                return Err(anyhow!(
+                    "Another backup of {} is running (pid {}, started at {}), its lock expires at {}. Use --force if it's no longer running",
+                    database_name,
+                    existing.pid,
+                    existing.acquired_at.to_rfc3339(),
+                    existing.expires_at.to_rfc3339()
+                ));
+            }
+            Ok(Some(existing)) if existing.expires_at > now => {
+                warn!(
+                    "Overriding the lock of {} held by pid {} since {}",
+                    database_name,
+                    existing.pid,
+                    existing.acquired_at.to_rfc3339()
+                );
+            }
+            Ok(Some(existing)) => {
+                warn!(
+                    "Taking over the expired lock of {} from pid {}",
+                    database_name, existing.pid
+                );
+            }
+            Ok(None) => {}
+            // An unreadable lock can't tell whether its backup is still running
+            Err(e) if !force => {
+                return Err(anyhow!(
+                    "Failed to read the lock of {}: {}. Use --force to replace it",
+                    database_name,
+                    e
+                ));
+            }
+            Err(e) => warn!("Replacing the unreadable lock of {}: {}", database_name, e),
+        }
+
+        let info = LockInfo {
+            id: generate_id(),
+            pid: std::process::id(),
+            acquired_at: now,
+            expires_at: now + ttl,
+        };
+
+        let content = serde_json::to_vec_pretty(&info)?;
+        let mut writer = storage_provider.create_writer(&path).await?;
+        writer.write_all(&content)?;
+        writer.flush()?;
+
+        Ok(Self {
+            storage_provider: storage_provider.clone(),
+            path,
+            info,
+            content,
+            released: false,
+        })
+    }
+
+    pub fn info(&self) -> &LockInfo {
+        &self.info
+    }
+
+    /// Removes the lock, unless another run took it over in the meantime
+    pub async fn release(mut self) -> Result<()> {
+        self.released = true;
+
+        match read_lock(&self.storage_provider, &self.path).await? {
+            Some(current) if current.id == self.info.id => {
+                self.storage_provider.delete(&self.path).await
+            }
+            Some(_) => {
+                warn!("The lock {} was taken over by another backup", self.path);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BackupLock {
+    fn drop(&mut self) {
+        if !self.released {
+            // Can't wait for the storage here, the worker deletes it on its own, unless
+            // another run took it over in the meantime
+            self.storage_provider
+                .delete_detached_if_unchanged(&self.path, std::mem::take(&mut self.content));
+        }
+    }
+}
+
+pub async fn read_lock(storage_provider: &StorageProvider, path: &str) -> Result<Option<LockInfo>> {
+    if !storage_provider.exists(path).await? {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    storage_provider
+        .create_reader(path)
+        .await?
+        .read_to_end(&mut bytes)?;
+
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+#[cfg(test)]
+mod lock_tests {
+    use std::io::Write;
+
+    use chrono::{TimeDelta, Utc};
+
+    use crate::test_utils::test_utils::{get_local_provider, initialize_test};
+
+    use super::{lock_name, read_lock, BackupLock, LockInfo, DEFAULT_LOCK_TTL};
+
+    #[tokio::test]
+    async fn test_backup_lock() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let path = lock_name("shop");
+
+        let lock = BackupLock::acquire(&provider, "shop", DEFAULT_LOCK_TTL, false)
+            .await
+            .expect("Failed to acquire the lock");
+        assert_eq!(
+            read_lock(&provider, &path).await.unwrap().as_ref(),
+            Some(lock.info())
+        );
+
+        // Held by the first run, other databases aren't affected
+        assert!(
+            BackupLock::acquire(&provider, "shop", DEFAULT_LOCK_TTL, false)
+                .await
+                .is_err()
+        );
+        let other = BackupLock::acquire(&provider, "users", DEFAULT_LOCK_TTL, false)
+            .await
+            .expect("Failed to lock another database");
+
+        lock.release().await.expect("Failed to release the lock");
+        assert!(!provider.exists(&path).await.unwrap());
+
+        // Dropping the lock, e.g. when the backup fails, removes it as well
+        drop(other);
+        let lock = BackupLock::acquire(&provider, "users", DEFAULT_LOCK_TTL, false)
+            .await
+            .expect("Failed to acquire the lock after a drop");
+
+        // --force overrides a lock that is still valid, the old holder leaves it in place
+        let forced = BackupLock::acquire(&provider, "users", DEFAULT_LOCK_TTL, true)
+            .await
+            .expect("Failed to force the lock");
+        lock.release().await.expect("Failed to release the lock");
+        assert!(provider.exists(&lock_name("users")).await.unwrap());
+        forced.release().await.expect("Failed to release the lock");
+        assert!(!provider.exists(&lock_name("users")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_lock_keeps_takeover() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let path = lock_name("orders");
+
+        let lock = BackupLock::acquire(&provider, "orders", DEFAULT_LOCK_TTL, false)
+            .await
+            .expect("Failed to acquire the lock");
+        let forced = BackupLock::acquire(&provider, "orders", DEFAULT_LOCK_TTL, true)
+            .await
+            .expect("Failed to force the lock");
+
+        // The old holder fails, its drop leaves the new lock in place
+        drop(lock);
+        // Commands run in order, so the detached delete is done once this returns
+        assert_eq!(
+            read_lock(&provider, &path).await.unwrap().as_ref(),
+            Some(forced.info())
+        );
+
+        drop(forced);
+        assert!(!provider.exists(&path).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lock_is_taken_over() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let path = lock_name("shop");
+
+        let stale = LockInfo {
+            id: "stale".to_string(),
+            pid: 1,
+            acquired_at: Utc::now() - TimeDelta::hours(12),
+            expires_at: Utc::now() - TimeDelta::hours(6),
+        };
+        let mut writer = provider.create_writer(&path).await.unwrap();
+        writer
+            .write_all(&serde_json::to_vec(&stale).unwrap())
+            .unwrap();
+        writer.flush().unwrap();
+
+        let lock = BackupLock::acquire(&provider, "shop", DEFAULT_LOCK_TTL, false)
+            .await
+            .expect("Failed to take over the expired lock");
+        assert_ne!(lock.info().id, stale.id);
+        lock.release().await.expect("Failed to release the lock");
+    }
+}
@@ -0,0 +1,626 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    common::{backup_base_name, manifest_name, BACKUP_EXTENSIONS, DEFAULT_PIPELINE_BUFFER_SIZE},
+    compression::{CompressionFormat, Decompressor},
+    databases::{BackupScope, ConnectionType, DatabaseMetadata, RowCounts},
+    encryption::{Decryptor, EncryptionOptions},
+    storage::provider::StorageProvider,
+    BackupOptions,
+};
+
+/// Describes a backup independently of how it is stored, stored as `<name>.manifest.json`.
+/// The digest covers the dump as the database tool produced it, before compression and
+/// encryption, so it still holds after the backup is re-compressed into another format.
+///
+/// The optional fields are missing from manifests written by older versions.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub compression_format: CompressionFormat,
+    /// Whether the dump holds the schema, the data or both
+    #[serde(default)]
+    pub scope: BackupScope,
+    pub uncompressed_size: u64,
+    /// Hex encoded SHA-256 of the uncompressed dump
+    pub uncompressed_sha256: String,
+    /// Size of the stored object, after compression and encryption
+    #[serde(default)]
+    pub compressed_size: Option<u64>,
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub database_type: Option<ConnectionType>,
+    /// Server the dump was taken from
+    #[serde(default)]
+    pub database: Option<DatabaseMetadata>,
+    /// Version of dbkp that took the backup
+    #[serde(default)]
+    pub tool_version: Option<String>,
+    /// Options the backup was taken with, without the encryption secrets and the extra dump
+    /// arguments
+    #[serde(default)]
+    pub options: Option<BackupOptions>,
+    /// Rows of every backed up table when `BackupOptions::record_row_counts` is set
+    #[serde(default)]
+    pub row_counts: Option<RowCounts>,
+    /// Whether `row_counts` were taken in the snapshot the dump read (PostgreSQL). Counts
+    /// taken after the dump are advisory, rows written in between show up as mismatches,
+    /// so strict verification only warns about them.
+    #[serde(default)]
+    pub row_counts_exact: bool,
+}
+
+/// A table whose row count after a restore differs from the one in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowCountMismatch {
+    pub table: String,
+    pub expected: u64,
+    /// `None` when the table is missing from the restored database
+    pub actual: Option<u64>,
+}
+
+impl fmt::Display for RowCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                "{}: {} rows, {} at backup time",
+                self.table, actual, self.expected
+            ),
+            None => write!(
+                f,
+                "{}: missing, {} rows at backup time",
+                self.table, self.expected
+            ),
+        }
+    }
+}
+
+/// Tables of `expected` whose count in `actual` differs. Tables only found in `actual`
+/// are fine, the restore may have gone into a database that already had them.
+pub fn compare_row_counts(expected: &RowCounts, actual: &RowCounts) -> Vec<RowCountMismatch> {
+    expected
+        .iter()
+        .filter(|(table, count)| actual.get(*table) != Some(count))
+        .map(|(table, count)| RowCountMismatch {
+            table: table.clone(),
+            expected: *count,
+            actual: actual.get(table).copied(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpDigest {
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Hashes everything written through it on the way to `inner`
+pub struct DigestWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    pub fn into_parts(self) -> (W, DumpDigest) {
+        let digest = DumpDigest {
+            size: self.size,
+            sha256: hex(&self.hasher.finalize()),
+        };
+
+        (self.inner, digest)
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct VerifyOptions {
+    pub name: String,
+    /// Defaults to the format recorded in the manifest, then to the backup's extension
+    pub compression_format: Option<CompressionFormat>,
+    pub encryption: Option<EncryptionOptions>,
+    /// Compare the decompressed dump with the digest recorded in the manifest
+    pub uncompressed: bool,
+}
+
+/// A backup whose content is damaged: a chunk failing authentication, a decompression
+/// error or a dump that doesn't match its manifest. Other verification errors, such as a
+/// wrong key or the storage failing mid-read, say nothing about the backup itself.
+#[derive(Debug)]
+pub struct IntegrityError(String);
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for IntegrityError {}
+
+/// Whether `verify_backup` failed because the backup is damaged
+pub fn is_integrity_failure(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<IntegrityError>().is_some()
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub digest: DumpDigest,
+    /// Whether the digest was checked against the manifest
+    pub matched_manifest: bool,
+}
+
+/// Reads a backup to the end through decryption and decompression, which checks their
+/// own integrity data. With `uncompressed`, the dump is also compared with the digest in
+/// the backup's manifest.
+pub async fn verify_backup(
+    storage_provider: &StorageProvider,
+    options: VerifyOptions,
+) -> Result<VerifyReport> {
+    let manifest = read_manifest(storage_provider, &options.name).await?;
+
+    if options.uncompressed && manifest.is_none() {
+        return Err(anyhow!(
+            "{} has no manifest to verify the uncompressed dump against",
+            options.name
+        ));
+    }
+
+    let compression_format = options.compression_format.or_else(|| {
+        manifest
+            .as_ref()
+            .map(|manifest| manifest.compression_format.clone())
+    });
+
+    let reader = storage_provider.create_reader(&options.name).await?;
+    let digest = digest_backup(
+        reader,
+        &options.name,
+        options.encryption.as_ref(),
+        compression_format,
+    )?;
+
+    let matched_manifest = match (&manifest, options.uncompressed) {
+        (Some(manifest), true) => {
+            if digest.sha256 != manifest.uncompressed_sha256
+                || digest.size != manifest.uncompressed_size
+            {
+                return Err(IntegrityError(format!(
+                    "The uncompressed dump of {} doesn't match its manifest: expected {} bytes with SHA-256 {}, got {} bytes with SHA-256 {}",
+                    options.name,
+                    manifest.uncompressed_size,
+                    manifest.uncompressed_sha256,
+                    digest.size,
+                    digest.sha256
+                ))
+                .into());
+            }
+            true
+        }
+        _ => false,
+    };
+
+    Ok(VerifyReport {
+        digest,
+        matched_manifest,
+    })
+}
+
+/// Reads `reader` through decryption and decompression and hashes the dump. Read errors
+/// are integrity failures unless the storage failed or the first chunk didn't decrypt,
+/// which is most likely a wrong key.
+fn digest_backup<R: Read + Send + Unpin>(
+    reader: R,
+    name: &str,
+    encryption: Option<&EncryptionOptions>,
+    compression_format: Option<CompressionFormat>,
+) -> Result<DumpDigest> {
+    let (reader, storage_error) = ErrorTap::new(reader);
+    let decrypted_reader = Decryptor::new(reader, encryption)?;
+    let (decrypted_reader, decryption_error) = ErrorTap::new(decrypted_reader);
+    let mut decompressed_reader =
+        Decompressor::sniff(decrypted_reader, Some(name), compression_format)?;
+
+    let mut digest_writer = DigestWriter::new(io::sink());
+    if let Err(e) = copy(&mut decompressed_reader, &mut digest_writer) {
+        let message = format!("Failed to read {}: {}", name, e);
+        let storage_failed = storage_error.lock().unwrap().is_some();
+        let wrong_key = *decryption_error.lock().unwrap() == Some(io::ErrorKind::InvalidInput);
+
+        return Err(match storage_failed || wrong_key {
+            true => anyhow!(message),
+            false => IntegrityError(message).into(),
+        });
+    }
+
+    let (_, digest) = digest_writer.into_parts();
+    Ok(digest)
+}
+
+/// Remembers the kind of the first error read through it, to tell which layer of a backup
+/// a read error comes from
+struct ErrorTap<R> {
+    inner: R,
+    error: Arc<Mutex<Option<io::ErrorKind>>>,
+}
+
+impl<R> ErrorTap<R> {
+    fn new(inner: R) -> (Self, Arc<Mutex<Option<io::ErrorKind>>>) {
+        let error = Arc::new(Mutex::new(None));
+        let tap = Self {
+            inner,
+            error: error.clone(),
+        };
+
+        (tap, error)
+    }
+}
+
+impl<R: Read> Read for ErrorTap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).inspect_err(|e| {
+            if e.kind() != io::ErrorKind::Interrupted {
+                self.error.lock().unwrap().get_or_insert(e.kind());
+            }
+        })
+    }
+}
+
+/// Reads the manifest of `name`. Manifests are keyed on the backup they were written
+/// for, so when a backup was re-compressed into another format, the manifest of the same
+/// backup name with another compression extension is used.
+pub async fn read_manifest(
+    storage_provider: &StorageProvider,
+    name: &str,
+) -> Result<Option<BackupManifest>> {
+    for path in manifest_candidates(name) {
+        if !storage_provider.exists(&path).await? {
+            continue;
+        }
+
+        let mut reader = storage_provider.create_reader(&path).await?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        return Ok(Some(serde_json::from_slice(&bytes)?));
+    }
+
+    Ok(None)
+}
+
+/// Where the manifest of `name` may be stored, its own first
+fn manifest_candidates(name: &str) -> Vec<String> {
+    let base = backup_base_name(name);
+    let mut candidates = vec![manifest_name(name)];
+
+    if base != name {
+        for extension in BACKUP_EXTENSIONS {
+            let candidate = manifest_name(&format!("{}.{}", base, extension));
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+    }
+
+    candidates
+}
+
+fn copy(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<u64> {
+    let mut buffer = vec![0u8; DEFAULT_PIPELINE_BUFFER_SIZE];
+    let mut total = 0;
+
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buffer[..n])?;
+        total += n as u64;
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use chrono::Utc;
+    use flate2::Compression;
+
+    use crate::{
+        common::manifest_name,
+        compression::{CompressionFormat, Compressor},
+        databases::RowCounts,
+        encryption::{EncryptionKey, EncryptionOptions, Encryptor},
+        storage::provider::StorageProvider,
+        test_utils::test_utils::{get_local_provider, initialize_test},
+    };
+
+    use super::{
+        compare_row_counts, digest_backup, hex, is_integrity_failure, verify_backup,
+        BackupManifest, DigestWriter, RowCountMismatch, VerifyOptions,
+    };
+
+    async fn write_manifest(provider: &StorageProvider, manifest: &BackupManifest) {
+        let mut writer = provider
+            .create_writer(&manifest_name(&manifest.name))
+            .await
+            .expect("Failed to create writer");
+        writer
+            .write_all(&serde_json::to_vec(manifest).expect("Failed to serialize manifest"))
+            .expect("Failed to write manifest");
+        writer.flush().expect("Failed to flush manifest");
+    }
+
+    #[test]
+    fn digest_writer_hashes_passed_through_bytes() {
+        let mut writer = DigestWriter::new(Vec::new());
+        writer.write_all(b"abc").expect("Failed to write");
+
+        let (inner, digest) = writer.into_parts();
+
+        assert_eq!(inner, b"abc");
+        assert_eq!(digest.size, 3);
+        assert_eq!(
+            digest.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hex(&[0x00, 0xff]), "00ff");
+    }
+
+    #[tokio::test]
+    async fn verify_uncompressed_against_manifest() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let name = "app-2024-01-01-000000-abcdef12.gz";
+
+        let writer = provider
+            .create_writer(name)
+            .await
+            .expect("Failed to create writer");
+        let compressor = Compressor::new(writer, CompressionFormat::Gzip, Compression::new(6))
+            .expect("Failed to create compressor");
+        let mut digest_writer = DigestWriter::new(compressor);
+        digest_writer
+            .write_all(b"CREATE TABLE items (id integer);\n")
+            .expect("Failed to write dump");
+        let (compressor, digest) = digest_writer.into_parts();
+        compressor
+            .finish()
+            .expect("Failed to finish compression")
+            .flush()
+            .expect("Failed to flush backup");
+
+        let mut manifest = BackupManifest {
+            name: name.to_string(),
+            created_at: Utc::now(),
+            compression_format: CompressionFormat::Gzip,
+            scope: Default::default(),
+            uncompressed_size: digest.size,
+            uncompressed_sha256: digest.sha256.clone(),
+            compressed_size: None,
+            encrypted: false,
+            database_type: None,
+            database: None,
+            tool_version: None,
+            options: None,
+            row_counts: None,
+            row_counts_exact: false,
+        };
+        write_manifest(&provider, &manifest).await;
+
+        let options = VerifyOptions {
+            name: name.to_string(),
+            uncompressed: true,
+            ..Default::default()
+        };
+
+        let report = verify_backup(&provider, options.clone())
+            .await
+            .expect("Failed to verify backup");
+        assert!(report.matched_manifest);
+        assert_eq!(report.digest, digest);
+
+        manifest.uncompressed_sha256 = hex(&[0; 32]);
+        write_manifest(&provider, &manifest).await;
+
+        let error = verify_backup(&provider, options)
+            .await
+            .expect_err("Verified a dump that doesn't match its manifest");
+        assert!(is_integrity_failure(&error));
+    }
+
+    #[tokio::test]
+    async fn verify_recompressed_backup() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let dump = b"CREATE TABLE items (id integer);\n";
+
+        let mut digest_writer = DigestWriter::new(Vec::new());
+        digest_writer.write_all(dump).expect("Failed to write dump");
+        let (_, digest) = digest_writer.into_parts();
+
+        // The manifest was written for the gzip backup, which was re-compressed with zstd
+        let manifest = BackupManifest {
+            name: "app-2024-01-01-000000-abcdef12.gz".to_string(),
+            created_at: Utc::now(),
+            compression_format: CompressionFormat::Gzip,
+            scope: Default::default(),
+            uncompressed_size: digest.size,
+            uncompressed_sha256: digest.sha256.clone(),
+            compressed_size: None,
+            encrypted: false,
+            database_type: None,
+            database: None,
+            tool_version: None,
+            options: None,
+            row_counts: None,
+            row_counts_exact: false,
+        };
+        write_manifest(&provider, &manifest).await;
+
+        let name = "app-2024-01-01-000000-abcdef12.zst";
+        let writer = provider
+            .create_writer(name)
+            .await
+            .expect("Failed to create writer");
+        let mut compressor = Compressor::new(writer, CompressionFormat::Zstd, Compression::new(3))
+            .expect("Failed to create compressor");
+        compressor.write_all(dump).expect("Failed to write dump");
+        compressor
+            .finish()
+            .expect("Failed to finish compression")
+            .flush()
+            .expect("Failed to flush backup");
+
+        let report = verify_backup(
+            &provider,
+            VerifyOptions {
+                name: name.to_string(),
+                uncompressed: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Failed to verify backup");
+        assert!(report.matched_manifest);
+        assert_eq!(report.digest, digest);
+    }
+
+    /// Yields `data`, then fails like a dropped connection would
+    struct FailingReader(Cursor<Vec<u8>>);
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "Connection reset",
+                )),
+                n => Ok(n),
+            }
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut compressor =
+            Compressor::new(Vec::new(), CompressionFormat::Gzip, Compression::new(6))
+                .expect("Failed to create compressor");
+        compressor.write_all(data).expect("Failed to write dump");
+        compressor.finish().expect("Failed to finish compression")
+    }
+
+    #[test]
+    fn only_damaged_backups_are_integrity_failures() {
+        let dump: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let compressed = gzip(&dump);
+        let name = "app.gz";
+
+        // The storage failing mid-read says nothing about the backup
+        let truncated = FailingReader(Cursor::new(compressed[..compressed.len() / 2].to_vec()));
+        let error = digest_backup(truncated, name, None, None).expect_err("Read a failing reader");
+        assert!(!is_integrity_failure(&error));
+
+        let mut corrupted = compressed.clone();
+        let middle = corrupted.len() / 2;
+        corrupted[middle..middle + 64].fill(0xff);
+        let error = digest_backup(Cursor::new(corrupted), name, None, None)
+            .expect_err("Read a corrupted backup");
+        assert!(is_integrity_failure(&error));
+
+        let key = |byte| EncryptionOptions {
+            key: EncryptionKey::Key(vec![byte; 32]),
+        };
+        let mut encryptor =
+            Encryptor::new(Vec::new(), Some(&key(1))).expect("Failed to create encryptor");
+        encryptor.write_all(&compressed).expect("Failed to encrypt");
+        let encrypted = encryptor.finish().expect("Failed to finish encryption");
+
+        let error = digest_backup(Cursor::new(encrypted.clone()), name, Some(&key(2)), None)
+            .expect_err("Decrypted with the wrong key");
+        assert!(!is_integrity_failure(&error));
+
+        let digest = digest_backup(Cursor::new(encrypted), name, Some(&key(1)), None)
+            .expect("Failed to read the backup");
+        assert_eq!(digest.size, dump.len() as u64);
+    }
+
+    #[test]
+    fn test_compare_row_counts() {
+        let expected: RowCounts = [
+            ("public.orders".to_string(), 10),
+            ("public.users".to_string(), 3),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(compare_row_counts(&expected, &expected).is_empty());
+
+        let actual: RowCounts = [
+            ("public.orders".to_string(), 7),
+            ("public.audit".to_string(), 1),
+        ]
+        .into_iter()
+        .collect();
+        let mismatches = compare_row_counts(&expected, &actual);
+
+        assert_eq!(
+            mismatches,
+            vec![
+                RowCountMismatch {
+                    table: "public.orders".to_string(),
+                    expected: 10,
+                    actual: Some(7),
+                },
+                RowCountMismatch {
+                    table: "public.users".to_string(),
+                    expected: 3,
+                    actual: None,
+                },
+            ]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "public.orders: 7 rows, 10 at backup time"
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::{
+    common::write_atomically,
+    notifications::{Notification, Notifier, Operation},
+};
+
+/// Metrics written by [`MetricsFileNotifier`], with their help text
+const METRICS: [(&str, &str); 6] = [
+    (
+        "dbkp_last_success_timestamp",
+        "Unix time of the last successful run",
+    ),
+    (
+        "dbkp_last_run_success",
+        "Whether the last run succeeded (1) or failed (0)",
+    ),
+    ("dbkp_backup_bytes", "Size of the last successful backup"),
+    (
+        "dbkp_backup_duration_seconds",
+        "Duration of the last backup run",
+    ),
+    (
+        "dbkp_cleanup_bytes_reclaimed",
+        "Bytes freed by the last successful cleanup",
+    ),
+    (
+        "dbkp_cleanup_duration_seconds",
+        "Duration of the last cleanup run",
+    ),
+];
+
+/// Keeps Prometheus metrics of backups and cleanups in a text file, for the textfile
+/// collector of the node exporter. Samples are labeled by database, storage and
+/// operation; those of other databases already in the file are kept, so several jobs
+/// can share it. The file is replaced atomically, so the collector never reads half of it,
+/// and updates are serialized through a `<file>.lock` next to it, so concurrent runs,
+/// whether of `backup --all` or of separate jobs, don't overwrite each other's samples.
+pub struct MetricsFileNotifier {
+    path: PathBuf,
+    database: String,
+    storage: String,
+}
+
+impl MetricsFileNotifier {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        database: impl Into<String>,
+        storage: impl Into<String>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            database: database.into(),
+            storage: storage.into(),
+        }
+    }
+
+    /// Samples of `notification` as (metric, labels) and value
+    fn samples(&self, notification: &Notification) -> Vec<((String, String), f64)> {
+        let (duration_metric, size_metric) = match notification.operation {
+            Operation::Backup => ("dbkp_backup_duration_seconds", "dbkp_backup_bytes"),
+            Operation::Cleanup => (
+                "dbkp_cleanup_duration_seconds",
+                "dbkp_cleanup_bytes_reclaimed",
+            ),
+            Operation::Restore => return Vec::new(),
+        };
+
+        let labels = format!(
+            "database=\"{}\",storage=\"{}\",operation=\"{}\"",
+            escape_label(&self.database),
+            escape_label(&self.storage),
+            notification.operation.to_string().to_lowercase()
+        );
+        let sample = |metric: &str, value: f64| ((metric.to_string(), labels.clone()), value);
+
+        let mut samples = vec![
+            sample(
+                "dbkp_last_run_success",
+                if notification.success { 1.0 } else { 0.0 },
+            ),
+            sample(duration_metric, notification.duration_secs),
+        ];
+
+        // A failed run keeps the time and size of the last one that worked
+        if notification.success {
+            samples.push(sample(
+                "dbkp_last_success_timestamp",
+                Utc::now().timestamp() as f64,
+            ));
+            if let Some(size) = notification.size {
+                samples.push(sample(size_metric, size as f64));
+            }
+        }
+
+        samples
+    }
+}
+
+#[async_trait]
+impl Notifier for MetricsFileNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        let samples = self.samples(notification);
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = lock_metrics_file(&self.path)?;
+
+        let mut metrics = match fs::read_to_string(&self.path) {
+            Ok(content) => parse_samples(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        metrics.extend(samples);
+
+        write_atomically(&self.path, &render(&metrics))
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+/// Holds an exclusive lock on the `.lock` file next to `path` until dropped. The metrics
+/// file itself can't be locked, every update replaces it with a new one.
+fn lock_metrics_file(path: &Path) -> Result<fs::File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", lock_path.display(), e))?;
+    file.lock()
+        .map_err(|e| anyhow!("Failed to lock {}: {}", lock_path.display(), e))?;
+
+    Ok(file)
+}
+
+/// Sample lines of a metrics file keyed by metric and labels, comments are dropped
+fn parse_samples(content: &str) -> BTreeMap<(String, String), f64> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.trim().rsplit_once(' ')?;
+            let value = value.parse().ok()?;
+
+            let (metric, labels) = match series.split_once('{') {
+                Some((metric, labels)) => (metric, labels.strip_suffix('}')?),
+                None => (series, ""),
+            };
+
+            Some(((metric.to_string(), labels.to_string()), value))
+        })
+        .collect()
+}
+
+fn render(metrics: &BTreeMap<(String, String), f64>) -> String {
+    let mut content = String::new();
+    let mut current_metric = None;
+
+    for ((metric, labels), value) in metrics {
+        if current_metric != Some(metric) {
+            if let Some((_, help)) = METRICS.iter().find(|(name, _)| name == metric) {
+                let _ = writeln!(content, "# HELP {} {}", metric, help);
+                let _ = writeln!(content, "# TYPE {} gauge", metric);
+            }
+            current_metric = Some(metric);
+        }
+
+        if labels.is_empty() {
+            let _ = writeln!(content, "{} {}", metric, value);
+        } else {
+            let _ = writeln!(content, "{}{{{}}} {}", metric, labels, value);
+        }
+    }
+
+    content
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::{fs, time::Instant};
+
+    use anyhow::anyhow;
+    use tempfile::tempdir;
+
+    use crate::notifications::{Notification, Notifier, Operation};
+
+    use super::{parse_samples, MetricsFileNotifier};
+
+    fn notification(operation: Operation, result: Result<(), &str>, size: u64) -> Notification {
+        let result = result.map_err(|e| anyhow!("{}", e));
+        Notification::new(operation, "target", Instant::now(), &result).with_size(Some(size))
+    }
+
+    #[tokio::test]
+    async fn test_metrics_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dbkp.prom");
+        let shop = MetricsFileNotifier::new(&path, "shop", "s3-backups");
+        let users = MetricsFileNotifier::new(&path, "users", "s3-backups");
+
+        shop.notify(&notification(Operation::Backup, Ok(()), 2048))
+            .await
+            .expect("Failed to write metrics");
+        users
+            .notify(&notification(Operation::Backup, Ok(()), 512))
+            .await
+            .expect("Failed to write metrics");
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("# TYPE dbkp_backup_bytes gauge\n"));
+        assert!(content.contains(
+            "dbkp_backup_bytes{database=\"shop\",storage=\"s3-backups\",operation=\"backup\"} 2048\n"
+        ));
+        assert!(content.contains(
+            "dbkp_backup_bytes{database=\"users\",storage=\"s3-backups\",operation=\"backup\"} 512\n"
+        ));
+        let samples = parse_samples(&content);
+        let success_key = (
+            "dbkp_last_success_timestamp".to_string(),
+            "database=\"shop\",storage=\"s3-backups\",operation=\"backup\"".to_string(),
+        );
+        let last_success = samples[&success_key];
+        assert!(last_success > 0.0);
+
+        // A failure only updates the outcome and duration, other databases are kept
+        shop.notify(&notification(Operation::Backup, Err("dump failed"), 0))
+            .await
+            .expect("Failed to write metrics");
+        let samples = parse_samples(&fs::read_to_string(&path).unwrap());
+        let labels = "database=\"shop\",storage=\"s3-backups\",operation=\"backup\"";
+        assert_eq!(
+            samples[&("dbkp_last_run_success".to_string(), labels.to_string())],
+            0.0
+        );
+        assert_eq!(
+            samples[&("dbkp_backup_bytes".to_string(), labels.to_string())],
+            2048.0
+        );
+        assert_eq!(samples[&success_key], last_success);
+        assert_eq!(samples.len(), 8);
+
+        shop.notify(&notification(Operation::Cleanup, Ok(()), 4096))
+            .await
+            .expect("Failed to write metrics");
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(
+            "dbkp_cleanup_bytes_reclaimed{database=\"shop\",storage=\"s3-backups\",operation=\"cleanup\"} 4096\n"
+        ));
+
+        // No temporary file is left behind, only the lock file stays next to the metrics
+        let mut files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["dbkp.prom", "dbkp.prom.lock"]);
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_metrics_file() {
+        let dir = tempdir().unwrap();
+        let notifier = MetricsFileNotifier::new(dir.path().join("missing/dbkp.prom"), "shop", "");
+
+        assert!(notifier
+            .notify(&notification(Operation::Backup, Ok(()), 1))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// How long a webhook gets to answer before the notification is given up
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to report finished operations, stored with a workspace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Slack incoming webhook, or any endpoint accepting a JSON POST
+    pub webhook_url: Option<String>,
+}
+
+impl NotificationConfig {
+    pub fn notifiers(&self) -> Vec<Arc<dyn Notifier>> {
+        let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+
+        if let Some(url) = &self.webhook_url {
+            notifiers.push(Arc::new(WebhookNotifier::new(url.clone())));
+        }
+
+        notifiers
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Backup,
+    Restore,
+    Cleanup,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Backup => write!(f, "Backup"),
+            Operation::Restore => write!(f, "Restore"),
+            Operation::Cleanup => write!(f, "Cleanup"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub operation: Operation,
+    /// Backup, database or storage the operation ran on
+    pub target: String,
+    pub success: bool,
+    pub duration_secs: f64,
+    /// Bytes written by a backup or reclaimed by a cleanup
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl Notification {
+    pub fn new<T>(
+        operation: Operation,
+        target: impl Into<String>,
+        started_at: Instant,
+        result: &Result<T>,
+    ) -> Self {
+        Self {
+            operation,
+            target: target.into(),
+            success: result.is_ok(),
+            duration_secs: started_at.elapsed().as_secs_f64(),
+            size: None,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        }
+    }
+
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// One line summary, what Slack shows
+    pub fn text(&self) -> String {
+        let size = match self.size {
+            Some(size) => format!(", {} bytes", size),
+            None => String::new(),
+        };
+
+        match &self.error {
+            None => format!(
+                "{} of {} succeeded in {:.1}s{}",
+                self.operation, self.target, self.duration_secs, size
+            ),
+            Some(error) => format!(
+                "{} of {} failed after {:.1}s: {}",
+                self.operation, self.target, self.duration_secs, error
+            ),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// POSTs the notification as JSON. Slack reads `text`, other receivers can use the
+/// structured fields next to it.
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    text: String,
+    #[serde(flatten)]
+    notification: &'a Notification,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        let payload = WebhookPayload {
+            text: notification.text(),
+            notification,
+        };
+
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to call the webhook: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Webhook answered {}", response.status()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends `notification` to every notifier. A failed notification is only logged, it
+/// never changes the outcome of the operation it reports.
+pub async fn notify_all(notifiers: &[Arc<dyn Notifier>], notification: &Notification) {
+    for notifier in notifiers {
+        if let Err(e) = notifier.notify(notification).await {
+            warn!(
+                "Failed to send {} notification: {}",
+                notification.operation, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod notifications_tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    use anyhow::anyhow;
+
+    use super::{notify_all, Notification, Notifier, Operation, WebhookNotifier};
+
+    #[test]
+    fn test_text() {
+        let notification = Notification::new(
+            Operation::Backup,
+            "shop",
+            Instant::now(),
+            &Ok::<_, anyhow::Error>(()),
+        )
+        .with_size(Some(2048));
+        assert!(notification.success);
+        assert!(notification
+            .text()
+            .starts_with("Backup of shop succeeded in"));
+        assert!(notification.text().ends_with(", 2048 bytes"));
+
+        let notification = Notification::new(
+            Operation::Restore,
+            "shop",
+            Instant::now(),
+            &Err::<(), _>(anyhow!("connection refused")),
+        );
+        assert!(!notification.success);
+        assert!(notification.text().ends_with(": connection refused"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+
+            // Headers and the small JSON body arrive well within a few reads
+            while !String::from_utf8_lossy(&request).contains("\"operation\"") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        let notification = Notification::new(
+            Operation::Cleanup,
+            "backups",
+            Instant::now(),
+            &Ok::<_, anyhow::Error>(()),
+        );
+        WebhookNotifier::new(url)
+            .notify(&notification)
+            .await
+            .expect("Failed to notify");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.contains("\"text\":\"Cleanup of backups succeeded"));
+        assert!(request.contains("\"operation\":\"cleanup\""));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_webhook_is_ignored() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let notification = Notification::new(
+            Operation::Backup,
+            "shop",
+            Instant::now(),
+            &Ok::<_, anyhow::Error>(()),
+        );
+
+        notify_all(
+            &[std::sync::Arc::new(WebhookNotifier::new(url))],
+            &notification,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// Receives progress updates from a running backup or restore. Calls come from whichever
+/// thread moves the data, so implementations should return quickly.
+pub trait ProgressReporter: Send + Sync {
+    /// Bytes written to or read from storage so far. `total` is `None` when the size isn't
+    /// known up front, which is always the case while a dump is still running.
+    fn on_bytes(&self, transferred: u64, total: Option<u64>);
+
+    /// Bytes piped out of the dump tool, or into the restore tool, so far. These are
+    /// uncompressed, so they grow faster than the storage side.
+    fn on_dump_bytes(&self, _piped: u64) {}
+}
+
+/// Reports every write passing through to the dump side of a `ProgressReporter`
+pub(crate) struct ProgressWriter<W> {
+    inner: W,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    piped: u64,
+}
+
+impl<W: Write> ProgressWriter<W> {
+    pub(crate) fn new(inner: W, reporter: Option<Arc<dyn ProgressReporter>>) -> Self {
+        Self {
+            inner,
+            reporter,
+            piped: 0,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+
+        if let Some(reporter) = &self.reporter {
+            self.piped += n as u64;
+            reporter.on_dump_bytes(self.piped);
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reports every read passing through to the dump side of a `ProgressReporter`
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    piped: u64,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub(crate) fn new(inner: R, reporter: Option<Arc<dyn ProgressReporter>>) -> Self {
+        Self {
+            inner,
+            reporter,
+            piped: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if let Some(reporter) = &self.reporter {
+            self.piped += n as u64;
+            reporter.on_dump_bytes(self.piped);
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{ProgressReporter, ProgressWriter};
+
+    #[derive(Default)]
+    struct Recorder {
+        piped: Mutex<Vec<u64>>,
+    }
+
+    impl ProgressReporter for Recorder {
+        fn on_bytes(&self, _transferred: u64, _total: Option<u64>) {}
+
+        fn on_dump_bytes(&self, piped: u64) {
+            self.piped.lock().unwrap().push(piped);
+        }
+    }
+
+    #[test]
+    fn writer_reports_running_total() {
+        let recorder = Arc::new(Recorder::default());
+        let mut writer = ProgressWriter::new(Vec::new(), Some(recorder.clone()));
+
+        writer.write_all(b"abc").expect("Failed to write");
+        writer.write_all(b"de").expect("Failed to write");
+
+        assert_eq!(writer.into_inner(), b"abcde");
+        assert_eq!(*recorder.piped.lock().unwrap(), vec![3, 5]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{sidecar_paths, verification_failure_name, VERIFICATION_FAILURE_SUFFIX},
+    storage::{
+        provider::{ListOptions, StorageProvider},
+        transfer::copy_object_to,
+    },
+};
+
+/// Prefix `quarantine_backup` moves failed backups under
+pub const QUARANTINE_PREFIX: &str = "quarantine";
+
+/// Stored as `<name>.verify-failed.json` next to a backup that failed verification, until
+/// a later verification succeeds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationFailure {
+    /// Where the backup currently is, under [`QUARANTINE_PREFIX`] once quarantined
+    pub name: String,
+    pub failed_at: DateTime<Utc>,
+    pub error: String,
+}
+
+pub async fn record_verification_failure(
+    storage_provider: &StorageProvider,
+    name: &str,
+    error: &str,
+) -> Result<VerificationFailure> {
+    let failure = VerificationFailure {
+        name: name.to_string(),
+        failed_at: Utc::now(),
+        error: error.to_string(),
+    };
+
+    let mut writer = storage_provider
+        .create_writer(&verification_failure_name(name))
+        .await?;
+    writer.write_all(&serde_json::to_vec_pretty(&failure)?)?;
+    writer.flush()?;
+
+    Ok(failure)
+}
+
+/// Forgets an earlier failure, e.g. one caused by a transient read error
+pub async fn clear_verification_failure(
+    storage_provider: &StorageProvider,
+    name: &str,
+) -> Result<()> {
+    let marker = verification_failure_name(name);
+
+    if storage_provider.exists(&marker).await? {
+        storage_provider.delete(&marker).await?;
+    }
+
+    Ok(())
+}
+
+/// Every recorded failure, including those of quarantined backups, oldest first
+pub async fn list_verification_failures(
+    storage_provider: &StorageProvider,
+) -> Result<Vec<VerificationFailure>> {
+    let entries = storage_provider
+        .list_with_options(ListOptions {
+            latest_only: None,
+            limit: None,
+            recursive: None,
+            database: None,
+            before: None,
+            after: None,
+            include_quarantined: Some(true),
+        })
+        .await?;
+    let mut failures = Vec::new();
+
+    for entry in entries {
+        let Some(name) = entry.path.strip_suffix(VERIFICATION_FAILURE_SUFFIX) else {
+            continue;
+        };
+
+        let mut bytes = Vec::new();
+        storage_provider
+            .create_reader(&entry.path)
+            .await?
+            .read_to_end(&mut bytes)?;
+
+        match serde_json::from_slice::<VerificationFailure>(&bytes) {
+            Ok(failure) => failures.push(VerificationFailure {
+                name: name.to_string(),
+                ..failure
+            }),
+            Err(e) => warn!("Ignoring unreadable record {}: {}", entry.path, e),
+        }
+    }
+
+    failures.sort_by_key(|failure| failure.failed_at);
+
+    Ok(failures)
+}
+
+/// Moves a backup and its sidecars under [`QUARANTINE_PREFIX`] so it no longer looks like
+/// a restorable backup. Returns the new name of the backup.
+pub async fn quarantine_backup(storage_provider: &StorageProvider, name: &str) -> Result<String> {
+    if !storage_provider.exists(name).await? {
+        return Err(anyhow!("Backup not found: {}", name));
+    }
+
+    let mut objects = vec![name.to_string()];
+
+    for sidecar in sidecar_paths(name) {
+        if storage_provider.exists(&sidecar).await? {
+            objects.push(sidecar);
+        }
+    }
+
+    for object in &objects {
+        copy_object_to(
+            storage_provider,
+            storage_provider,
+            object,
+            &quarantined_name(object),
+        )
+        .await?;
+    }
+
+    for object in &objects {
+        storage_provider.delete(object).await?;
+    }
+
+    info!("Quarantined {}", name);
+    Ok(quarantined_name(name))
+}
+
+fn quarantined_name(name: &str) -> String {
+    format!("{}/{}", QUARANTINE_PREFIX, name.trim_start_matches('/'))
+}
+
+/// Whether `path` is under [`QUARANTINE_PREFIX`]. Listings and cleanup skip these, so a
+/// quarantined backup is never picked as the latest one nor deleted by retention.
+pub fn is_quarantined(path: &str) -> bool {
+    path.trim_start_matches('/')
+        .strip_prefix(QUARANTINE_PREFIX)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod quarantine_tests {
+    use std::io::Write;
+
+    use crate::test_utils::test_utils::{get_local_provider, initialize_test};
+
+    use super::{
+        clear_verification_failure, is_quarantined, list_verification_failures, quarantine_backup,
+        record_verification_failure,
+    };
+
+    #[tokio::test]
+    async fn test_record_and_quarantine() {
+        initialize_test();
+        let provider = get_local_provider().expect("Failed to create local provider");
+        let name = "app-2024-01-01-000000-abcdef12.gz";
+
+        let mut writer = provider
+            .create_writer(name)
+            .await
+            .expect("Failed to create writer");
+        writer.write_all(b"not gzip").expect("Failed to write");
+        writer.flush().expect("Failed to flush");
+
+        record_verification_failure(&provider, name, "invalid gzip header")
+            .await
+            .expect("Failed to record failure");
+
+        let failures = list_verification_failures(&provider)
+            .await
+            .expect("Failed to list failures");
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, name);
+        assert_eq!(failures[0].error, "invalid gzip header");
+
+        let quarantined = quarantine_backup(&provider, name)
+            .await
+            .expect("Failed to quarantine backup");
+        assert_eq!(quarantined, format!("quarantine/{}", name));
+        assert!(!provider
+            .exists(name)
+            .await
+            .expect("Failed to check existence"));
+        assert!(provider
+            .exists(&quarantined)
+            .await
+            .expect("Failed to check existence"));
+        assert!(is_quarantined(&quarantined));
+        assert!(!is_quarantined(
+            "quarantine-app-2024-01-01-000000-abcdef12.gz"
+        ));
+
+        // Neither listed, so `--latest` can't pick it, nor deleted by cleanup once a newer
+        // backup exists
+        let mut writer = provider
+            .create_writer("app-2024-01-02-000000-abcdef12.gz")
+            .await
+            .expect("Failed to create writer");
+        writer.write_all(b"newer").expect("Failed to write");
+        writer.flush().expect("Failed to flush");
+
+        assert!(provider
+            .list()
+            .await
+            .expect("Failed to list backups")
+            .iter()
+            .all(|entry| !is_quarantined(&entry.path)));
+        let report = provider
+            .cleanup(0, false)
+            .await
+            .expect("Failed to clean up");
+        assert_eq!(report.count, 0);
+        assert!(provider
+            .exists(&quarantined)
+            .await
+            .expect("Failed to check existence"));
+
+        // The record moved along with the backup
+        let failures = list_verification_failures(&provider)
+            .await
+            .expect("Failed to list failures");
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, quarantined);
+
+        clear_verification_failure(&provider, &quarantined)
+            .await
+            .expect("Failed to clear failure");
+        assert!(list_verification_failures(&provider)
+            .await
+            .expect("Failed to list failures")
+            .is_empty());
+    }
+}
@@ -1,15 +1,25 @@
 use std::{
     io::{Error, ErrorKind, Read, Write},
-    sync::mpsc::{channel, Sender},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
 };
 
-use crate::storage::provider::{StorageProviderCommand, StorageProviderReadResponse};
+use crate::progress::ProgressReporter;
+use crate::storage::{
+    provider::{StorageProviderCommand, StorageProviderReadResponse, StorageWorkerUnavailable},
+    throttle::RateLimiter,
+};
 
 #[derive(Clone)]
 pub struct StorageWriter {
     writer_id: u64,
     command_tx: Sender<StorageProviderCommand>,
     is_closed: bool,
+    rate_limiter: Option<RateLimiter>,
+    progress: Option<Arc<dyn ProgressReporter>>,
+    written: u64,
 }
 
 impl StorageWriter {
@@ -18,7 +28,58 @@ impl StorageWriter {
             writer_id,
             command_tx,
             is_closed: false,
+            rate_limiter: None,
+            progress: None,
+            written: 0,
+        }
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Reports the bytes uploaded so far after every write. The final size of an upload
+    /// isn't known while it is written, so the total is always `None`.
+    pub fn with_progress(mut self, progress: Option<Arc<dyn ProgressReporter>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Bytes written so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Discards everything written so far. Nothing is left at the target path.
+    pub fn abort(&mut self) -> std::io::Result<()> {
+        if self.is_closed {
+            return Ok(());
         }
+
+        let (response_tx, response_rx) = channel();
+
+        self.command_tx
+            .send(StorageProviderCommand::AbortWriter {
+                writer_id: self.writer_id,
+                response: response_tx,
+            })
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
+            })?;
+
+        self.is_closed = true;
+
+        let result = response_rx.recv().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
+        })?;
+
+        result.map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Abort operation failed: {}", e),
+            )
+        })
     }
 }
 
@@ -31,6 +92,10 @@ impl Write for StorageWriter {
             ));
         }
 
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(bytes.len());
+        }
+
         let (response_tx, response_rx) = channel();
 
         self.command_tx
@@ -39,18 +104,12 @@ impl Write for StorageWriter {
                 data: bytes.to_vec(),
                 response: response_tx,
             })
-            .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::BrokenPipe,
-                    format!("Failed to send write command: {}", e),
-                )
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
             })?;
 
-        let result = response_rx.recv().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::BrokenPipe,
-                format!("Failed to receive write response: {}", e),
-            )
+        let result = response_rx.recv().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
         })?;
 
         result.map_err(|e| {
@@ -60,6 +119,12 @@ impl Write for StorageWriter {
             )
         })?;
 
+        self.written += bytes.len() as u64;
+
+        if let Some(progress) = &self.progress {
+            progress.on_bytes(self.written, None);
+        }
+
         Ok(bytes.len())
     }
 
@@ -75,18 +140,12 @@ impl Write for StorageWriter {
                 writer_id: self.writer_id,
                 response: response_tx,
             })
-            .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::BrokenPipe,
-                    format!("Failed to send close command: {}", e),
-                )
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
             })?;
 
-        let result = response_rx.recv().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::BrokenPipe,
-                format!("Failed to receive close response: {}", e),
-            )
+        let result = response_rx.recv().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, StorageWorkerUnavailable)
         })?;
 
         result.map_err(|e| {
@@ -104,8 +163,10 @@ impl Write for StorageWriter {
 impl Drop for StorageWriter {
     fn drop(&mut self) {
         if !self.is_closed {
+            // A writer dropped without being flushed didn't finish its upload, so throw
+            // away the incomplete object rather than committing it.
             let (response_tx, _response_rx) = channel();
-            let _ = self.command_tx.send(StorageProviderCommand::CloseWriter {
+            let _ = self.command_tx.send(StorageProviderCommand::AbortWriter {
                 writer_id: self.writer_id,
                 response: response_tx,
             });
@@ -117,20 +178,49 @@ impl Drop for StorageWriter {
 #[derive(Clone)]
 pub struct StorageReader {
     reader_id: u64,
+    /// Size of the object being read
+    size: u64,
     command_tx: Sender<StorageProviderCommand>,
     is_closed: bool,
+    /// Last chunk fetched from storage, `position` bytes of it were already read
     buffer: Vec<u8>,
+    position: usize,
+    rate_limiter: Option<RateLimiter>,
+    progress: Option<Arc<dyn ProgressReporter>>,
+    read: u64,
 }
 impl StorageReader {
-    pub fn new(reader_id: u64, command_tx: Sender<StorageProviderCommand>) -> Self {
+    pub fn new(reader_id: u64, size: u64, command_tx: Sender<StorageProviderCommand>) -> Self {
         StorageReader {
             reader_id,
+            size,
             command_tx,
             is_closed: false,
             buffer: Vec::new(),
+            position: 0,
+            rate_limiter: None,
+            progress: None,
+            read: 0,
         }
     }
 
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Reports the bytes downloaded so far after every chunk fetched from storage, out of
+    /// the size of the object
+    pub fn with_progress(mut self, progress: Option<Arc<dyn ProgressReporter>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Size of the object being read
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     pub fn is_closed(&self) -> bool {
         self.is_closed
     }
@@ -138,6 +228,16 @@ impl StorageReader {
     pub fn close(&mut self) {
         self.is_closed = true;
         self.buffer.clear();
+        self.position = 0;
+    }
+
+    /// Copies what is left of the buffered chunk into `buf`
+    fn read_buffered(&mut self, buf: &mut [u8]) -> usize {
+        let remaining = &self.buffer[self.position..];
+        let bytes_to_copy = std::cmp::min(remaining.len(), buf.len());
+        buf[..bytes_to_copy].copy_from_slice(&remaining[..bytes_to_copy]);
+        self.position += bytes_to_copy;
+        bytes_to_copy
     }
 
     fn fetch_more_data(&mut self) -> Result<StorageProviderReadResponse, Error> {
@@ -152,19 +252,11 @@ impl StorageReader {
                 reader_id: self.reader_id,
                 response: response_tx,
             })
-            .map_err(|e| {
-                Error::new(
-                    ErrorKind::BrokenPipe,
-                    format!("Failed to send read command: {}", e),
-                )
-            })?;
+            .map_err(|_| Error::new(ErrorKind::BrokenPipe, StorageWorkerUnavailable))?;
 
-        let result = response_rx.recv().map_err(|e| {
-            Error::new(
-                ErrorKind::BrokenPipe,
-                format!("Failed to receive read response: {}", e),
-            )
-        })?;
+        let result = response_rx
+            .recv()
+            .map_err(|_| Error::new(ErrorKind::BrokenPipe, StorageWorkerUnavailable))?;
 
         result.map_err(|e| Error::new(ErrorKind::Other, format!("Read operation failed: {}", e)))
     }
@@ -176,11 +268,8 @@ impl Read for StorageReader {
             return Err(Error::new(ErrorKind::BrokenPipe, "Reader is closed"));
         }
 
-        if !self.buffer.is_empty() {
-            let bytes_to_copy = std::cmp::min(self.buffer.len(), buf.len());
-            buf[..bytes_to_copy].copy_from_slice(&self.buffer[..bytes_to_copy]);
-            self.buffer.drain(..bytes_to_copy);
-            return Ok(bytes_to_copy);
+        if self.position < self.buffer.len() {
+            return Ok(self.read_buffered(buf));
         }
 
         match self.fetch_more_data() {
@@ -195,13 +284,19 @@ impl Read for StorageReader {
                     return Ok(0); // EOF
                 }
 
-                self.buffer.extend_from_slice(&read_response.data);
+                if let Some(rate_limiter) = &self.rate_limiter {
+                    rate_limiter.acquire(read_response.data.len());
+                }
+
+                if let Some(progress) = &self.progress {
+                    self.read += read_response.data.len() as u64;
+                    progress.on_bytes(self.read, Some(self.size));
+                }
 
-                let bytes_to_copy = std::cmp::min(self.buffer.len(), buf.len());
-                buf[..bytes_to_copy].copy_from_slice(&self.buffer[..bytes_to_copy]);
-                self.buffer.drain(..bytes_to_copy);
+                self.buffer = read_response.data;
+                self.position = 0;
 
-                Ok(bytes_to_copy)
+                Ok(self.read_buffered(buf))
             }
             Err(e) => {
                 self.is_closed = true;
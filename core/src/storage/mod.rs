@@ -3,7 +3,10 @@ use serde::{Deserialize, Serialize};
 
 pub mod io;
 pub mod provider;
+pub mod retry;
 mod test;
+pub mod throttle;
+pub mod transfer;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum EntryMode {
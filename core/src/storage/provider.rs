@@ -1,19 +1,24 @@
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Utc};
-use futures::StreamExt;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
 use log::{debug, error, info, warn};
+#[cfg(unix)]
+use opendal::services::Sftp;
 use opendal::{
     layers::LoggingLayer,
-    services::{Fs, S3},
-    BufferStream, Metadata, Operator, Writer,
+    services::{Azblob, Fs, Gcs, S3},
+    BufferStream, EntryMode, Metadata, Operator, Writer,
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{Read, Write},
     path::Path,
     sync::{
-        mpsc::{channel, Sender},
+        mpsc::{channel, Receiver, Sender},
         Arc,
     },
     thread::{self, JoinHandle},
@@ -21,9 +26,24 @@ use std::{
 };
 use tokio::{runtime::Runtime, sync::oneshot};
 
-use crate::{common::extract_timestamp_from_filename, storage::Entry};
+use crate::{
+    clock::{Clock, SystemClock},
+    common::{
+        extract_database_from_filename, extract_timestamp_from_filename, generate_id, is_backup_of,
+        sidecar_paths, BINLOG_STATE_SUFFIX,
+    },
+    databases::ssh_tunnel::SshAuthMethod,
+    lock::LOCK_SUFFIX,
+    quarantine::is_quarantined,
+    storage::Entry,
+    BinlogState,
+};
 
-use super::io::{StorageReader, StorageWriter};
+use super::{
+    io::{StorageReader, StorageWriter},
+    retry::{with_retry, RetryConfig},
+    throttle::RateLimiter,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StorageCredentials {
@@ -47,8 +67,10 @@ pub enum StorageCredentials {
 pub enum StorageType {
     FileSystem,
     S3,
+    Gcs,
+    Azure,
     // WebDAV,
-    // SFTP,
+    Sftp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,18 +90,289 @@ pub struct S3StorageConfig {
     pub access_key: String,
     pub secret_key: String,
     pub location: String,
+    /// Parts uploaded in parallel by a writer, see [`DEFAULT_UPLOAD_CONCURRENCY`]
+    pub upload_concurrency: Option<usize>,
+    /// Server-side encryption of the objects written, the bucket default when unset
+    #[serde(default)]
+    pub sse: Option<SseConfig>,
+}
+
+/// S3 server-side encryption. It only applies to writes, S3 decrypts on read without
+/// being asked to, as long as the credentials may use the key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SseConfig {
+    /// SSE-S3, keys managed by S3 (`AES256`)
+    S3,
+    /// SSE-KMS with this KMS key id or ARN (`aws:kms`)
+    Kms { key_id: String },
+}
+
+/// Without credentials, the default application credentials of the environment are used
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcsStorageConfig {
+    pub id: String,
+    pub name: String,
+    pub bucket: String,
+    /// Path to a service account JSON key
+    pub credential_path: Option<String>,
+    /// Content of a service account JSON key, takes precedence over `credential_path`
+    pub credential: Option<String>,
+    pub location: String,
+}
+
+/// Authenticates with the account key, or with a SAS token when there is none
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AzureStorageConfig {
+    pub id: String,
+    pub name: String,
+    pub account_name: String,
+    pub account_key: Option<String>,
+    pub sas_token: Option<String>,
+    pub container: String,
+    /// Defaults to `https://<account_name>.blob.core.windows.net`
+    pub endpoint: Option<String>,
+    pub location: String,
+}
+
+/// Goes through the system `ssh` client, so the host must already be in `known_hosts`.
+/// Only key authentication is supported, and the key must not need a passphrase unless
+/// it is loaded in an ssh agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpStorageConfig {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth_method: SshAuthMethod,
+    /// Remote directory the backups are stored in
+    pub location: String,
+}
+
+/// The key to pass to ssh, none lets ssh pick the identities of the running agent
+fn sftp_key_path(config: &SftpStorageConfig) -> Result<Option<&str>> {
+    if cfg!(not(unix)) {
+        return Err(anyhow!("SFTP storage is only supported on Unix"));
+    }
+
+    match &config.auth_method {
+        SshAuthMethod::PrivateKey {
+            key_path,
+            passphrase_key: None,
+        } => Ok(Some(key_path)),
+        SshAuthMethod::PrivateKey { .. } => Err(anyhow!(
+            "SFTP storage can't use a key passphrase, load the key in an ssh agent instead"
+        )),
+        SshAuthMethod::Agent => Ok(None),
+        SshAuthMethod::Password { .. } => Err(anyhow!(
+            "SFTP storage only supports key or agent authentication"
+        )),
+    }
+}
+
+/// Worker of [`StorageConfig::Stdout`], the first writer goes to stdout and every other
+/// operation fails
+fn serve_stdout(command_rx: Receiver<StorageProviderCommand>) -> Result<()> {
+    const STDOUT_WRITER_ID: u64 = 1;
+
+    let unsupported =
+        |operation: &str| anyhow!("{} isn't supported when writing to stdout", operation);
+
+    let mut stdout = std::io::stdout();
+    let mut writer_created = false;
+    let mut written = 0u64;
+
+    while let Ok(command) = command_rx.recv() {
+        match command {
+            StorageProviderCommand::CreateWriter { response, .. } if !writer_created => {
+                writer_created = true;
+                let _ = response.send(Ok(STDOUT_WRITER_ID));
+            }
+            StorageProviderCommand::CreateWriter { path, response, .. } => {
+                let _ = response.send(Err(anyhow!(
+                    "Can't write {}, stdout only takes a single backup",
+                    path
+                )));
+            }
+            StorageProviderCommand::Write { data, response, .. } => {
+                written += data.len() as u64;
+                let _ = response.send(
+                    stdout
+                        .write_all(&data)
+                        .map_err(|e| anyhow!("Failed to write to stdout: {}", e)),
+                );
+            }
+            StorageProviderCommand::CloseWriter { response, .. } => {
+                let _ = response.send(
+                    stdout
+                        .flush()
+                        .map(|_| Metadata::new(EntryMode::FILE).with_content_length(written))
+                        .map_err(|e| anyhow!("Failed to write to stdout: {}", e)),
+                );
+            }
+            // What was streamed already can't be taken back, the caller reports the failure
+            StorageProviderCommand::AbortWriter { response, .. } => {
+                let _ = response.send(Ok(()));
+            }
+            StorageProviderCommand::Test { response } => {
+                let _ = response.send(Ok(true));
+            }
+            StorageProviderCommand::List { response, .. } => {
+                let _ = response.send(Err(unsupported("Listing backups")));
+            }
+            StorageProviderCommand::CreateReader { response, .. } => {
+                let _ = response.send(Err(unsupported("Reading backups")));
+            }
+            StorageProviderCommand::Read { response, .. } => {
+                let _ = response.send(Err(unsupported("Reading backups")));
+            }
+            StorageProviderCommand::Delete { response, .. } => {
+                let _ = response.send(Err(unsupported("Deleting backups")));
+            }
+            StorageProviderCommand::Exists { response, .. } => {
+                let _ = response.send(Err(unsupported("Looking up backups")));
+            }
+            StorageProviderCommand::Cleanup { response, .. } => {
+                let _ = response.send(Err(unsupported("Cleanup")));
+            }
+            StorageProviderCommand::Shutdown { response } => {
+                let _ = response.send(stdout.flush().map_err(|e| anyhow!("{}", e)));
+                break;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StorageConfig {
     Local(LocalStorageConfig),
     S3(S3StorageConfig),
+    Gcs(GcsStorageConfig),
+    Azure(AzureStorageConfig),
+    Sftp(SftpStorageConfig),
+    /// Streams a single backup to stdout for piping it into another tool. Nothing can be
+    /// read back, listed or cleaned up.
+    Stdout,
+}
+
+impl StorageConfig {
+    /// Name given to the storage in its workspace
+    pub fn name(&self) -> &str {
+        match self {
+            StorageConfig::Local(config) => &config.name,
+            StorageConfig::S3(config) => &config.name,
+            StorageConfig::Gcs(config) => &config.name,
+            StorageConfig::Azure(config) => &config.name,
+            StorageConfig::Sftp(config) => &config.name,
+            StorageConfig::Stdout => "stdout",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListOptions {
     pub latest_only: Option<bool>,
+    /// Only return the newest backups, all of them when unset
     pub limit: Option<usize>,
+    /// Descend into sub-prefixes, defaults to true. A shallow list is much faster when all
+    /// backups live directly under the location.
+    pub recursive: Option<bool>,
+    /// Only list backups of this database, see [`crate::common::get_default_backup_name`]
+    pub database: Option<String>,
+    /// Only list backups taken before this time, read from their name
+    #[serde(default)]
+    pub before: Option<DateTime<Utc>>,
+    /// Only list backups taken at or after this time, read from their name
+    #[serde(default)]
+    pub after: Option<DateTime<Utc>>,
+    /// Also list the backups moved under [`crate::quarantine::QUARANTINE_PREFIX`], defaults
+    /// to false
+    #[serde(default)]
+    pub include_quarantined: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupOptions {
+    pub retention_days: u64,
+    pub dry_run: bool,
+    /// Cleanup deletes every timestamped object it finds, so it refuses to run against an
+    /// empty or `/` location, which could be a shared bucket, unless this is set
+    pub allow_bucket_root: bool,
+    /// Also keep the backups selected by this policy, whatever their age
+    pub policy: Option<RetentionPolicy>,
+    /// Only consider backups of this database, other backups are never deleted
+    pub database: Option<String>,
+    /// Also consider objects whose name has no timestamp, aged by their last modification
+    /// time, so backups with an unexpected name don't pile up forever. Otherwise they're
+    /// skipped with a warning. Unlike regular backups, the newest of them isn't kept. JSON
+    /// objects (sidecars, pointers) and unfinished uploads are never considered.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// A backup removed by cleanup, or that a dry run would remove
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub deleted: Vec<DeletedEntry>,
+    pub count: usize,
+    pub bytes_reclaimed: u64,
+    pub dry_run: bool,
+}
+
+/// Grandfather-father-son retention: keeps the newest backup of each of the most recent
+/// `daily` days, `weekly` ISO weeks, `monthly` months and `yearly` years that have a
+/// backup. A backup selected by any of the rules is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub daily: usize,
+    pub weekly: usize,
+    pub monthly: usize,
+    pub yearly: usize,
+}
+
+/// How many periods a retention rule keeps, and the period a backup time falls in
+type RetentionRule = (usize, fn(&DateTime<Utc>) -> (i32, u32));
+
+impl RetentionPolicy {
+    /// Indices into `timestamps` of the backups to keep
+    pub fn backups_to_keep(&self, timestamps: &[DateTime<Utc>]) -> HashSet<usize> {
+        let mut newest_first: Vec<usize> = (0..timestamps.len()).collect();
+        newest_first.sort_by(|a, b| timestamps[*b].cmp(&timestamps[*a]));
+
+        let rules: [RetentionRule; 4] = [
+            (self.daily, |t| (t.year(), t.ordinal())),
+            (self.weekly, |t| (t.iso_week().year(), t.iso_week().week())),
+            (self.monthly, |t| (t.year(), t.month())),
+            (self.yearly, |t| (t.year(), 0)),
+        ];
+
+        let mut keep = HashSet::new();
+
+        for (count, period_of) in rules {
+            let mut periods = HashSet::new();
+
+            for &index in &newest_first {
+                if periods.len() >= count {
+                    break;
+                }
+
+                // The first backup seen in a period is its newest one
+                if periods.insert(period_of(&timestamps[index])) {
+                    keep.insert(index);
+                }
+            }
+        }
+
+        keep
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -98,7 +391,7 @@ pub enum StorageProviderCommand {
     },
     CreateWriter {
         path: String,
-        concurrency: usize,
+        concurrency: Option<usize>,
         response: oneshot::Sender<Result<u64>>,
     },
     Write {
@@ -110,9 +403,15 @@ pub enum StorageProviderCommand {
         writer_id: u64,
         response: Sender<Result<Metadata>>,
     },
+    AbortWriter {
+        writer_id: u64,
+        response: Sender<Result<()>>,
+    },
     CreateReader {
         path: String,
-        response: oneshot::Sender<Result<u64>>,
+        options: ReaderOptions,
+        /// The id of the reader and the size of the object
+        response: oneshot::Sender<Result<(u64, u64)>>,
     },
     Read {
         reader_id: u64,
@@ -120,31 +419,474 @@ pub enum StorageProviderCommand {
     },
     Delete {
         path: String,
+        /// Only deletes the object while it still holds exactly these bytes
+        if_content: Option<Vec<u8>>,
         response: oneshot::Sender<Result<()>>,
     },
+    Exists {
+        path: String,
+        response: oneshot::Sender<Result<bool>>,
+    },
     Test {
         response: oneshot::Sender<Result<bool>>,
     },
     Cleanup {
-        retention_days: u64,
-        dry_run: bool,
-        response: oneshot::Sender<Result<(usize, u64)>>,
+        options: CleanupOptions,
+        response: oneshot::Sender<Result<CleanupReport>>,
     },
     Shutdown {
         response: oneshot::Sender<Result<()>>,
     },
 }
 
+/// Parts a writer uploads in parallel unless configured otherwise. Filesystem storage
+/// writes sequentially and ignores it.
+pub const DEFAULT_UPLOAD_CONCURRENCY: usize = 5;
+
+/// Bytes a reader fetches per request unless configured otherwise. Object storage
+/// charges a round-trip per request, small chunks make large restores crawl.
+pub const DEFAULT_READ_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Chunks a reader fetches ahead in parallel unless configured otherwise
+pub const DEFAULT_READ_CONCURRENCY: usize = 4;
+
+/// Objects are never read in more chunks than this, larger ones get larger chunks
+const MAX_READ_CHUNKS: u64 = 10_000;
+
+/// How a reader fetches an object, see [`StorageProvider::create_reader_with_options`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReaderOptions {
+    /// See [`DEFAULT_READ_CHUNK_SIZE`], adjusted to the object by [`read_chunk_size`]
+    pub chunk_size: Option<usize>,
+    /// See [`DEFAULT_READ_CONCURRENCY`]
+    pub concurrency: Option<usize>,
+}
+
+/// Chunk size for reading an object of `file_size` bytes: the requested size, grown so
+/// the object takes at most `MAX_READ_CHUNKS` requests and shrunk to the object itself
+pub fn read_chunk_size(file_size: u64, chunk_size: Option<usize>) -> usize {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_READ_CHUNK_SIZE) as u64;
+
+    chunk_size
+        .max(file_size.div_ceil(MAX_READ_CHUNKS))
+        .min(file_size)
+        .max(1) as usize
+}
+
+/// Prefix of the object written and removed again by `StorageProvider::test_readwrite`
+pub const PROBE_OBJECT_PREFIX: &str = ".dbkp-probe-";
+
+/// Backups younger than this are never removed by cleanup, regardless of retention
+const MIN_BACKUP_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Objects a strict cleanup leaves alone even though their name has no timestamp:
+/// manifests, backup sets and other JSON records, backup locks and uploads still in
+/// progress
+fn is_metadata_object(name: &str) -> bool {
+    name.ends_with(".json")
+        || name.ends_with(LOCK_SUFFIX)
+        || name.ends_with(PARTIAL_SUFFIX)
+        || name.starts_with(PROBE_OBJECT_PREFIX)
+}
+
+/// Age of an object a strict cleanup found without a timestamp in its name. Listings of
+/// some services leave the modification time out, it's then read with a stat.
+async fn last_modified(
+    operator: &Operator,
+    retry: &RetryConfig,
+    entry: &Entry,
+) -> Option<DateTime<Utc>> {
+    if let Some(last_modified) = entry.metadata.last_modified {
+        return Some(last_modified);
+    }
+
+    with_retry(retry, "Stat", || operator.stat(&entry.path))
+        .await
+        .ok()
+        .and_then(|metadata| metadata.last_modified())
+}
+
+/// Objects requested from the storage per list call. Listings are read page by page and
+/// only the backups that are kept stay in memory, so a limited list of a location with
+/// many objects doesn't hold them all.
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Backups under `path` matching `options`, newest first, without their sizes
+async fn list_backups(
+    operator: &Operator,
+    path: &str,
+    options: &ListOptions,
+) -> opendal::Result<Vec<Entry>> {
+    let limit = match options.latest_only {
+        Some(true) => Some(1),
+        _ => options.limit,
+    };
+
+    let mut lister = operator
+        .lister_with(path)
+        .recursive(options.recursive.unwrap_or(true))
+        .limit(LIST_PAGE_SIZE)
+        .await?;
+    let mut entries: Vec<Entry> = Vec::new();
+
+    while let Some(opendal_entry) = lister.try_next().await? {
+        let entry = Entry::from(&opendal_entry);
+
+        if !entry.metadata.is_file
+            || entry.metadata.name.ends_with(PARTIAL_SUFFIX)
+            || !is_backup_of(&entry.metadata.name, options.database.as_deref())
+            || (is_quarantined(&entry.path) && !options.include_quarantined.unwrap_or(false))
+        {
+            continue;
+        }
+
+        if options.before.is_some() || options.after.is_some() {
+            let Ok(timestamp) = extract_timestamp_from_filename(&entry.metadata.name) else {
+                continue;
+            };
+
+            if options.before.is_some_and(|before| timestamp >= before)
+                || options.after.is_some_and(|after| timestamp < after)
+            {
+                continue;
+            }
+        }
+
+        entries.push(entry);
+
+        // Only the newest `limit` backups are returned, drop the others as the listing goes
+        if let Some(limit) = limit {
+            if entries.len() >= limit.max(LIST_PAGE_SIZE) * 2 {
+                sort_newest_first(&mut entries);
+                entries.truncate(limit);
+            }
+        }
+    }
+
+    sort_newest_first(&mut entries);
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+
+    Ok(entries)
+}
+
+/// Backups without a timestamp in their name come last
+fn sort_newest_first(entries: &mut [Entry]) {
+    entries.sort_by_cached_key(|entry| {
+        Reverse(
+            extract_timestamp_from_filename(&entry.metadata.name)
+                .unwrap_or_else(|_| DateTime::<Utc>::from(SystemTime::UNIX_EPOCH)),
+        )
+    });
+}
+
+/// Object store listings (S3, GCS, Azure) usually carry the size, the local one doesn't
+async fn fill_size(
+    operator: &Operator,
+    config: &StorageConfig,
+    entry: &mut Entry,
+    stat_missing_sizes: bool,
+) {
+    if let StorageConfig::Local(local_config) = config {
+        let full_path = Path::new(&local_config.location).join(&entry.path);
+        if let Ok(metadata) = fs::metadata(&full_path) {
+            entry.metadata.content_length = metadata.len();
+        }
+    } else if stat_missing_sizes && entry.metadata.content_length == 0 {
+        match operator.stat(&entry.path).await {
+            Ok(metadata) => entry.metadata.content_length = metadata.content_length(),
+            Err(e) => warn!("Failed to get the size of {}: {}", entry.path, e),
+        }
+    }
+}
+
+/// Deletes cleanup runs at once, deleting thousands of expired backups one by one is slow
+/// on object storage
+const CLEANUP_DELETE_CONCURRENCY: usize = 16;
+
+/// An incremental chain recorded next to the backups, see [`crate::BinlogState`]
+struct BackupChain {
+    database: String,
+    state_path: String,
+    /// Paths of the base and its increments
+    members: HashSet<String>,
+}
+
+/// Reads the incremental chains among `entries`, only the one of `database` when set. A
+/// state that can't be read is skipped with a warning, its backups are then aged one by
+/// one like before chains were tracked.
+async fn read_backup_chains(
+    operator: &Operator,
+    retry: &RetryConfig,
+    entries: &[Entry],
+    database: Option<&str>,
+) -> Vec<BackupChain> {
+    let mut chains = Vec::new();
+
+    for entry in entries {
+        let Some(chain_database) = entry.path.strip_suffix(BINLOG_STATE_SUFFIX) else {
+            continue;
+        };
+
+        if database.is_some_and(|database| database != chain_database) {
+            continue;
+        }
+
+        let state = with_retry(retry, "Read", || operator.read(&entry.path))
+            .await
+            .and_then(|buffer| {
+                serde_json::from_slice::<BinlogState>(&buffer.to_vec()).map_err(Into::into)
+            });
+
+        match state {
+            Ok(state) => chains.push(BackupChain {
+                database: chain_database.to_string(),
+                state_path: entry.path.clone(),
+                members: std::iter::once(state.base)
+                    .chain(state.increments)
+                    .collect(),
+            }),
+            Err(e) => warn!("Failed to read the chain in {}: {}", entry.path, e),
+        }
+    }
+
+    chains
+}
+
+/// Backups cleanup keeps or removes together: the base and increments of a chain, which
+/// can only be restored as a whole, or a single backup. Aged by their newest backup.
+struct CleanupUnit {
+    database: Option<String>,
+    /// Indices into the backups
+    members: Vec<usize>,
+    newest: DateTime<Utc>,
+    /// Index into the chains, for the units of a chain
+    chain: Option<usize>,
+}
+
+fn cleanup_units(backups: &[(Entry, DateTime<Utc>)], chains: &[BackupChain]) -> Vec<CleanupUnit> {
+    let mut units: Vec<CleanupUnit> = Vec::new();
+    let mut chain_units: HashMap<usize, usize> = HashMap::new();
+
+    for (index, (entry, timestamp)) in backups.iter().enumerate() {
+        match chains
+            .iter()
+            .position(|chain| chain.members.contains(&entry.path))
+        {
+            Some(chain) => {
+                let unit = *chain_units.entry(chain).or_insert_with(|| {
+                    units.push(CleanupUnit {
+                        database: Some(chains[chain].database.clone()),
+                        members: Vec::new(),
+                        newest: *timestamp,
+                        chain: Some(chain),
+                    });
+                    units.len() - 1
+                });
+
+                units[unit].members.push(index);
+                units[unit].newest = units[unit].newest.max(*timestamp);
+            }
+            None => units.push(CleanupUnit {
+                database: extract_database_from_filename(&entry.metadata.name).ok(),
+                members: vec![index],
+                newest: *timestamp,
+                chain: None,
+            }),
+        }
+    }
+
+    units
+}
+
+/// Indices of the units cleanup keeps whatever their age: the newest of each database,
+/// and the ones `policy` selects among the units of that database. Databases sharing a
+/// location are handled apart, so the one backed up last can't take the policy slots of
+/// the others. Objects whose name has no database, which only strict cleanups consider,
+/// don't belong to any of them and get no such protection.
+fn units_to_keep_per_database(
+    units: &[CleanupUnit],
+    policy: Option<&RetentionPolicy>,
+) -> HashSet<usize> {
+    let mut databases: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, unit) in units.iter().enumerate() {
+        if let Some(database) = &unit.database {
+            databases.entry(database.as_str()).or_default().push(index);
+        }
+    }
+
+    let mut keep = HashSet::new();
+
+    for indices in databases.values() {
+        let timestamps: Vec<DateTime<Utc>> =
+            indices.iter().map(|&index| units[index].newest).collect();
+
+        if let Some(newest) = timestamps.iter().max() {
+            keep.extend(
+                indices
+                    .iter()
+                    .filter(|&&index| units[index].newest == *newest),
+            );
+        }
+
+        if let Some(policy) = policy {
+            keep.extend(
+                policy
+                    .backups_to_keep(&timestamps)
+                    .into_iter()
+                    .map(|position| indices[position]),
+            );
+        }
+    }
+
+    keep
+}
+
+/// Deletes the expired backups and their sidecars, a few at a time, and returns the ones
+/// that were removed in the order they were given. A backup that fails to delete is
+/// logged and left out, the others are still deleted.
+async fn delete_backups(operator: &Operator, entries: Vec<Entry>) -> Vec<Entry> {
+    stream::iter(entries)
+        .map(|entry| async move {
+            if let Err(e) = operator.delete(&entry.path).await {
+                error!("Failed to delete {}: {}", entry.path, e);
+                return None;
+            }
+
+            info!("Successfully deleted {}", entry.path);
+
+            for sidecar in sidecar_paths(&entry.path) {
+                if let Err(e) = operator.delete(&sidecar).await {
+                    warn!("Failed to delete {}: {}", sidecar, e);
+                }
+            }
+
+            Some(entry)
+        })
+        .buffered(CLEANUP_DELETE_CONCURRENCY)
+        .filter_map(|entry| async move { entry })
+        .collect()
+        .await
+}
+
+/// Suffix used for in-progress uploads on local storage. Files are renamed to their final
+/// name only once the writer is closed successfully.
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+struct OpenWriter {
+    writer: Writer,
+    path: String,
+    partial_path: Option<String>,
+}
+
+impl OpenWriter {
+    async fn close(mut self, operator: &Operator) -> Result<Metadata> {
+        let result = self.writer.close().await;
+
+        let Some(partial_path) = self.partial_path else {
+            return result.map_err(|e| anyhow!("{}", e));
+        };
+
+        match result {
+            Ok(metadata) => {
+                operator
+                    .rename(&partial_path, &self.path)
+                    .await
+                    .map_err(|e| anyhow!("{}", e))?;
+                Ok(metadata)
+            }
+            Err(e) => {
+                if let Err(delete_error) = operator.delete(&partial_path).await {
+                    warn!("Failed to remove {}: {}", partial_path, delete_error);
+                }
+                Err(anyhow!("{}", e))
+            }
+        }
+    }
+
+    async fn abort(mut self, operator: &Operator) -> Result<()> {
+        let result = self.writer.abort().await.map_err(|e| anyhow!("{}", e));
+
+        if let Some(partial_path) = &self.partial_path {
+            operator
+                .delete(partial_path)
+                .await
+                .map_err(|e| anyhow!("{}", e))?;
+        }
+
+        result
+    }
+}
+
+/// Returned when the worker thread owning the storage operator has exited or panicked,
+/// after which the provider can't process any further commands.
+#[derive(Debug)]
+pub struct StorageWorkerUnavailable;
+
+impl fmt::Display for StorageWorkerUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Storage worker is no longer running")
+    }
+}
+
+impl std::error::Error for StorageWorkerUnavailable {}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageProviderOptions {
+    /// Shared bandwidth budget for every writer and reader created by the provider. Hand
+    /// the same limiter to several providers to cap their aggregate throughput.
+    pub rate_limiter: Option<RateLimiter>,
+    /// How many times to poll for an object that was just written but isn't listed yet,
+    /// for S3-compatible stores that are only eventually consistent. Disabled (0) by default.
+    pub visibility_retries: u32,
+    /// Applies to every operation of the worker, see [`RetryConfig`]
+    pub retry: RetryConfig,
+    /// Stat listed backups whose size the listing left out, which some S3-compatible
+    /// stores do. Costs one request per such backup, so it's off by default.
+    pub stat_missing_sizes: bool,
+    /// Time cleanup measures the age of backups against, the system clock when unset
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+/// First delay between visibility polls, doubled after every attempt
+const VISIBILITY_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// How long dropping the last provider waits for the worker to finish pending operations
+const DROP_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Clones share the same worker. Prefer [`StorageProvider::close`] once done with the
+/// provider: dropping the last clone also shuts the worker down, but has to block the
+/// current thread to do so and gives up after a timeout.
 #[derive(Clone)]
 pub struct StorageProvider {
     command_tx: Sender<StorageProviderCommand>,
-    _worker_handle: Arc<Option<JoinHandle<Result<()>>>>,
+    worker_handle: Arc<Option<JoinHandle<Result<()>>>>,
+    options: StorageProviderOptions,
 }
 
 impl StorageProvider {
     pub fn new(config: StorageConfig) -> anyhow::Result<Self> {
+        Self::new_with_options(config, StorageProviderOptions::default())
+    }
+
+    pub fn new_with_options(
+        config: StorageConfig,
+        options: StorageProviderOptions,
+    ) -> anyhow::Result<Self> {
+        // Errors in the worker only surface as an unavailable worker, so check this early
+        if let StorageConfig::Sftp(config) = &config {
+            sftp_key_path(config)?;
+        }
+
         let (command_tx, command_rx) = channel::<StorageProviderCommand>();
         let config_clone = config.clone();
+        let retry = options.retry.clone();
+        let clock = options
+            .clock
+            .clone()
+            .unwrap_or_else(|| Arc::new(SystemClock));
+        let stat_missing_sizes = options.stat_missing_sizes;
 
         let worker_handle = thread::spawn(move || -> Result<()> {
             let rt = Runtime::new()?;
@@ -169,13 +911,85 @@ impl StorageProvider {
                             None => builder,
                         };
 
+                        builder = match &config.sse {
+                            Some(SseConfig::S3) => builder.server_side_encryption("AES256"),
+                            Some(SseConfig::Kms { key_id }) => builder
+                                .server_side_encryption("aws:kms")
+                                .server_side_encryption_aws_kms_key_id(key_id),
+                            None => builder,
+                        };
+
                         Operator::new(builder)?
                             .layer(LoggingLayer::default())
                             .finish()
                     }
+                    StorageConfig::Gcs(config) => {
+                        let mut builder = Gcs::default()
+                            .root(&config.location)
+                            .bucket(&config.bucket);
+
+                        // opendal expects inline credentials base64 encoded
+                        builder = match (&config.credential, &config.credential_path) {
+                            (Some(credential), _) => {
+                                builder.credential(&BASE64_STANDARD.encode(credential))
+                            }
+                            (None, Some(path)) => builder.credential_path(path),
+                            (None, None) => builder,
+                        };
+
+                        Operator::new(builder)?
+                            .layer(LoggingLayer::default())
+                            .finish()
+                    }
+                    StorageConfig::Azure(config) => {
+                        let endpoint = config.endpoint.clone().unwrap_or_else(|| {
+                            format!("https://{}.blob.core.windows.net", config.account_name)
+                        });
+
+                        let mut builder = Azblob::default()
+                            .root(&config.location)
+                            .container(&config.container)
+                            .endpoint(&endpoint)
+                            .account_name(&config.account_name);
+
+                        builder = match (&config.account_key, &config.sas_token) {
+                            (Some(account_key), _) => builder.account_key(account_key),
+                            (None, Some(sas_token)) => builder.sas_token(sas_token),
+                            (None, None) => builder,
+                        };
+
+                        Operator::new(builder)?
+                            .layer(LoggingLayer::default())
+                            .finish()
+                    }
+                    #[cfg(unix)]
+                    StorageConfig::Sftp(config) => {
+                        let mut builder = Sftp::default()
+                            .endpoint(&format!("{}:{}", config.host, config.port))
+                            .user(&config.username)
+                            .root(&config.location)
+                            .enable_copy(true);
+
+                        if let Some(key_path) = sftp_key_path(config)? {
+                            builder = builder.key(key_path);
+                        }
+
+                        Operator::new(builder)?
+                            .layer(LoggingLayer::default())
+                            .finish()
+                    }
+                    #[cfg(not(unix))]
+                    StorageConfig::Sftp(_) => {
+                        return Err(anyhow!("SFTP storage is only supported on Unix"))
+                    }
+                    StorageConfig::Stdout => return serve_stdout(command_rx),
                 };
 
-                let mut writers: HashMap<u64, Writer> = HashMap::new();
+                // Writers and reader streams are retried chunk by chunk inside opendal,
+                // one-shot operations below are wrapped in with_retry instead
+                let streaming_operator = operator.clone().layer(retry.layer());
+
+                let mut writers: HashMap<u64, OpenWriter> = HashMap::new();
                 let mut next_writer_id = 1u64;
 
                 let mut streams: HashMap<u64, BufferStream> = HashMap::new();
@@ -190,58 +1004,30 @@ impl StorageProvider {
                         } => {
                             debug!("Processing List command for path: {}", path);
 
-                            let limit = options.limit.unwrap_or(1000);
-                            let latest_only = options.latest_only.unwrap_or(false);
-
-                            let result =
-                                operator.list_with(&path).recursive(true).limit(limit).await;
+                            let result = with_retry(&retry, "List", || {
+                                list_backups(&operator, &path, &options)
+                            })
+                            .await;
 
                             let _ = response.send(match result {
-                                Ok(entries) => {
-                                    let mut filtered_results: Vec<Entry> = entries
-                                        .into_iter()
-                                        .map(|opendal_entry| {
-                                            let mut entry = Entry::from(&opendal_entry);
-                                            // Get content length for local files
-                                            if let StorageConfig::Local(local_config) =
-                                                &config_clone
-                                            {
-                                                let full_path = Path::new(&local_config.location)
-                                                    .join(&entry.path);
-                                                if let Ok(metadata) = fs::metadata(&full_path) {
-                                                    entry.metadata.content_length = metadata.len();
-                                                }
-                                            }
-                                            entry
-                                        })
-                                        .filter(|entry| entry.metadata.is_file)
-                                        .collect();
+                                Ok(mut entries) => {
+                                    for entry in &mut entries {
+                                        fill_size(
+                                            &operator,
+                                            &config_clone,
+                                            entry,
+                                            stat_missing_sizes,
+                                        )
+                                        .await;
+                                    }
 
-                                    // Sort by timestamp (newest first)
-                                    filtered_results.sort_by(|a, b| {
-                                        let a_timestamp =
-                                            extract_timestamp_from_filename(&a.metadata.name)
-                                                .unwrap_or_else(|_| {
-                                                    DateTime::<Utc>::from(SystemTime::UNIX_EPOCH)
-                                                });
-                                        let b_timestamp =
-                                            extract_timestamp_from_filename(&b.metadata.name)
-                                                .unwrap_or_else(|_| {
-                                                    DateTime::<Utc>::from(SystemTime::UNIX_EPOCH)
-                                                });
-                                        b_timestamp.cmp(&a_timestamp)
-                                    });
-
-                                    if latest_only {
-                                        match filtered_results.first() {
-                                            Some(entry) => Ok(vec![entry.clone()]),
-                                            None => Err(anyhow!("No entry found")),
-                                        }
+                                    if options.latest_only.unwrap_or(false) && entries.is_empty() {
+                                        Err(anyhow!("No entry found"))
                                     } else {
-                                        Ok(filtered_results)
+                                        Ok(entries)
                                     }
                                 }
-                                Err(error) => Err(anyhow!("{}", error)),
+                                Err(error) => Err(error),
                             });
                         }
 
@@ -251,15 +1037,52 @@ impl StorageProvider {
                             response,
                         } => {
                             debug!("Processing CreateWriter command for path: {}", path);
-                            match operator.writer_with(&path).concurrent(concurrency).await {
+
+                            let partial_path = match &config_clone {
+                                StorageConfig::Local(_) => {
+                                    Some(format!("{}{}", path, PARTIAL_SUFFIX))
+                                }
+                                StorageConfig::S3(_)
+                                | StorageConfig::Gcs(_)
+                                | StorageConfig::Azure(_)
+                                | StorageConfig::Sftp(_)
+                                | StorageConfig::Stdout => None,
+                            };
+                            let target = partial_path.as_deref().unwrap_or(&path);
+
+                            let concurrency = match &config_clone {
+                                StorageConfig::S3(config) => {
+                                    concurrency.or(config.upload_concurrency)
+                                }
+                                _ => concurrency,
+                            }
+                            .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+                            .max(1);
+
+                            let result = with_retry(&retry, "Opening writer", || async {
+                                streaming_operator
+                                    .writer_with(target)
+                                    .concurrent(concurrency)
+                                    .await
+                            })
+                            .await;
+
+                            match result {
                                 Ok(writer) => {
                                     let writer_id = next_writer_id;
                                     next_writer_id += 1;
-                                    writers.insert(writer_id, writer);
+                                    writers.insert(
+                                        writer_id,
+                                        OpenWriter {
+                                            writer,
+                                            path,
+                                            partial_path,
+                                        },
+                                    );
                                     let _ = response.send(Ok(writer_id));
                                 }
                                 Err(e) => {
-                                    let _ = response.send(Err(anyhow!("{}", e)));
+                                    let _ = response.send(Err(e));
                                 }
                             }
                         }
@@ -274,8 +1097,8 @@ impl StorageProvider {
                                 writer_id,
                                 data.len()
                             );
-                            if let Some(writer) = writers.get_mut(&writer_id) {
-                                let result = writer.write(data).await;
+                            if let Some(open_writer) = writers.get_mut(&writer_id) {
+                                let result = open_writer.writer.write(data).await;
                                 let _ = response.send(result.map_err(|e| anyhow!("{}", e)));
                             } else {
                                 let _ =
@@ -288,37 +1111,58 @@ impl StorageProvider {
                             response,
                         } => {
                             debug!("Processing CloseWriter command for writer {}", writer_id);
-                            if let Some(mut writer) = writers.remove(&writer_id) {
-                                let result = writer.close().await;
-                                let _ = response.send(result.map_err(|e| anyhow!("{}", e)));
+                            if let Some(open_writer) = writers.remove(&writer_id) {
+                                let _ = response.send(open_writer.close(&operator).await);
                             } else {
                                 let _ =
                                     response.send(Err(anyhow!("Writer {} not found", writer_id)));
                             }
                         }
 
-                        StorageProviderCommand::CreateReader { path, response } => {
+                        StorageProviderCommand::AbortWriter {
+                            writer_id,
+                            response,
+                        } => {
+                            debug!("Processing AbortWriter command for writer {}", writer_id);
+                            if let Some(open_writer) = writers.remove(&writer_id) {
+                                let _ = response.send(open_writer.abort(&operator).await);
+                            } else {
+                                let _ =
+                                    response.send(Err(anyhow!("Writer {} not found", writer_id)));
+                            }
+                        }
+
+                        StorageProviderCommand::CreateReader {
+                            path,
+                            options,
+                            response,
+                        } => {
                             debug!("Processing CreateReader command for path: {}", path);
 
-                            match operator.stat(&path).await {
+                            match with_retry(&retry, "Stat", || operator.stat(&path)).await {
                                 Ok(metadata) => {
-                                    let file_size = metadata.content_length() as usize;
-                                    let chunk_size = if file_size > 512 { 512 } else { file_size };
-
-                                    match operator
+                                    let file_size = metadata.content_length();
+                                    let chunk_size = read_chunk_size(file_size, options.chunk_size);
+                                    let concurrency = options
+                                        .concurrency
+                                        .unwrap_or(DEFAULT_READ_CONCURRENCY)
+                                        .max(1);
+
+                                    match streaming_operator
                                         .reader_with(&path)
                                         .chunk(chunk_size)
-                                        .concurrent(2)
+                                        .concurrent(concurrency)
                                         .await
                                     {
                                         Ok(reader) => {
-                                            match reader.into_stream(0u64..(file_size as u64)).await
+                                            match reader.into_stream(0u64..file_size).await
                                             {
                                                 Ok(stream) => {
                                                     let reader_id = next_stream_id;
                                                     next_stream_id += 1;
                                                     streams.insert(reader_id, stream);
-                                                    let _ = response.send(Ok(reader_id));
+                                                    let _ = response
+                                                        .send(Ok((reader_id, file_size)));
                                                 }
                                                 Err(e) => {
                                                     let _ = response.send(Err(anyhow!("{}", e)));
@@ -331,7 +1175,7 @@ impl StorageProvider {
                                     }
                                 }
                                 Err(e) => {
-                                    let _ = response.send(Err(anyhow!("{}", e)));
+                                    let _ = response.send(Err(e));
                                 }
                             }
                         }
@@ -371,74 +1215,140 @@ impl StorageProvider {
                             }
                         }
 
-                        StorageProviderCommand::Delete { path, response } => {
+                        StorageProviderCommand::Delete {
+                            path,
+                            if_content,
+                            response,
+                        } => {
                             debug!("Processing Delete command for path: {}", path);
-                            let result = operator.delete(&path).await;
-                            let _ = response.send(result.map_err(|e| anyhow!("{}", e)));
+
+                            let unchanged = match if_content {
+                                Some(expected) => {
+                                    with_retry(&retry, "Read", || operator.read(&path))
+                                        .await
+                                        .map(|buffer| buffer.to_vec() == expected)
+                                }
+                                None => Ok(true),
+                            };
+
+                            let result = match unchanged {
+                                Ok(true) => {
+                                    with_retry(&retry, "Delete", || operator.delete(&path)).await
+                                }
+                                Ok(false) => Ok(()),
+                                Err(e) => Err(e),
+                            };
+                            let _ = response.send(result);
+                        }
+
+                        StorageProviderCommand::Exists { path, response } => {
+                            debug!("Processing Exists command for path: {}", path);
+                            let result = with_retry(&retry, "Exists", || operator.exists(&path)).await;
+                            let _ = response.send(result);
                         }
 
                         StorageProviderCommand::Test { response } => {
                             debug!("Processing Test command");
-                            let result = operator.list_with("/").recursive(true).limit(1).await;
-                            let _ = response.send(match result {
-                                Ok(_) => Ok(true),
-                                Err(e) => Err(anyhow!("{}", e)),
-                            });
+                            let result = with_retry(&retry, "Test", || async {
+                                operator.list_with("/").recursive(true).limit(1).await
+                            })
+                            .await;
+                            let _ = response.send(result.map(|_| true));
                         }
 
-                        StorageProviderCommand::Cleanup {
-                            retention_days,
-                            dry_run,
-                            response,
-                        } => {
+                        StorageProviderCommand::Cleanup { options, response } => {
                             debug!("Processing Cleanup command");
 
+                            let location = match &config_clone {
+                                StorageConfig::Local(config) => &config.location,
+                                StorageConfig::S3(config) => &config.location,
+                                StorageConfig::Gcs(config) => &config.location,
+                                StorageConfig::Azure(config) => &config.location,
+                                StorageConfig::Sftp(config) => &config.location,
+                                StorageConfig::Stdout => unreachable!("stdout has its own worker"),
+                            };
+
+                            if location.trim_matches('/').is_empty() && !options.allow_bucket_root
+                            {
+                                let _ = response.send(Err(anyhow!(
+                                    "Refusing to clean up the storage root, set a location or allow the bucket root explicitly"
+                                )));
+                                continue;
+                            }
+
+                            let CleanupOptions {
+                                retention_days,
+                                dry_run,
+                                policy,
+                                database,
+                                strict,
+                                ..
+                            } = options;
+
                             // Get all files
-                            let list_result =
-                                operator.list_with("").recursive(true).limit(10000).await;
+                            let list_result = with_retry(&retry, "List", || async {
+                                operator.list_with("").recursive(true).limit(10000).await
+                            })
+                            .await;
 
                             let result = match list_result {
                                 Ok(entries) => {
-                                    let cutoff = SystemTime::now()
-                                        .checked_sub(Duration::from_secs(retention_days * 86400))
+                                    let now = clock.now();
+                                    let cutoff_datetime = TimeDelta::try_days(retention_days as i64)
+                                        .and_then(|retention| now.checked_sub_signed(retention))
                                         .ok_or_else(|| {
                                             anyhow!("Failed to calculate cutoff date")
                                         })?;
 
-                                    let cutoff_datetime: DateTime<Utc> = cutoff.into();
-
-                                    let mut deleted_count = 0;
-                                    let mut deleted_size = 0;
-
-                                    for opendal_entry in entries {
-                                        let entry = Entry::from(&opendal_entry);
-                                        if !entry.metadata.is_file {
+                                    let mut report = CleanupReport {
+                                        dry_run,
+                                        ..Default::default()
+                                    };
+
+                                    let entries: Vec<Entry> =
+                                        entries.iter().map(Entry::from).collect();
+                                    let chains = read_backup_chains(
+                                        &operator,
+                                        &retry,
+                                        &entries,
+                                        database.as_deref(),
+                                    )
+                                    .await;
+
+                                    let mut backups: Vec<(Entry, DateTime<Utc>)> = Vec::new();
+
+                                    for entry in entries {
+                                        // Quarantined backups are kept for inspection.
+                                        // Increments are named after `<database>-incremental`,
+                                        // they belong to a database through its chain.
+                                        if !entry.metadata.is_file
+                                            || is_quarantined(&entry.path)
+                                            || !(is_backup_of(
+                                                &entry.metadata.name,
+                                                database.as_deref(),
+                                            ) || chains
+                                                .iter()
+                                                .any(|chain| chain.members.contains(&entry.path)))
+                                        {
                                             continue;
                                         }
 
                                         match extract_timestamp_from_filename(&entry.metadata.name)
                                         {
-                                            Ok(timestamp) => {
-                                                if timestamp < cutoff_datetime {
-                                                    let size = entry.metadata.content_length;
-                                                    deleted_size += size;
-                                                    deleted_count += 1;
-
-                                                    if !dry_run {
-                                                        if let Err(e) =
-                                                            operator.delete(&entry.path).await
-                                                        {
-                                                            error!(
-                                                                "Failed to delete {}: {}",
-                                                                entry.path, e
-                                                            );
-                                                        } else {
-                                                            info!(
-                                                                "Successfully deleted {}",
-                                                                entry.path
-                                                            );
-                                                        }
+                                            Ok(timestamp) => backups.push((entry, timestamp)),
+                                            Err(_)
+                                                if strict
+                                                    && !is_metadata_object(&entry.metadata.name) =>
+                                            {
+                                                match last_modified(&operator, &retry, &entry).await
+                                                {
+                                                    Some(timestamp) => {
+                                                        backups.push((entry, timestamp))
                                                     }
+                                                    None => warn!(
+                                                        "Failed to get the modification time of {}",
+                                                        entry.metadata.name
+                                                    ),
                                                 }
                                             }
                                             Err(_) => {
@@ -450,9 +1360,91 @@ impl StorageProvider {
                                         }
                                     }
 
-                                    Ok((deleted_count, deleted_size))
+                                    // Whatever the retention says, keep the newest backup of
+                                    // each database and anything taken in the last few minutes,
+                                    // so a bad retention or clock can't remove the backup that
+                                    // was just uploaded
+                                    let units = cleanup_units(&backups, &chains);
+                                    let kept = units_to_keep_per_database(&units, policy.as_ref());
+                                    let recent = now - MIN_BACKUP_AGE;
+
+                                    let expired_units: Vec<&CleanupUnit> = units
+                                        .iter()
+                                        .enumerate()
+                                        .filter(|(index, unit)| {
+                                            unit.newest < cutoff_datetime
+                                                && unit.newest <= recent
+                                                && !kept.contains(index)
+                                        })
+                                        .map(|(_, unit)| unit)
+                                        .collect();
+
+                                    let mut expired: Vec<Entry> = expired_units
+                                        .iter()
+                                        .flat_map(|unit| unit.members.iter())
+                                        .map(|&index| backups[index].0.clone())
+                                        .collect();
+
+                                    // Listings of some services carry no sizes, they have
+                                    // to be looked up before the objects are gone
+                                    for entry in &mut expired {
+                                        fill_size(&operator, &config_clone, entry, true).await;
+                                    }
+
+                                    let deleted = if dry_run {
+                                        expired
+                                    } else {
+                                        delete_backups(&operator, expired).await
+                                    };
+
+                                    // Once all of a chain is gone, the next incremental run
+                                    // starts a new one from a fresh base
+                                    if !dry_run {
+                                        let deleted_paths: HashSet<&str> = deleted
+                                            .iter()
+                                            .map(|entry| entry.path.as_str())
+                                            .collect();
+
+                                        for unit in &expired_units {
+                                            let Some(chain) = unit.chain.map(|chain| &chains[chain])
+                                            else {
+                                                continue;
+                                            };
+
+                                            if unit.members.iter().all(|&index| {
+                                                deleted_paths.contains(backups[index].0.path.as_str())
+                                            }) {
+                                                if let Err(e) = with_retry(&retry, "Delete", || {
+                                                    operator.delete(&chain.state_path)
+                                                })
+                                                .await
+                                                {
+                                                    warn!(
+                                                        "Failed to delete {}: {}",
+                                                        chain.state_path, e
+                                                    );
+                                                }
+                                            } else {
+                                                warn!(
+                                                    "Part of the chain in {} couldn't be deleted, it's kept",
+                                                    chain.state_path
+                                                );
+                                            }
+                                        }
+                                    }
+
+                                    for entry in deleted {
+                                        report.count += 1;
+                                        report.bytes_reclaimed += entry.metadata.content_length;
+                                        report.deleted.push(DeletedEntry {
+                                            name: entry.path,
+                                            size: entry.metadata.content_length,
+                                        });
+                                    }
+
+                                    Ok(report)
                                 }
-                                Err(e) => Err(anyhow!("{}", e)),
+                                Err(e) => Err(e),
                             };
 
                             let _ = response.send(result);
@@ -461,11 +1453,11 @@ impl StorageProvider {
                         StorageProviderCommand::Shutdown { response } => {
                             debug!("Processing Shutdown command");
 
-                            // Close all remaining writers
-                            for (writer_id, mut writer) in writers.drain() {
-                                debug!("Closing remaining writer {}", writer_id);
-                                if let Err(e) = writer.close().await {
-                                    error!("Error closing writer {}: {}", writer_id, e);
+                            // Writers still open at shutdown never finished, so discard them
+                            for (writer_id, open_writer) in writers.drain() {
+                                debug!("Aborting remaining writer {}", writer_id);
+                                if let Err(e) = open_writer.abort(&operator).await {
+                                    error!("Error aborting writer {}: {}", writer_id, e);
                                 }
                             }
 
@@ -485,18 +1477,72 @@ impl StorageProvider {
 
         Ok(StorageProvider {
             command_tx,
-            _worker_handle: Arc::new(Some(worker_handle)),
+            worker_handle: Arc::new(Some(worker_handle)),
+            options,
         })
     }
 
+    fn send(&self, command: StorageProviderCommand) -> Result<()> {
+        self.command_tx
+            .send(command)
+            .map_err(|_| anyhow::Error::new(StorageWorkerUnavailable))
+    }
+
+    async fn receive<T>(response_rx: oneshot::Receiver<Result<T>>) -> Result<T> {
+        response_rx
+            .await
+            .map_err(|_| anyhow::Error::new(StorageWorkerUnavailable))?
+    }
+
     pub async fn test(&self) -> Result<bool> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::Test {
+        self.send(StorageProviderCommand::Test {
             response: response_tx,
         })?;
 
-        let _ = response_rx.await?;
+        Self::receive(response_rx).await
+    }
+
+    /// Unlike `test`, which only lists, this checks every permission a backup needs by
+    /// writing a small probe object, reading it back and deleting it.
+    pub async fn test_readwrite(&self) -> Result<bool> {
+        self.test().await?;
+
+        let probe = format!("{}{}", PROBE_OBJECT_PREFIX, generate_id());
+        let content = probe.as_bytes();
+
+        let mut writer = self
+            .create_writer(&probe)
+            .await
+            .map_err(|e| anyhow!("Write check failed: {}", e))?;
+        writer
+            .write_all(content)
+            .and_then(|_| writer.flush())
+            .map_err(|e| anyhow!("Write check failed: {}", e))?;
+
+        self.wait_until_visible(&probe).await?;
+
+        let read_result = match self.create_reader(&probe).await {
+            Ok(mut reader) => {
+                let mut read_back = Vec::new();
+                reader
+                    .read_to_end(&mut read_back)
+                    .map(|_| read_back)
+                    .map_err(|e| anyhow!("Read check failed: {}", e))
+            }
+            Err(e) => Err(anyhow!("Read check failed: {}", e)),
+        };
+
+        // Delete even when reading failed, so the probe doesn't linger
+        self.delete(&probe)
+            .await
+            .map_err(|e| anyhow!("Delete check failed for {}: {}", probe, e))?;
+
+        if read_result? != content {
+            return Err(anyhow!("Read check failed: probe content doesn't match"));
+        }
+
         Ok(true)
     }
 
@@ -504,6 +1550,11 @@ impl StorageProvider {
         self.list_with_options(ListOptions {
             latest_only: None,
             limit: None,
+            recursive: None,
+            database: None,
+            before: None,
+            after: None,
+            include_quarantined: None,
         })
         .await
     }
@@ -511,77 +1562,247 @@ impl StorageProvider {
     pub async fn list_with_options(&self, options: ListOptions) -> Result<Vec<Entry>> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::List {
+        self.send(StorageProviderCommand::List {
             path: String::new(),
             options,
             response: response_tx,
         })?;
 
-        response_rx.await?
+        Self::receive(response_rx).await
     }
 
     pub async fn create_writer(&self, path: &str) -> Result<StorageWriter> {
+        self.create_writer_with_concurrency(path, None).await
+    }
+
+    /// `concurrency` overrides the `upload_concurrency` of an S3 config for this writer
+    pub async fn create_writer_with_concurrency(
+        &self,
+        path: &str,
+        concurrency: Option<usize>,
+    ) -> Result<StorageWriter> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::CreateWriter {
+        self.send(StorageProviderCommand::CreateWriter {
             path: path.to_string(),
             response: response_tx,
-            concurrency: 5,
+            concurrency,
         })?;
 
-        let writer_id = response_rx.await??;
-        Ok(StorageWriter::new(writer_id, self.command_tx.clone()))
+        let writer_id = Self::receive(response_rx).await?;
+        Ok(StorageWriter::new(writer_id, self.command_tx.clone())
+            .with_rate_limiter(self.options.rate_limiter.clone()))
     }
 
     pub async fn create_reader(&self, filename: &str) -> Result<StorageReader> {
+        self.create_reader_with_options(filename, ReaderOptions::default())
+            .await
+    }
+
+    pub async fn create_reader_with_chunk_size(
+        &self,
+        filename: &str,
+        chunk_size: usize,
+    ) -> Result<StorageReader> {
+        self.create_reader_with_options(
+            filename,
+            ReaderOptions {
+                chunk_size: Some(chunk_size),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    pub async fn create_reader_with_options(
+        &self,
+        filename: &str,
+        options: ReaderOptions,
+    ) -> Result<StorageReader> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::CreateReader {
+        self.send(StorageProviderCommand::CreateReader {
             path: filename.to_string(),
+            options,
             response: response_tx,
         })?;
 
-        let reader_id = response_rx.await??;
-        Ok(StorageReader::new(reader_id, self.command_tx.clone()))
+        let (reader_id, size) = Self::receive(response_rx).await?;
+        Ok(StorageReader::new(reader_id, size, self.command_tx.clone())
+            .with_rate_limiter(self.options.rate_limiter.clone()))
     }
 
     pub async fn delete(&self, path: &str) -> Result<()> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::Delete {
+        self.send(StorageProviderCommand::Delete {
             path: path.to_string(),
+            if_content: None,
             response: response_tx,
         })?;
 
-        response_rx.await?
+        Self::receive(response_rx).await
+    }
+
+    /// Deletes `path` without waiting for the storage, for cleanups in `Drop`, but only
+    /// while it still holds `content`. A failure is not reported.
+    pub fn delete_detached_if_unchanged(&self, path: &str, content: Vec<u8>) {
+        let (response_tx, _response_rx) = oneshot::channel();
+
+        let _ = self.send(StorageProviderCommand::Delete {
+            path: path.to_string(),
+            if_content: Some(content),
+            response: response_tx,
+        });
     }
 
-    pub async fn cleanup(&self, retention_days: u64, dry_run: bool) -> Result<(usize, u64)> {
+    pub async fn exists(&self, path: &str) -> Result<bool> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::Cleanup {
+        self.send(StorageProviderCommand::Exists {
+            path: path.to_string(),
+            response: response_tx,
+        })?;
+
+        Self::receive(response_rx).await
+    }
+
+    /// Waits for a just-written object to become visible, see
+    /// `StorageProviderOptions::visibility_retries`. Returns immediately when retries are
+    /// disabled.
+    pub async fn wait_until_visible(&self, path: &str) -> Result<()> {
+        let retries = self.options.visibility_retries;
+
+        if retries == 0 {
+            return Ok(());
+        }
+
+        let mut delay = VISIBILITY_RETRY_DELAY;
+
+        for attempt in 0..=retries {
+            if self.exists(path).await? {
+                return Ok(());
+            }
+
+            if attempt < retries {
+                debug!("{} is not visible yet, checking again in {:?}", path, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+
+        Err(anyhow!(
+            "{} is still not visible after {} retries",
+            path,
+            retries
+        ))
+    }
+
+    pub async fn cleanup(&self, retention_days: u64, dry_run: bool) -> Result<CleanupReport> {
+        self.cleanup_with_options(CleanupOptions {
             retention_days,
             dry_run,
+            allow_bucket_root: false,
+            policy: None,
+            database: None,
+            strict: false,
+        })
+        .await
+    }
+
+    /// Deletes every backup the policy doesn't select, apart from the newest one of each
+    /// database and backups taken in the last few minutes. The policy applies to each
+    /// database on its own.
+    pub async fn cleanup_with_policy(
+        &self,
+        policy: RetentionPolicy,
+        dry_run: bool,
+    ) -> Result<CleanupReport> {
+        self.cleanup_with_options(CleanupOptions {
+            retention_days: 0,
+            dry_run,
+            allow_bucket_root: false,
+            policy: Some(policy),
+            database: None,
+            strict: false,
+        })
+        .await
+    }
+
+    pub async fn cleanup_with_options(&self, options: CleanupOptions) -> Result<CleanupReport> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.send(StorageProviderCommand::Cleanup {
+            options,
             response: response_tx,
         })?;
 
-        response_rx.await?
+        Self::receive(response_rx).await
     }
 
     pub async fn shutdown(&self) -> Result<()> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.command_tx.send(StorageProviderCommand::Shutdown {
+        self.send(StorageProviderCommand::Shutdown {
             response: response_tx,
         })?;
 
-        response_rx.await?
+        Self::receive(response_rx).await
+    }
+
+    /// Shuts the worker down once it has processed every command sent before, and waits
+    /// for its thread to exit. Writers that weren't closed yet are aborted. Other clones
+    /// of the provider can't be used afterwards.
+    pub async fn close(mut self) -> Result<()> {
+        self.shutdown().await?;
+
+        if let Some(handle) = self.take_worker_handle() {
+            tokio::task::spawn_blocking(move || handle.join())
+                .await
+                .map_err(|e| anyhow!("Failed to wait for the storage worker: {}", e))?
+                .map_err(|_| anyhow!("Storage worker panicked"))??;
+        }
+
+        Ok(())
+    }
+
+    /// The worker thread, only handed to the last clone of the provider
+    fn take_worker_handle(&mut self) -> Option<JoinHandle<Result<()>>> {
+        Arc::into_inner(std::mem::replace(&mut self.worker_handle, Arc::new(None))).flatten()
     }
 }
 
 impl Drop for StorageProvider {
     fn drop(&mut self) {
-        // Attempt graceful shutdown
-        let _ = self.shutdown();
+        let Some(handle) = self.take_worker_handle() else {
+            return;
+        };
+
+        // Nothing awaits the response, the worker exits right after sending it
+        let (response_tx, _) = oneshot::channel();
+        if self
+            .send(StorageProviderCommand::Shutdown {
+                response: response_tx,
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        let deadline = std::time::Instant::now() + DROP_SHUTDOWN_TIMEOUT;
+        while !handle.is_finished() {
+            if std::time::Instant::now() >= deadline {
+                warn!(
+                    "Storage worker didn't shut down within {:?}, pending operations may be lost",
+                    DROP_SHUTDOWN_TIMEOUT
+                );
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        if let Ok(Err(e)) = handle.join() {
+            error!("Storage worker failed: {}", e);
+        }
     }
 }
@@ -0,0 +1,167 @@
+use std::{future::Future, time::Duration};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use opendal::layers::RetryLayer;
+
+/// How storage operations that fail with a temporary error (a 5xx from the store, a
+/// timeout, a dropped connection) are retried. Other errors fail on the first attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Attempts including the first one, 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every attempt
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before the attempt following `attempt`, counted from 1
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// The same policy for the reads and part uploads opendal streams itself, which
+    /// can't be repeated from the outside once they've started
+    pub(crate) fn layer(&self) -> RetryLayer {
+        RetryLayer::new()
+            .with_max_times(self.max_attempts.saturating_sub(1) as usize)
+            .with_min_delay(self.base_delay)
+            .with_max_delay(self.max_delay)
+            .with_factor(2.0)
+            .with_jitter()
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with a permanent error or runs out of
+/// attempts. The error of the last attempt is returned along with the attempt count.
+pub(crate) async fn with_retry<T, F, Fut>(
+    config: &RetryConfig,
+    description: &str,
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = opendal::Result<T>>,
+{
+    let max_attempts = config.max_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if e.is_temporary() && attempt < max_attempts => {
+                let delay = config.delay_after(attempt);
+                warn!(
+                    "{} failed (attempt {}/{}), retrying in {:?}: {}",
+                    description, attempt, max_attempts, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) if attempt > 1 => {
+                return Err(anyhow!(
+                    "{} failed after {} attempts: {}",
+                    description,
+                    attempt,
+                    e
+                ))
+            }
+            Err(e) => return Err(anyhow!("{}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use opendal::{Error, ErrorKind};
+
+    use super::{with_retry, RetryConfig};
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    fn temporary_error() -> Error {
+        Error::new(ErrorKind::Unexpected, "503 Service Unavailable").set_temporary()
+    }
+
+    #[tokio::test]
+    async fn test_retries_temporary_errors() {
+        let calls = AtomicU32::new(0);
+
+        let result = with_retry(&config(), "List", || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(temporary_error())
+            } else {
+                Ok("listed")
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), "listed");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_with_attempt_count() {
+        let calls = AtomicU32::new(0);
+
+        let error = with_retry(&config(), "List", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(temporary_error())
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(error.to_string().contains("after 3 attempts"));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_fail_immediately() {
+        let calls = AtomicU32::new(0);
+
+        let result = with_retry(&config(), "Exists", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::new(ErrorKind::PermissionDenied, "403 Forbidden"))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_delay_is_capped() {
+        let config = config();
+
+        assert_eq!(config.delay_after(1), Duration::from_millis(1));
+        assert_eq!(config.delay_after(3), Duration::from_millis(4));
+        assert_eq!(config.delay_after(10), Duration::from_millis(5));
+        assert_eq!(config.delay_after(40), Duration::from_millis(5));
+    }
+}
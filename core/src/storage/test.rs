@@ -6,22 +6,47 @@
 //! - Connection and basic operations
 //! - Write and read operations with various file sizes
 //! - Empty file handling
+//! - Uploads with a concurrency of 1 matching the default
 //! - Large file operations (10KB test files)
+//! - Reading a 50MB object in a handful of chunks, reporting its size as the total
+//! - Reads smaller than a chunk and straddling chunk boundaries
 //! - List operations with timestamp sorting
 //! - List operations with options (latest_only, limit)
+//! - Limited and date-bounded listings of more backups than a listing page
 //! - Delete operations
-//! - Cleanup operations with retention policies
+//! - Existence checks
+//! - Read/write/delete permission probe
+//! - Unfinished writes never appear under their final name
+//! - Closing the provider only once pending writes are complete
+//! - Dropping a clone leaving the shared worker running
+//! - Cleanup operations with retention policies, against a fixed clock
+//! - Cleanup always keeping the newest backup
+//! - Cleanup with daily/weekly/monthly retention policies
+//! - Cleanup keeping the newest backups of every database sharing a location
+//! - Listing and cleanup restricted to one database
+//! - Strict cleanup aging backups without a timestamp by their modification time
+//! - Cleanup deleting many expired backups and their sidecars concurrently
+//! - Cleanup keeping or removing an incremental chain as a whole
 //! - Error handling for edge cases
 //!
 //! ## S3 Storage Tests (`s3_storage_tests`)
 //! - Connection validation (gracefully skips if credentials unavailable)
-//! - Write and read operations
+//! - Write and read operations, waiting for new objects to become visible
 //! - List operations with cleanup
+//! - Listed sizes matching the written files
+//! - Uploads with a concurrency of 1 matching the default
+//!
+//! ## SFTP Storage Tests (`sftp_storage_tests`)
+//! - Write, read, list and delete round trip (skips unless `SFTP_HOST` is set)
 //!
 //! ## Edge Cases and Integration Tests (`edge_cases_and_integration_tests`)
 //! - Concurrent operations testing
 //! - Filename timestamp extraction validation
 //! - Storage metadata accuracy verification
+//! - Clean failures once the storage worker has exited
+//! - Connection test failing against an unreachable endpoint
+//! - Cleanup refusing to run against the storage root
+//! - Stdout sink rejecting list and cleanup
 //!
 //! ## Test Features
 //! - Uses temporary directories for local storage tests
@@ -34,17 +59,55 @@
 #[cfg(test)]
 mod storage_tests {
     use crate::{
-        common::extract_timestamp_from_filename,
+        clock::FixedClock,
+        common::{
+            binlog_state_name, extract_timestamp_from_filename, manifest_name,
+            BACKUP_TIMESTAMP_FORMAT,
+        },
+        databases::BinlogPosition,
+        progress::ProgressReporter,
         storage::{
-            provider::{ListOptions, StorageProvider},
+            provider::{
+                read_chunk_size, CleanupOptions, ListOptions, LocalStorageConfig, ReaderOptions,
+                RetentionPolicy, S3StorageConfig, StorageConfig, StorageProvider,
+                StorageProviderOptions, StorageWorkerUnavailable, DEFAULT_READ_CHUNK_SIZE,
+                PARTIAL_SUFFIX, PROBE_OBJECT_PREFIX,
+            },
+            retry::RetryConfig,
             Entry,
         },
-        test_utils::test_utils::{get_local_provider, get_s3_provider, initialize_test},
+        test_utils::test_utils::{
+            get_local_provider, get_local_provider_with_options, get_s3_provider,
+            get_s3_provider_with_options, get_sftp_provider, initialize_test,
+        },
+        BinlogState,
     };
-    use chrono::Utc;
-    use std::io::{Cursor, Read, Write};
+    use chrono::{TimeDelta, TimeZone, Utc};
+    use std::{
+        collections::HashSet,
+        io::{Cursor, Read, Write},
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tempfile::tempdir;
 
     const TEST_CONTENT: &[u8] = b"This is test content for storage operations";
+
+    #[derive(Default)]
+    struct ChunkCounter {
+        count: AtomicUsize,
+        total: AtomicU64,
+    }
+
+    impl ProgressReporter for ChunkCounter {
+        fn on_bytes(&self, _transferred: u64, total: Option<u64>) {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            self.total
+                .store(total.unwrap_or_default(), Ordering::SeqCst);
+        }
+    }
     const TEST_FILENAME: &str = "test_backup_2024-01-15-120000-abc123.dump";
 
     // Helper function to create test content
@@ -101,6 +164,46 @@ mod storage_tests {
         Ok(content)
     }
 
+    /// Uploads the same content with the default concurrency and with a concurrency of 1,
+    /// and checks both read back unchanged
+    async fn assert_single_part_upload_matches(
+        provider: &StorageProvider,
+        concurrent_filename: &str,
+        sequential_filename: &str,
+        size: usize,
+    ) {
+        let content = create_test_content(size);
+
+        for (filename, concurrency) in [(concurrent_filename, None), (sequential_filename, Some(1))]
+        {
+            let mut writer = provider
+                .create_writer_with_concurrency(filename, concurrency)
+                .await
+                .expect("Failed to create writer");
+            for chunk in content.chunks(64 * 1024) {
+                writer.write_all(chunk).expect("Failed to write chunk");
+            }
+            writer.flush().expect("Failed to flush writer");
+        }
+
+        for filename in [concurrent_filename, sequential_filename] {
+            provider
+                .wait_until_visible(filename)
+                .await
+                .expect("Written object never became visible");
+        }
+
+        let concurrent = read_test_content(provider, concurrent_filename)
+            .await
+            .expect("Failed to read test content");
+        let sequential = read_test_content(provider, sequential_filename)
+            .await
+            .expect("Failed to read sequential content");
+
+        assert_eq!(sequential, content);
+        assert_eq!(sequential, concurrent);
+    }
+
     mod local_storage_tests {
         use super::*;
 
@@ -137,6 +240,20 @@ mod storage_tests {
             );
         }
 
+        #[tokio::test]
+        async fn test_single_part_upload_matches() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            assert_single_part_upload_matches(
+                &provider,
+                TEST_FILENAME,
+                "sequential_backup_2024-01-15-120000-abc123.dump",
+                64 * 1024,
+            )
+            .await;
+        }
+
         #[tokio::test]
         async fn test_empty_file_operations() {
             initialize_test();
@@ -177,6 +294,128 @@ mod storage_tests {
             );
         }
 
+        #[test]
+        fn test_read_chunk_size() {
+            assert_eq!(read_chunk_size(50 << 20, None), DEFAULT_READ_CHUNK_SIZE);
+            assert_eq!(read_chunk_size(1024, None), 1024);
+            assert_eq!(read_chunk_size(0, None), 1);
+            assert_eq!(read_chunk_size(50 << 20, Some(1 << 20)), 1 << 20);
+            // A tiny chunk would take a request per 512 bytes of a large backup
+            assert_eq!(read_chunk_size(50 << 20, Some(512)), 5243);
+        }
+
+        #[tokio::test]
+        async fn test_large_object_chunk_count() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+            let size = 50 * 1024 * 1024;
+
+            let mut writer = provider
+                .create_writer("large.dump")
+                .await
+                .expect("Failed to create writer");
+            let block = create_test_content(1024 * 1024);
+            for _ in 0..size / block.len() {
+                writer.write_all(&block).expect("Failed to write");
+            }
+            writer.flush().expect("Failed to close writer");
+
+            // Progress is reported once per chunk fetched from storage
+            let chunks = Arc::new(ChunkCounter::default());
+            let mut reader = provider
+                .create_reader_with_options("large.dump", ReaderOptions::default())
+                .await
+                .expect("Failed to create reader")
+                .with_progress(Some(chunks.clone() as Arc<dyn ProgressReporter>));
+
+            let started_at = std::time::Instant::now();
+            let read = std::io::copy(&mut reader, &mut std::io::sink()).expect("Failed to read");
+
+            assert_eq!(read, size as u64);
+            assert_eq!(chunks.total.load(Ordering::SeqCst), size as u64);
+            assert!(
+                chunks.count.load(Ordering::SeqCst) <= size.div_ceil(DEFAULT_READ_CHUNK_SIZE),
+                "Read in {} chunks",
+                chunks.count.load(Ordering::SeqCst)
+            );
+            println!("Read {} bytes in {:?}", read, started_at.elapsed());
+        }
+
+        #[tokio::test]
+        async fn test_small_reads_across_chunks() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+            let content = create_test_content(100_000);
+
+            write_test_content(&provider, "chunked.dump", &content)
+                .await
+                .expect("Failed to write file");
+
+            let mut reader = provider
+                .create_reader_with_options(
+                    "chunked.dump",
+                    ReaderOptions {
+                        chunk_size: Some(8192),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .expect("Failed to create reader");
+
+            // Reads that don't line up with the chunks are served from the buffered rest
+            let mut read_content = Vec::new();
+            let mut buffer = [0u8; 1000];
+            loop {
+                match reader.read(&mut buffer).expect("Failed to read") {
+                    0 => break,
+                    n => read_content.extend_from_slice(&buffer[..n]),
+                }
+            }
+
+            assert_eq!(read_content, content);
+        }
+
+        #[tokio::test]
+        async fn test_close_completes_pending_writes() {
+            initialize_test();
+            let location = tempdir().expect("Failed to create temp dir");
+            let provider = StorageProvider::new(StorageConfig::Local(LocalStorageConfig {
+                id: "test".into(),
+                name: "local".into(),
+                location: location.path().to_str().unwrap().to_string(),
+            }))
+            .expect("Failed to create provider");
+            let content = create_test_content(8 * 1024 * 1024); // 8MB
+
+            let mut writer = provider
+                .create_writer("close_test.dump")
+                .await
+                .expect("Failed to create writer");
+            for chunk in content.chunks(64 * 1024) {
+                writer.write_all(chunk).expect("Failed to write chunk");
+            }
+            writer.flush().expect("Failed to flush writer");
+
+            provider.close().await.expect("Failed to close provider");
+
+            let written = std::fs::read(location.path().join("close_test.dump"))
+                .expect("Failed to read the written file");
+            assert_eq!(written.len(), content.len(), "File should be complete");
+            assert!(written == content, "File content should match");
+        }
+
+        #[tokio::test]
+        async fn test_dropping_clone_keeps_worker() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            drop(provider.clone());
+
+            write_test_content(&provider, TEST_FILENAME, TEST_CONTENT)
+                .await
+                .expect("Worker should still run after dropping a clone");
+        }
+
         #[tokio::test]
         async fn test_list_operations() {
             initialize_test();
@@ -243,6 +482,11 @@ mod storage_tests {
                 .list_with_options(ListOptions {
                     latest_only: Some(true),
                     limit: None,
+                    recursive: None,
+                    database: None,
+                    before: None,
+                    after: None,
+                    include_quarantined: None,
                 })
                 .await;
 
@@ -260,18 +504,92 @@ mod storage_tests {
                 .list_with_options(ListOptions {
                     latest_only: None,
                     limit: Some(10), // Use a higher limit since filtering happens after
+                    recursive: None,
+                    database: None,
+                    before: None,
+                    after: None,
+                    include_quarantined: None,
                 })
                 .await
                 .expect("Failed to list with limit");
 
-            // The limit is applied at the OpenDAL level before filtering, so we just verify
-            // that we get reasonable results and the operation succeeds
+            // The limit is applied after filtering, so we just verify that we get
+            // reasonable results and the operation succeeds
             assert!(
                 limited_entries.len() <= 10,
                 "Should not exceed the reasonable limit"
             );
         }
 
+        #[tokio::test]
+        async fn test_list_limit_and_date_range() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            // More than a page of the listing, so the limit is applied while it's read
+            let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+            for hour in 0..2500 {
+                let name = format!(
+                    "shop-{}-abc123.gz",
+                    (start + TimeDelta::hours(hour)).format(BACKUP_TIMESTAMP_FORMAT)
+                );
+                write_test_content(&provider, &name, b"backup")
+                    .await
+                    .expect("Failed to write backup");
+            }
+            write_test_content(&provider, "shop-before-migration.gz", b"backup")
+                .await
+                .expect("Failed to write backup");
+
+            let options = ListOptions {
+                latest_only: None,
+                limit: None,
+                recursive: None,
+                database: None,
+                before: None,
+                after: None,
+                include_quarantined: None,
+            };
+
+            let entries = provider
+                .list_with_options(options.clone())
+                .await
+                .expect("Failed to list every backup");
+            assert_eq!(entries.len(), 2501);
+
+            let entries = provider
+                .list_with_options(ListOptions {
+                    limit: Some(3),
+                    ..options.clone()
+                })
+                .await
+                .expect("Failed to list with limit");
+            let names: Vec<&str> = entries.iter().map(|e| e.metadata.name.as_str()).collect();
+            assert_eq!(
+                names,
+                [
+                    "shop-2024-04-14-150000-abc123.gz",
+                    "shop-2024-04-14-140000-abc123.gz",
+                    "shop-2024-04-14-130000-abc123.gz",
+                ]
+            );
+
+            // Backups of January 2nd, the bounds taken from the names
+            let entries = provider
+                .list_with_options(ListOptions {
+                    after: Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+                    include_quarantined: None,
+                    before: Some(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap()),
+                    ..options
+                })
+                .await
+                .expect("Failed to list a date range");
+            assert_eq!(entries.len(), 24);
+            assert!(entries
+                .iter()
+                .all(|e| e.metadata.name.starts_with("shop-2024-01-02-")));
+        }
+
         #[tokio::test]
         async fn test_delete_operations() {
             initialize_test();
@@ -300,72 +618,627 @@ mod storage_tests {
         }
 
         #[tokio::test]
-        async fn test_cleanup_operations() {
+        async fn test_readwrite_probe_cleans_up() {
             initialize_test();
             let provider = get_local_provider().expect("Failed to create local provider");
 
-            // Write test files with different timestamps (use a more reasonable old date)
-            let old_file = "backup_2023-01-01-120000-old123.dump";
-            let new_file = "backup_2024-01-01-120000-new456.dump";
+            assert!(provider
+                .test_readwrite()
+                .await
+                .expect("Read/write test failed"));
 
-            write_test_content(&provider, old_file, b"old content")
+            let entries = provider.list().await.expect("Failed to list entries");
+            assert!(
+                !entries
+                    .iter()
+                    .any(|e| e.metadata.name.starts_with(PROBE_OBJECT_PREFIX)),
+                "Probe object should be deleted"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_exists_operations() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+            let test_file = "exists_test.dump";
+
+            assert!(
+                !provider
+                    .exists(test_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "File should not exist before writing"
+            );
+
+            write_test_content(&provider, test_file, TEST_CONTENT)
                 .await
-                .expect("Failed to write old file");
-            write_test_content(&provider, new_file, b"new content")
+                .expect("Failed to write test file");
+
+            assert!(
+                provider
+                    .exists(test_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "File should exist after writing"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_unfinished_writes_leave_no_backup() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+            let test_file = "partial_test_2024-01-15-120000-abc123.dump";
+            let partial_file = format!("{}{}", test_file, PARTIAL_SUFFIX);
+
+            let mut writer = provider
+                .create_writer(test_file)
                 .await
-                .expect("Failed to write new file");
+                .expect("Failed to create writer");
+            writer
+                .write_all(TEST_CONTENT)
+                .expect("Failed to write test content");
 
-            // Test dry run cleanup (should not delete anything)
-            let (dry_count, dry_size) = provider
-                .cleanup(30, true) // 30 days retention, dry run
+            assert!(
+                !provider
+                    .exists(test_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "Final name should not exist while the write is in progress"
+            );
+
+            // Dropping the writer without flushing simulates a backup that failed before close
+            drop(writer);
+
+            assert!(
+                !provider
+                    .exists(test_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "Final name should not exist after an unfinished write"
+            );
+            assert!(
+                !provider
+                    .exists(&partial_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "Partial file should be cleaned up"
+            );
+
+            let entries = provider.list().await.expect("Failed to list entries");
+            assert!(entries
+                .iter()
+                .all(|entry| !entry.metadata.name.starts_with("partial_test")));
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_keeps_newest_backup() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            let older_file = "backup_2023-01-01-120000-abc123.dump";
+            let newest_file = "backup_2023-06-01-120000-def456.dump";
+
+            write_test_content(&provider, older_file, b"older content")
                 .await
-                .expect("Failed to perform dry run cleanup");
+                .expect("Failed to write older file");
+            write_test_content(&provider, newest_file, b"newest content")
+                .await
+                .expect("Failed to write newest file");
 
-            // The old file from 2023 should be identified for deletion
-            if dry_count == 0 {
-                // If no files are identified, it might be because the timestamp extraction failed
-                // This is acceptable for this test
-                println!("No files identified for cleanup - this may be expected");
-                return;
+            // Both backups are far past a zero-day retention
+            let report = provider
+                .cleanup(0, false)
+                .await
+                .expect("Failed to perform cleanup");
+
+            assert_eq!(report.count, 1, "Only the older backup should be deleted");
+            assert_eq!(report.deleted[0].name, older_file);
+            assert_eq!(report.bytes_reclaimed, b"older content".len() as u64);
+            assert!(!report.dry_run);
+            assert!(
+                !provider
+                    .exists(older_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "Older backup should be deleted"
+            );
+            assert!(
+                provider
+                    .exists(newest_file)
+                    .await
+                    .expect("Failed to check existence"),
+                "Newest backup should survive cleanup"
+            );
+        }
+
+        #[test]
+        fn test_retention_policy_selection() {
+            let timestamps = vec![
+                Utc.with_ymd_and_hms(2024, 3, 31, 20, 0, 0).unwrap(), // 0: newest, Sunday
+                Utc.with_ymd_and_hms(2024, 3, 31, 8, 0, 0).unwrap(),  // 1: same day
+                Utc.with_ymd_and_hms(2024, 3, 30, 8, 0, 0).unwrap(),  // 2: same ISO week
+                Utc.with_ymd_and_hms(2024, 3, 20, 8, 0, 0).unwrap(),  // 3: previous week
+                Utc.with_ymd_and_hms(2024, 2, 10, 8, 0, 0).unwrap(),  // 4: previous month
+                Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap(),  // 5: previous year
+            ];
+
+            let policy = RetentionPolicy {
+                daily: 2,
+                ..Default::default()
+            };
+            let mut kept: Vec<_> = policy.backups_to_keep(&timestamps).into_iter().collect();
+            kept.sort();
+            assert_eq!(kept, vec![0, 2]);
+
+            let policy = RetentionPolicy {
+                weekly: 2,
+                monthly: 3,
+                ..Default::default()
+            };
+            let mut kept: Vec<_> = policy.backups_to_keep(&timestamps).into_iter().collect();
+            kept.sort();
+            assert_eq!(kept, vec![0, 3, 4, 5]);
+
+            assert!(RetentionPolicy::default()
+                .backups_to_keep(&timestamps)
+                .is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_with_policy() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            let files = [
+                "backup_2023-06-01-120000-aaa111.dump",
+                "backup_2023-05-31-120000-bbb222.dump",
+                "backup_2023-05-15-120000-ccc333.dump",
+                "backup_2023-04-15-120000-ddd444.dump",
+            ];
+
+            for file in files {
+                write_test_content(&provider, file, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write backup file");
+            }
+
+            // One per month for the last two months with a backup
+            let report = provider
+                .cleanup_with_policy(
+                    RetentionPolicy {
+                        monthly: 2,
+                        ..Default::default()
+                    },
+                    false,
+                )
+                .await
+                .expect("Failed to perform cleanup");
+
+            assert_eq!(report.count, 2);
+
+            for (file, expected) in files.iter().zip([true, true, false, false]) {
+                assert_eq!(
+                    provider
+                        .exists(file)
+                        .await
+                        .expect("Failed to check existence"),
+                    expected,
+                    "Unexpected state for {}",
+                    file
+                );
             }
+        }
 
-            assert!(dry_size > 0, "Dry run should calculate size to delete");
+        #[tokio::test]
+        async fn test_cleanup_keeps_each_database() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
 
-            // Verify files still exist after dry run
-            let entries_after_dry = provider.list().await.expect("Failed to list entries");
+            // orders backs up more recently than users, which shouldn't cost users its backups
+            let files = [
+                "orders-2023-06-01-120000-aaa111.gz",
+                "orders-2023-05-01-120000-bbb222.gz",
+                "users-2023-03-01-120000-ccc333.gz",
+                "users-2023-02-01-120000-ddd444.gz",
+            ];
+
+            for file in files {
+                write_test_content(&provider, file, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write backup file");
+            }
+
+            // The newest backup is kept per database
+            let report = provider
+                .cleanup(30, true)
+                .await
+                .expect("Failed to perform cleanup");
+            let deleted: HashSet<&str> = report.deleted.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(deleted, HashSet::from([files[1], files[3]]));
+
+            // Policy slots as well, two months of each database
+            let report = provider
+                .cleanup_with_policy(
+                    RetentionPolicy {
+                        monthly: 2,
+                        ..Default::default()
+                    },
+                    false,
+                )
+                .await
+                .expect("Failed to perform cleanup");
+            assert_eq!(report.count, 0);
+
+            let report = provider
+                .cleanup_with_policy(
+                    RetentionPolicy {
+                        monthly: 1,
+                        ..Default::default()
+                    },
+                    false,
+                )
+                .await
+                .expect("Failed to perform cleanup");
+            assert_eq!(report.count, 2);
+
+            for (file, expected) in files.iter().zip([true, false, true, false]) {
+                assert_eq!(
+                    provider
+                        .exists(file)
+                        .await
+                        .expect("Failed to check existence"),
+                    expected,
+                    "Unexpected state for {}",
+                    file
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_database_filter() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            let orders = [
+                "orders-2023-01-01-120000-aaa111.gz",
+                "orders-2023-02-01-120000-bbb222.gz",
+            ];
+            let users = [
+                "users-2023-01-01-120000-ccc333.gz",
+                "users-2023-02-01-120000-ddd444.gz",
+            ];
+
+            for file in orders.iter().chain(users.iter()) {
+                write_test_content(&provider, file, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write backup file");
+            }
+
+            let entries = provider
+                .list_with_options(ListOptions {
+                    latest_only: None,
+                    limit: None,
+                    recursive: None,
+                    database: Some("orders".to_string()),
+                    before: None,
+                    after: None,
+                    include_quarantined: None,
+                })
+                .await
+                .expect("Failed to list entries");
+
+            let mut names: Vec<_> = entries.iter().map(|e| e.metadata.name.as_str()).collect();
+            names.sort();
+            assert_eq!(names, orders);
+
+            let report = provider
+                .cleanup_with_options(CleanupOptions {
+                    retention_days: 0,
+                    dry_run: false,
+                    allow_bucket_root: false,
+                    policy: None,
+                    database: Some("users".to_string()),
+                    strict: false,
+                })
+                .await
+                .expect("Failed to perform cleanup");
+
+            // Only the older users backup goes, the newest one is always kept
+            assert_eq!(report.count, 1);
+            assert!(!provider
+                .exists(users[0])
+                .await
+                .expect("Failed to check existence"));
+
+            for file in orders.iter().chain(&users[1..]) {
+                assert!(provider
+                    .exists(file)
+                    .await
+                    .expect("Failed to check existence"));
+            }
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_operations() {
+            initialize_test();
+            let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+            let provider = get_local_provider_with_options(StorageProviderOptions {
+                clock: Some(Arc::new(FixedClock(now))),
+                ..Default::default()
+            })
+            .expect("Failed to create local provider");
+
+            // 45, 14 and 1 days old at the fixed time
+            let old_file = "backup-2023-12-01-120000-aaa111.dump";
+            let recent_file = "backup-2024-01-01-120000-bbb222.dump";
+            let new_file = "backup-2024-01-14-120000-ccc333.dump";
+
+            for file in [old_file, recent_file, new_file] {
+                write_test_content(&provider, file, b"content")
+                    .await
+                    .expect("Failed to write backup file");
+            }
+
+            // Test dry run cleanup (should not delete anything)
+            let dry_report = provider
+                .cleanup(30, true) // 30 days retention, dry run
+                .await
+                .expect("Failed to perform dry run cleanup");
+            assert!(dry_report.dry_run);
+            assert_eq!(dry_report.count, 1);
+            assert_eq!(dry_report.deleted[0].name, old_file);
+            assert_eq!(dry_report.bytes_reclaimed, b"content".len() as u64);
             assert!(
-                entries_after_dry
-                    .iter()
-                    .any(|e| e.metadata.name == old_file),
+                provider
+                    .exists(old_file)
+                    .await
+                    .expect("Failed to check existence"),
                 "Old file should still exist after dry run"
             );
 
             // Test actual cleanup
-            let (actual_count, actual_size) = provider
+            let actual_report = provider
                 .cleanup(30, false) // 30 days retention, actual cleanup
                 .await
                 .expect("Failed to perform actual cleanup");
+            assert_eq!(actual_report.deleted, dry_report.deleted);
+
+            for (file, expected) in [(old_file, false), (recent_file, true), (new_file, true)] {
+                assert_eq!(
+                    provider
+                        .exists(file)
+                        .await
+                        .expect("Failed to check existence"),
+                    expected,
+                    "Unexpected state for {}",
+                    file
+                );
+            }
 
-            assert_eq!(actual_count, dry_count, "Actual count should match dry run");
-            assert_eq!(actual_size, dry_size, "Actual size should match dry run");
+            // Ten days later the second backup is past the retention as well
+            let later = get_local_provider_with_options(StorageProviderOptions {
+                clock: Some(Arc::new(FixedClock(now + TimeDelta::days(10)))),
+                ..Default::default()
+            })
+            .expect("Failed to create local provider");
 
-            // Verify cleanup results
-            let entries_after_cleanup = provider.list().await.expect("Failed to list entries");
+            for file in [recent_file, new_file] {
+                write_test_content(&later, file, b"content")
+                    .await
+                    .expect("Failed to write backup file");
+            }
 
-            // Check if the old file was actually deleted (it should be since it's from 2023)
-            let old_file_exists = entries_after_cleanup
-                .iter()
-                .any(|e| e.metadata.name == old_file);
+            let report = later.cleanup(20, true).await.expect("Failed to cleanup");
+            assert_eq!(report.count, 1);
+            assert_eq!(report.deleted[0].name, recent_file);
+        }
 
-            // The new file should remain
-            let new_file_exists = entries_after_cleanup
-                .iter()
-                .any(|e| e.metadata.name == new_file);
+        #[tokio::test]
+        async fn test_strict_cleanup() {
+            initialize_test();
+            // Far enough ahead that everything written now is past the retention
+            let now = Utc::now() + TimeDelta::days(60);
+            let provider = get_local_provider_with_options(StorageProviderOptions {
+                clock: Some(Arc::new(FixedClock(now))),
+                ..Default::default()
+            })
+            .expect("Failed to create local provider");
+
+            let backup = format!(
+                "shop-{}-abc123.gz",
+                (now - TimeDelta::days(1)).format(BACKUP_TIMESTAMP_FORMAT)
+            );
+            let backup = backup.as_str();
+            let renamed = "shop-before-migration.gz";
+            let manifest = "shop-before-migration.gz.manifest.json";
+
+            for file in [backup, renamed, manifest] {
+                write_test_content(&provider, file, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write file");
+            }
+
+            let options = CleanupOptions {
+                retention_days: 30,
+                dry_run: true,
+                allow_bucket_root: false,
+                policy: None,
+                database: None,
+                strict: false,
+            };
+
+            // Without strict mode the backup without a timestamp is skipped
+            let report = provider
+                .cleanup_with_options(options.clone())
+                .await
+                .expect("Failed to perform cleanup");
+            assert_eq!(report.count, 0);
+
+            // With it, its modification time puts it past the retention
+            let report = provider
+                .cleanup_with_options(CleanupOptions {
+                    strict: true,
+                    dry_run: false,
+                    ..options
+                })
+                .await
+                .expect("Failed to perform strict cleanup");
+            assert_eq!(report.count, 1);
+            assert_eq!(report.deleted[0].name, renamed);
+
+            for (file, expected) in [(backup, true), (renamed, false), (manifest, true)] {
+                assert_eq!(
+                    provider
+                        .exists(file)
+                        .await
+                        .expect("Failed to check existence"),
+                    expected,
+                    "Unexpected state for {}",
+                    file
+                );
+            }
+        }
 
-            if dry_count > 0 {
-                assert!(!old_file_exists, "Old file should be deleted");
+        #[tokio::test]
+        async fn test_cleanup_many_expired_backups() {
+            initialize_test();
+            let now = Utc::now();
+            let provider = get_local_provider_with_options(StorageProviderOptions {
+                clock: Some(Arc::new(FixedClock(now))),
+                ..Default::default()
+            })
+            .expect("Failed to create local provider");
+
+            // More than the deletes cleanup runs at once
+            let backups: Vec<String> = (0..100)
+                .map(|day| {
+                    format!(
+                        "shop-{}-abc123.gz",
+                        (now - TimeDelta::days(40 + day)).format(BACKUP_TIMESTAMP_FORMAT)
+                    )
+                })
+                .collect();
+            let newest = format!(
+                "shop-{}-abc123.gz",
+                (now - TimeDelta::days(1)).format(BACKUP_TIMESTAMP_FORMAT)
+            );
+
+            for backup in backups.iter().chain([&newest]) {
+                write_test_content(&provider, backup, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write backup");
+                write_test_content(&provider, &manifest_name(backup), TEST_CONTENT)
+                    .await
+                    .expect("Failed to write manifest");
             }
-            assert!(new_file_exists, "New file should remain");
+
+            let report = provider
+                .cleanup(30, false)
+                .await
+                .expect("Failed to perform cleanup");
+            assert_eq!(report.count, backups.len());
+            assert_eq!(
+                report.bytes_reclaimed,
+                (backups.len() * TEST_CONTENT.len()) as u64
+            );
+
+            let deleted: HashSet<&str> = report.deleted.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(deleted, backups.iter().map(String::as_str).collect());
+
+            for backup in &backups {
+                assert!(
+                    !provider.exists(backup).await.unwrap(),
+                    "{} was kept",
+                    backup
+                );
+                assert!(!provider.exists(&manifest_name(backup)).await.unwrap());
+            }
+            assert!(provider.exists(&newest).await.unwrap());
+            assert!(provider.exists(&manifest_name(&newest)).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_incremental_chain() {
+            initialize_test();
+            let now = Utc::now();
+            let provider = get_local_provider_with_options(StorageProviderOptions {
+                clock: Some(Arc::new(FixedClock(now))),
+                ..Default::default()
+            })
+            .expect("Failed to create local provider");
+
+            let name = |database: &str, days: i64| {
+                format!(
+                    "{}-{}-abc123.gz",
+                    database,
+                    (now - TimeDelta::days(days)).format(BACKUP_TIMESTAMP_FORMAT)
+                )
+            };
+            let full = name("shop", 50);
+            let base = name("shop", 40);
+            let increments = vec![name("shop-incremental", 39), name("shop-incremental", 10)];
+            let newest = name("shop", 1);
+            let state_name = binlog_state_name("shop");
+
+            for backup in [&full, &base].into_iter().chain(&increments) {
+                write_test_content(&provider, backup, TEST_CONTENT)
+                    .await
+                    .expect("Failed to write backup");
+            }
+            let state = BinlogState {
+                base: base.clone(),
+                increments: increments.clone(),
+                position: BinlogPosition {
+                    file: "binlog.000042".to_string(),
+                    position: 157,
+                },
+            };
+            write_test_content(&provider, &state_name, &serde_json::to_vec(&state).unwrap())
+                .await
+                .expect("Failed to write chain state");
+
+            let options = CleanupOptions {
+                retention_days: 30,
+                dry_run: false,
+                allow_bucket_root: false,
+                policy: None,
+                database: Some("shop".to_string()),
+                strict: false,
+            };
+
+            // The chain's last increment is recent, so its older base and first increment
+            // are kept with it, and its increments count as backups of the database
+            let report = provider
+                .cleanup_with_options(options.clone())
+                .await
+                .expect("Failed to perform cleanup");
+            let deleted: Vec<&str> = report.deleted.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(deleted, vec![full.as_str()]);
+
+            // Past the retention and superseded by a newer backup, the chain goes as a whole
+            write_test_content(&provider, &newest, TEST_CONTENT)
+                .await
+                .expect("Failed to write backup");
+            let report = provider
+                .cleanup_with_options(CleanupOptions {
+                    retention_days: 5,
+                    ..options
+                })
+                .await
+                .expect("Failed to perform cleanup");
+            let deleted: HashSet<&str> = report.deleted.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(
+                deleted,
+                [&base]
+                    .into_iter()
+                    .chain(&increments)
+                    .map(String::as_str)
+                    .collect()
+            );
+
+            // The next incremental run starts a new chain
+            assert!(!provider.exists(&state_name).await.unwrap());
+            assert!(provider.exists(&newest).await.unwrap());
         }
 
         #[tokio::test]
@@ -387,6 +1260,11 @@ mod storage_tests {
                 .list_with_options(ListOptions {
                     latest_only: Some(true),
                     limit: None,
+                    recursive: None,
+                    database: None,
+                    before: None,
+                    after: None,
+                    include_quarantined: None,
                 })
                 .await;
 
@@ -438,18 +1316,23 @@ mod storage_tests {
                 .await
                 .expect("Failed to write test content to S3");
 
+            provider
+                .wait_until_visible(&test_filename)
+                .await
+                .expect("Written object never became visible");
+
             // Read and verify content
-            // let read_content = read_test_content(&provider, &test_filename)
-            //     .await
-            //     .expect("Failed to read test content from S3");
+            let read_content = read_test_content(&provider, &test_filename)
+                .await
+                .expect("Failed to read test content from S3");
 
-            // assert_eq!(
-            //     read_content, TEST_CONTENT,
-            //     "S3 read content should match written content"
-            // );
+            assert_eq!(
+                read_content, TEST_CONTENT,
+                "S3 read content should match written content"
+            );
 
-            // // Clean up
-            // let _ = provider.delete(&test_filename).await;
+            // Clean up
+            let _ = provider.delete(&test_filename).await;
         }
 
         #[tokio::test]
@@ -493,6 +1376,127 @@ mod storage_tests {
                 let _ = provider.delete(filename).await;
             }
         }
+
+        #[tokio::test]
+        #[serial]
+        async fn test_listed_sizes() {
+            initialize_test();
+            // Some S3-compatible stores list objects without their size
+            let provider = get_s3_provider_with_options(StorageProviderOptions {
+                visibility_retries: 5,
+                stat_missing_sizes: true,
+                ..Default::default()
+            })
+            .expect("Unable to get s3 provider");
+
+            if provider.test().await.is_err() {
+                println!("Skipping S3 tests - S3 connection failed");
+                return;
+            }
+
+            let timestamp = Utc::now().timestamp();
+            let test_files = [
+                (format!("s3_size_{}_small.dump", timestamp), 100),
+                (format!("s3_size_{}_large.dump", timestamp), 64 * 1024),
+            ];
+
+            for (filename, size) in &test_files {
+                write_test_content(&provider, filename, &create_test_content(*size))
+                    .await
+                    .expect("Failed to write S3 test file");
+                provider
+                    .wait_until_visible(filename)
+                    .await
+                    .expect("Written object never became visible");
+            }
+
+            let entries = provider.list().await.expect("Failed to list S3 entries");
+
+            for (filename, size) in &test_files {
+                let entry = entries
+                    .iter()
+                    .find(|e| &e.metadata.name == filename)
+                    .expect("Written file should be listed");
+                assert_eq!(
+                    entry.metadata.content_length, *size as u64,
+                    "Listed size of {} should match",
+                    filename
+                );
+            }
+
+            for (filename, _) in &test_files {
+                let _ = provider.delete(filename).await;
+            }
+        }
+
+        #[tokio::test]
+        #[serial]
+        async fn test_single_part_upload_matches() {
+            initialize_test();
+            let provider = get_s3_provider().expect("Unable to get s3 provider");
+
+            if provider.test().await.is_err() {
+                println!("Skipping S3 tests - S3 connection failed");
+                return;
+            }
+
+            let timestamp = Utc::now().timestamp();
+            let concurrent_filename = format!("s3_concurrent_{}.dump", timestamp);
+            let sequential_filename = format!("s3_sequential_{}.dump", timestamp);
+
+            // Large enough for a multipart upload
+            assert_single_part_upload_matches(
+                &provider,
+                &concurrent_filename,
+                &sequential_filename,
+                12 * 1024 * 1024,
+            )
+            .await;
+
+            for filename in [&concurrent_filename, &sequential_filename] {
+                let _ = provider.delete(filename).await;
+            }
+        }
+    }
+
+    mod sftp_storage_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_write_read_list_and_delete() {
+            initialize_test();
+            let Some(provider) = get_sftp_provider().expect("Unable to get sftp provider") else {
+                println!("Skipping SFTP test, SFTP_HOST is not set");
+                return;
+            };
+
+            let test_filename = format!("sftp_test_{}.dump", Utc::now().timestamp());
+
+            write_test_content(&provider, &test_filename, TEST_CONTENT)
+                .await
+                .expect("Failed to write test content to SFTP");
+
+            let content = read_test_content(&provider, &test_filename)
+                .await
+                .expect("Failed to read test content from SFTP");
+            assert_eq!(content, TEST_CONTENT);
+
+            let entries = provider.list().await.expect("Failed to list entries");
+            let entry = entries
+                .iter()
+                .find(|entry| entry.metadata.name == test_filename)
+                .expect("Written file should be listed");
+            assert_eq!(entry.metadata.content_length, TEST_CONTENT.len() as u64);
+
+            provider
+                .delete(&test_filename)
+                .await
+                .expect("Failed to delete test file");
+            assert!(!provider
+                .exists(&test_filename)
+                .await
+                .expect("Failed to check existence"));
+        }
     }
 
     mod edge_cases_and_integration_tests {
@@ -610,5 +1614,111 @@ mod storage_tests {
             //     "Should have last modified timestamp"
             // );
         }
+
+        #[tokio::test]
+        async fn test_operations_fail_cleanly_after_worker_exit() {
+            initialize_test();
+            let provider = get_local_provider().expect("Failed to create local provider");
+
+            provider
+                .shutdown()
+                .await
+                .expect("Failed to shut down provider");
+
+            let error = provider
+                .list()
+                .await
+                .expect_err("Listing should fail once the worker has exited");
+
+            assert!(
+                error.downcast_ref::<StorageWorkerUnavailable>().is_some(),
+                "Expected StorageWorkerUnavailable, got: {}",
+                error
+            );
+        }
+
+        #[tokio::test]
+        async fn test_unreachable_storage_fails_test() {
+            initialize_test();
+            // Nothing listens on port 1, and retrying wouldn't change that
+            let provider = StorageProvider::new_with_options(
+                StorageConfig::S3(S3StorageConfig {
+                    id: "test".into(),
+                    name: "bogus".into(),
+                    region: "us-east-1".into(),
+                    endpoint: Some("http://127.0.0.1:1".into()),
+                    bucket: "missing".into(),
+                    access_key: "invalid".into(),
+                    secret_key: "invalid".into(),
+                    location: "backups".into(),
+                    upload_concurrency: None,
+                    sse: None,
+                }),
+                StorageProviderOptions {
+                    retry: RetryConfig {
+                        max_attempts: 1,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .expect("Failed to create provider");
+
+            assert!(
+                provider.test().await.is_err(),
+                "Testing an unreachable endpoint should fail"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_cleanup_refuses_storage_root() {
+            initialize_test();
+            let provider = StorageProvider::new(StorageConfig::Local(LocalStorageConfig {
+                id: "test".into(),
+                name: "root".into(),
+                location: "/".into(),
+            }))
+            .expect("Failed to create provider");
+
+            let result = provider
+                .cleanup_with_options(CleanupOptions {
+                    retention_days: 30,
+                    dry_run: true,
+                    allow_bucket_root: false,
+                    policy: None,
+                    database: None,
+                    strict: false,
+                })
+                .await;
+
+            assert!(
+                result.is_err(),
+                "Cleanup should refuse to run against the storage root"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_stdout_rejects_list_and_cleanup() {
+            initialize_test();
+            let provider =
+                StorageProvider::new(StorageConfig::Stdout).expect("Failed to create provider");
+
+            assert!(provider
+                .test()
+                .await
+                .expect("Stdout should always be available"));
+
+            let error = provider
+                .list()
+                .await
+                .expect_err("Listing stdout should fail");
+            assert!(error.to_string().contains("stdout"));
+
+            let error = provider
+                .cleanup(30, true)
+                .await
+                .expect_err("Cleaning up stdout should fail");
+            assert!(error.to_string().contains("stdout"));
+        }
     }
 }
@@ -0,0 +1,91 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Token bucket shared by every writer it is handed to, so the aggregate throughput
+/// of concurrent streams stays under a single budget.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    bytes_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1) as f64;
+
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                bytes_per_second,
+                tokens: bytes_per_second,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bucket
+            .lock()
+            .map(|bucket| bucket.bytes_per_second as u64)
+            .unwrap_or_default()
+    }
+
+    /// Takes `bytes` tokens from the bucket, blocking the calling thread until the
+    /// budget allows it. Requests larger than the bucket put it in debt, which later
+    /// callers pay back by waiting.
+    pub fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = match self.bucket.lock() {
+                Ok(bucket) => bucket,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.last_refill = now;
+            bucket.tokens =
+                (bucket.tokens + elapsed * bucket.bytes_per_second).min(bucket.bytes_per_second);
+            bucket.tokens -= bytes as f64;
+
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / bucket.bytes_per_second)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod throttle_tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn test_shared_budget_across_clones() {
+        let limiter = RateLimiter::new(1000);
+        let other = limiter.clone();
+
+        let start = Instant::now();
+        limiter.acquire(1000);
+        other.acquire(500);
+
+        assert!(
+            start.elapsed() >= Duration::from_millis(450),
+            "Clones should draw from the same bucket"
+        );
+    }
+}
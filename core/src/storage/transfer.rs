@@ -0,0 +1,318 @@
+use std::{
+    hash::{DefaultHasher, Hasher},
+    io::{self, Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+use crate::{
+    common::{extract_timestamp_from_filename, sidecar_paths, DEFAULT_PIPELINE_BUFFER_SIZE},
+    storage::provider::StorageProvider,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferOptions {
+    /// Only transfer backups older than this many days, based on the timestamp in their name
+    pub older_than_days: Option<u64>,
+    /// Delete the backup and its sidecars from the source once every copy is verified
+    pub delete_source_after_copy: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferStatus {
+    Copied,
+    Moved,
+    /// Would be transferred, in a dry run
+    Pending,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferResult {
+    pub path: String,
+    pub size: u64,
+    pub status: TransferStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferReport {
+    pub backups: Vec<TransferResult>,
+}
+
+impl TransferReport {
+    fn count(&self, predicate: impl Fn(&TransferStatus) -> bool) -> usize {
+        self.backups
+            .iter()
+            .filter(|backup| predicate(&backup.status))
+            .count()
+    }
+
+    pub fn copied(&self) -> usize {
+        self.count(|status| *status == TransferStatus::Copied)
+    }
+
+    pub fn moved(&self) -> usize {
+        self.count(|status| *status == TransferStatus::Moved)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.count(|status| *status == TransferStatus::Pending)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, TransferStatus::Failed(_)))
+    }
+}
+
+/// Size and checksum of the bytes that went through a copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ObjectDigest {
+    size: u64,
+    checksum: u64,
+}
+
+/// Copies the backups of `source` to the same paths on `destination`, with their sidecars.
+/// Every copied object is read back from the destination and compared with what was read
+/// from the source. With `delete_source_after_copy`, a backup is only removed from the
+/// source after all of its objects passed that check.
+pub async fn transfer_backups(
+    source: &StorageProvider,
+    destination: &StorageProvider,
+    options: TransferOptions,
+) -> Result<TransferReport> {
+    let cutoff: Option<DateTime<Utc>> = match options.older_than_days {
+        Some(days) => Some(
+            SystemTime::now()
+                .checked_sub(Duration::from_secs(days * 86400))
+                .ok_or_else(|| anyhow!("Failed to calculate cutoff date"))?
+                .into(),
+        ),
+        None => None,
+    };
+
+    let mut backups: Vec<_> = source
+        .list()
+        .await?
+        .into_iter()
+        .filter(|entry| entry.metadata.is_file)
+        .filter(
+            |entry| match extract_timestamp_from_filename(&entry.metadata.name) {
+                Ok(timestamp) => cutoff.is_none_or(|cutoff| timestamp < cutoff),
+                Err(_) => false,
+            },
+        )
+        .collect();
+    backups.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut report = TransferReport::default();
+
+    for backup in backups {
+        let size = backup.metadata.content_length;
+
+        let status = if options.dry_run {
+            TransferStatus::Pending
+        } else {
+            match transfer_backup(source, destination, &backup.path, &options).await {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("Failed to transfer {}: {}", backup.path, e);
+                    TransferStatus::Failed(e.to_string())
+                }
+            }
+        };
+
+        report.backups.push(TransferResult {
+            path: backup.path,
+            size,
+            status,
+        });
+    }
+
+    Ok(report)
+}
+
+async fn transfer_backup(
+    source: &StorageProvider,
+    destination: &StorageProvider,
+    path: &str,
+    options: &TransferOptions,
+) -> Result<TransferStatus> {
+    let mut objects = vec![path.to_string()];
+
+    for sidecar in sidecar_paths(path) {
+        if source.exists(&sidecar).await? {
+            objects.push(sidecar);
+        }
+    }
+
+    for object in &objects {
+        copy_object(source, destination, object).await?;
+    }
+
+    if !options.delete_source_after_copy {
+        return Ok(TransferStatus::Copied);
+    }
+
+    // The backup goes first: if deleting it fails, its sidecars still describe it
+    for object in &objects {
+        source.delete(object).await.map_err(|e| {
+            anyhow!(
+                "Copied, but failed to delete {} from the source: {}",
+                object,
+                e
+            )
+        })?;
+    }
+
+    info!("Moved {}", path);
+    Ok(TransferStatus::Moved)
+}
+
+/// Copies one object and reads it back from the destination to make sure it arrived
+/// complete and unchanged.
+pub async fn copy_object(
+    source: &StorageProvider,
+    destination: &StorageProvider,
+    path: &str,
+) -> Result<()> {
+    copy_object_to(source, destination, path, path).await
+}
+
+/// Same as [`copy_object`], storing the copy under another path
+pub async fn copy_object_to(
+    source: &StorageProvider,
+    destination: &StorageProvider,
+    path: &str,
+    destination_path: &str,
+) -> Result<()> {
+    let mut reader = source.create_reader(path).await?;
+    let mut writer = destination.create_writer(destination_path).await?;
+
+    let copied = digest(&mut reader, Some(&mut writer as &mut dyn Write))
+        .map_err(|e| anyhow!("Failed to copy {}: {}", path, e))?;
+    writer.flush()?;
+
+    destination.wait_until_visible(destination_path).await?;
+
+    let mut reader = destination.create_reader(destination_path).await?;
+    let stored = digest(&mut reader, None)
+        .map_err(|e| anyhow!("Failed to read back {}: {}", destination_path, e))?;
+
+    if stored != copied {
+        return Err(anyhow!(
+            "Verification of {} failed: copied {} bytes but the destination holds {} bytes with a different checksum",
+            path,
+            copied.size,
+            stored.size
+        ));
+    }
+
+    Ok(())
+}
+
+/// Reads `reader` to the end, passing the data on to `writer` if there is one
+fn digest(reader: &mut impl Read, mut writer: Option<&mut dyn Write>) -> io::Result<ObjectDigest> {
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0u8; DEFAULT_PIPELINE_BUFFER_SIZE];
+    let mut size = 0;
+
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        hasher.write(&buffer[..n]);
+        size += n as u64;
+
+        if let Some(writer) = writer.as_mut() {
+            writer.write_all(&buffer[..n])?;
+        }
+    }
+
+    Ok(ObjectDigest {
+        size,
+        checksum: hasher.finish(),
+    })
+}
+
+#[cfg(test)]
+mod transfer_tests {
+    use std::io::{Read, Write};
+
+    use crate::{
+        common::manifest_name,
+        storage::provider::StorageProvider,
+        test_utils::test_utils::{get_local_provider, initialize_test},
+    };
+
+    use super::{transfer_backups, TransferOptions};
+
+    async fn write(provider: &StorageProvider, path: &str, content: &[u8]) {
+        let mut writer = provider
+            .create_writer(path)
+            .await
+            .expect("Failed to create writer");
+        writer.write_all(content).expect("Failed to write");
+        writer.flush().expect("Failed to flush");
+    }
+
+    async fn read(provider: &StorageProvider, path: &str) -> Vec<u8> {
+        let mut reader = provider
+            .create_reader(path)
+            .await
+            .expect("Failed to create reader");
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).expect("Failed to read");
+        content
+    }
+
+    #[tokio::test]
+    async fn test_move_old_backups() {
+        initialize_test();
+        let hot = get_local_provider().expect("Failed to create source provider");
+        let cold = get_local_provider().expect("Failed to create destination provider");
+
+        let old = "app-2020-01-01-000000-abcdef12.gz";
+        let new = format!(
+            "app-{}-abcdef12.gz",
+            chrono::Utc::now().format("%Y-%m-%d-%H%M%S")
+        );
+
+        write(&hot, old, b"old backup").await;
+        write(&hot, &manifest_name(old), b"{}").await;
+        write(&hot, &new, b"new backup").await;
+
+        let report = transfer_backups(
+            &hot,
+            &cold,
+            TransferOptions {
+                older_than_days: Some(90),
+                delete_source_after_copy: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Failed to transfer backups");
+
+        assert_eq!(report.moved(), 1);
+        assert_eq!(report.failed(), 0);
+
+        assert_eq!(read(&cold, old).await, b"old backup");
+        assert_eq!(read(&cold, &manifest_name(old)).await, b"{}");
+        assert!(!hot.exists(old).await.expect("Failed to check existence"));
+        assert!(!hot
+            .exists(&manifest_name(old))
+            .await
+            .expect("Failed to check existence"));
+
+        assert!(hot.exists(&new).await.expect("Failed to check existence"));
+        assert!(!cold.exists(&new).await.expect("Failed to check existence"));
+    }
+}
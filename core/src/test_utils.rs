@@ -1,6 +1,6 @@
 #[cfg(test)]
 pub mod test_utils {
-    use std::{env, time::Duration};
+    use std::env;
 
     use anyhow::Result;
     use dotenv::dotenv;
@@ -12,8 +12,14 @@ pub mod test_utils {
     use tempfile::tempdir;
 
     use crate::{
-        databases::{postgres::connection::PostgreSqlConnection, ConnectionType, DatabaseConfig},
-        storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig, StorageProvider},
+        databases::{
+            postgres::connection::PostgreSqlConnection, ssh_tunnel::SshAuthMethod, ConnectionType,
+            DatabaseConfig, DEFAULT_CONNECT_TIMEOUT,
+        },
+        storage::provider::{
+            LocalStorageConfig, S3StorageConfig, SftpStorageConfig, StorageConfig, StorageProvider,
+            StorageProviderOptions,
+        },
     };
 
     pub fn initialize_test() {
@@ -41,7 +47,7 @@ pub mod test_utils {
 
         let pool = PgPoolOptions::new()
             .max_connections(5)
-            .acquire_timeout(Duration::from_secs(30))
+            .acquire_timeout(DEFAULT_CONNECT_TIMEOUT)
             .connect_with(connect_options)
             .await?;
 
@@ -64,7 +70,7 @@ pub mod test_utils {
 
         let pool = MySqlPoolOptions::new()
             .max_connections(5)
-            .acquire_timeout(Duration::from_secs(30))
+            .acquire_timeout(DEFAULT_CONNECT_TIMEOUT)
             .connect_with(connect_options)
             .await?;
 
@@ -90,6 +96,7 @@ pub mod test_utils {
             },
             port,
             ssh_tunnel: None,
+            connect_timeout: None,
         })
         .await?;
 
@@ -97,17 +104,34 @@ pub mod test_utils {
     }
 
     pub fn get_local_provider() -> Result<StorageProvider> {
+        get_local_provider_with_options(StorageProviderOptions::default())
+    }
+
+    pub fn get_local_provider_with_options(
+        options: StorageProviderOptions,
+    ) -> Result<StorageProvider> {
         let temp_path = tempdir()?;
         let config = StorageConfig::Local(LocalStorageConfig {
             id: "test".into(),
             name: "local".into(),
             location: temp_path.path().to_str().unwrap().to_string(),
         });
-        let provider = StorageProvider::new(config)?;
+        let provider = StorageProvider::new_with_options(config, options)?;
         Ok(provider)
     }
 
+    /// Some S3-compatible stores don't show new objects right away, so the provider waits
+    /// for them
     pub fn get_s3_provider() -> Result<StorageProvider> {
+        get_s3_provider_with_options(StorageProviderOptions {
+            visibility_retries: 5,
+            ..Default::default()
+        })
+    }
+
+    pub fn get_s3_provider_with_options(
+        options: StorageProviderOptions,
+    ) -> Result<StorageProvider> {
         let location = format!("s3_provider_test_{}", chrono::Utc::now().timestamp());
 
         let endpoint = env::var("S3_ENDPOINT")
@@ -122,10 +146,41 @@ pub mod test_utils {
             endpoint: Some(endpoint),
             region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
             location,
+            upload_concurrency: None,
+            sse: None,
         });
 
-        let provider = StorageProvider::new(config)?;
+        let provider = StorageProvider::new_with_options(config, options)?;
 
         Ok(provider)
     }
+
+    /// `None` unless `SFTP_HOST` points at a test server
+    pub fn get_sftp_provider() -> Result<Option<StorageProvider>> {
+        let Ok(host) = env::var("SFTP_HOST") else {
+            return Ok(None);
+        };
+
+        let config = StorageConfig::Sftp(SftpStorageConfig {
+            id: "test".into(),
+            name: "sftp".into(),
+            host,
+            port: env::var("SFTP_PORT")
+                .ok()
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(22),
+            username: env::var("SFTP_USERNAME").unwrap_or_else(|_| "dbkp".to_string()),
+            auth_method: SshAuthMethod::PrivateKey {
+                key_path: env::var("SFTP_KEY_PATH")?,
+                passphrase_key: None,
+            },
+            location: format!(
+                "{}/sftp_provider_test_{}",
+                env::var("SFTP_LOCATION").unwrap_or_else(|_| "/tmp".to_string()),
+                chrono::Utc::now().timestamp()
+            ),
+        });
+
+        Ok(Some(StorageProvider::new(config)?))
+    }
 }
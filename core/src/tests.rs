@@ -1,17 +1,23 @@
 #[cfg(test)]
 mod vprdbbkp_tests {
     use anyhow::Result;
-    use std::env;
+    use sqlx::{sqlite::SqliteConnectOptions, Connection, SqliteConnection};
+    use std::{env, io::Write, path::Path};
     use tempfile::tempdir;
 
     use crate::{
+        common::manifest_name,
+        compression::CompressionFormat,
         databases::{
             ssh_tunnel::{SshAuthMethod, SshTunnelConfig},
-            ConnectionType, DatabaseConfig, DatabaseConnection,
+            BackupScope, ConnectionType, DatabaseConfig, DatabaseConnection,
+        },
+        delete_backup,
+        storage::provider::{
+            LocalStorageConfig, RetentionPolicy, S3StorageConfig, StorageConfig, StorageProvider,
         },
-        storage::provider::{LocalStorageConfig, S3StorageConfig, StorageConfig, StorageProvider},
         test_utils::test_utils::{get_mysql_pool, get_postgresql_pool, initialize_test},
-        DbBkp, RestoreOptions,
+        BackupOptions, DbBkp, RestoreOptions, PRIMARY_DESTINATION,
     };
 
     fn get_local_provider() -> Result<StorageProvider> {
@@ -27,6 +33,30 @@ mod vprdbbkp_tests {
         Ok(provider)
     }
 
+    fn get_sqlite_config(path: &Path) -> DatabaseConfig {
+        DatabaseConfig {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            connection_type: ConnectionType::SQLite,
+            host: String::new(),
+            port: 0,
+            database: path.to_string_lossy().to_string(),
+            username: String::new(),
+            password: None,
+            ssh_tunnel: None,
+            connect_timeout: None,
+        }
+    }
+
+    async fn write_object(provider: &StorageProvider, name: &str) {
+        let mut writer = provider
+            .create_writer(name)
+            .await
+            .expect("Failed to create writer");
+        writer.write_all(b"backup").expect("Failed to write");
+        writer.flush().expect("Failed to close writer");
+    }
+
     fn get_s3_provider() -> Result<StorageProvider> {
         initialize_test();
 
@@ -44,6 +74,8 @@ mod vprdbbkp_tests {
             endpoint: Some(endpoint),
             region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
             location,
+            upload_concurrency: None,
+            sse: None,
         });
 
         let provider = StorageProvider::new(config)?;
@@ -67,6 +99,7 @@ mod vprdbbkp_tests {
             database: env::var("POSTGRESQL_NAME").unwrap_or_default(),
             port,
             ssh_tunnel: None,
+            connect_timeout: None,
         };
 
         Ok(config)
@@ -88,6 +121,7 @@ mod vprdbbkp_tests {
             database: env::var("MYSQL_NAME").unwrap_or_default(),
             port,
             ssh_tunnel: None,
+            connect_timeout: None,
         };
 
         Ok(config)
@@ -114,6 +148,7 @@ mod vprdbbkp_tests {
                 key_path: env::var("SSH_KEY_PATH").unwrap_or_default(),
                 passphrase_key: None,
             },
+            jump_hosts: Vec::new(),
         });
 
         Ok(config)
@@ -160,6 +195,16 @@ mod vprdbbkp_tests {
 
         let backup_name = engine.backup().await.expect("Failed to backup");
 
+        let manifest = engine
+            .read_manifest(&backup_name)
+            .await
+            .expect("Failed to read manifest")
+            .expect("Backup should have a manifest");
+        assert_eq!(manifest.database_type, Some(ConnectionType::PostgreSql));
+        assert!(manifest.database.is_some());
+        assert!(manifest.compressed_size.is_some_and(|size| size > 0));
+        assert_eq!(manifest.scope, BackupScope::Full);
+
         sqlx::query("UPDATE backup_test_table SET value = 999 WHERE name = 'test1'")
             .execute(&db_pool)
             .await
@@ -184,6 +229,7 @@ mod vprdbbkp_tests {
                 name: backup_name,
                 compression_format: None,
                 drop_database_first: Some(true),
+                ..Default::default()
             })
             .await
             .expect("Failed to restore");
@@ -298,6 +344,7 @@ mod vprdbbkp_tests {
                 name: backup_name,
                 compression_format: None,
                 drop_database_first: Some(true),
+                ..Default::default()
             })
             .await
             .expect("Failed to restore");
@@ -325,4 +372,322 @@ mod vprdbbkp_tests {
         let test3_exists = restored_rows.iter().any(|(name, _)| name == "test3");
         assert!(test3_exists, "test3 should be restored");
     }
+
+    #[tokio::test]
+    async fn test_04_postgresql_restore_detects_compression() {
+        initialize_test();
+        let config = get_postgresql_config().expect("Failed to get postgresql config");
+        let database_connection = DatabaseConnection::new(config.clone())
+            .await
+            .expect("Failed to get database connection");
+
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+
+        let engine = DbBkp::new(database_connection, storage_provider);
+
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Zlib,
+            CompressionFormat::Deflate,
+            CompressionFormat::Zstd,
+            CompressionFormat::Xz,
+            CompressionFormat::None,
+        ] {
+            // The restore terminates the connections to the database, start fresh each time
+            let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+
+            sqlx::query("DROP TABLE IF EXISTS compression_test_table")
+                .execute(&db_pool)
+                .await
+                .expect("Failed to drop test table");
+
+            sqlx::query("CREATE TABLE compression_test_table (id SERIAL PRIMARY KEY, name TEXT)")
+                .execute(&db_pool)
+                .await
+                .expect("Failed to create test table");
+
+            sqlx::query("INSERT INTO compression_test_table (name) VALUES ('a'), ('b')")
+                .execute(&db_pool)
+                .await
+                .expect("Failed to insert test data");
+
+            let backup_name = engine
+                .backup_with(Some(BackupOptions {
+                    compression_format: Some(format.clone()),
+                    ..Default::default()
+                }))
+                .await
+                .expect("Failed to backup");
+
+            sqlx::query("DELETE FROM compression_test_table")
+                .execute(&db_pool)
+                .await
+                .expect("Failed to delete test data");
+
+            engine
+                .restore(RestoreOptions {
+                    name: backup_name.clone(),
+                    compression_format: None,
+                    drop_database_first: Some(true),
+                    ..Default::default()
+                })
+                .await
+                .expect("Failed to restore");
+
+            let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+            let rows: Vec<(String,)> =
+                sqlx::query_as("SELECT name FROM compression_test_table ORDER BY id")
+                    .fetch_all(&db_pool)
+                    .await
+                    .expect("Failed to fetch restored data");
+
+            assert_eq!(rows.len(), 2, "{} should restore both rows", backup_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_05_postgresql_parallel_backup() {
+        initialize_test();
+        let config = get_postgresql_config().expect("Failed to get postgresql config");
+        let database_connection = DatabaseConnection::new(config.clone())
+            .await
+            .expect("Failed to get database connection");
+
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+
+        let engine = DbBkp::new(database_connection, storage_provider);
+
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+
+        sqlx::query("DROP TABLE IF EXISTS parallel_test_table")
+            .execute(&db_pool)
+            .await
+            .expect("Failed to drop test table");
+
+        sqlx::query("CREATE TABLE parallel_test_table (id SERIAL PRIMARY KEY, name TEXT)")
+            .execute(&db_pool)
+            .await
+            .expect("Failed to create test table");
+
+        sqlx::query("INSERT INTO parallel_test_table (name) VALUES ('a'), ('b'), ('c')")
+            .execute(&db_pool)
+            .await
+            .expect("Failed to insert test data");
+
+        let backup_name = engine
+            .backup_with(Some(BackupOptions {
+                jobs: Some(2),
+                ..Default::default()
+            }))
+            .await
+            .expect("Failed to backup");
+
+        sqlx::query("DELETE FROM parallel_test_table")
+            .execute(&db_pool)
+            .await
+            .expect("Failed to delete test data");
+
+        engine
+            .restore(RestoreOptions {
+                name: backup_name.clone(),
+                compression_format: None,
+                drop_database_first: Some(true),
+                jobs: Some(2),
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to restore");
+
+        let db_pool = get_postgresql_pool().await.expect("Failed to get db pool");
+        let rows: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM parallel_test_table ORDER BY id")
+                .fetch_all(&db_pool)
+                .await
+                .expect("Failed to fetch restored data");
+
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_06_sqlite_row_counts_round_trip() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let path = dir.path().join("app.db");
+
+        let connect_options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let mut db = SqliteConnection::connect_with(&connect_options)
+            .await
+            .expect("Failed to create database");
+        sqlx::query("CREATE TABLE items (name TEXT NOT NULL)")
+            .execute(&mut db)
+            .await
+            .expect("Failed to create table");
+        sqlx::query("INSERT INTO items (name) VALUES ('a'), ('b'), ('c')")
+            .execute(&mut db)
+            .await
+            .expect("Failed to insert test data");
+
+        let database_connection = DatabaseConnection::new(get_sqlite_config(&path))
+            .await
+            .expect("Failed to get database connection");
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+        let engine = DbBkp::new(database_connection, storage_provider);
+
+        let backup_name = engine
+            .backup_with(Some(BackupOptions {
+                record_row_counts: Some(true),
+                ..Default::default()
+            }))
+            .await
+            .expect("Failed to backup");
+
+        // Counted after the dump, so only advisory
+        let manifest = engine
+            .read_manifest(&backup_name)
+            .await
+            .expect("Failed to read manifest")
+            .expect("Missing manifest");
+        assert_eq!(manifest.row_counts.unwrap().get("items"), Some(&3));
+        assert!(!manifest.row_counts_exact);
+
+        sqlx::query("INSERT INTO items (name) VALUES ('d')")
+            .execute(&mut db)
+            .await
+            .expect("Failed to insert test data");
+        db.close().await.expect("Failed to close database");
+
+        let result = engine
+            .restore_with_result(RestoreOptions {
+                name: backup_name,
+                // Restored over the source file, which now holds an extra row
+                drop_database_first: Some(true),
+                verify_row_counts: Some(true),
+                strict_verify: Some(true),
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to restore");
+
+        assert!(result.verified);
+        assert!(result.row_count_mismatches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_07_cleanup_keeps_other_databases() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+
+        let own = [
+            "test-2023-01-01-120000-aaa111.gz",
+            "test-2023-02-01-120000-bbb222.gz",
+        ];
+        let other = [
+            "other-2023-01-01-120000-ccc333.gz",
+            "other-2023-02-01-120000-ddd444.gz",
+        ];
+        for name in own.iter().chain(other.iter()) {
+            write_object(&storage_provider, name).await;
+        }
+
+        let database_connection =
+            DatabaseConnection::new(get_sqlite_config(&dir.path().join("app.db")))
+                .await
+                .expect("Failed to get database connection");
+        let engine = DbBkp::new(database_connection, storage_provider.clone());
+
+        let report = engine.cleanup(0, false).await.expect("Failed to cleanup");
+
+        // The newest backup of the database is always kept
+        assert_eq!(report.count, 1);
+        assert!(!storage_provider.exists(own[0]).await.unwrap());
+        for name in other.iter().chain(&own[1..]) {
+            assert!(storage_provider.exists(name).await.unwrap());
+        }
+
+        // The older backup of the other database would go without the database filter
+        let report = engine
+            .cleanup_with_policy(RetentionPolicy::default(), true)
+            .await
+            .expect("Failed to cleanup");
+        assert_eq!(report.count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_08_delete_backup_with_sidecars() {
+        initialize_test();
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+
+        let name = "test-2023-01-01-120000-aaa111.gz";
+        write_object(&storage_provider, name).await;
+        write_object(&storage_provider, &manifest_name(name)).await;
+
+        // Only the storage is needed, no database connection
+        delete_backup(&storage_provider, name)
+            .await
+            .expect("Failed to delete backup");
+
+        assert!(!storage_provider.exists(name).await.unwrap());
+        assert!(!storage_provider.exists(&manifest_name(name)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_09_failing_mirror_keeps_primary() {
+        initialize_test();
+        let dir = tempdir().expect("Failed to create directory");
+        let path = dir.path().join("app.db");
+
+        let connect_options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let mut db = SqliteConnection::connect_with(&connect_options)
+            .await
+            .expect("Failed to create database");
+        sqlx::query("CREATE TABLE items (name TEXT NOT NULL)")
+            .execute(&mut db)
+            .await
+            .expect("Failed to create table");
+        db.close().await.expect("Failed to close database");
+
+        let storage_provider = get_local_provider().expect("Failed to get local storage provider");
+        let mirror = get_local_provider().expect("Failed to get mirror storage provider");
+        let broken_mirror = get_local_provider().expect("Failed to get mirror storage provider");
+        // Its worker is gone, so every upload to it fails
+        broken_mirror
+            .clone()
+            .close()
+            .await
+            .expect("Failed to close mirror");
+
+        let database_connection = DatabaseConnection::new(get_sqlite_config(&path))
+            .await
+            .expect("Failed to get database connection");
+        let engine = DbBkp::new(database_connection, storage_provider.clone())
+            .with_mirror("broken", broken_mirror)
+            .with_mirror("mirror", mirror.clone());
+
+        let result = engine
+            .backup_with_result(None)
+            .await
+            .expect("A failing mirror failed the backup");
+
+        let errors: Vec<_> = result
+            .destinations
+            .iter()
+            .map(|status| (status.destination.as_str(), status.error.is_some()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (PRIMARY_DESTINATION, false),
+                ("broken", true),
+                ("mirror", false)
+            ]
+        );
+
+        assert!(storage_provider.exists(&result.name).await.unwrap());
+        assert!(mirror.exists(&result.name).await.unwrap());
+    }
 }